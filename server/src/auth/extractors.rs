@@ -1,6 +1,6 @@
 use crate::{auth::middleware::AuthUser, error::AppError};
 use axum::{
-    extract::{FromRequestParts},
+    extract::{FromRequestParts, OptionalFromRequestParts},
     http::request::Parts,
 };
 
@@ -22,4 +22,16 @@ where
         
         Ok(AuthenticatedUser(auth_user.clone()))
     }
-}
\ No newline at end of file
+}
+
+// `Option<AuthenticatedUser>`：公开路由上没有凭据时为 None（见 `optional_auth_middleware`）
+impl<S> OptionalFromRequestParts<S> for AuthenticatedUser
+where
+    S: Send + Sync,
+{
+    type Rejection = AppError;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Option<Self>, Self::Rejection> {
+        Ok(parts.extensions.get::<AuthUser>().cloned().map(AuthenticatedUser))
+    }
+}
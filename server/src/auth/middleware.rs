@@ -1,7 +1,7 @@
 use crate::{auth::token::TokenService, error::AppError};
 use axum::{
     extract::{Request, State},
-    http::{header::AUTHORIZATION},
+    http::{header::{AUTHORIZATION, COOKIE}, HeaderMap},
     middleware::Next,
     response::Response,
 };
@@ -9,11 +9,16 @@ use serde::Serialize;
 use std::sync::Arc;
 use uuid::Uuid;
 
+/// 开启 `server.auth_cookie` 时登录下发的 cookie 名
+pub const AUTH_COOKIE_NAME: &str = "op_token";
+
 // 用于在扩展中传递的用户信息
 #[derive(Debug, Clone, Serialize)]
 pub struct AuthUser {
     pub id: Uuid,
     pub username: String,
+    /// Token expiration (unix seconds) taken from the verified claims
+    pub exp: usize,
 }
 
 pub async fn auth_middleware(
@@ -21,15 +26,40 @@ pub async fn auth_middleware(
     mut request: Request,
     next: Next,
 ) -> Result<Response, AppError> {
-    let auth_header = request
-        .headers()
-        .get(AUTHORIZATION)
-        .and_then(|header| header.to_str().ok())
-        .ok_or(AppError::AuthenticationFailed)?;
+    let auth_user = authenticate_headers(&token_service, request.headers())?;
+    
+    // 将用户信息添加到请求扩展中
+    request.extensions_mut().insert(auth_user);
+    
+    Ok(next.run(request).await)
+}
 
-    let token = auth_header
-        .strip_prefix("Bearer ")
-        .ok_or(AppError::AuthenticationFailed)?;
+/// 公开路由上的可选鉴权：带了凭据时与 `auth_middleware` 一样校验并放入 `AuthUser`，没带时直接放行
+pub async fn optional_auth_middleware(
+    State(token_service): State<Arc<TokenService>>,
+    mut request: Request,
+    next: Next,
+) -> Result<Response, AppError> {
+    let headers = request.headers();
+    if headers.contains_key(AUTHORIZATION) || cookie_token(headers).is_some() {
+        let auth_user = authenticate_headers(&token_service, headers)?;
+        request.extensions_mut().insert(auth_user);
+    }
+
+    Ok(next.run(request).await)
+}
+
+/// 校验 `Authorization: Bearer <token>`（没有该头时读取 `AUTH_COOKIE_NAME` cookie）并返回对应用户
+/// （供中间件与公开路由上的可选鉴权使用）
+pub fn authenticate_headers(token_service: &TokenService, headers: &HeaderMap) -> Result<AuthUser, AppError> {
+    let token = match headers.get(AUTHORIZATION) {
+        Some(auth_header) => auth_header
+            .to_str()
+            .ok()
+            .and_then(|h| h.strip_prefix("Bearer "))
+            .ok_or(AppError::AuthenticationFailed)?,
+        None => cookie_token(headers).ok_or(AppError::AuthenticationFailed)?,
+    };
 
     let claims = token_service.verify_token(token)?;
     
@@ -37,14 +67,23 @@ pub async fn auth_middleware(
     let user_id = claims.sub.parse::<Uuid>()
         .map_err(|_| AppError::InvalidInput("Invalid user ID in token".to_string()))?;
     
-    // 将用户信息添加到请求扩展中
-    let auth_user = AuthUser {
+    Ok(AuthUser {
         id: user_id,
         username: claims.username,
-    };
-    request.extensions_mut().insert(auth_user);
-    
-    Ok(next.run(request).await)
+        exp: claims.exp,
+    })
+}
+
+fn cookie_token(headers: &HeaderMap) -> Option<&str> {
+    headers
+        .get_all(COOKIE)
+        .iter()
+        .filter_map(|h| h.to_str().ok())
+        .flat_map(|h| h.split(';'))
+        .find_map(|pair| {
+            let (name, value) = pair.trim().split_once('=')?;
+            (name == AUTH_COOKIE_NAME).then_some(value)
+        })
 }
 
 // 辅助函数，从请求中提取用户信息
@@ -3,6 +3,7 @@ pub mod extractors;
 pub mod middleware;
 pub mod service;
 pub mod token;
+pub mod validation;
 
 #[allow(unused_imports)]
 pub use extractors::*;
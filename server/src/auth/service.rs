@@ -1,58 +1,177 @@
 use crate::{
-    auth::token::TokenService,
-    error::AppError,
-    models::{LoginRequest, LoginResponse, RegisterRequest, User, UserResponse},
-    storage::UserStorage,
+    auth::{middleware::AUTH_COOKIE_NAME, token::TokenService, validation::{normalize_email, validate_login, validate_new_password, validate_registration}},
+    error::{AppError, FieldError},
+    models::{
+        AuditAction, AuditEvent, LoginRequest, LoginResponse, PasswordReset, PasswordResetConfirm,
+        PasswordResetRequest, PasswordResetRequestResponse, RegisterRequest, User, UserResponse,
+    },
+    storage::{AuditStorage, InviteStorage, PasswordResetStorage, UserStorage},
 };
+use chrono::Utc;
+use tracing::{info, warn};
+
+/// Same answer whether or not the account exists
+const PASSWORD_RESET_REQUESTED: &str = "If the account exists, a password reset token has been issued";
+
+/// Role granted by `--create-admin`; holders may use the `/api/admin/*` endpoints
+pub const ADMIN_ROLE: &str = "admin";
 
 pub struct AuthService {
     pub user_storage: UserStorage,
+    pub audit_storage: AuditStorage,
     token_service: TokenService,
     allow_plaintext: bool,
+    auth_cookie: bool,
+    /// Mark the auth cookie `Secure` (HTTPS deployments)
+    secure_cookie: bool,
+    /// Set when registration requires an invite code
+    invites: Option<InviteStorage>,
+    /// Set when password reset is enabled
+    password_resets: Option<PasswordResetStorage>,
+    reset_ttl: chrono::Duration,
+    /// Return minted reset tokens to the caller (no mail delivery yet)
+    expose_reset_token: bool,
 }
 
 impl AuthService {
     pub fn new(
         user_storage: UserStorage,
+        audit_storage: AuditStorage,
         token_service: TokenService,
         allow_plaintext: bool,
     ) -> Self {
         Self {
             user_storage,
+            audit_storage,
             token_service,
             allow_plaintext,
+            auth_cookie: false,
+            secure_cookie: false,
+            invites: None,
+            password_resets: None,
+            reset_ttl: chrono::Duration::zero(),
+            expose_reset_token: false,
         }
     }
 
+    /// Enable `/auth/password-reset/*`; tokens live `ttl_minutes` and are only returned
+    /// to the caller when `expose_token` is set
+    pub fn with_password_resets(mut self, resets: PasswordResetStorage, ttl_minutes: i64, expose_token: bool) -> Self {
+        self.password_resets = Some(resets);
+        self.reset_ttl = chrono::Duration::try_minutes(ttl_minutes).unwrap_or_else(chrono::Duration::zero);
+        self.expose_reset_token = expose_token;
+        self
+    }
+
+    /// Only accept registrations carrying a usable invite code from `invites`
+    pub fn with_required_invites(mut self, invites: InviteStorage) -> Self {
+        self.invites = Some(invites);
+        self
+    }
+
+    /// Deliver the login token as an HttpOnly cookie instead of in the response body
+    pub fn with_auth_cookie(mut self, enabled: bool) -> Self {
+        self.auth_cookie = enabled;
+        self
+    }
+
+    /// `Secure; SameSite=Strict` for HTTPS deployments; plain-HTTP (dev) cookies get `SameSite=Lax`
+    pub fn with_secure_cookie(mut self, secure: bool) -> Self {
+        self.secure_cookie = secure;
+        self
+    }
+
+    pub fn auth_cookie(&self) -> bool {
+        self.auth_cookie
+    }
+
+    /// `Set-Cookie` value delivering `token`
+    pub fn auth_cookie_header(&self, token: &str) -> String {
+        let attributes = if self.secure_cookie { "Secure; SameSite=Strict" } else { "SameSite=Lax" };
+        format!(
+            "{}={}; HttpOnly; {}; Path=/; Max-Age={}",
+            AUTH_COOKIE_NAME,
+            token,
+            attributes,
+            self.token_expiration_hours() * 3600,
+        )
+    }
+
+    pub fn token_expiration_hours(&self) -> i64 {
+        self.token_service.expiration_hours()
+    }
+
     pub async fn register(&self, req: RegisterRequest) -> Result<UserResponse, AppError> {
-        // 检查用户是否已存在
-        if self.user_storage.get_by_username(&req.username).await?.is_some() {
-            return Err(AppError::UserAlreadyExists);
-        }
+        validate_registration(&req)?;
 
         // 创建用户
-        let password = if self.allow_plaintext {
-            req.password
-        } else {
-            // 生产环境应该使用 bcrypt
-            bcrypt::hash(req.password, bcrypt::DEFAULT_COST)
-                .map_err(|e| AppError::Internal(e.to_string()))?
+        let password = self.hash_password(req.password)?;
+
+        // 先原子地占用一次邀请码，并发注册不会超额使用
+        let claimed = match &self.invites {
+            Some(invites) => Some((invites, claim_invite(invites, req.invite_code.as_deref()).await?)),
+            None => None,
         };
 
-        let user = User::new(req.username, password);
-        let user_response = UserResponse::from(user.clone());
-        
-    self.user_storage.create(user).await?;
+        // 用户名冲突由存储层的唯一约束报告为 UserAlreadyExists
+        let mut user = User::new(req.username, password);
+        user.email = req.email.as_deref().map(normalize_email).filter(|e| !e.is_empty());
+        let user = match self.user_storage.create(user).await {
+            Ok(user) => user,
+            Err(e) => {
+                // 用户没建成，归还占用的次数
+                if let Some((invites, code)) = claimed
+                    && let Err(release_err) = invites.release(&code).await
+                {
+                    warn!("Releasing invite {} after a failed registration failed: {}", code, release_err);
+                }
+                return Err(e);
+            }
+        };
+
+        let user_response = UserResponse::from(user);
+        self.audit_storage.append(AuditEvent::new(
+            AuditAction::Register,
+            Some(user_response.id),
+            Some(user_response.username.clone()),
+        )).await?;
         
         Ok(user_response)
     }
 
+    /// Create a user holding `ADMIN_ROLE`, bypassing invites (for `--create-admin`, when the
+    /// server isn't running); same username/password rules and hashing as `register`
+    pub async fn create_admin(&self, username: String, password: String) -> Result<UserResponse, AppError> {
+        validate_registration(&RegisterRequest { username: username.clone(), password: password.clone(), invite_code: None, email: None })?;
+
+        let mut user = User::new(username, self.hash_password(password)?);
+        user.roles = vec![ADMIN_ROLE.to_string()];
+        let user = self.user_storage.create(user).await?;
+        info!("Created admin user {} ({})", user.username, user.id);
+
+        let user_response = UserResponse::from(user);
+        self.audit_storage.append(AuditEvent::new(
+            AuditAction::Register,
+            Some(user_response.id),
+            Some(user_response.username.clone()),
+        )).await?;
+        Ok(user_response)
+    }
+
     pub async fn login(&self, req: LoginRequest) -> Result<LoginResponse, AppError> {
-        let user = self
-            .user_storage
-            .get_by_username(&req.username)
-            .await?
-            .ok_or(AppError::AuthenticationFailed)?;
+        validate_login(&req)?;
+
+        let user = match self.user_storage.get_by_username(&req.username).await? {
+            Some(user) => user,
+            None => {
+                self.audit_storage.append(AuditEvent::new(
+                    AuditAction::LoginFailure,
+                    None,
+                    Some(req.username),
+                )).await?;
+                return Err(AppError::AuthenticationFailed);
+            }
+        };
 
         // 验证密码
         let password_valid = if self.allow_plaintext {
@@ -63,15 +182,117 @@ impl AuthService {
         };
 
         if !password_valid {
+            self.audit_storage.append(AuditEvent::new(
+                AuditAction::LoginFailure,
+                Some(user.id),
+                Some(user.username),
+            )).await?;
             return Err(AppError::AuthenticationFailed);
         }
 
-        let token = self.token_service.generate_token(user.id, user.username.clone())?;
+        let mut user = user;
+        user.last_login = Some(Utc::now());
+        self.user_storage.update(user.clone()).await?;
+
+        self.audit_storage.append(AuditEvent::new(
+            AuditAction::LoginSuccess,
+            Some(user.id),
+            Some(user.username.clone()),
+        )).await?;
+
+        let token = self.token_service.generate_token_with_roles(user.id, user.username.clone(), user.roles.clone())?;
         let user_response = UserResponse::from(user);
 
         Ok(LoginResponse {
-            token,
+            token: Some(token),
             user: user_response,
         })
     }
-}
\ No newline at end of file
+
+    /// Mint a single-use reset token for the account named by email (preferred) or username.
+    /// The response is the same whether or not an account matched.
+    pub async fn request_password_reset(&self, req: PasswordResetRequest) -> Result<PasswordResetRequestResponse, AppError> {
+        let resets = self.password_resets.as_ref()
+            .ok_or_else(|| AppError::Internal("password reset is not configured".to_string()))?;
+
+        let email = req.email.as_deref().map(normalize_email).filter(|e| !e.is_empty());
+        let username = req.username.as_deref().map(str::trim).filter(|u| !u.is_empty());
+        let user = match (email, username) {
+            (Some(email), _) => self.user_storage.get_by_email(&email).await?,
+            (None, Some(username)) => self.user_storage.get_by_username(username).await?,
+            (None, None) => return Err(AppError::Validation(vec![FieldError {
+                field: "username".to_string(),
+                message: "username or email is required".to_string(),
+            }])),
+        };
+
+        let mut response = PasswordResetRequestResponse {
+            message: PASSWORD_RESET_REQUESTED.to_string(),
+            token: None,
+        };
+        let Some(user) = user else { return Ok(response) };
+
+        let (reset, token) = PasswordReset::new(user.id, self.reset_ttl);
+        resets.create(reset).await?;
+        self.audit_storage.append(AuditEvent::new(
+            AuditAction::PasswordResetRequest,
+            Some(user.id),
+            Some(user.username.clone()),
+        )).await?;
+
+        if self.expose_reset_token {
+            info!("Password reset token for '{}': {}", user.username, token);
+            response.token = Some(token);
+        }
+        Ok(response)
+    }
+
+    /// Set a new password using a token from `request_password_reset`; the token is spent either way
+    pub async fn confirm_password_reset(&self, req: PasswordResetConfirm) -> Result<(), AppError> {
+        let resets = self.password_resets.as_ref()
+            .ok_or_else(|| AppError::Internal("password reset is not configured".to_string()))?;
+        validate_new_password(&req.new_password)?;
+
+        let invalid = || AppError::Validation(vec![FieldError {
+            field: "token".to_string(),
+            message: "is invalid, expired or already used".to_string(),
+        }]);
+        // 先原子地作废令牌，再改密码；并发确认时只有一方拿到令牌
+        let reset = resets.redeem(&PasswordReset::hash_token(req.token.trim()), Utc::now()).await?
+            .ok_or_else(invalid)?;
+
+        let mut user = self.user_storage.get(reset.user_id).await?.ok_or_else(invalid)?;
+        user.password = self.hash_password(req.new_password)?;
+        self.user_storage.update(user.clone()).await?;
+
+        self.audit_storage.append(AuditEvent::new(
+            AuditAction::PasswordReset,
+            Some(user.id),
+            Some(user.username),
+        )).await?;
+        Ok(())
+    }
+
+    fn hash_password(&self, password: String) -> Result<String, AppError> {
+        if self.allow_plaintext {
+            Ok(password)
+        } else {
+            // 生产环境应该使用 bcrypt
+            bcrypt::hash(password, bcrypt::DEFAULT_COST)
+                .map_err(|e| AppError::Internal(e.to_string()))
+        }
+    }
+}
+
+/// Take one use of the invite `code`, returning the trimmed code for a later `release`
+async fn claim_invite(invites: &InviteStorage, code: Option<&str>) -> Result<String, AppError> {
+    let problem = |message: &str| AppError::Validation(vec![FieldError {
+        field: "invite_code".to_string(),
+        message: message.to_string(),
+    }]);
+    let code = code.map(str::trim).filter(|c| !c.is_empty()).ok_or_else(|| problem("is required"))?;
+    if !invites.claim(code, Utc::now()).await? {
+        return Err(problem("is invalid, expired or already used"));
+    }
+    Ok(code.to_string())
+}
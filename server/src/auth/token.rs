@@ -1,20 +1,42 @@
-use crate::{error::AppError, models::Claims};
+use crate::{config::default_jwt_leeway_secs, error::AppError, models::{Claims, IntrospectResponse}};
 use chrono::{Duration, Utc};
-use jsonwebtoken::{decode, encode, DecodingKey, EncodingKey, Header, Validation};
+use jsonwebtoken::{decode, encode, errors::ErrorKind, DecodingKey, EncodingKey, Header, Validation};
 use uuid::Uuid;
 
 #[derive(Clone)]
 pub struct TokenService {
     secret: String,
     expiration_hours: i64,
+    custom_claims: Option<serde_json::Value>,
+    leeway_secs: u64,
 }
 
 impl TokenService {
     pub fn new(secret: String, expiration_hours: i64) -> Self {
-        Self { secret, expiration_hours }
+        Self { secret, expiration_hours, custom_claims: None, leeway_secs: default_jwt_leeway_secs() }
+    }
+
+    /// 校验 `exp` 时容忍的时钟偏差（秒）
+    pub fn with_leeway(mut self, leeway_secs: u64) -> Self {
+        self.leeway_secs = leeway_secs;
+        self
+    }
+
+    /// 每个 token 都带上的 `custom` claim；非 JSON 对象会被忽略（配置校验时已告警）
+    pub fn with_custom_claims(mut self, custom_claims: Option<serde_json::Value>) -> Self {
+        self.custom_claims = custom_claims.filter(|v| v.is_object());
+        self
+    }
+
+    pub fn expiration_hours(&self) -> i64 {
+        self.expiration_hours
     }
 
     pub fn generate_token(&self, user_id: Uuid, username: String) -> Result<String, AppError> {
+        self.generate_token_with_roles(user_id, username, Vec::new())
+    }
+
+    pub fn generate_token_with_roles(&self, user_id: Uuid, username: String, roles: Vec<String>) -> Result<String, AppError> {
         let expiration = Utc::now()
             .checked_add_signed(Duration::hours(self.expiration_hours))
             .expect("valid timestamp")
@@ -24,6 +46,8 @@ impl TokenService {
             sub: user_id.to_string(),
             username,
             exp: expiration as usize,
+            roles,
+            custom: self.custom_claims.clone(),
         };
 
         let token = encode(
@@ -35,13 +59,48 @@ impl TokenService {
         Ok(token)
     }
 
+    /// 过期（超出 leeway）返回 `TokenExpired`，客户端据此刷新而不是重新登录；
+    /// 格式错误、签名不符等一律为 `AuthenticationFailed`
     pub fn verify_token(&self, token: &str) -> Result<Claims, AppError> {
+        let mut validation = Validation::default();
+        validation.leeway = self.leeway_secs;
         let token_data = decode::<Claims>(
             token,
             &DecodingKey::from_secret(self.secret.as_ref()),
-            &Validation::default(),
-        )?;
+            &validation,
+        )
+        .map_err(|e| match e.kind() {
+            ErrorKind::ExpiredSignature => AppError::TokenExpired(self.seconds_since_expiry(token)),
+            _ => AppError::AuthenticationFailed,
+        })?;
 
         Ok(token_data.claims)
     }
-}
\ No newline at end of file
+
+    /// Seconds since the `exp` of a token already known to be validly signed but expired
+    fn seconds_since_expiry(&self, token: &str) -> i64 {
+        let mut validation = Validation::default();
+        validation.validate_exp = false;
+        decode::<Claims>(token, &DecodingKey::from_secret(self.secret.as_ref()), &validation)
+            .map(|data| (Utc::now().timestamp() - data.claims.exp as i64).max(0))
+            .unwrap_or(0)
+    }
+
+    /// 解析 token 而不报错：无效、过期或签名不符时返回 inactive
+    pub fn introspect(&self, token: &str) -> IntrospectResponse {
+        match self.verify_token(token) {
+            Ok(claims) => {
+                let expires_in = claims.exp as i64 - Utc::now().timestamp();
+                IntrospectResponse {
+                    active: true,
+                    sub: Some(claims.sub),
+                    username: Some(claims.username),
+                    exp: Some(claims.exp),
+                    expires_in: Some(expires_in),
+                    roles: Some(claims.roles),
+                }
+            }
+            Err(_) => IntrospectResponse::inactive(),
+        }
+    }
+}
@@ -0,0 +1,99 @@
+use crate::{
+    error::{AppError, FieldError},
+    models::{LoginRequest, RegisterRequest},
+};
+
+pub const MAX_USERNAME_LEN: usize = 64;
+pub const MIN_PASSWORD_LEN: usize = 8;
+/// bcrypt ignores everything past the first 72 bytes
+pub const MAX_PASSWORD_BYTES: usize = 72;
+/// RFC 5321 path limit
+pub const MAX_EMAIL_LEN: usize = 254;
+
+fn username_problem(username: &str) -> Option<String> {
+    if username.trim().is_empty() {
+        Some("must not be empty".to_string())
+    } else if username.chars().count() > MAX_USERNAME_LEN {
+        Some(format!("must be at most {} characters", MAX_USERNAME_LEN))
+    } else if username.chars().any(|c| c.is_whitespace() || c.is_control()) {
+        Some("must not contain spaces or control characters".to_string())
+    } else {
+        None
+    }
+}
+
+fn password_problem(password: &str) -> Option<String> {
+    if password.chars().count() < MIN_PASSWORD_LEN {
+        Some(format!("must be at least {} characters", MIN_PASSWORD_LEN))
+    } else if password.len() > MAX_PASSWORD_BYTES {
+        Some(format!("must be at most {} bytes", MAX_PASSWORD_BYTES))
+    } else {
+        None
+    }
+}
+
+/// Basic shape only (`local@domain.tld`); whether the address exists is not checked
+fn email_problem(email: &str) -> Option<String> {
+    let Some((local, domain)) = email.split_once('@') else {
+        return Some("must be an email address like name@example.com".to_string());
+    };
+    if email.len() > MAX_EMAIL_LEN {
+        Some(format!("must be at most {} characters", MAX_EMAIL_LEN))
+    } else if email.chars().any(|c| c.is_whitespace() || c.is_control())
+        || local.is_empty()
+        || domain.contains('@')
+        || !domain.contains('.')
+        || domain.starts_with('.')
+        || domain.ends_with('.')
+    {
+        Some("must be an email address like name@example.com".to_string())
+    } else {
+        None
+    }
+}
+
+/// Canonical stored form of an email address
+pub fn normalize_email(email: &str) -> String {
+    email.trim().to_lowercase()
+}
+
+/// Turn the collected `(field, problem)` pairs into `AppError::Validation`, if any
+fn collect(checks: Vec<(&str, Option<String>)>) -> Result<(), AppError> {
+    let fields: Vec<FieldError> = checks
+        .into_iter()
+        .filter_map(|(field, problem)| problem.map(|message| FieldError { field: field.to_string(), message }))
+        .collect();
+    if fields.is_empty() { Ok(()) } else { Err(AppError::Validation(fields)) }
+}
+
+/// 注册：用户名格式与密码强度，所有字段的问题一次性返回
+pub fn validate_registration(req: &RegisterRequest) -> Result<(), AppError> {
+    collect(vec![
+        ("username", username_problem(&req.username)),
+        ("password", password_problem(&req.password)),
+        ("email", req.email.as_deref().and_then(|e| email_problem(&normalize_email(e)))),
+    ])
+}
+
+/// 重置密码时的新密码，规则与注册相同
+pub fn validate_new_password(password: &str) -> Result<(), AppError> {
+    collect(vec![("new_password", password_problem(password))])
+}
+
+/// 登录只检查字段非空；密码强度规则变化不应把已有用户挡在门外
+pub fn validate_login(req: &LoginRequest) -> Result<(), AppError> {
+    collect(vec![
+        ("username", req.username.trim().is_empty().then(|| "must not be empty".to_string())),
+        ("password", req.password.is_empty().then(|| "must not be empty".to_string())),
+    ])
+}
+
+/// 修改资料时的新用户名
+pub fn validate_username(username: &str) -> Result<(), AppError> {
+    collect(vec![("username", username_problem(username))])
+}
+
+/// 修改资料时的新邮箱（已规范化）
+pub fn validate_email(email: &str) -> Result<(), AppError> {
+    collect(vec![("email", email_problem(email))])
+}
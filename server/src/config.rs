@@ -1,7 +1,10 @@
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
-use std::path::{Path, PathBuf};
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::path::{Component, Path, PathBuf};
 use std::fs;
+use crate::error::ERROR_CODES;
 use crate::utils::secrets::generate_secret;
 use regex::Regex;
 
@@ -24,10 +27,103 @@ pub struct ServerConfig {
     pub port: u16,
     pub jwt_secret: String,
     pub static_root: Option<PathBuf>,
+    /// `RUST_LOG`-style filter, e.g. "info,obsidian_publisher_server=debug"
+    #[serde(default)]
+    pub log_filter: Option<String>,
+    /// Private instance: `GET /api/sites` requires auth (caller's sites only) and
+    /// `/api/admin/*` only accept a signed-in admin, not `?key=`
+    #[serde(default)]
+    pub require_auth_for_listing: bool,
+    /// Login sets the JWT as an HttpOnly cookie instead of returning it in the body
+    #[serde(default)]
+    pub auth_cookie: bool,
+    /// Force the auth cookie's `Secure` flag on or off; by default it follows `url`'s scheme
+    #[serde(default)]
+    pub secure_cookie: Option<bool>,
+    /// Replacement `error` messages keyed by error code (e.g. `AUTH_FAILED`), for
+    /// white-labeled deployments; status and code are unchanged
+    #[serde(default)]
+    pub error_messages: HashMap<String, String>,
+    /// Store the uploader's IP and User-Agent on each site version (admin-only);
+    /// turn off where that counts as personal data you don't want to keep
+    #[serde(default = "default_record_upload_origin")]
+    pub record_upload_origin: bool,
+    /// Include extraction timings and byte counts (`metrics`) in upload responses;
+    /// they are logged at debug level either way
+    #[serde(default)]
+    pub upload_metrics: bool,
+    /// An upload whose `siteName` is not a valid name is published under its slug
+    /// ("My Cool Site!" becomes "my-cool-site") instead of being rejected
+    #[serde(default)]
+    pub allow_slugify: bool,
+    /// HTML page returned (404) for `/sites/<name>` when no such site exists;
+    /// unset gives a JSON 404. A site's own missing files are unaffected
+    #[serde(default)]
+    pub sites_not_found_page: Option<PathBuf>,
+    /// Clock skew tolerated when checking a token's `exp`, in seconds
+    #[serde(default = "default_jwt_leeway_secs")]
+    pub jwt_leeway_secs: u64,
+    /// Reverse proxies whose `X-Forwarded-For` / `X-Real-IP` headers are believed; requests
+    /// from any other peer are attributed to the socket address
+    #[serde(default)]
+    pub trusted_proxies: Vec<IpAddr>,
+    /// Shape of the `url` / `url_by_id` links returned for sites
+    #[serde(default)]
+    pub site_url_style: SiteUrlStyle,
+    /// Largest request body accepted, in bytes; bigger requests (uploads, mostly) get a 413
+    #[serde(default = "default_max_body_bytes")]
+    pub max_body_bytes: u64,
 }
 
+/// How site links end: `/sites/blog/` (default), `/sites/blog` or `/sites/blog/index.html`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SiteUrlStyle {
+    #[default]
+    TrailingSlash,
+    NoSlash,
+    ExplicitIndex,
+}
+
+impl SiteUrlStyle {
+    /// Link to the site served at `/sites/<segment>` under `base_url`
+    pub fn site_url(self, base_url: &str, segment: &str) -> String {
+        match self {
+            SiteUrlStyle::TrailingSlash => format!("{}/sites/{}/", base_url, segment),
+            SiteUrlStyle::NoSlash => format!("{}/sites/{}", base_url, segment),
+            SiteUrlStyle::ExplicitIndex => format!("{}/sites/{}/index.html", base_url, segment),
+        }
+    }
+}
+
+fn default_record_upload_origin() -> bool { true }
+
+/// 250 MB
+pub fn default_max_body_bytes() -> u64 { 250 * 1024 * 1024 }
+
+/// Same as jsonwebtoken's own default
+pub fn default_jwt_leeway_secs() -> u64 { 60 }
+
 impl ServerConfig {
     pub fn bind_url(&self) -> String { format!("{}:{}", self.host, self.port) }
+
+    /// 仅在 https 部署下设置 `Secure`，否则浏览器在 http 开发环境不会回传 cookie
+    pub fn cookie_secure(&self) -> bool {
+        self.secure_cookie.unwrap_or_else(|| {
+            self.url.get(..8).is_some_and(|scheme| scheme.eq_ignore_ascii_case("https://"))
+        })
+    }
+}
+
+pub const DEFAULT_LOG_FILTER: &str = "info,obsidian_publisher_server=debug";
+
+/// 日志过滤串优先级：配置 > RUST_LOG 环境变量 > 默认值（空串视为未设置）
+pub fn resolve_log_filter(config_filter: Option<&str>, env_filter: Option<&str>) -> String {
+    config_filter
+        .filter(|f| !f.trim().is_empty())
+        .or(env_filter.filter(|f| !f.trim().is_empty()))
+        .unwrap_or(DEFAULT_LOG_FILTER)
+        .to_string()
 }
 
 impl Validate for ServerConfig {
@@ -50,6 +146,22 @@ impl Validate for ServerConfig {
             _ => {}
         }
 
+        if let Some(p) = &self.sites_not_found_page
+            && !p.is_file()
+        {
+            warnings.push(format!("server.sites_not_found_page '{}' is not a file; a JSON 404 is used instead", p.display()));
+        }
+
+        if self.max_body_bytes == 0 {
+            warnings.push("server.max_body_bytes is 0; every request with a body will be rejected".to_string());
+        }
+
+        for code in self.error_messages.keys() {
+            if !ERROR_CODES.contains(&code.as_str()) {
+                warnings.push(format!("server.error_messages has unknown error code '{}'", code));
+            }
+        }
+
         warnings
     }
 }
@@ -60,6 +172,93 @@ pub struct StorageConfig {
     pub sites: StaticStorageConfig,
     // Multiple storage backends supported. Order defines preference when applicable.
     pub db: Vec<StorageEntry>,
+    // Limits applied while extracting uploaded archives
+    #[serde(default)]
+    pub archive: ArchiveConfig,
+    // Keep upload temp files and extraction dirs when an upload fails, for debugging
+    #[serde(default)]
+    pub keep_temp_on_error: bool,
+    // Uploads beyond this many extract at once wait for a slot
+    #[serde(default = "default_max_concurrent_extractions")]
+    pub max_concurrent_extractions: usize,
+    // A user's further uploads are refused (429) while this many of theirs are being published; 0 = no limit
+    #[serde(default = "default_max_concurrent_uploads_per_user")]
+    pub max_concurrent_uploads_per_user: usize,
+    // Audit events older than this many days are pruned at startup and hourly; 0 = keep forever
+    #[serde(default)]
+    pub audit_retention_days: u64,
+    // With `debug_sled_and_orm`, write to sled and orm concurrently instead of one after the
+    // other; results are compared the same way
+    #[serde(default)]
+    pub debug_concurrent_writes: bool,
+    // sqlite / postgres reads that fail on a dropped connection, pool timeout or busy database are
+    // retried this many times; writes only when no connection could be acquired. Constraint
+    // violations and missing rows never are. 0 = no retries
+    #[serde(default = "default_db_retries")]
+    pub db_retries: u32,
+    // Pause before the first retry, doubled for each one after it
+    #[serde(default = "default_db_retry_backoff_ms")]
+    pub db_retry_backoff_ms: u64,
+}
+
+fn default_max_concurrent_extractions() -> usize { 4 }
+fn default_max_concurrent_uploads_per_user() -> usize { 0 }
+pub fn default_db_retries() -> u32 { 3 }
+pub fn default_db_retry_backoff_ms() -> u64 { 100 }
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ArchiveConfig {
+    /// Maximum number of path components for an archive entry
+    pub max_path_depth: usize,
+    /// Maximum length in bytes of a single path component
+    pub max_component_length: usize,
+    /// Maximum number of entries (files and directories) in one archive
+    #[serde(default = "default_max_archive_entries")]
+    pub max_entries: usize,
+    /// Maximum total uncompressed size in bytes, as declared by the archive's entries
+    #[serde(default = "default_max_archive_bytes")]
+    pub max_total_bytes: u64,
+    /// File extensions that may not be published, e.g. `["php", "exe"]` (case-insensitive,
+    /// leading dot optional)
+    #[serde(default)]
+    pub forbidden_extensions: Vec<String>,
+    /// Whether a forbidden file refuses the whole upload or is left out with a warning
+    #[serde(default)]
+    pub forbidden_extension_mode: ForbiddenExtensionMode,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ForbiddenExtensionMode {
+    #[default]
+    Reject,
+    Skip,
+}
+
+impl ArchiveConfig {
+    /// Whether `path`'s extension is listed in `forbidden_extensions`
+    pub fn is_forbidden(&self, path: &Path) -> bool {
+        let Some(ext) = path.extension().and_then(|e| e.to_str()) else { return false };
+        self.forbidden_extensions
+            .iter()
+            .any(|f| f.trim_start_matches('.').eq_ignore_ascii_case(ext))
+    }
+}
+
+fn default_max_archive_entries() -> usize { 100_000 }
+fn default_max_archive_bytes() -> u64 { 1 << 30 }
+
+impl Default for ArchiveConfig {
+    fn default() -> Self {
+        Self {
+            max_path_depth: 32,
+            max_component_length: 255,
+            max_entries: default_max_archive_entries(),
+            max_total_bytes: default_max_archive_bytes(),
+            forbidden_extensions: Vec::new(),
+            forbidden_extension_mode: ForbiddenExtensionMode::default(),
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -72,11 +271,60 @@ pub struct StaticStorageConfig {
 pub struct StorageEntry {
     /// Optional logical name for this storage (useful for diagnostics)
     pub name: Option<String>,
-    /// Backend identifier, e.g. "sled", "sqlite", "postgres", etc.
+    /// Backend identifier: "sled", "sqlite", "postgres" or "memory"
     pub backend: String,
     /// Optional path (for file-backed storages)
     #[serde(default)]
     pub path: Option<PathBuf>,
+    /// Optional routing hint: serve only users (and the audit log) or only sites from this entry
+    #[serde(default)]
+    pub role: Option<StorageRole>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StorageRole {
+    Users,
+    Sites,
+}
+
+impl StorageRole {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            StorageRole::Users => "users",
+            StorageRole::Sites => "sites",
+        }
+    }
+}
+
+/// `path` made absolute against the working directory with `.` and `..` folded away,
+/// without touching the filesystem
+fn lexical_absolute(path: &Path) -> PathBuf {
+    let absolute = std::path::absolute(path).unwrap_or_else(|_| path.to_path_buf());
+    let mut folded = PathBuf::new();
+    for component in absolute.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                folded.pop();
+            }
+            other => folded.push(other),
+        }
+    }
+    folded
+}
+
+/// Whether one directory is the other or lies inside it
+fn paths_nest(a: &Path, b: &Path) -> bool {
+    a.starts_with(b) || b.starts_with(a)
+}
+
+/// sqlite 与 postgres 都由 orm 实现提供，未声明 role 时只能存在一个
+fn backend_family(backend: &str) -> &str {
+    match backend {
+        "sqlite" | "postgres" => "orm",
+        other => other,
+    }
 }
 
 impl Validate for StorageConfig {
@@ -86,31 +334,127 @@ impl Validate for StorageConfig {
             warns.push("storage.db is empty; no storage configured".to_string());
         }
         for (i, s) in self.db.iter().enumerate() {
-            if !matches!(s.backend.as_ref(), "sled" | "sqlite" | "postgres") {
+            if !matches!(s.backend.as_ref(), "sled" | "sqlite" | "postgres" | "memory") {
                 warns.push(format!(
-                    "storage.storages[{}].backend '{}' is not supported; must be one of: sled, sqlite, postgres",
+                    "storage.storages[{}].backend '{}' is not supported; must be one of: sled, sqlite, postgres, memory",
                     i, s.backend
                 ));
             }
-            // if backend is file-based, ensure path is present
-            if matches!(s.backend.as_ref(), "sled" | "sqlite") && s.path.is_none() {
+            if s.backend == "memory" {
                 warns.push(format!(
-                    "storage.storages[{}] with backend '{}' requires a 'path' field",
-                    i, s.backend
+                    "storage.storages[{}] uses the 'memory' backend; its data is lost when the server stops",
+                    i
+                ));
+            }
+        }
+        warns.extend(self.conflicts());
+        // 字面上不嵌套、但经符号链接等解析后重叠的路径只警告；两者都存在时才能判断
+        let sites = lexical_absolute(&self.sites.path);
+        for (i, s, path) in self.file_backed_db() {
+            if paths_nest(&sites, &lexical_absolute(path)) {
+                continue;
+            }
+            if let (Ok(a), Ok(b)) = (fs::canonicalize(&self.sites.path), fs::canonicalize(path))
+                && paths_nest(&a, &b)
+            {
+                warns.push(format!(
+                    "storage.sites.path '{}' and storage.db[{}] ({}) path '{}' resolve to overlapping directories",
+                    self.sites.path.display(), i, s.backend, path.display()
                 ));
             }
         }
+        if self.archive.max_path_depth == 0 {
+            warns.push("storage.archive.max_path_depth is 0; every archive entry will be rejected".to_string());
+        }
+        if self.archive.max_component_length == 0 {
+            warns.push("storage.archive.max_component_length is 0; every archive entry will be rejected".to_string());
+        }
+        if self.archive.max_entries == 0 {
+            warns.push("storage.archive.max_entries is 0; every archive will be rejected".to_string());
+        }
+        if self.max_concurrent_extractions == 0 {
+            warns.push("storage.max_concurrent_extractions is 0; treating it as 1".to_string());
+        }
         warns
     }
 }
 
+/// storage.db 最多允许的条目数（sled + orm 各一个，外加按 role 路由的两个）
+pub const MAX_STORAGE_ENTRIES: usize = 4;
+
 impl StorageConfig {
-    /// 获取第一个匹配指定后端的存储路径（如果有）
+    /// 无法使用的 storage.db 组合；`Storage::new` 遇到任何一条都会拒绝启动
+    pub fn conflicts(&self) -> Vec<String> {
+        let mut errs = Vec::new();
+        if self.db.len() > MAX_STORAGE_ENTRIES {
+            errs.push(format!(
+                "storage.db has {} entries; at most {} are supported",
+                self.db.len(), MAX_STORAGE_ENTRIES
+            ));
+        }
+        for (i, s) in self.db.iter().enumerate() {
+            // if backend is file-based, ensure path is present
+            if matches!(s.backend.as_ref(), "sled" | "sqlite") && s.path.is_none() {
+                errs.push(format!(
+                    "storage.storages[{}] with backend '{}' requires a 'path' field",
+                    i, s.backend
+                ));
+            }
+            for (j, t) in self.db.iter().enumerate().skip(i + 1) {
+                if let Some(role) = s.role.filter(|_| s.role == t.role) {
+                    errs.push(format!(
+                        "storage.db[{}] and storage.db[{}] both declare role '{}'",
+                        i, j, role.as_str()
+                    ));
+                }
+                // 未声明 role 的条目按 first_db_with_backend 选取，同类后端重复时会被静默忽略
+                if s.role.is_none() && t.role.is_none() && backend_family(&s.backend) == backend_family(&t.backend) {
+                    errs.push(format!(
+                        "storage.db[{}] and storage.db[{}] are duplicate '{}' backends without a role",
+                        i, j, backend_family(&s.backend)
+                    ));
+                }
+                // sled 独占目录锁，sqlite 共用同一个文件也会互相覆盖
+                if let Some(path) = s.path.as_ref().filter(|_| s.path == t.path) {
+                    errs.push(format!(
+                        "storage.db[{}] and storage.db[{}] share the path '{}'",
+                        i, j, path.display()
+                    ));
+                }
+            }
+        }
+        // 静态文件服务会把嵌套在站点目录里的数据库文件直接暴露出去
+        let sites = lexical_absolute(&self.sites.path);
+        for (i, s, path) in self.file_backed_db() {
+            if paths_nest(&sites, &lexical_absolute(path)) {
+                errs.push(format!(
+                    "storage.sites.path '{}' and storage.db[{}] ({}) path '{}' are nested; site files and database files must not share a directory",
+                    self.sites.path.display(), i, s.backend, path.display()
+                ));
+            }
+        }
+        errs
+    }
+
+    /// (index, entry, path) of every sled/sqlite entry that has a path
+    fn file_backed_db(&self) -> impl Iterator<Item = (usize, &StorageEntry, &PathBuf)> {
+        self.db.iter().enumerate().filter_map(|(i, s)| match (s.backend.as_str(), &s.path) {
+            ("sled" | "sqlite", Some(path)) => Some((i, s, path)),
+            _ => None,
+        })
+    }
+
+    /// 获取第一个匹配指定后端、且未声明 role 的存储路径（如果有）
     pub fn first_db_with_backend(&self, backends: &[&str]) -> Option<&StorageEntry> {
         self.db.iter().find(|entry| {
-            backends.contains(&entry.backend.as_str())
+            entry.role.is_none() && backends.contains(&entry.backend.as_str())
         })
     }
+
+    /// 获取第一个显式声明了该 role 的存储（如果有）
+    pub fn db_for_role(&self, role: StorageRole) -> Option<&StorageEntry> {
+        self.db.iter().find(|entry| entry.role == Some(role))
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -118,14 +462,35 @@ pub struct AuthConfig {
     pub allow_plaintext_password: bool,
     /// Token expiration in hours
     pub token_expiration_hours: i64,
+    /// Extra claims copied into every token as `custom` (for reverse proxies); must be a JSON object
+    #[serde(default)]
+    pub custom_claims: Option<serde_json::Value>,
+    /// Registration needs an `invite_code` minted via `POST /api/admin/invites`
+    #[serde(default)]
+    pub require_invite: bool,
+    /// Lifetime of password reset tokens
+    #[serde(default = "default_password_reset_ttl_minutes")]
+    pub password_reset_ttl_minutes: i64,
+    /// Return reset tokens in the `/auth/password-reset/request` response and log them.
+    /// There is no mail delivery yet; never enable this on a public instance.
+    #[serde(default)]
+    pub expose_reset_token: bool,
 }
 
+pub fn default_password_reset_ttl_minutes() -> i64 { 30 }
+
 impl Validate for AuthConfig {
     fn validate(&self) -> Vec<String> {
         let mut warns = Vec::new();
         if self.token_expiration_hours <= 0 {
             warns.push("auth.token_expiration_hours must be > 0".to_string());
         }
+        if self.custom_claims.as_ref().is_some_and(|v| !v.is_object()) {
+            warns.push("auth.custom_claims must be a JSON object; it is ignored".to_string());
+        }
+        if self.password_reset_ttl_minutes <= 0 {
+            warns.push("auth.password_reset_ttl_minutes must be > 0".to_string());
+        }
         warns
     }
 }
@@ -139,14 +504,39 @@ impl Default for Config {
                 port: 8080,
                 jwt_secret: generate_secret(),
                 static_root: None,
+                log_filter: None,
+                require_auth_for_listing: false,
+                auth_cookie: false,
+                secure_cookie: None,
+                error_messages: HashMap::new(),
+                record_upload_origin: true,
+                upload_metrics: false,
+                allow_slugify: false,
+                sites_not_found_page: None,
+                jwt_leeway_secs: default_jwt_leeway_secs(),
+                trusted_proxies: Vec::new(),
+                site_url_style: SiteUrlStyle::default(),
+                max_body_bytes: default_max_body_bytes(),
             },
             storage: StorageConfig {
                 sites: StaticStorageConfig { path: PathBuf::from("./data/sites") },
-                db: vec![StorageEntry { name: Some("default".to_string()), backend: "sled".to_string(), path: Some(PathBuf::from("./data/sled")) }],
+                db: vec![StorageEntry { name: Some("default".to_string()), backend: "sled".to_string(), path: Some(PathBuf::from("./data/sled")), role: None }],
+                archive: ArchiveConfig::default(),
+                keep_temp_on_error: false,
+                max_concurrent_extractions: default_max_concurrent_extractions(),
+                max_concurrent_uploads_per_user: default_max_concurrent_uploads_per_user(),
+                audit_retention_days: 0,
+                debug_concurrent_writes: false,
+                db_retries: default_db_retries(),
+                db_retry_backoff_ms: default_db_retry_backoff_ms(),
             },
             auth: AuthConfig {
                 allow_plaintext_password: true,
                 token_expiration_hours: 24,
+                custom_claims: None,
+                require_invite: false,
+                password_reset_ttl_minutes: default_password_reset_ttl_minutes(),
+                expose_reset_token: false,
             },
         }
     }
@@ -180,6 +570,13 @@ impl Config {
 
         Ok(config)
     }
+
+    /// 完整的默认配置模板（所有字段齐全，jwt_secret 已生成），格式与写回的配置文件一致
+    pub fn template() -> anyhow::Result<String> {
+        let default_val = serde_json::to_value(Config::default())?;
+        let merged = normalize_config(None, default_val)?;
+        Ok(serde_json::to_string_pretty(&merged)?)
+    }
 }
 
 // ---------------- helper functions ----------------
@@ -266,6 +663,19 @@ mod jwt_tests {
         assert_eq!(path, "./data/sites");
     }
 
+    // 生成的模板应能原样读回为 Config，且包含默认配置的所有字段
+    #[test]
+    fn template_round_trips() {
+        let template = Config::template().expect("template");
+        let config: Config = serde_json::from_str(&template).expect("template should parse as Config");
+        assert!(!config.server.jwt_secret.is_empty());
+        assert_eq!(config.server.port, 8080);
+
+        let v: Value = serde_json::from_str(&template).unwrap();
+        let default_val = serde_json::to_value(&Config::default()).unwrap();
+        assert!(check_unknown_keys(&v, &default_val).is_empty(), "template is missing fields");
+    }
+
     // userconfig = {jwt_secret: ""}
     // 如果用户给了空的 jwt，应当被替换为非空值
     #[test]
@@ -277,6 +687,125 @@ mod jwt_tests {
     }
 }
 
+#[cfg(test)]
+mod storage_validate_tests {
+    use super::*;
+
+    fn entry(backend: &str, path: Option<&str>, role: Option<StorageRole>) -> StorageEntry {
+        StorageEntry { name: None, backend: backend.to_string(), path: path.map(PathBuf::from), role }
+    }
+
+    fn storage(db: Vec<StorageEntry>) -> StorageConfig {
+        StorageConfig {
+            sites: StaticStorageConfig { path: PathBuf::from("./data/sites") },
+            db,
+            archive: ArchiveConfig::default(),
+            keep_temp_on_error: false,
+            max_concurrent_extractions: default_max_concurrent_extractions(),
+            max_concurrent_uploads_per_user: default_max_concurrent_uploads_per_user(),
+            audit_retention_days: 0,
+            debug_concurrent_writes: false,
+            db_retries: default_db_retries(),
+            db_retry_backoff_ms: default_db_retry_backoff_ms(),
+        }
+    }
+
+    #[test]
+    fn default_storage_has_no_warnings() {
+        assert!(Config::default().storage.validate().is_empty());
+    }
+
+    #[test]
+    fn duplicate_backend_warns() {
+        let cfg = storage(vec![
+            entry("sled", Some("./a"), None),
+            entry("sled", Some("./b"), None),
+        ]);
+        let warns = cfg.validate();
+        assert!(warns.iter().any(|w| w.contains("duplicate 'sled' backends")), "got {:?}", warns);
+    }
+
+    #[test]
+    fn conflicting_roles_and_missing_path_warn() {
+        let cfg = storage(vec![
+            entry("sled", None, Some(StorageRole::Users)),
+            entry("sqlite", Some("./db"), Some(StorageRole::Users)),
+        ]);
+        let warns = cfg.validate();
+        assert!(warns.iter().any(|w| w.contains("requires a 'path' field")), "got {:?}", warns);
+        assert!(warns.iter().any(|w| w.contains("both declare role 'users'")), "got {:?}", warns);
+    }
+
+    #[test]
+    fn routed_entries_of_same_backend_are_fine() {
+        let cfg = storage(vec![
+            entry("sled", Some("./users"), Some(StorageRole::Users)),
+            entry("sled", Some("./sites"), Some(StorageRole::Sites)),
+        ]);
+        assert!(cfg.conflicts().is_empty());
+    }
+
+    #[test]
+    fn nested_sites_and_db_paths_conflict() {
+        for db_path in ["./data/sites/sled", "./data", "./data/./sites", "./data/other/../sites/db"] {
+            let cfg = storage(vec![entry("sled", Some(db_path), None)]);
+            let errs = cfg.conflicts();
+            assert!(errs.iter().any(|e| e.contains("are nested")), "{}: got {:?}", db_path, errs);
+        }
+        // A sibling that merely shares a name prefix is fine
+        for db_path in ["./data/sites-db", "./data/sled", "./data/sites/../sled"] {
+            assert!(storage(vec![entry("sled", Some(db_path), None)]).conflicts().is_empty(), "{}", db_path);
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn sites_and_db_overlapping_through_symlink_warns() {
+        let temp = tempfile::tempdir().unwrap();
+        let sites = temp.path().join("sites");
+        std::fs::create_dir_all(&sites).unwrap();
+        std::os::unix::fs::symlink(&sites, temp.path().join("alias")).unwrap();
+        let db = temp.path().join("alias").join("db");
+        std::fs::create_dir_all(&db).unwrap();
+
+        let mut cfg = storage(vec![StorageEntry { name: None, backend: "sqlite".to_string(), path: Some(db), role: None }]);
+        cfg.sites.path = sites;
+        assert!(cfg.conflicts().is_empty(), "not nested on paper: {:?}", cfg.conflicts());
+        let warns = cfg.validate();
+        assert!(warns.iter().any(|w| w.contains("resolve to overlapping directories")), "got {:?}", warns);
+    }
+
+    #[test]
+    fn memory_backend_is_supported_but_warns_about_data_loss() {
+        let warns = storage(vec![entry("memory", None, None)]).validate();
+        assert!(!warns.iter().any(|w| w.contains("is not supported")), "got {:?}", warns);
+        assert!(warns.iter().any(|w| w.contains("lost when the server stops")), "got {:?}", warns);
+    }
+}
+
+#[cfg(test)]
+mod log_filter_tests {
+    use super::*;
+
+    #[test]
+    fn config_filter_takes_precedence() {
+        let f = resolve_log_filter(Some("warn"), Some("trace"));
+        assert_eq!(f, "warn");
+    }
+
+    #[test]
+    fn env_filter_used_when_config_unset_or_empty() {
+        assert_eq!(resolve_log_filter(None, Some("trace")), "trace");
+        assert_eq!(resolve_log_filter(Some("  "), Some("trace")), "trace");
+    }
+
+    #[test]
+    fn default_used_when_nothing_set() {
+        assert_eq!(resolve_log_filter(None, None), DEFAULT_LOG_FILTER);
+        assert_eq!(resolve_log_filter(Some(""), Some("")), DEFAULT_LOG_FILTER);
+    }
+}
+
 /// 通用的未知字段检查：返回警告字符串列表
 fn check_unknown_keys(a: &Value, b: &Value) -> Vec<String> {
     // a = default, b = user
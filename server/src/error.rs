@@ -1,12 +1,84 @@
+use crate::config::Config;
 use axum::{
-    http::StatusCode,
+    body::{to_bytes, Body},
+    extract::{rejection::JsonRejection, State},
+    http::{header::{ALLOW, CONTENT_LENGTH}, StatusCode},
     response::{IntoResponse, Response},
-    Json,
+    Extension, Json,
 };
+use std::sync::Arc;
+use serde::Serialize;
 use serde_json::json;
 use thiserror::Error;
 use tracing::error;
 
+/// One rejected request field, reported in the `fields` array of a 422 response
+#[derive(Debug, Clone, Serialize)]
+pub struct FieldError {
+    pub field: String,
+    pub message: String,
+}
+
+fn describe_fields(fields: &[FieldError]) -> String {
+    fields
+        .iter()
+        .map(|f| format!("{} {}", f.field, f.message))
+        .collect::<Vec<_>>()
+        .join("; ")
+}
+
+/// Why an uploaded archive was refused. `code()` is stable, so clients can map each
+/// reason to their own message instead of parsing `details`.
+#[derive(Error, Debug)]
+pub enum ArchiveError {
+    #[error("{0}")]
+    UnsupportedFormat(String),
+
+    #[error("archive expands to more than {0} bytes")]
+    TooLarge(u64),
+
+    #[error("archive has more than {0} entries")]
+    TooManyEntries(usize),
+
+    #[error("{0}")]
+    PathTooLong(String),
+
+    #[error("path '{0}' points outside the archive")]
+    PathTraversal(String),
+
+    #[error("{0}")]
+    NoIndexHtml(String),
+
+    #[error("rootDir '{0}' does not exist in the archive")]
+    RootDirNotFound(String),
+
+    #[error("archive contains no files")]
+    Empty,
+
+    #[error("'{0}' has a file type that may not be published")]
+    ForbiddenFile(String),
+
+    #[error("archive could not be read: {0}")]
+    Corrupt(String),
+}
+
+impl ArchiveError {
+    pub fn code(&self) -> &'static str {
+        match self {
+            ArchiveError::UnsupportedFormat(_) => "ARCHIVE_UNSUPPORTED_FORMAT",
+            ArchiveError::TooLarge(_) => "ARCHIVE_TOO_LARGE",
+            ArchiveError::TooManyEntries(_) => "ARCHIVE_TOO_MANY_ENTRIES",
+            ArchiveError::PathTooLong(_) => "ARCHIVE_PATH_TOO_LONG",
+            ArchiveError::PathTraversal(_) => "ARCHIVE_PATH_TRAVERSAL",
+            ArchiveError::NoIndexHtml(_) => "ARCHIVE_NO_INDEX_HTML",
+            ArchiveError::RootDirNotFound(_) => "ARCHIVE_ROOT_DIR_NOT_FOUND",
+            ArchiveError::Empty => "ARCHIVE_EMPTY",
+            ArchiveError::ForbiddenFile(_) => "ARCHIVE_FORBIDDEN_FILE",
+            ArchiveError::Corrupt(_) => "ARCHIVE_CORRUPT",
+        }
+    }
+}
+
 #[derive(Error, Debug)]
 pub enum AppError {
     #[error("Database error: {0}")]
@@ -27,6 +99,9 @@ pub enum AppError {
     #[error("Authentication failed")]
     AuthenticationFailed,
     
+    #[error("Token expired {0} seconds ago")]
+    TokenExpired(i64),
+    
     #[error("Authorization failed")]
     AuthorizationFailed,
     
@@ -39,12 +114,36 @@ pub enum AppError {
     #[error("User already exists")]
     UserAlreadyExists,
     
+    #[error("Email address is already in use")]
+    EmailAlreadyExists,
+    
     #[error("Site name already exists: {0}")]
     SiteNameConflict(String),
     
     #[error("User has active sites, cannot delete account")]
     UserDeletionBlocked,
     
+    #[error("Upload not found")]
+    UploadNotFound,
+    
+    #[error("Upload offset mismatch: expected {0}")]
+    UploadOffsetMismatch(u64),
+    
+    #[error("Too many uploads in progress (at most {0} per user)")]
+    TooManyUploads(usize),
+    
+    #[error("Request body exceeds the limit of {0} bytes")]
+    PayloadTooLarge(u64),
+    
+    #[error("Allowed methods: {0}")]
+    MethodNotAllowed(String),
+    
+    #[error("Invalid archive: {0}")]
+    Archive(#[from] ArchiveError),
+    
+    #[error("Validation failed: {}", describe_fields(.0))]
+    Validation(Vec<FieldError>),
+    
     #[error("Invalid input: {0}")]
     InvalidInput(String),
     
@@ -52,17 +151,71 @@ pub enum AppError {
     Internal(String),
 }
 
+/// Every code `AppError::code` can return; `server.error_messages` keys are checked against it
+pub const ERROR_CODES: &[&str] = &[
+    "AUTH_FAILED", "TOKEN_EXPIRED", "FORBIDDEN", "TOKEN_INVALID", "USER_NOT_FOUND", "SITE_NOT_FOUND",
+    "USER_EXISTS", "EMAIL_EXISTS", "SITE_NAME_CONFLICT", "USER_HAS_SITES", "UPLOAD_NOT_FOUND",
+    "UPLOAD_OFFSET_MISMATCH", "TOO_MANY_UPLOADS", "PAYLOAD_TOO_LARGE", "METHOD_NOT_ALLOWED", "ARCHIVE_UNSUPPORTED_FORMAT", "ARCHIVE_TOO_LARGE",
+    "ARCHIVE_TOO_MANY_ENTRIES", "ARCHIVE_PATH_TOO_LONG", "ARCHIVE_PATH_TRAVERSAL",
+    "ARCHIVE_NO_INDEX_HTML", "ARCHIVE_ROOT_DIR_NOT_FOUND", "ARCHIVE_EMPTY", "ARCHIVE_FORBIDDEN_FILE", "ARCHIVE_CORRUPT", "VALIDATION_FAILED",
+    "INVALID_INPUT", "CONFIG_ERROR", "INTERNAL_ERROR",
+];
+
+impl AppError {
+    /// Stable machine-readable code, sent as `code` in the error body
+    pub fn code(&self) -> &'static str {
+        match self {
+            AppError::AuthenticationFailed => "AUTH_FAILED",
+            AppError::TokenExpired(_) => "TOKEN_EXPIRED",
+            AppError::AuthorizationFailed => "FORBIDDEN",
+            AppError::Jwt(_) => "TOKEN_INVALID",
+            AppError::UserNotFound => "USER_NOT_FOUND",
+            AppError::SiteNotFound => "SITE_NOT_FOUND",
+            AppError::UserAlreadyExists => "USER_EXISTS",
+            AppError::EmailAlreadyExists => "EMAIL_EXISTS",
+            AppError::SiteNameConflict(_) => "SITE_NAME_CONFLICT",
+            AppError::UserDeletionBlocked => "USER_HAS_SITES",
+            AppError::UploadNotFound => "UPLOAD_NOT_FOUND",
+            AppError::UploadOffsetMismatch(_) => "UPLOAD_OFFSET_MISMATCH",
+            AppError::TooManyUploads(_) => "TOO_MANY_UPLOADS",
+            AppError::PayloadTooLarge(_) => "PAYLOAD_TOO_LARGE",
+            AppError::MethodNotAllowed(_) => "METHOD_NOT_ALLOWED",
+            AppError::Archive(e) => e.code(),
+            AppError::Validation(_) => "VALIDATION_FAILED",
+            AppError::InvalidInput(_) => "INVALID_INPUT",
+            AppError::Config(_) => "CONFIG_ERROR",
+            _ => "INTERNAL_ERROR",
+        }
+    }
+}
+
+/// Code of an `AppError` response, kept as a response extension so outer layers can
+/// recognise the error without parsing the body
+#[derive(Debug, Clone, Copy)]
+pub struct ErrorCode(pub &'static str);
+
 impl IntoResponse for AppError {
     fn into_response(self) -> Response {
         let (status, error_message) = match self {
             AppError::AuthenticationFailed => (StatusCode::UNAUTHORIZED, "Authentication failed"),
+            AppError::TokenExpired(_) => (StatusCode::UNAUTHORIZED, "Token expired"),
             AppError::AuthorizationFailed => (StatusCode::FORBIDDEN, "Authorization failed"),
             AppError::Jwt(_) => (StatusCode::UNAUTHORIZED, "Token expired or invalid"),
             AppError::UserNotFound => (StatusCode::NOT_FOUND, "User not found"),
             AppError::SiteNotFound => (StatusCode::NOT_FOUND, "Site not found"),
             AppError::UserAlreadyExists => (StatusCode::CONFLICT, "User already exists"),
+            AppError::EmailAlreadyExists => (StatusCode::CONFLICT, "Email address is already in use"),
             AppError::SiteNameConflict(_) => (StatusCode::CONFLICT, "Site name already exists"),
             AppError::UserDeletionBlocked => (StatusCode::BAD_REQUEST, "User has active sites, cannot delete account"),
+            AppError::UploadNotFound => (StatusCode::NOT_FOUND, "Upload not found"),
+            AppError::UploadOffsetMismatch(_) => (StatusCode::CONFLICT, "Upload offset mismatch"),
+            AppError::TooManyUploads(_) => (StatusCode::TOO_MANY_REQUESTS, "Too many uploads in progress"),
+            AppError::PayloadTooLarge(_) => (StatusCode::PAYLOAD_TOO_LARGE, "Payload too large"),
+            AppError::MethodNotAllowed(_) => (StatusCode::METHOD_NOT_ALLOWED, "Method not allowed"),
+            // The archive is fine; the rootDir field doesn't match it
+            AppError::Archive(ArchiveError::RootDirNotFound(_)) => (StatusCode::UNPROCESSABLE_ENTITY, "Invalid archive"),
+            AppError::Archive(_) => (StatusCode::BAD_REQUEST, "Invalid archive"),
+            AppError::Validation(_) => (StatusCode::UNPROCESSABLE_ENTITY, "Validation failed"),
             AppError::InvalidInput(_) => (StatusCode::BAD_REQUEST, "Invalid input"),
             AppError::Config(_) => (StatusCode::INTERNAL_SERVER_ERROR, "Configuration error"),
             _ => (StatusCode::INTERNAL_SERVER_ERROR, "Internal server error"),
@@ -73,12 +226,79 @@ impl IntoResponse for AppError {
             error!("Internal server error: {:?}", self);
         }
 
-        let body = Json(json!({
+        let code = self.code();
+        let mut body = json!({
             "error": error_message,
+            "code": code,
             "details": self.to_string()
-        }));
+        });
+        if let AppError::Validation(fields) = &self {
+            body["fields"] = json!(fields);
+        }
+
+        (status, Extension(ErrorCode(code)), Json(body)).into_response()
+    }
+}
+
+/// Response middleware: axum's default 405 has an `Allow` header but an empty body.
+/// Replace the body with the usual `AppError` body, keeping the headers.
+pub async fn method_not_allowed_json(response: Response) -> Response {
+    if response.status() != StatusCode::METHOD_NOT_ALLOWED {
+        return response;
+    }
+
+    let (mut parts, _) = response.into_parts();
+    let allowed = parts
+        .headers
+        .get(ALLOW)
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or("")
+        .to_string();
+
+    let (body_parts, body) = AppError::MethodNotAllowed(allowed).into_response().into_parts();
+    parts.headers.remove(CONTENT_LENGTH);
+    parts.headers.extend(body_parts.headers);
+    parts.extensions.extend(body_parts.extensions);
+    Response::from_parts(parts, body)
+}
+
+/// Response middleware: swap the `error` message of `AppError` responses for the
+/// operator's text in `server.error_messages`, looked up by code. Status, code and
+/// details are left as they are.
+pub async fn custom_error_messages(State(config): State<Arc<Config>>, response: Response) -> Response {
+    let Some(ErrorCode(code)) = response.extensions().get::<ErrorCode>().copied() else {
+        return response;
+    };
+    let Some(message) = config.server.error_messages.get(code) else {
+        return response;
+    };
+
+    let (mut parts, body) = response.into_parts();
+    let bytes = to_bytes(body, usize::MAX).await.unwrap_or_default();
+    let mut json: serde_json::Value = match serde_json::from_slice(&bytes) {
+        Ok(json) => json,
+        Err(_) => return Response::from_parts(parts, Body::from(bytes)),
+    };
+    json["error"] = json!(message);
+    parts.headers.remove(CONTENT_LENGTH);
+    Response::from_parts(parts, Body::from(json.to_string()))
+}
+
+/// Response middleware, outside `RequestBodyLimitLayer`: its 413 has a plain-text body, so
+/// replace it with the usual error body naming `server.max_body_bytes`. 413s that are
+/// already `AppError` responses pass through.
+pub async fn payload_too_large_json(State(config): State<Arc<Config>>, response: Response) -> Response {
+    if response.status() != StatusCode::PAYLOAD_TOO_LARGE || response.extensions().get::<ErrorCode>().is_some() {
+        return response;
+    }
+    let response = AppError::PayloadTooLarge(config.server.max_body_bytes).into_response();
+    custom_error_messages(State(config), response).await
+}
 
-        (status, body).into_response()
+/// Bodies `JsonBody` could not read; axum's plain-text rejection is replaced by the usual error body
+impl From<JsonRejection> for AppError {
+    fn from(rejection: JsonRejection) -> Self {
+        AppError::InvalidInput(rejection.body_text())
     }
 }
 
@@ -1,17 +1,25 @@
 use crate::{
+    auth::AuthenticatedUser,
     error::AppError,
-    models::{SiteResponse, User},
+    handlers::sites::{is_admin, is_admin_request},
+    models::{AuditAction, AuditEvent, Invite, SiteResponse, User},
     storage::Storage,
     config::Config,
+    utils::pagination::{pagination_headers, Page},
 };
 use axum::{
+    body::{Body, Bytes},
     extract::{State, Query},
+    http::{header::CONTENT_TYPE, HeaderMap},
+    response::{IntoResponse, Response},
     Json,
 };
+use chrono::{DateTime, NaiveDate, NaiveTime, Utc};
 use serde::Serialize;
 use std::collections::HashMap;
 use std::path::PathBuf;
 use std::sync::Arc;
+use uuid::Uuid;
 
 #[derive(Debug, Serialize)]
 pub struct StorageUsage {
@@ -28,22 +36,28 @@ pub struct StorageSummary {
     per_site: Vec<StorageUsage>,
 }
 
+// public site fields plus the upload origin, which only admins get to see
+#[derive(Debug, Serialize)]
+pub struct AdminSiteResponse {
+    #[serde(flatten)]
+    site: SiteResponse,
+    source_ip: Option<String>,
+    user_agent: Option<String>,
+}
+
 #[derive(Debug, Serialize)]
 pub struct AdminReport {
-    sites: Vec<SiteResponse>,
+    sites: Vec<AdminSiteResponse>,
     users: Vec<User>,
     config: Config,
 }
 
 pub async fn admin_all(
     State((storage, config)): State<(Arc<Storage>, Arc<Config>)>,
+    user: Option<AuthenticatedUser>,
     Query(params): Query<HashMap<String, String>>,
 ) -> Result<Json<AdminReport>, AppError> {
-    // require ?key=<jwt_secret> on the request URL for admin access
-    match params.get("key") {
-        Some(k) if k == &config.server.jwt_secret => {}
-        _ => return Err(AppError::AuthorizationFailed),
-    }
+    authorize_admin(&storage, &config, &params, user, "/api/admin/all").await?;
 
     let sites = storage.sites.list_all().await?;
     let users = storage.users.list_all().await?;
@@ -51,7 +65,11 @@ pub async fn admin_all(
     let report = AdminReport {
         sites: sites
             .into_iter()
-            .map(|site| SiteResponse::from_site(site, config.server.url.as_ref()))
+            .map(|site| AdminSiteResponse {
+                source_ip: site.source_ip.clone(),
+                user_agent: site.user_agent.clone(),
+                site: SiteResponse::from_site(site, config.server.url.as_ref(), config.server.site_url_style),
+            })
             .collect(),
         users,
         config: (*config).clone(),
@@ -75,15 +93,14 @@ pub struct SitesMismatchReport {
 // GET /api/admin/sites - returns mismatch report between DB and site folders
 pub async fn admin_sites(
     State((storage, config)): State<(Arc<Storage>, Arc<Config>)>,
+    user: Option<AuthenticatedUser>,
     Query(params): Query<HashMap<String, String>>,
 ) -> Result<Json<SitesMismatchReport>, AppError> {
-    match params.get("key") {
-        Some(k) if k == &config.server.jwt_secret => {}
-        _ => return Err(AppError::AuthorizationFailed),
-    }
+    authorize_admin(&storage, &config, &params, user, "/api/admin/sites").await?;
 
     let sites = storage.sites.list_all().await?;
-    let db_site_ids: Vec<String> = sites.iter().map(|s| s.id.to_string()).collect();
+    let mut db_site_ids: Vec<String> = sites.iter().map(|s| s.id.to_string()).collect();
+    db_site_ids.sort();
 
     let sites_base: PathBuf = config.storage.sites.path.clone();
 
@@ -97,6 +114,8 @@ pub async fn admin_sites(
             }
         }
     }
+    // read_dir 顺序依赖文件系统；排序后报告可稳定比对（两个差集沿用这个顺序）
+    dir_names_on_disk.sort();
 
     let orphan_site_dirs: Vec<String> = dir_names_on_disk
         .iter()
@@ -121,14 +140,13 @@ pub async fn admin_sites(
 }
 
 // GET /api/admin/storage - returns storage usage summary
+// per_site supports ?offset=&limit=; totals always cover every site directory
 pub async fn admin_storage(
-    State((_storage, config)): State<(Arc<Storage>, Arc<Config>)>,
+    State((storage, config)): State<(Arc<Storage>, Arc<Config>)>,
+    user: Option<AuthenticatedUser>,
     Query(params): Query<HashMap<String, String>>,
-) -> Result<Json<StorageSummary>, AppError> {
-    match params.get("key") {
-        Some(k) if k == &config.server.jwt_secret => {}
-        _ => return Err(AppError::AuthorizationFailed),
-    }
+) -> Result<(HeaderMap, Json<StorageSummary>), AppError> {
+    authorize_admin(&storage, &config, &params, user, "/api/admin/storage").await?;
 
     let sites_base: PathBuf = config.storage.sites.path.clone();
 
@@ -141,7 +159,7 @@ pub async fn admin_storage(
             if entry.file_type()?.is_dir() {
                 let name = entry.file_name().to_string_lossy().to_string();
                 let path = sites_base.join(&name);
-                let (size, count) = dir_size_and_count(&path)?;
+                let (size, count) = storage.sizes.get_or_walk(&path)?;
                 total_bytes += size;
                 per_site.push(StorageUsage {
                     site_id: name,
@@ -153,35 +171,257 @@ pub async fn admin_storage(
         }
     }
 
+    // read_dir 顺序不稳定，按目录名排序以便分页
+    per_site.sort_by(|a, b| a.site_id.cmp(&b.site_id));
+    let total_sites = per_site.len();
+    let page = Page::from_params(&params);
+    let headers = pagination_headers(config.server.url.as_ref(), "/api/admin/storage", &params, &page, total_sites);
+
     let storage_summary = StorageSummary {
         total_bytes,
-        total_sites: per_site.len(),
-        per_site,
+        total_sites,
+        per_site: page.apply(per_site),
     };
 
-    Ok(Json(storage_summary))
+    Ok((headers, Json(storage_summary)))
 }
 
-// recursively compute directory size and file count
-fn dir_size_and_count(path: &PathBuf) -> Result<(u64, u64), AppError> {
-    let mut total: u64 = 0;
-    let mut count: u64 = 0;
+// GET /api/admin/audit - returns the most recent audit events (newest first)
+pub async fn admin_audit(
+    State((storage, config)): State<(Arc<Storage>, Arc<Config>)>,
+    user: Option<AuthenticatedUser>,
+    Query(params): Query<HashMap<String, String>>,
+) -> Result<Json<Vec<AuditEvent>>, AppError> {
+    authorize_admin(&storage, &config, &params, user, "/api/admin/audit").await?;
 
-    let mut stack = vec![path.clone()];
-    while let Some(p) = stack.pop() {
-        for entry in std::fs::read_dir(&p)? {
-            let entry = entry?;
-            let ft = entry.file_type()?;
-            let p = entry.path();
-            if ft.is_dir() {
-                stack.push(p);
-            } else if ft.is_file() {
-                let meta = entry.metadata()?;
-                total += meta.len();
-                count += 1;
-            }
-        }
+    let limit = params.get("limit").and_then(|v| v.parse().ok()).unwrap_or(100);
+    let events = storage.audit.recent(limit).await?;
+
+    Ok(Json(events))
+}
+
+/// Largest sites listed by `/api/admin/report` unless `?top=` says otherwise
+pub const DEFAULT_REPORT_TOP: usize = 10;
+
+#[derive(Debug, Serialize)]
+pub struct UsageReport {
+    pub from: Option<DateTime<Utc>>,
+    pub to: Option<DateTime<Utc>>,
+    pub site_count: usize,
+    pub total_bytes: u64,
+    /// Biggest total first
+    pub per_user: Vec<UserUsage>,
+    /// Biggest first, at most `?top=`
+    pub largest_sites: Vec<SiteUsage>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct UserUsage {
+    pub owner_id: Uuid,
+    /// None once the account is gone
+    pub username: Option<String>,
+    pub site_count: usize,
+    pub total_bytes: u64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SiteUsage {
+    pub id: Uuid,
+    pub name: String,
+    pub owner_id: Uuid,
+    pub created_at: DateTime<Utc>,
+    pub size_bytes: u64,
+}
+
+// GET /api/admin/report?from=&to=&top= - aggregates over the site versions created in [from, to)
+// from/to are RFC 3339 timestamps or dates (midnight UTC); either may be left out
+// sizes are each version's UUID directory, through the size cache
+pub async fn admin_report(
+    State((storage, config)): State<(Arc<Storage>, Arc<Config>)>,
+    user: Option<AuthenticatedUser>,
+    Query(params): Query<HashMap<String, String>>,
+) -> Result<Json<UsageReport>, AppError> {
+    authorize_admin(&storage, &config, &params, user, "/api/admin/report").await?;
+
+    let from = params.get("from").map(|v| parse_report_bound("from", v)).transpose()?;
+    let to = params.get("to").map(|v| parse_report_bound("to", v)).transpose()?;
+    if let (Some(from), Some(to)) = (from, to)
+        && from > to
+    {
+        return Err(AppError::InvalidInput("from must not be later than to".to_string()));
+    }
+    let top = match params.get("top") {
+        Some(v) => v.parse::<usize>().map_err(|_| AppError::InvalidInput("top must be a non-negative integer".to_string()))?,
+        None => DEFAULT_REPORT_TOP,
+    };
+
+    let in_window = |at: DateTime<Utc>| from.is_none_or(|f| at >= f) && to.is_none_or(|t| at < t);
+    let mut sites = Vec::new();
+    for site in storage.sites.list_all().await?.into_iter().filter(|s| in_window(s.created_at)) {
+        let dir = storage.sites.get_site_files_path(site.id);
+        let size_bytes = if dir.exists() { storage.sizes.get_or_walk(&dir)?.0 } else { 0 };
+        sites.push(SiteUsage { id: site.id, name: site.name, owner_id: site.owner_id, created_at: site.created_at, size_bytes });
+    }
+
+    let mut per_owner: HashMap<Uuid, (usize, u64)> = HashMap::new();
+    for site in &sites {
+        let entry = per_owner.entry(site.owner_id).or_default();
+        entry.0 += 1;
+        entry.1 += site.size_bytes;
+    }
+    let mut per_user = Vec::with_capacity(per_owner.len());
+    for (owner_id, (site_count, total_bytes)) in per_owner {
+        let username = storage.users.get(owner_id).await?.map(|u| u.username);
+        per_user.push(UserUsage { owner_id, username, site_count, total_bytes });
+    }
+    per_user.sort_by(|a, b| b.total_bytes.cmp(&a.total_bytes).then(a.owner_id.cmp(&b.owner_id)));
+
+    let site_count = sites.len();
+    let total_bytes = sites.iter().map(|s| s.size_bytes).sum();
+    sites.sort_by(|a, b| b.size_bytes.cmp(&a.size_bytes).then(a.id.cmp(&b.id)));
+    sites.truncate(top);
+
+    Ok(Json(UsageReport { from, to, site_count, total_bytes, per_user, largest_sites: sites }))
+}
+
+/// RFC 3339 timestamp, or a `YYYY-MM-DD` date meaning midnight UTC
+fn parse_report_bound(name: &str, value: &str) -> Result<DateTime<Utc>, AppError> {
+    DateTime::parse_from_rfc3339(value)
+        .map(|t| t.with_timezone(&Utc))
+        .or_else(|_| NaiveDate::parse_from_str(value, "%Y-%m-%d").map(|d| d.and_time(NaiveTime::MIN).and_utc()))
+        .map_err(|_| AppError::InvalidInput(format!("{} must be an RFC 3339 timestamp or a YYYY-MM-DD date, got '{}'", name, value)))
+}
+
+// POST /api/admin/invites?max_uses=N&expires_in_hours=H - mints a registration invite code
+// max_uses defaults to 1; without expires_in_hours the code never expires
+pub async fn admin_create_invite(
+    State((storage, config)): State<(Arc<Storage>, Arc<Config>)>,
+    user: Option<AuthenticatedUser>,
+    Query(params): Query<HashMap<String, String>>,
+) -> Result<Json<Invite>, AppError> {
+    authorize_admin(&storage, &config, &params, user, "/api/admin/invites").await?;
+
+    let max_uses = match params.get("max_uses") {
+        Some(v) => match v.parse::<u32>() {
+            Ok(n) if n > 0 => n,
+            _ => return Err(AppError::InvalidInput("max_uses must be a positive integer".to_string())),
+        },
+        None => 1,
+    };
+    let expires_at = match params.get("expires_in_hours") {
+        Some(v) => Some(
+            v.parse::<i64>().ok()
+                .filter(|h| *h > 0)
+                .and_then(chrono::Duration::try_hours)
+                .and_then(|d| chrono::Utc::now().checked_add_signed(d))
+                .ok_or_else(|| AppError::InvalidInput("expires_in_hours must be a positive integer".to_string()))?,
+        ),
+        None => None,
+    };
+
+    let invite = Invite::new(max_uses, expires_at);
+    storage.invites.create(invite.clone()).await?;
+
+    Ok(Json(invite))
+}
+
+#[derive(Debug, Serialize)]
+pub struct PruneTempReport {
+    pub removed: Vec<String>,
+}
+
+// POST /api/admin/prune-temp - runs the startup temp-directory sweep now
+pub async fn admin_prune_temp(
+    State((storage, config)): State<(Arc<Storage>, Arc<Config>)>,
+    user: Option<AuthenticatedUser>,
+    Query(params): Query<HashMap<String, String>>,
+) -> Result<Json<PruneTempReport>, AppError> {
+    authorize_admin(&storage, &config, &params, user, "/api/admin/prune-temp").await?;
+
+    let removed = storage.cleanup_temp().await?;
+    Ok(Json(PruneTempReport { removed }))
+}
+
+// GET /api/admin/export - NDJSON dump, one `{"type": "user"|"site", "record": ...}` per line
+// records are read from storage a page at a time as the body is streamed, so the full export
+// is never held in memory
+pub async fn admin_export(
+    State((storage, config)): State<(Arc<Storage>, Arc<Config>)>,
+    user: Option<AuthenticatedUser>,
+    Query(params): Query<HashMap<String, String>>,
+) -> Result<Response, AppError> {
+    authorize_admin(&storage, &config, &params, user, "/api/admin/export").await?;
+
+    let pages = futures_util::stream::unfold(ExportCursor::Users(None), move |cursor| {
+        let storage = storage.clone();
+        async move { next_export_page(&storage, cursor).await }
+    });
+    let body = Body::from_stream(pages);
+
+    Ok(([(CONTENT_TYPE, "application/x-ndjson")], body).into_response())
+}
+
+/// Records read from storage per chunk of the export
+const EXPORT_PAGE_SIZE: usize = 100;
+
+/// Where the export is: users, then sites, each walked by ascending id
+enum ExportCursor {
+    Users(Option<Uuid>),
+    Sites(Option<Uuid>),
+    Done,
+}
+
+async fn next_export_page(storage: &Storage, cursor: ExportCursor) -> Option<(Result<Bytes, AppError>, ExportCursor)> {
+    let page = match cursor {
+        ExportCursor::Users(after) => storage.users.list_page(after, EXPORT_PAGE_SIZE).await.and_then(|users| {
+            let next = match users.last() {
+                Some(last) if users.len() == EXPORT_PAGE_SIZE => ExportCursor::Users(Some(last.id)),
+                _ => ExportCursor::Sites(None),
+            };
+            Ok((export_lines("user", &users)?, next))
+        }),
+        ExportCursor::Sites(after) => storage.sites.list_page(after, EXPORT_PAGE_SIZE).await.and_then(|sites| {
+            let next = match sites.last() {
+                Some(last) if sites.len() == EXPORT_PAGE_SIZE => ExportCursor::Sites(Some(last.id)),
+                _ => ExportCursor::Done,
+            };
+            Ok((export_lines("site", &sites)?, next))
+        }),
+        ExportCursor::Done => return None,
+    };
+    Some(match page {
+        Ok((lines, next)) => (Ok(lines), next),
+        // Headers are already sent; cutting the body short is all that's left
+        Err(e) => (Err(e), ExportCursor::Done),
+    })
+}
+
+fn export_lines<T: Serialize>(kind: &str, records: &[T]) -> Result<Bytes, AppError> {
+    let mut lines = Vec::new();
+    for record in records {
+        serde_json::to_writer(&mut lines, &serde_json::json!({ "type": kind, "record": record }))?;
+        lines.push(b'\n');
     }
+    Ok(Bytes::from(lines))
+}
 
-    Ok((total, count))
-}
\ No newline at end of file
+/// Admin endpoints accept whoever `is_admin` does; the access is audited
+async fn authorize_admin(
+    storage: &Storage,
+    config: &Config,
+    params: &HashMap<String, String>,
+    user: Option<AuthenticatedUser>,
+    endpoint: &str,
+) -> Result<(), AppError> {
+    let user = user.map(|AuthenticatedUser(user)| user);
+    if !is_admin(storage, config, params, user.as_ref()).await? {
+        return Err(AppError::AuthorizationFailed);
+    }
+    // Key access is not tied to an account
+    let actor_id = if is_admin_request(params, config) { None } else { user.map(|u| u.id) };
+    storage.audit.append(AuditEvent::new(
+        AuditAction::AdminAccess,
+        actor_id,
+        Some(endpoint.to_string()),
+    )).await
+}
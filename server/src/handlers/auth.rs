@@ -1,34 +1,94 @@
 use crate::{
-    auth::{AuthenticatedUser, AuthService},
+    auth::{AuthenticatedUser, AuthService, TokenService},
     error::AppError,
-    models::{LoginRequest, RegisterRequest},
+    handlers::json::JsonBody,
+    models::{
+        IntrospectRequest, IntrospectResponse, LoginRequest, MeResponse, PasswordResetConfirm, PasswordResetRequest,
+        PasswordResetRequestResponse, RegisterRequest,
+    },
 };
 use axum::{
+    body::Bytes,
     extract::State,
+    http::{header::{AUTHORIZATION, SET_COOKIE}, HeaderMap, HeaderValue},
     Json,
 };
 use std::sync::Arc;
 
 pub async fn register(
     State(auth_service): State<Arc<AuthService>>,
-    Json(req): Json<RegisterRequest>,
+    JsonBody(req): JsonBody<RegisterRequest>,
 ) -> Result<Json<crate::models::UserResponse>, AppError> {
     let user = auth_service.register(req).await?;
     Ok(Json(user))
 }
 
+/// POST /auth/login - 开启 `server.auth_cookie` 时 token 通过 HttpOnly cookie 下发，不出现在响应体中
 pub async fn login(
     State(auth_service): State<Arc<AuthService>>,
-    Json(req): Json<LoginRequest>,
-) -> Result<Json<crate::models::LoginResponse>, AppError> {
-    let response = auth_service.login(req).await?;
-    Ok(Json(response))
+    JsonBody(req): JsonBody<LoginRequest>,
+) -> Result<(HeaderMap, Json<crate::models::LoginResponse>), AppError> {
+    let mut response = auth_service.login(req).await?;
+    let mut headers = HeaderMap::new();
+    if auth_service.auth_cookie()
+        && let Some(token) = response.token.take()
+    {
+        let cookie = auth_service.auth_cookie_header(&token);
+        headers.insert(SET_COOKIE, HeaderValue::from_str(&cookie).map_err(|e| AppError::Internal(e.to_string()))?);
+    }
+    Ok((headers, Json(response)))
 }
 
 pub async fn me(
     State(auth_service): State<Arc<AuthService>>,
     AuthenticatedUser(auth_user): AuthenticatedUser,
-) -> Result<Json<crate::models::UserResponse>, AppError> {
+) -> Result<Json<MeResponse>, AppError> {
     let user = auth_service.user_storage.get(auth_user.id).await?.ok_or(AppError::UserNotFound)?;
-    Ok(Json(user.into()))
-}
\ No newline at end of file
+    let seconds_remaining = auth_user.exp as i64 - chrono::Utc::now().timestamp();
+    Ok(Json(MeResponse {
+        user: user.into(),
+        exp: auth_user.exp,
+        seconds_remaining,
+    }))
+}
+
+/// POST /auth/password-reset/request - 总是 200，不透露账户是否存在
+pub async fn request_password_reset(
+    State(auth_service): State<Arc<AuthService>>,
+    JsonBody(req): JsonBody<PasswordResetRequest>,
+) -> Result<Json<PasswordResetRequestResponse>, AppError> {
+    Ok(Json(auth_service.request_password_reset(req).await?))
+}
+
+/// POST /auth/password-reset/confirm - 用重置令牌设置新密码
+pub async fn confirm_password_reset(
+    State(auth_service): State<Arc<AuthService>>,
+    JsonBody(req): JsonBody<PasswordResetConfirm>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    auth_service.confirm_password_reset(req).await?;
+    Ok(Json(serde_json::json!({
+        "message": "Password has been reset"
+    })))
+}
+
+/// POST /auth/introspect - 解析 token（请求体 `{ "token": ... }` 或 Bearer 头），不访问存储
+pub async fn introspect(
+    State(token_service): State<Arc<TokenService>>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Json<IntrospectResponse> {
+    // 请求体无法解析时回退到 Authorization 头
+    let from_body = serde_json::from_slice::<IntrospectRequest>(&body)
+        .unwrap_or_default()
+        .token;
+    let from_header = headers
+        .get(AUTHORIZATION)
+        .and_then(|h| h.to_str().ok())
+        .and_then(|h| h.strip_prefix("Bearer "))
+        .map(str::to_string);
+
+    match from_body.or(from_header) {
+        Some(token) => Json(token_service.introspect(&token)),
+        None => Json(IntrospectResponse::inactive()),
+    }
+}
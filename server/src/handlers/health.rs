@@ -0,0 +1,43 @@
+use crate::{
+    config::Config,
+    storage::Storage,
+};
+use axum::{
+    extract::State,
+    http::StatusCode,
+    Json,
+};
+use serde::Serialize;
+use std::sync::Arc;
+use tracing::warn;
+
+#[derive(Debug, Serialize)]
+pub struct ReadinessResponse {
+    ready: bool,
+    database: bool,
+    storage_writable: bool,
+}
+
+/// GET /ready - 数据库可读且站点目录可写时返回 200，否则 503（供负载均衡/编排探活）
+pub async fn ready(
+    State((storage, _config)): State<(Arc<Storage>, Arc<Config>)>,
+) -> (StatusCode, Json<ReadinessResponse>) {
+    let database = match storage.users.count().await {
+        Ok(_) => true,
+        Err(e) => {
+            warn!("Readiness: database check failed: {}", e);
+            false
+        }
+    };
+    let storage_writable = match storage.check_writable() {
+        Ok(()) => true,
+        Err(e) => {
+            warn!("Readiness: sites directory is not writable: {}", e);
+            false
+        }
+    };
+
+    let ready = database && storage_writable;
+    let status = if ready { StatusCode::OK } else { StatusCode::SERVICE_UNAVAILABLE };
+    (status, Json(ReadinessResponse { ready, database, storage_writable }))
+}
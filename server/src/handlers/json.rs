@@ -0,0 +1,8 @@
+use crate::error::AppError;
+use axum::extract::FromRequest;
+
+/// `axum::Json` 的包装：请求体不是合法 JSON（或缺少 Content-Type、字段不匹配）时
+/// 返回统一的 `{error, code, details}` 错误体，而不是 axum 默认的纯文本
+#[derive(Debug, FromRequest)]
+#[from_request(via(axum::Json), rejection(AppError))]
+pub struct JsonBody<T>(pub T);
@@ -1,5 +1,8 @@
 #![cfg_attr(debug_assertions, allow(dead_code))]
 pub mod auth;
+pub mod health;
+pub mod json;
 pub mod sites;
 pub mod users;
-pub mod admin;
\ No newline at end of file
+pub mod admin;
+pub mod uploads;
\ No newline at end of file
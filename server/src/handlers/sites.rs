@@ -1,21 +1,31 @@
 use crate::{
-    auth::{AuthenticatedUser},
-    error::AppError,
-    models::{Site, SiteResponse, UpdateSiteRequest},
+    auth::{AuthUser, AuthenticatedUser, ADMIN_ROLE},
+    error::{AppError, ArchiveError, FieldError},
+    handlers::json::JsonBody,
+    models::{AuditAction, AuditEvent, BulkSiteStatsRequest, ExtractionMetrics, PatchSiteRequest, RedirectRule, RenameSiteRequest, ResolveSiteResponse, SetSitePasswordRequest, Site, SiteResponse, SiteStats, UpdateSiteRequest},
     storage::Storage,
-    config::Config,
-    utils::archive,
+    config::{ArchiveConfig, Config},
+    utils::{archive, client_ip::client_ip, fingerprint, fs::{list_files, TempGuard}, pagination::{pagination_headers, Page}, redirects::{find_redirect, load_redirects}, sitemap::build_sitemap, text::{sanitize_text, MAX_DESCRIPTION_LEN}},
 };
 use axum::{
-    extract::{Multipart, Path, State},
+    extract::{connect_info::ConnectInfo, multipart::{Field, MultipartError}, FromRequestParts, Multipart, Path, Query, Request, State},
+    body::{Body, Bytes},
+    http::{header::{ACCEPT, CONTENT_TYPE, LOCATION, USER_AGENT, WWW_AUTHENTICATE}, request::Parts, HeaderMap, HeaderName, HeaderValue, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
     Json,
 };
+use axum_extra::headers::{authorization::Basic, Authorization, HeaderMapExt};
 use futures_util::TryStreamExt;
+use std::collections::{HashMap, HashSet};
+use std::convert::Infallible;
+use std::net::SocketAddr;
 use std::sync::Arc;
 use std::path::PathBuf;
+use std::time::Instant;
 use uuid::Uuid;
 
-use tracing::debug;
+use tracing::{debug, warn};
 
 /// Parameters for site upload
 #[derive(Debug)]
@@ -25,6 +35,113 @@ pub struct SiteUploadParams {
     pub user_id: Uuid,
     pub archive_filename: String,
     pub archive_path: PathBuf,
+    /// Archive subdirectory published as the site root (`rootDir`); `None` uses the archive root
+    pub root_dir: Option<PathBuf>,
+    /// `spaMode`; `None` keeps the setting of the version being replaced
+    pub spa_mode: Option<bool>,
+    /// `fingerprintAssets`; `None` keeps the setting of the version being replaced
+    pub fingerprint_assets: Option<bool>,
+    /// `autoSitemap`; `None` keeps the setting of the version being replaced
+    pub auto_sitemap: Option<bool>,
+    /// Where the upload came from; dropped when `server.record_upload_origin` is off
+    pub origin: UploadOrigin,
+}
+
+/// Longest User-Agent kept on a site record
+const MAX_USER_AGENT_LEN: usize = 512;
+
+/// 上传来源（客户端地址与 User-Agent），作为提取器使用；
+/// 客户端地址经 `server.trusted_proxies` 判断是否采信转发头（见 `utils::client_ip`），
+/// 没有 `ConnectInfo`（如直接调用 handler 的测试）时地址为空
+#[derive(Debug, Clone, Default)]
+pub struct UploadOrigin {
+    pub source_ip: Option<String>,
+    pub user_agent: Option<String>,
+}
+
+impl FromRequestParts<(Arc<Storage>, Arc<Config>)> for UploadOrigin {
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, state: &(Arc<Storage>, Arc<Config>)) -> Result<Self, Self::Rejection> {
+        // The extractor also accepts `MockConnectInfo`, unlike reading the extension directly
+        let peer = ConnectInfo::<SocketAddr>::from_request_parts(parts, state)
+            .await
+            .ok()
+            .map(|ConnectInfo(addr)| addr.ip());
+        let source_ip = client_ip(peer, &parts.headers, &state.1.server.trusted_proxies).map(|ip| ip.to_string());
+        let user_agent = parts
+            .headers
+            .get(USER_AGENT)
+            .and_then(|v| v.to_str().ok())
+            .map(|ua| ua.chars().take(MAX_USER_AGENT_LEN).collect());
+        Ok(Self { source_ip, user_agent })
+    }
+}
+
+/// Settings stored on a new version record; `inherit` carries over the per-name ones
+/// from the version it replaces
+#[derive(Debug, Clone, Default)]
+pub struct SiteSettings {
+    pub password_hash: Option<String>,
+    pub spa_mode: bool,
+    pub fingerprint_assets: bool,
+    pub auto_sitemap: bool,
+    pub custom_headers: Vec<(String, String)>,
+    /// Per-version, never inherited
+    pub root_dir: Option<String>,
+    pub origin: UploadOrigin,
+}
+
+impl SiteSettings {
+    pub fn inherit(latest: Option<&Site>) -> Self {
+        latest
+            .map(|s| Self {
+                password_hash: s.password_hash.clone(),
+                spa_mode: s.spa_mode,
+                fingerprint_assets: s.fingerprint_assets,
+                auto_sitemap: s.auto_sitemap,
+                custom_headers: s.custom_headers.clone(),
+                root_dir: None,
+                origin: UploadOrigin::default(),
+            })
+            .unwrap_or_default()
+    }
+}
+
+/// bcrypt cost for site share passwords; lower than for accounts because the hash is
+/// checked on every protected file request
+const SITE_PASSWORD_COST: u32 = 8;
+
+/// Permission check for site endpoints: the owner may act on the site, and so may an
+/// admin when `is_admin` (see `is_admin`) and the endpoint allows it
+pub fn authorize_site_access(site: &Site, user: &AuthUser, is_admin: bool) -> Result<(), AppError> {
+    if is_admin {
+        return Ok(());
+    }
+    site.ensure_owned_by(user.id)
+}
+
+/// Whether the request carries the admin `?key=<jwt_secret>`; the key is not accepted at
+/// all in `require_auth_for_listing` deployments
+pub fn is_admin_request(params: &HashMap<String, String>, config: &Config) -> bool {
+    !config.server.require_auth_for_listing
+        && params.get("key").is_some_and(|k| k == &config.server.jwt_secret)
+}
+
+/// Admin check shared by the admin and site endpoints: the admin key (see `is_admin_request`)
+/// or a signed-in user whose stored roles include `ADMIN_ROLE`. Roles come from storage
+/// rather than the token, so revoking one takes effect at once
+pub async fn is_admin(
+    storage: &Storage,
+    config: &Config,
+    params: &HashMap<String, String>,
+    user: Option<&AuthUser>,
+) -> Result<bool, AppError> {
+    if is_admin_request(params, config) {
+        return Ok(true);
+    }
+    let Some(user) = user else { return Ok(false) };
+    Ok(storage.users.get(user.id).await?.is_some_and(|u| u.roles.iter().any(|r| r == ADMIN_ROLE)))
 }
 
 /// Validate siteName format
@@ -39,20 +156,122 @@ pub fn validate_site_name(name: &str) -> Result<(), AppError> {
             "siteName must be between 1 and 64 characters".to_string()
         ));
     }
+    // Names and UUIDs share /sites/ and the files directory
+    if Uuid::parse_str(name).is_ok() {
+        return Err(AppError::InvalidInput(
+            "siteName must not be a UUID".to_string()
+        ));
+    }
+    Ok(())
+}
+
+/// Lowercase `name`, turn whitespace and runs of `-` into single hyphens, and drop every other
+/// character `validate_site_name` would refuse. Truncated to 64 bytes; may come out empty.
+pub fn slugify_site_name(name: &str) -> String {
+    let mut slug = String::new();
+    for c in name.trim().chars().flat_map(char::to_lowercase) {
+        if c.is_whitespace() || c == '-' {
+            if !slug.is_empty() && !slug.ends_with('-') {
+                slug.push('-');
+            }
+        } else if c.is_alphanumeric() || c == '_' {
+            if slug.len() + c.len_utf8() > 64 {
+                break;
+            }
+            slug.push(c);
+        }
+    }
+    slug.trim_end_matches('-').to_string()
+}
+
+/// The siteName an upload is published under: `name` itself when valid, otherwise (with
+/// `server.allow_slugify`) its slug. Rejected only when the slug is empty or still invalid
+pub fn accept_site_name(name: String, allow_slugify: bool) -> Result<String, AppError> {
+    let err = match validate_site_name(&name) {
+        Ok(()) => return Ok(name),
+        Err(e) if !allow_slugify => return Err(e),
+        Err(e) => e,
+    };
+    let slug = slugify_site_name(&name);
+    if slug.is_empty() {
+        return Err(AppError::InvalidInput(format!("siteName '{}' has no characters usable in a site name", name)));
+    }
+    validate_site_name(&slug).map_err(|_| err)?;
+    debug!("Slugified siteName '{}' to '{}'", name, slug);
+    Ok(slug)
+}
+
+/// siteName and UUID directories live side by side under the same base directory.
+/// Refuse an upload whose siteName directory would be another site's UUID directory,
+/// or whose UUID directory would be an existing siteName directory.
+async fn check_dir_namespace(storage: &Storage, params: &SiteUploadParams) -> Result<(), AppError> {
+    if let Ok(named_id) = Uuid::parse_str(&params.site_name) {
+        if named_id == params.site_id {
+            return Err(AppError::InvalidInput("siteName must differ from the site's UUID".to_string()));
+        }
+        if storage.sites.get(named_id).await?.is_some() {
+            return Err(AppError::SiteNameConflict(params.site_name.clone()));
+        }
+    }
+    if storage.sites.get_latest_by_name(&params.site_id.to_string()).await?.is_some() {
+        return Err(AppError::InvalidInput(format!(
+            "site uuid {} is already used as a siteName", params.site_id
+        )));
+    }
     Ok(())
 }
 
 /// Process site archive extraction - creates both UUID and siteName directories
 /// - UUID directory: original content (no replacement)
 /// - siteName directory: with path replacement (/sites/{uuid}/ -> /sites/{siteName}/)
-/// Returns paths to both directories
+///
+/// Returns paths to both directories, warnings for archive entries that were skipped,
+/// and how long each extraction pass took
+///
+/// At most `storage.max_concurrent_extractions` archives are extracted at a time.
+/// The archive file and temp extraction directory are removed afterwards, unless
+/// processing failed and `keep_temp_on_error` is set (their paths are logged instead).
 pub async fn process_site_archive(
     storage: &Storage,
     params: &SiteUploadParams,
-) -> Result<(PathBuf, PathBuf), AppError> {
+    limits: &ArchiveConfig,
+    keep_temp_on_error: bool,
+) -> Result<(PathBuf, PathBuf, Vec<String>, ExtractionMetrics), AppError> {
+    let temp_extract_dir = storage.sites.get_site_files_path_str(&format!(".extract_temp_{}", params.site_id));
+    // Also covers this future being dropped while waiting or extracting
+    let mut cleanup = TempGuard::new();
+    cleanup.track(&temp_extract_dir);
+    cleanup.track(&params.archive_path);
+
+    // Excess uploads queue here instead of all extracting at once
+    let _permit = storage.extractions.acquire().await
+        .map_err(|e| AppError::Internal(e.to_string()))?;
+    let result = extract_site_dirs(storage, params, limits, &temp_extract_dir).await;
+
+    if result.is_err() && keep_temp_on_error {
+        warn!("Site archive processing failed; keeping temp artifacts: {:?}", cleanup.keep());
+    }
+
+    result
+}
+
+async fn extract_site_dirs(
+    storage: &Storage,
+    params: &SiteUploadParams,
+    limits: &ArchiveConfig,
+    temp_extract_dir: &PathBuf,
+) -> Result<(PathBuf, PathBuf, Vec<String>, ExtractionMetrics), AppError> {
     let site_id = params.site_id;
-    let site_name = &params.site_name;
     let archive_path = &params.archive_path;
+    let mut metrics = ExtractionMetrics {
+        archive_bytes: std::fs::metadata(archive_path)?.len(),
+        ..Default::default()
+    };
+
+    // === 0. Validate the whole archive before any existing directory is cleared ===
+    let decompress_started = Instant::now();
+    archive::validate_archive(archive_path, limits, params.root_dir.as_deref())?;
+    check_dir_namespace(storage, params).await?;
     
     // === 1. Create UUID directory with ORIGINAL content (no replacement) ===
     let uuid_dir = storage.sites.get_site_files_path_str(&site_id.to_string());
@@ -63,67 +282,198 @@ pub async fn process_site_archive(
         std::fs::remove_dir_all(&uuid_dir)?;
     }
     std::fs::create_dir_all(&uuid_dir)?;
-    
+
+    // Whatever fails from here on takes the new UUID directory with it; the live siteName
+    // directory is only swapped out as the last step, so it keeps serving until then
+    match fill_site_dirs(storage, params, limits, temp_extract_dir, &uuid_dir, decompress_started, &mut metrics).await {
+        Ok((name_dir, warnings)) => Ok((uuid_dir, name_dir, warnings, metrics)),
+        Err(e) => {
+            std::fs::remove_dir_all(&uuid_dir).ok();
+            storage.sizes.invalidate(&uuid_dir);
+            Err(e)
+        }
+    }
+}
+
+async fn fill_site_dirs(
+    storage: &Storage,
+    params: &SiteUploadParams,
+    limits: &ArchiveConfig,
+    temp_extract_dir: &PathBuf,
+    uuid_dir: &PathBuf,
+    decompress_started: Instant,
+    metrics: &mut ExtractionMetrics,
+) -> Result<(PathBuf, Vec<String>), AppError> {
+    let site_id = params.site_id;
+    let site_name = &params.site_name;
+    let archive_path = &params.archive_path;
+
     // Extract archive to UUID directory without any replacement
-    archive::extract_archive(archive_path, &uuid_dir).await?;
+    // Both passes see the same entries, so warnings are only collected from this one
+    let warnings = archive::extract_archive(archive_path, uuid_dir, limits).await?;
+    metrics.decompress_us = elapsed_us(decompress_started);
     debug!("Extracted original archive to UUID directory at {:?}", uuid_dir);
+    std::fs::create_dir_all(temp_extract_dir)?;
 
-    // === 2. Create siteName directory with REPLACED content ===
-    let name_dir = storage.sites.get_site_files_path_str(site_name);
-    
-    // Clear existing siteName directory if present
-    if name_dir.exists() {
-        debug!("Removing existing site directory for siteName '{}' at {:?}", site_name, name_dir);
-        std::fs::remove_dir_all(&name_dir)?;
+    // With rootDir, only that subdirectory becomes the site
+    if let Some(root_dir) = &params.root_dir {
+        promote_root_dir(uuid_dir, root_dir, temp_extract_dir)?;
     }
-    
-    // Extract with replacement to a temp directory
+    check_site_root(uuid_dir)?;
+
+    // === 2. Build the siteName copy with REPLACED content in the temp directory ===
     // extract_archive_with_replace creates 'original' and 'replaced' subdirs
-    let temp_extract_dir = storage.sites.get_site_files_path_str(&format!(".extract_temp_{}", site_id));
-    std::fs::create_dir_all(&temp_extract_dir)?;
+    let replace_started = Instant::now();
     
     let pattern = format!("/sites/{}/", site_id);
     let replacement = format!("/sites/{}/", site_name);
     
     archive::extract_archive_with_replace(
         archive_path,
-        &temp_extract_dir,
+        temp_extract_dir,
         Some((pattern, replacement)),
+        limits,
     ).await?;
-    
-    // Move 'replaced' content to name_dir
     let replaced_dir = temp_extract_dir.join("replaced");
-    if replaced_dir.exists() {
-        std::fs::rename(&replaced_dir, &name_dir)?;
+
+    // === 3. The siteName copy gets the same rootDir treatment ===
+    if let Some(root_dir) = &params.root_dir {
+        promote_root_dir(&replaced_dir, root_dir, temp_extract_dir)?;
+        debug!("Promoted {:?} to the site root", root_dir);
     }
-    debug!("Moved replaced content to siteName directory at {:?}", name_dir);
-    
-    // Cleanup temp extraction directory
-    tokio::fs::remove_dir_all(&temp_extract_dir).await.ok();
 
-    // Cleanup archive file
-    tokio::fs::remove_file(archive_path).await.ok();
+    // Sizes are known now; later reads come from the cache instead of walking
+    (metrics.extracted_bytes, metrics.extracted_files) = storage.sizes.record(uuid_dir)?;
+
+    // === 4. Swap the finished copy in for the live siteName directory ===
+    let name_dir = storage.sites.get_site_files_path_str(site_name);
+    swap_in_dir(&replaced_dir, &name_dir, &temp_extract_dir.join("previous"))?;
+    metrics.replace_us = elapsed_us(replace_started);
+    debug!("Moved replaced content to siteName directory at {:?}", name_dir);
+    if let Err(e) = storage.sizes.record(&name_dir) {
+        warn!("Sizing {:?} failed: {}", name_dir, e);
+        storage.sizes.invalidate(&name_dir);
+    }
+    debug!(
+        %site_id,
+        decompress_us = metrics.decompress_us,
+        replace_us = metrics.replace_us,
+        archive_bytes = metrics.archive_bytes,
+        extracted_bytes = metrics.extracted_bytes,
+        extracted_files = metrics.extracted_files,
+        "Extraction metrics"
+    );
 
-    Ok((uuid_dir, name_dir))
+    Ok((name_dir, warnings))
 }
 
-/// Recursively copy a directory
-fn copy_dir_recursive(src: &PathBuf, dst: &PathBuf) -> Result<(), AppError> {
-    std::fs::create_dir_all(dst)?;
-    
-    for entry in std::fs::read_dir(src)? {
-        let entry = entry?;
-        let file_type = entry.file_type()?;
-        let src_path = entry.path();
-        let dst_path = dst.join(entry.file_name());
-        
-        if file_type.is_dir() {
-            copy_dir_recursive(&src_path, &dst_path)?;
-        } else {
-            std::fs::copy(&src_path, &dst_path)?;
+/// Replace `live` with `staged`. The old copy is moved to `backup` first and put back if
+/// the rename fails, so `live` is never left missing; `backup` is removed on success
+fn swap_in_dir(staged: &std::path::Path, live: &std::path::Path, backup: &std::path::Path) -> Result<(), AppError> {
+    let had_live = live.exists();
+    if had_live {
+        std::fs::rename(live, backup)?;
+    }
+    if let Err(e) = std::fs::rename(staged, live) {
+        if had_live {
+            std::fs::rename(backup, live).ok();
         }
+        return Err(e.into());
+    }
+    if had_live {
+        std::fs::remove_dir_all(backup).ok();
     }
-    
+    Ok(())
+}
+
+fn elapsed_us(started: Instant) -> u64 {
+    u64::try_from(started.elapsed().as_micros()).unwrap_or(u64::MAX)
+}
+
+/// Replace `site_dir` with its `root_dir` subdirectory, dropping everything else.
+/// The subdirectory is staged under `temp_extract_dir` so it survives removing `site_dir`.
+fn promote_root_dir(site_dir: &std::path::Path, root_dir: &std::path::Path, temp_extract_dir: &std::path::Path) -> Result<(), AppError> {
+    let root = site_dir.join(root_dir);
+    if !root.is_dir() {
+        return Err(ArchiveError::RootDirNotFound(root_dir.display().to_string()).into());
+    }
+    let staged = temp_extract_dir.join("root_dir");
+    std::fs::rename(&root, &staged)?;
+    std::fs::remove_dir_all(site_dir)?;
+    std::fs::rename(&staged, site_dir)?;
+    Ok(())
+}
+
+/// Files that archivers and desktop file managers leave behind (plus AppleDouble `._*`)
+const ARCHIVE_METADATA_NAMES: &[&str] = &["__MACOSX", ".DS_Store", "Thumbs.db", "desktop.ini"];
+
+/// How many offending names an error message lists before summarizing the rest
+const MAX_LISTED_NAMES: usize = 5;
+
+fn is_archive_metadata(name: &str) -> bool {
+    ARCHIVE_METADATA_NAMES.contains(&name) || name.starts_with("._")
+}
+
+fn format_name_list(names: &[String]) -> String {
+    let mut listed = names.iter().take(MAX_LISTED_NAMES).map(String::as_str).collect::<Vec<_>>().join(", ");
+    if names.len() > MAX_LISTED_NAMES {
+        listed.push_str(&format!(" and {} more", names.len() - MAX_LISTED_NAMES));
+    }
+    listed
+}
+
+/// Final check on an extracted site once rootDir has been applied: it must serve an
+/// `index.html` from its top level and hold no archiver/OS metadata files.
+pub fn check_site_root(site_dir: &std::path::Path) -> Result<(), AppError> {
+    let files = list_files(site_dir)?;
+    let stray: Vec<String> = files
+        .iter()
+        .filter(|p| p.components().any(|c| is_archive_metadata(&c.as_os_str().to_string_lossy())))
+        .map(|p| p.display().to_string())
+        .collect();
+    if !stray.is_empty() {
+        return Err(AppError::InvalidInput(format!(
+            "The site contains archive metadata that must not be published: {}", format_name_list(&stray)
+        )));
+    }
+
+    if !site_dir.join("index.html").is_file() {
+        let mut top_level: Vec<String> = std::fs::read_dir(site_dir)?
+            .filter_map(|entry| entry.ok())
+            .map(|entry| {
+                let name = entry.file_name().to_string_lossy().into_owned();
+                if entry.path().is_dir() { format!("{}/", name) } else { name }
+            })
+            .collect();
+        top_level.sort();
+        let found = if top_level.is_empty() { "nothing".to_string() } else { format_name_list(&top_level) };
+        return Err(AppError::InvalidInput(format!(
+            "The site has no index.html at its top level (found {}); set rootDir to the folder that holds it", found
+        )));
+    }
+    Ok(())
+}
+
+/// Spot-check the path replacement: the siteName copy of index.html should no longer
+/// contain `/sites/{uuid}/`. A missing index.html has nothing to check and passes.
+pub fn verify_replacement(name_dir: &std::path::Path, site_id: Uuid) -> bool {
+    let index = name_dir.join("index.html");
+    let Ok(html) = std::fs::read_to_string(&index) else {
+        debug!("No readable index.html at {:?}; skipping replacement check", index);
+        return true;
+    };
+    let verified = !html.contains(&format!("/sites/{}/", site_id));
+    if !verified {
+        warn!("Path replacement incomplete: {:?} still references /sites/{}/", index, site_id);
+    }
+    verified
+}
+
+/// Append `?v=<content hash>` to local asset links in the siteName copy's HTML
+fn fingerprint_name_dir(storage: &Storage, name_dir: &std::path::Path, site_name: &str) -> Result<(), AppError> {
+    let rewritten = fingerprint::fingerprint_assets(name_dir, &format!("/sites/{}/", site_name))?;
+    debug!("Fingerprinted {} asset links in {:?}", rewritten, name_dir);
+    storage.sizes.record(name_dir)?;
     Ok(())
 }
 
@@ -134,15 +484,28 @@ pub async fn save_site_record(
     site_id: Uuid,
     site_name: &str,
     user_id: Uuid,
+    content_hash: Option<String>,
+    redirects: Vec<RedirectRule>,
+    settings: SiteSettings,
 ) -> Result<Site, AppError> {
     let site = {
         // Create new site record
-        let site = Site::new(
+        let mut site = Site::new(
             site_id,
             user_id,
             site_name.to_string(),
             "Site uploaded from CLI".to_string(),
         );
+        site.content_hash = content_hash;
+        site.root_dir = settings.root_dir;
+        site.redirects = redirects;
+        site.password_hash = settings.password_hash;
+        site.spa_mode = settings.spa_mode;
+        site.fingerprint_assets = settings.fingerprint_assets;
+        site.auto_sitemap = settings.auto_sitemap;
+        site.custom_headers = settings.custom_headers;
+        site.source_ip = settings.origin.source_ip;
+        site.user_agent = settings.origin.user_agent;
         storage.sites.create(site.clone()).await?;
         site
     };
@@ -150,49 +513,86 @@ pub async fn save_site_record(
     Ok(site)
 }
 
+/// POST /api/sites - multipart fields: uuid, siteName, site (archive), optional mode and rootDir
+/// Missing required parts are all reported together as a 422 with a `fields` list
+/// mode=version (default) adds a new version of an owned name; mode=create refuses any existing name
+/// rootDir=dist publishes only the archive's `dist/` subdirectory, e.g. to leave sources unserved
+/// spaMode=true|false turns the single-page-app fallback on or off (default: as the previous version)
+/// fingerprintAssets=true|false appends `?v=<content hash>` to local asset links in the served HTML
+/// autoSitemap=true|false generates `sitemap.xml` from the site's pages when the archive has none
 pub async fn upload_site(
     State((storage, config)): State<(Arc<Storage>, Arc<Config>)>,
     AuthenticatedUser(user): AuthenticatedUser,
+    origin: UploadOrigin,
     mut multipart: Multipart,
 ) -> Result<Json<SiteResponse>, AppError> {
     let user_id = user.id;
 
+    // 每个用户同时进行的上传有上限（429）；槽位在返回时（包括出错）释放
+    let _slot = storage.uploads.acquire(user_id)?;
+
     // First pass: collect metadata fields and stream archive to temp location
     let mut site_id: Option<Uuid> = None;
     let mut site_name: Option<String> = None;
     let mut temp_archive_path: Option<PathBuf> = None;
     let mut archive_filename: Option<String> = None;
+    // mode=create: only publish if no version of the name exists yet (any owner)
+    let mut create_only = false;
+    let mut root_dir: Option<PathBuf> = None;
+    let mut spa_mode: Option<bool> = None;
+    let mut fingerprint_assets: Option<bool> = None;
+    let mut auto_sitemap: Option<bool> = None;
     
     // Use a temp directory for initial archive storage
     let temp_dir = storage.sites.get_site_files_path_str(".upload_temp");
     std::fs::create_dir_all(&temp_dir)?;
+    // A partly received archive is removed if the client disconnects or a field is rejected
+    let mut received = TempGuard::new();
     
     while let Some(field) = multipart.next_field().await
-        .map_err(|e| AppError::Internal(e.to_string()))? 
+        .map_err(|e| multipart_error(e, &config))?
     {
         let name = field.name().unwrap_or("unknown").to_string();
         
         match name.as_ref() {
             "uuid" => {
-                let id = field.text().await
-                    .map_err(|e| AppError::Internal(e.to_string()))?;
+                let id = read_text_field(field, MAX_TEXT_FIELD_BYTES).await?;
                 site_id = Some(Uuid::parse_str(&id)
                     .map_err(|e| AppError::InvalidInput(e.to_string()))?);
             },
             "siteName" => {
-                let name_str = field.text().await
-                    .map_err(|e| AppError::Internal(e.to_string()))?;
-                // Validate siteName
-                validate_site_name(&name_str)?;
-                site_name = Some(name_str);
+                let name_str = read_text_field(field, MAX_TEXT_FIELD_BYTES).await?;
+                site_name = Some(accept_site_name(name_str, config.server.allow_slugify)?);
+            },
+            "mode" => {
+                let mode = read_text_field(field, MAX_TEXT_FIELD_BYTES).await?;
+                create_only = parse_upload_mode(&mode)?;
+            },
+            "rootDir" => {
+                let dir = read_text_field(field, MAX_TEXT_FIELD_BYTES).await?;
+                root_dir = archive::parse_root_dir(&dir)?;
+            },
+            "spaMode" => {
+                let flag = read_text_field(field, MAX_TEXT_FIELD_BYTES).await?;
+                spa_mode = Some(parse_flag_field("spaMode", &flag)?);
+            },
+            "fingerprintAssets" => {
+                let flag = read_text_field(field, MAX_TEXT_FIELD_BYTES).await?;
+                fingerprint_assets = Some(parse_flag_field("fingerprintAssets", &flag)?);
+            },
+            "autoSitemap" => {
+                let flag = read_text_field(field, MAX_TEXT_FIELD_BYTES).await?;
+                auto_sitemap = Some(parse_flag_field("autoSitemap", &flag)?);
             },
             "site" => {
                 let file_name = field.file_name().ok_or_else(
                     || AppError::InvalidInput("Uploaded file must have a filename".to_string())
                 )?.to_string();
                 
-                // Stream to temp file instead of reading into memory
-                let temp_path = temp_dir.join(&file_name);
+                // Stream to temp file instead of reading into memory. Concurrent uploads share
+                // the temp directory, so the client's filename is kept as metadata only
+                let temp_path = temp_dir.join(staged_archive_name(&file_name));
+                received.track(&temp_path);
                 archive::save_archive_field(
                     field.map_err(|e| std::io::Error::other(e.to_string())),
                     &temp_path
@@ -206,86 +606,656 @@ pub async fn upload_site(
         }
     }
 
-    // Validate required fields
-    let site_id = site_id.ok_or_else(|| AppError::InvalidInput("Missing site uuid".to_string()))?;
-    let site_name = site_name.ok_or_else(|| AppError::InvalidInput("Missing siteName".to_string()))?;
-    let temp_archive = temp_archive_path.ok_or_else(|| AppError::InvalidInput("Missing site archive".to_string()))?;
-    let filename = archive_filename.ok_or_else(|| AppError::InvalidInput("Missing archive filename".to_string()))?;
+    // Validate required fields, reporting every missing part at once (422)
+    let (Some(site_id), Some(site_name), Some(temp_archive), Some(filename)) =
+        (site_id, site_name.clone(), temp_archive_path.clone(), archive_filename)
+    else {
+        let missing: Vec<FieldError> = [
+            ("uuid", site_id.is_none()),
+            ("siteName", site_name.is_none()),
+            ("site", temp_archive_path.is_none()),
+        ]
+        .into_iter()
+        .filter(|(_, absent)| *absent)
+        .map(|(field, _)| FieldError { field: field.to_string(), message: "is required".to_string() })
+        .collect();
+        return Err(AppError::Validation(missing));
+    };
+    // publish_archive cleans up from here on
+    received.keep();
+
+    let params = SiteUploadParams {
+        site_id,
+        site_name,
+        user_id,
+        archive_filename: filename,
+        archive_path: temp_archive,
+        root_dir,
+        spa_mode,
+        fingerprint_assets,
+        auto_sitemap,
+        origin,
+    };
+    publish_archive(&storage, &config, params, create_only, None).await.map(Json)
+}
+
+/// Unique name for staging an uploaded archive in `.upload_temp`. Keeps the archive
+/// extension of `file_name`, if it has one, since the format is picked by it
+fn staged_archive_name(file_name: &str) -> String {
+    match archive::ArchiveFormat::from_file_name(std::path::Path::new(file_name)) {
+        Ok(format) => format!("{}.{}", Uuid::new_v4(), format.name()),
+        Err(_) => Uuid::new_v4().to_string(),
+    }
+}
+
+/// A body without `Content-Length` passes `RequestBodyLimitLayer` and only fails once it's
+/// read past the limit; report that as the same 413 instead of a 500
+fn multipart_error(e: MultipartError, config: &Config) -> AppError {
+    if e.status() == StatusCode::PAYLOAD_TOO_LARGE {
+        AppError::PayloadTooLarge(config.server.max_body_bytes)
+    } else {
+        AppError::Internal(e.to_string())
+    }
+}
+
+/// Upper bound for non-file multipart fields (uuid, siteName, mode, ...)
+pub const MAX_TEXT_FIELD_BYTES: usize = 1024;
+
+/// Read a text field chunk by chunk, giving up as soon as it exceeds `max_bytes`
+/// instead of buffering whatever the client sends
+pub async fn read_text_field(mut field: Field<'_>, max_bytes: usize) -> Result<String, AppError> {
+    let name = field.name().unwrap_or("unknown").to_string();
+    let mut buf = Vec::new();
+    while let Some(chunk) = field.chunk().await.map_err(|e| AppError::Internal(e.to_string()))? {
+        if buf.len() + chunk.len() > max_bytes {
+            return Err(AppError::InvalidInput(format!("Field '{}' exceeds {} bytes", name, max_bytes)));
+        }
+        buf.extend_from_slice(&chunk);
+    }
+    String::from_utf8(buf).map_err(|_| AppError::InvalidInput(format!("Field '{}' is not valid UTF-8", name)))
+}
+
+/// Parse a `true`/`false` multipart field such as `spaMode`
+fn parse_flag_field(name: &str, value: &str) -> Result<bool, AppError> {
+    match value {
+        "true" => Ok(true),
+        "false" => Ok(false),
+        other => Err(AppError::InvalidInput(format!("{} must be 'true' or 'false', got '{}'", name, other))),
+    }
+}
+
+/// Parse the upload `mode` field; returns whether the upload is create-only
+pub fn parse_upload_mode(mode: &str) -> Result<bool, AppError> {
+    match mode {
+        "create" => Ok(true),
+        "version" => Ok(false),
+        other => Err(AppError::InvalidInput(format!("Unknown upload mode '{}'; expected 'create' or 'version'", other))),
+    }
+}
+
+/// Publish an archive that has been fully received: conflict and dedup checks, extraction,
+/// record write and audit. Shared by multipart and chunked uploads; the archive and
+/// `session_dir`, if any, are removed afterwards (kept on failure when `keep_temp_on_error`
+/// is set). Multipart uploads share `.upload_temp`, so they pass no session dir.
+pub async fn publish_archive(
+    storage: &Storage,
+    config: &Config,
+    params: SiteUploadParams,
+    create_only: bool,
+    session_dir: Option<&std::path::Path>,
+) -> Result<SiteResponse, AppError> {
+    let site_id = params.site_id;
+    let site_name = params.site_name.clone();
+    let user_id = params.user_id;
+    let temp_archive = params.archive_path.clone();
+    // The archive goes however this ends, including the future being dropped
+    let mut cleanup = TempGuard::new();
+    cleanup.track(&temp_archive);
 
     // Check for siteName conflict
-    if let Some(existing_site) = storage.sites.get_latest_by_name(&site_name).await? {
-        // Allow overwrite if same owner, otherwise conflict
-        if existing_site.owner_id != user_id {
-            // Cleanup temp file before returning error
-            tokio::fs::remove_file(&temp_archive).await.ok();
+    let latest = storage.sites.get_latest_by_name(&site_name).await?;
+    if let Some(existing_site) = &latest {
+        // Allow overwrite if same owner, otherwise conflict; create-only never overwrites
+        if create_only || existing_site.owner_id != user_id {
             return Err(AppError::SiteNameConflict(site_name));
         }
     }
+    // The uuid comes from the client; reusing one would overwrite that site's record and files
+    if storage.sites.get(site_id).await?.is_some() {
+        return Err(AppError::InvalidInput(format!("site uuid {} is already in use", site_id)));
+    }
+
+    // A new version keeps the share password, SPA, fingerprint, sitemap and header settings of the version it replaces
+    let mut settings = SiteSettings::inherit(latest.as_ref());
+    if let Some(spa_mode) = params.spa_mode {
+        settings.spa_mode = spa_mode;
+    }
+    if let Some(fingerprint_assets) = params.fingerprint_assets {
+        settings.fingerprint_assets = fingerprint_assets;
+    }
+    if let Some(auto_sitemap) = params.auto_sitemap {
+        settings.auto_sitemap = auto_sitemap;
+    }
+
+    settings.root_dir = params.root_dir.as_ref().map(|d| d.to_string_lossy().into_owned());
+
+    // Byte-identical re-upload of the latest version: keep it instead of creating a new one
+    // (unless it publishes another rootDir or changes a setting that lives on the version record)
+    let content_hash = archive::content_hash(&temp_archive)?;
+    let unchanged = |s: &Site| s.content_hash.as_deref() == Some(content_hash.as_str())
+        && s.root_dir == settings.root_dir
+        && s.spa_mode == settings.spa_mode
+        && s.fingerprint_assets == settings.fingerprint_assets
+        && s.auto_sitemap == settings.auto_sitemap;
+    if let Some(existing_site) = latest.clone().filter(unchanged) {
+        debug!("Upload for '{}' matches latest version {}; skipping", site_name, existing_site.id);
+        if let Some(dir) = session_dir {
+            cleanup.track(dir);
+        }
+        let mut response = SiteResponse::from_site(existing_site, config.server.url.as_ref(), config.server.site_url_style);
+        response.deduplicated = Some(true);
+        return Ok(response);
+    }
 
     // Keep archive in temp location - process_site_archive will clean it up
     // Don't move to name_dir because process_site_archive will clear that directory
     debug!("Archive at temp path {:?}", temp_archive);
 
-    // Clean up temp directory if empty (but keep the archive file)
-    // temp_dir cleanup will happen after archive is processed
+    // Process archive and create both directories
+    let keep_temp_on_error = config.storage.keep_temp_on_error;
+    if let Some(dir) = session_dir {
+        cleanup.track(dir);
+    }
+    let processed = process_site_archive(storage, &params, &config.storage.archive, keep_temp_on_error).await;
 
-    // Prepare upload parameters
-    let params = SiteUploadParams {
-        site_id,
-        site_name: site_name.clone(),
-        user_id,
-        archive_filename: filename,
-        archive_path: temp_archive.clone(),
-    };
+    // Clean up temp files (kept for inspection when processing failed and the flag is on)
+    if processed.is_err() && keep_temp_on_error {
+        cleanup.keep();
+    } else {
+        drop(cleanup);
+    }
 
-    // Process archive and create both directories
-    let (uuid_dir, name_dir) = process_site_archive(&storage, &params).await?;
+    let (uuid_dir, name_dir, mut warnings, metrics) = processed?;
     debug!("Site files created: UUID path {:?}, Name path {:?}", uuid_dir, name_dir);
-
-    // Clean up temp directory
-    tokio::fs::remove_dir_all(&temp_dir).await.ok();
+    if settings.fingerprint_assets {
+        // Links stay valid without the hash, so a failure here only costs cacheability
+        if let Err(e) = fingerprint_name_dir(storage, &name_dir, &site_name) {
+            warn!("Fingerprinting assets in {:?} failed: {}", name_dir, e);
+            warnings.push(format!("asset fingerprinting failed: {}", e));
+        }
+    }
+    let (redirects, redirect_warnings) = load_redirects(&uuid_dir);
+    warnings.extend(redirect_warnings);
+    let replacement_verified = verify_replacement(&name_dir, site_id);
 
     // Save site record
-    let site = save_site_record(&storage, site_id, &site_name, user_id).await?;
+    // Files are already on disk; if the record can't be written, remove the UUID dir again
+    // so disk and DB don't drift apart, and put the previous version back under siteName
+    if config.server.record_upload_origin {
+        settings.origin = params.origin;
+    }
+    let site = match save_site_record(storage, site_id, &site_name, user_id, Some(content_hash), redirects, settings).await {
+        Ok(site) => site,
+        Err(e) => {
+            warn!("Saving site record failed; removing extracted dir {:?}: {}", uuid_dir, e);
+            tokio::fs::remove_dir_all(&uuid_dir).await.ok();
+            storage.sizes.invalidate(&uuid_dir);
+            restore_name_dir(storage, &name_dir, latest.as_ref()).await;
+            return Err(e);
+        }
+    };
+    storage.audit.append(AuditEvent::new(
+        AuditAction::SiteUpload,
+        Some(user_id),
+        Some(format!("{}:{}", site_name, site_id)),
+    )).await?;
 
-    let response = SiteResponse::from_site(site, config.server.url.as_ref());
-    Ok(Json(response))
+    let mut response = SiteResponse::from_site(site, config.server.url.as_ref(), config.server.site_url_style);
+    response.replacement_verified = Some(replacement_verified);
+    response.warnings = warnings;
+    if config.server.upload_metrics {
+        response.metrics = Some(metrics);
+    }
+    Ok(response)
+}
+
+/// Undo a failed publish's siteName dir: rebuild it from the version it replaced, or
+/// remove it when the name had no earlier version
+async fn restore_name_dir(storage: &Storage, name_dir: &std::path::Path, previous: Option<&Site>) {
+    match previous {
+        Some(previous) => {
+            let Ok(_permit) = storage.extractions.acquire().await else { return };
+            if let Err(e) = rebuild_name_dir(storage, previous) {
+                warn!("Restoring {:?} from version {} failed: {}", name_dir, previous.id, e);
+            }
+        }
+        None => {
+            tokio::fs::remove_dir_all(name_dir).await.ok();
+            storage.sizes.invalidate(name_dir);
+        }
+    }
 }
 
+/// Body of `GET /api/sites`: a JSON array, or with `Accept: application/x-ndjson` one
+/// `SiteResponse` per line (see `list_all` for which listings are streamed from storage)
+#[derive(Debug)]
+pub enum SiteList {
+    Array(Vec<SiteResponse>),
+    Lines(Body),
+}
+
+impl SiteList {
+    fn negotiate(sites: Vec<SiteResponse>, headers: &HeaderMap) -> Self {
+        if !wants_ndjson(headers) {
+            return SiteList::Array(sites);
+        }
+        let lines = sites.into_iter().map(|site| {
+            let mut line = serde_json::to_vec(&site)?;
+            line.push(b'\n');
+            Ok::<_, serde_json::Error>(Bytes::from(line))
+        });
+        SiteList::Lines(Body::from_stream(futures_util::stream::iter(lines)))
+    }
+}
+
+impl IntoResponse for SiteList {
+    fn into_response(self) -> Response {
+        match self {
+            SiteList::Array(sites) => Json(sites).into_response(),
+            SiteList::Lines(body) => ([(CONTENT_TYPE, "application/x-ndjson")], body).into_response(),
+        }
+    }
+}
+
+fn wants_ndjson(headers: &HeaderMap) -> bool {
+    headers
+        .get_all(ACCEPT)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .any(|v| v.split(',').any(|t| t.trim().starts_with("application/x-ndjson")))
+}
+
+/// `?with_stats=` and `?latest_only=` (implied by with_stats)
+fn listing_flags(params: &HashMap<String, String>) -> (bool, bool) {
+    let with_stats = params.get("with_stats").map(|v| v == "true").unwrap_or(false);
+    let latest_only = with_stats || params.get("latest_only").map(|v| v == "true").unwrap_or(false);
+    (with_stats, latest_only)
+}
+
+/// GET /api/sites - 支持 ?offset=&limit= 分页，分页信息通过响应头返回
+/// ?latest_only=true 时每个站点名只返回最新版本
+/// ?with_stats=true 隐含 latest_only，并附带每个名称的版本数与磁盘占用（开销较大）
+/// ?include_owner=true 附带站点所有者用户名，需要携带有效的 Bearer token
+/// Accept: application/x-ndjson 时每行一个站点（NDJSON），否则返回 JSON 数组。
+/// NDJSON 按存储顺序（id 升序）逐页读取并边读边写，不带分页响应头；
+/// latest_only / with_stats 需要先汇总所有版本，仍按创建时间倒序整体生成后逐行输出
 pub async fn list_all(
     State((storage, config)): State<(Arc<Storage>, Arc<Config>)>,
-) -> Result<Json<Vec<SiteResponse>>, AppError> {
-    let sites = storage.sites.list_all().await?;
-    let responses: Vec<SiteResponse> = sites
+    user: Option<AuthenticatedUser>,
+    headers: HeaderMap,
+    Query(params): Query<HashMap<String, String>>,
+) -> Result<(HeaderMap, SiteList), AppError> {
+    if include_owner(&params) && user.is_none() {
+        return Err(AppError::AuthenticationFailed);
+    }
+    let (_, latest_only) = listing_flags(&params);
+    if wants_ndjson(&headers) && !latest_only {
+        return Ok((HeaderMap::new(), stream_sites(storage, config, &params)));
+    }
+    let (page_headers, sites) = list_sites(&storage, &config, &params, None).await?;
+    Ok((page_headers, SiteList::negotiate(sites, &headers)))
+}
+
+/// GET /api/sites when `server.require_auth_for_listing` is set: same query options,
+/// but only the caller's own sites are listed. Those are read through the per-owner
+/// index and sorted, so NDJSON is written from the loaded list
+pub async fn list_own(
+    State((storage, config)): State<(Arc<Storage>, Arc<Config>)>,
+    AuthenticatedUser(user): AuthenticatedUser,
+    headers: HeaderMap,
+    Query(params): Query<HashMap<String, String>>,
+) -> Result<(HeaderMap, SiteList), AppError> {
+    let (page_headers, sites) = list_sites(&storage, &config, &params, Some(user.id)).await?;
+    Ok((page_headers, SiteList::negotiate(sites, &headers)))
+}
+
+async fn list_sites(
+    storage: &Storage,
+    config: &Config,
+    params: &HashMap<String, String>,
+    owner_id: Option<Uuid>,
+) -> Result<(HeaderMap, Vec<SiteResponse>), AppError> {
+    let (with_stats, latest_only) = listing_flags(params);
+    let mut sites = match owner_id {
+        // Only the caller's versions are read, through the per-owner index
+        Some(owner_id) => {
+            let mut sites = storage.sites.list_by_owner(owner_id).await?;
+            if latest_only {
+                // list_by_owner is newest first, so the first version of each name is its latest
+                let mut seen = HashSet::new();
+                sites.retain(|site| seen.insert(site.name.clone()));
+            }
+            sites
+        }
+        None if latest_only => storage.sites.list_latest_per_name().await?,
+        None => storage.sites.list_all().await?,
+    };
+    // 两种后端的 list_all 顺序不同，分页前统一按创建时间倒序
+    sites.sort_by_key(|site| std::cmp::Reverse(site.created_at));
+
+    let page = Page::from_params(params);
+    let headers = pagination_headers(config.server.url.as_ref(), "/api/sites", params, &page, sites.len());
+
+    let sites = page.apply(sites);
+    let owners = if include_owner(params) {
+        owner_usernames(storage, &sites).await?
+    } else {
+        HashMap::new()
+    };
+
+    let mut responses: Vec<SiteResponse> = sites
         .into_iter()
-        .map(|site| SiteResponse::from_site(site, config.server.url.as_ref()))
+        .map(|site| site_response(site, &owners, config))
+        .collect();
+
+    // 只为当前页计算统计
+    if with_stats {
+        for response in responses.iter_mut() {
+            let (version_count, total_bytes) = site_name_stats(storage, &response.name).await?;
+            response.version_count = Some(version_count);
+            response.total_bytes = Some(total_bytes);
+        }
+    }
+
+    Ok((headers, responses))
+}
+
+fn site_response(site: Site, owners: &HashMap<Uuid, String>, config: &Config) -> SiteResponse {
+    let owner_username = owners.get(&site.owner_id).cloned();
+    let mut response = SiteResponse::from_site(site, config.server.url.as_ref(), config.server.site_url_style);
+    response.owner_username = owner_username;
+    response
+}
+
+/// Sites read from storage per chunk of a streamed NDJSON listing
+pub const LIST_PAGE_SIZE: usize = 100;
+
+/// Where a streamed listing is: the last id read, how many sites `?offset=` still skips
+/// and how many more `?limit=` allows
+struct ListCursor {
+    after: Option<Uuid>,
+    skip: usize,
+    remaining: Option<usize>,
+    done: bool,
+}
+
+/// NDJSON listing of every version, read from storage a page at a time by ascending id as
+/// the body is written, so the full list is never held in memory
+fn stream_sites(storage: Arc<Storage>, config: Arc<Config>, params: &HashMap<String, String>) -> SiteList {
+    let page = Page::from_params(params);
+    let with_owner = include_owner(params);
+    let cursor = ListCursor { after: None, skip: page.offset, remaining: page.limit, done: false };
+    let pages = futures_util::stream::unfold(cursor, move |cursor| {
+        let (storage, config) = (storage.clone(), config.clone());
+        async move { next_list_page(&storage, &config, with_owner, cursor).await }
+    });
+    SiteList::Lines(Body::from_stream(pages))
+}
+
+async fn next_list_page(
+    storage: &Storage,
+    config: &Config,
+    with_owner: bool,
+    mut cursor: ListCursor,
+) -> Option<(Result<Bytes, AppError>, ListCursor)> {
+    if cursor.done || cursor.remaining == Some(0) {
+        return None;
+    }
+    let sites = match storage.sites.list_page(cursor.after, LIST_PAGE_SIZE).await {
+        Ok(sites) => sites,
+        // Headers are already sent; cutting the body short is all that's left
+        Err(e) => {
+            cursor.done = true;
+            return Some((Err(e), cursor));
+        }
+    };
+    cursor.done = sites.len() < LIST_PAGE_SIZE;
+    cursor.after = sites.last().map(|site| site.id);
+    let skipped = cursor.skip.min(sites.len());
+    cursor.skip -= skipped;
+    let sites: Vec<Site> = sites.into_iter().skip(skipped).take(cursor.remaining.unwrap_or(usize::MAX)).collect();
+    cursor.remaining = cursor.remaining.map(|n| n - sites.len());
+    Some((site_lines(storage, config, sites, with_owner).await, cursor))
+}
+
+async fn site_lines(storage: &Storage, config: &Config, sites: Vec<Site>, with_owner: bool) -> Result<Bytes, AppError> {
+    let owners = if with_owner {
+        owner_usernames(storage, &sites).await?
+    } else {
+        HashMap::new()
+    };
+    let mut lines = Vec::new();
+    for site in sites {
+        serde_json::to_writer(&mut lines, &site_response(site, &owners, config))?;
+        lines.push(b'\n');
+    }
+    Ok(Bytes::from(lines))
+}
+
+/// GET /api/sites/names - 去重后按字母序排列的站点名，支持 ?offset=&limit= 分页
+/// ?mine=true 只返回调用者自己的站点名（需要 Bearer token）；开启 require_auth_for_listing 时总是如此
+pub async fn list_names(
+    State((storage, config)): State<(Arc<Storage>, Arc<Config>)>,
+    user: Option<AuthenticatedUser>,
+    Query(params): Query<HashMap<String, String>>,
+) -> Result<(HeaderMap, Json<Vec<String>>), AppError> {
+    let mine = config.server.require_auth_for_listing
+        || params.get("mine").map(|v| v == "true").unwrap_or(false);
+    let names = if mine {
+        let AuthenticatedUser(user) = user.ok_or(AppError::AuthenticationFailed)?;
+        let owned: std::collections::BTreeSet<String> = storage.sites.list_by_owner(user.id).await?
+            .into_iter()
+            .map(|site| site.name)
+            .collect();
+        owned.into_iter().collect()
+    } else {
+        storage.sites.list_distinct_names().await?
+    };
+
+    let page = Page::from_params(&params);
+    let headers = pagination_headers(config.server.url.as_ref(), "/api/sites/names", &params, &page, names.len());
+    Ok((headers, Json(page.apply(names))))
+}
+
+fn include_owner(params: &HashMap<String, String>) -> bool {
+    params.get("include_owner").map(|v| v == "true").unwrap_or(false)
+}
+
+/// Resolve each distinct owner once, rather than once per site
+async fn owner_usernames(storage: &Storage, sites: &[Site]) -> Result<HashMap<Uuid, String>, AppError> {
+    let owner_ids: HashSet<Uuid> = sites.iter().map(|site| site.owner_id).collect();
+    let mut owners = HashMap::new();
+    for owner_id in owner_ids {
+        if let Some(user) = storage.users.get(owner_id).await? {
+            owners.insert(owner_id, user.username);
+        }
+    }
+    Ok(owners)
+}
+
+/// Most ids accepted by one `POST /api/sites/stats` request
+pub const MAX_STATS_IDS: usize = 100;
+
+/// POST /api/sites/stats - 一次返回多个版本的统计：id -> { size_bytes, file_count, version_count, last_modified }
+/// 只包含调用者自己的版本（管理员可查看全部，见 `is_admin`）；不存在或无权查看的 id 直接省略
+pub async fn bulk_site_stats(
+    State((storage, config)): State<(Arc<Storage>, Arc<Config>)>,
+    Query(params): Query<HashMap<String, String>>,
+    AuthenticatedUser(user): AuthenticatedUser,
+    JsonBody(req): JsonBody<BulkSiteStatsRequest>,
+) -> Result<Json<HashMap<Uuid, SiteStats>>, AppError> {
+    if req.ids.len() > MAX_STATS_IDS {
+        return Err(AppError::InvalidInput(format!("at most {} ids per request", MAX_STATS_IDS)));
+    }
+    let is_admin = is_admin(&storage, &config, &params, Some(&user)).await?;
+
+    let mut stats = HashMap::new();
+    // 同名版本只查询一次
+    let mut names: HashMap<String, (usize, chrono::DateTime<chrono::Utc>)> = HashMap::new();
+    for id in req.ids {
+        if stats.contains_key(&id) {
+            continue;
+        }
+        let Some(site) = storage.sites.get(id).await? else { continue };
+        if authorize_site_access(&site, &user, is_admin).is_err() {
+            continue;
+        }
+        let (version_count, last_modified) = match names.get(&site.name) {
+            Some(entry) => *entry,
+            None => {
+                let versions = storage.sites.get_all_by_name(&site.name).await?;
+                let newest = versions.iter().map(|v| v.created_at).max().unwrap_or(site.created_at);
+                *names.entry(site.name.clone()).or_insert((versions.len(), newest))
+            }
+        };
+        let dir = storage.sites.get_site_files_path(id);
+        let (size_bytes, file_count) = if dir.exists() { storage.sizes.get_or_walk(&dir)? } else { (0, 0) };
+        stats.insert(id, SiteStats { size_bytes, file_count, version_count, last_modified });
+    }
+    Ok(Json(stats))
+}
+
+/// Version count and on-disk bytes (every UUID directory plus the siteName directory) for a name
+async fn site_name_stats(storage: &Storage, site_name: &str) -> Result<(usize, u64), AppError> {
+    let versions = storage.sites.get_all_by_name(site_name).await?;
+    let mut dirs: Vec<PathBuf> = versions
+        .iter()
+        .map(|site| storage.sites.get_site_files_path(site.id))
         .collect();
+    dirs.push(storage.sites.get_site_files_path_str(site_name));
 
-    Ok(Json(responses))
+    let mut total_bytes = 0;
+    for dir in dirs.iter().filter(|d| d.exists()) {
+        let (size, _count) = storage.sizes.get_or_walk(dir)?;
+        total_bytes += size;
+    }
+    Ok((versions.len(), total_bytes))
 }
 
 pub async fn update_site(
     State((storage, config)): State<(Arc<Storage>, Arc<Config>)>,
     Path(site_id): Path<Uuid>,
     AuthenticatedUser(user): AuthenticatedUser,
-    Json(req): Json<UpdateSiteRequest>,
+    JsonBody(req): JsonBody<UpdateSiteRequest>,
 ) -> Result<Json<SiteResponse>, AppError> {
-    let user_id = user.id;
-
     let mut site = storage.sites.get(site_id).await?.ok_or(AppError::SiteNotFound)?;
+    authorize_site_access(&site, &user, false)?;
 
-    // 检查权限
-    if site.owner_id != user_id {
-        return Err(AppError::AuthorizationFailed);
+    site.description = sanitize_text(&req.description, MAX_DESCRIPTION_LEN);
+    if let Some(spa_mode) = req.spa_mode {
+        site.spa_mode = spa_mode;
     }
-
-    site.description = req.description;
     storage.sites.update(site.clone()).await?;
 
-    let response = SiteResponse::from_site(site, config.server.url.as_ref());
+    let response = SiteResponse::from_site(site, config.server.url.as_ref(), config.server.site_url_style);
     Ok(Json(response))
 }
 
+/// PATCH /api/sites/{id} - 只修改请求中出现的字段，其余保持不变
+pub async fn patch_site(
+    State((storage, config)): State<(Arc<Storage>, Arc<Config>)>,
+    Path(site_id): Path<Uuid>,
+    AuthenticatedUser(user): AuthenticatedUser,
+    JsonBody(req): JsonBody<PatchSiteRequest>,
+) -> Result<Json<SiteResponse>, AppError> {
+    let mut site = storage.sites.get(site_id).await?.ok_or(AppError::SiteNotFound)?;
+    authorize_site_access(&site, &user, false)?;
+
+    if let Some(description) = req.description {
+        site.description = sanitize_text(&description, MAX_DESCRIPTION_LEN);
+    }
+    if let Some(spa_mode) = req.spa_mode {
+        site.spa_mode = spa_mode;
+    }
+    if let Some(auto_sitemap) = req.auto_sitemap {
+        site.auto_sitemap = auto_sitemap;
+    }
+    if let Some(custom_headers) = req.custom_headers {
+        site.custom_headers = validate_custom_headers(custom_headers)?;
+    }
+    storage.sites.update(site.clone()).await?;
+
+    Ok(Json(SiteResponse::from_site(site, config.server.url.as_ref(), config.server.site_url_style)))
+}
+
+/// Most custom headers a site may set
+pub const MAX_CUSTOM_HEADERS: usize = 32;
+
+/// Headers a site may not set: framing and hop-by-hop ones the server owns, and ones that
+/// would reach past the site itself, since every site shares the server's origin (cookies,
+/// credentialed CORS, clearing storage, widening a service worker's scope)
+pub const DENIED_CUSTOM_HEADERS: &[&str] = &[
+    "connection", "content-encoding", "content-length", "host", "keep-alive", "location",
+    "proxy-authenticate", "proxy-authorization", "te", "trailer", "transfer-encoding", "upgrade",
+    "www-authenticate", "set-cookie", "access-control-allow-credentials", "clear-site-data",
+    "service-worker-allowed",
+];
+
+/// Check `customHeaders` from a PATCH: valid names and values, none denied, no duplicates.
+/// Names are stored lowercase.
+pub fn validate_custom_headers(headers: Vec<(String, String)>) -> Result<Vec<(String, String)>, AppError> {
+    let problem = |message: String| AppError::Validation(vec![FieldError { field: "customHeaders".to_string(), message }]);
+    if headers.len() > MAX_CUSTOM_HEADERS {
+        return Err(problem(format!("must have at most {} entries", MAX_CUSTOM_HEADERS)));
+    }
+    let mut seen = HashSet::new();
+    let mut checked = Vec::with_capacity(headers.len());
+    for (name, value) in headers {
+        let header = HeaderName::from_bytes(name.trim().as_bytes())
+            .map_err(|_| problem(format!("'{}' is not a valid header name", name)))?;
+        if DENIED_CUSTOM_HEADERS.contains(&header.as_str()) {
+            return Err(problem(format!("'{}' can't be set per site", header)));
+        }
+        HeaderValue::from_str(&value).map_err(|_| problem(format!("value for '{}' is not a valid header value", header)))?;
+        if !seen.insert(header.clone()) {
+            return Err(problem(format!("'{}' is listed more than once", header)));
+        }
+        checked.push((header.as_str().to_string(), value));
+    }
+    Ok(checked)
+}
+
+/// PUT /api/sites/{id}/password - 设置或清除（空值 / null）站点分享密码
+/// 作用于该站点名下自己的全部版本，之后上传的新版本沿用同一密码
+pub async fn set_site_password(
+    State((storage, config)): State<(Arc<Storage>, Arc<Config>)>,
+    Path(site_id): Path<Uuid>,
+    AuthenticatedUser(user): AuthenticatedUser,
+    JsonBody(req): JsonBody<SetSitePasswordRequest>,
+) -> Result<Json<SiteResponse>, AppError> {
+    let site = storage.sites.get(site_id).await?.ok_or(AppError::SiteNotFound)?;
+    authorize_site_access(&site, &user, false)?;
+
+    let password_hash = match req.password.as_deref() {
+        None | Some("") => None,
+        Some(password) => Some(
+            bcrypt::hash(password, SITE_PASSWORD_COST)
+                .map_err(|e| AppError::Internal(e.to_string()))?,
+        ),
+    };
+
+    for mut version in storage.sites.get_all_by_name(&site.name).await? {
+        if version.owner_id != user.id {
+            continue;
+        }
+        version.password_hash = password_hash.clone();
+        storage.sites.update(version).await?;
+    }
+
+    let site = Site { password_hash, ..site };
+    Ok(Json(SiteResponse::from_site(site, config.server.url.as_ref(), config.server.site_url_style)))
+}
+
 pub async fn delete_site(
     State((storage, _config)): State<(Arc<Storage>, Arc<Config>)>,
     Path(site_id): Path<Uuid>,
@@ -294,17 +1264,355 @@ pub async fn delete_site(
     let user_id = user.id;
 
     let site = storage.sites.get(site_id).await?.ok_or(AppError::SiteNotFound)?;
+    authorize_site_access(&site, &user, false)?;
 
-    // 检查权限
-    if site.owner_id != user_id {
-        return Err(AppError::AuthorizationFailed);
-    }
-
+    // sites.delete 同时删除 UUID 目录，以及不再被其他版本使用的 siteName 目录
     storage.sites.delete(site_id).await?;
+    storage.sizes.invalidate(&storage.sites.get_site_files_path(site_id));
+    storage.sizes.invalidate(&storage.sites.get_site_files_path_str(&site.name));
+    storage.audit.append(AuditEvent::new(
+        AuditAction::SiteDelete,
+        Some(user_id),
+        Some(format!("{}:{}", site.name, site_id)),
+    )).await?;
 
     // 站点索引由 sites 存储维护（不再维护用户记录中的 sites 列表）
 
     Ok(Json(serde_json::json!({
         "message": "Site deleted successfully"
     })))
-}
\ No newline at end of file
+}
+/// POST /api/sites/{id}/rebuild - 由 UUID 目录（未替换的原始内容）重新生成 siteName 目录
+/// 用于 siteName 目录损坏或替换规则升级之后；只有该名称的最新版本可以重建。
+/// 站点所有者或管理员（见 `is_admin`）可调用
+pub async fn rebuild_site(
+    State((storage, config)): State<(Arc<Storage>, Arc<Config>)>,
+    Path(site_id): Path<Uuid>,
+    Query(params): Query<HashMap<String, String>>,
+    AuthenticatedUser(user): AuthenticatedUser,
+) -> Result<Json<SiteResponse>, AppError> {
+    let site = storage.sites.get(site_id).await?.ok_or(AppError::SiteNotFound)?;
+    authorize_site_access(&site, &user, is_admin(&storage, &config, &params, Some(&user)).await?)?;
+
+    // siteName 目录跟随最新版本，重建旧版本会覆盖它
+    let latest = storage.sites.get_all_by_name(&site.name).await?.first().map(|s| s.id);
+    if latest != Some(site_id) {
+        return Err(AppError::InvalidInput(format!(
+            "Only the latest version of '{}' can be rebuilt", site.name
+        )));
+    }
+
+    let _permit = storage.extractions.acquire().await
+        .map_err(|e| AppError::Internal(e.to_string()))?;
+    rebuild_name_dir(&storage, &site)?;
+
+    Ok(Json(SiteResponse::from_site(site, config.server.url.as_ref(), config.server.site_url_style)))
+}
+
+/// Regenerate `site.name`'s directory from the version's UUID directory (path replacement,
+/// plus fingerprinting when the version has it on). Callers hold an extraction permit.
+fn rebuild_name_dir(storage: &Storage, site: &Site) -> Result<PathBuf, AppError> {
+    let uuid_dir = storage.sites.get_site_files_path(site.id);
+    if !uuid_dir.is_dir() {
+        return Err(AppError::Internal(format!("Site files for {} are missing", site.id)));
+    }
+    let name_dir = storage.sites.get_site_files_path_str(&site.name);
+
+    // Build next to the live directory and swap, so a failure leaves the old copy serving
+    let staged = storage.sites.get_site_files_path_str(&format!(".rebuild_temp_{}", site.id));
+    if staged.exists() {
+        std::fs::remove_dir_all(&staged)?;
+    }
+    let replacement = (format!("/sites/{}/", site.id), format!("/sites/{}/", site.name));
+    let rebuilt = archive::replace_in_directory(&uuid_dir, &staged, replacement).and_then(|()| {
+        if site.fingerprint_assets {
+            fingerprint::fingerprint_assets(&staged, &format!("/sites/{}/", site.name))?;
+        }
+        Ok(())
+    });
+    if let Err(e) = rebuilt {
+        std::fs::remove_dir_all(&staged).ok();
+        return Err(e);
+    }
+    if name_dir.exists() {
+        std::fs::remove_dir_all(&name_dir)?;
+    }
+    std::fs::rename(&staged, &name_dir)?;
+    verify_replacement(&name_dir, site.id);
+    storage.sizes.record(&name_dir)?;
+    debug!("Rebuilt siteName directory {:?} from {:?}", name_dir, uuid_dir);
+    Ok(name_dir)
+}
+
+/// POST /api/sites/{id}/rename - 把一个版本改到新的站点名下
+/// 新名称不能已被任何版本使用；新 siteName 目录由该版本的 UUID 目录重新生成。
+/// 旧名称没有剩余版本时删除其目录；该版本原本是旧名称的最新版本时，旧目录改由剩余的最新版本重建。
+/// 站点所有者或管理员（见 `is_admin`）可调用
+pub async fn rename_site(
+    State((storage, config)): State<(Arc<Storage>, Arc<Config>)>,
+    Path(site_id): Path<Uuid>,
+    Query(params): Query<HashMap<String, String>>,
+    AuthenticatedUser(user): AuthenticatedUser,
+    JsonBody(req): JsonBody<RenameSiteRequest>,
+) -> Result<Json<SiteResponse>, AppError> {
+    let site = storage.sites.get(site_id).await?.ok_or(AppError::SiteNotFound)?;
+    authorize_site_access(&site, &user, is_admin(&storage, &config, &params, Some(&user)).await?)?;
+
+    let new_name = req.new_name.trim().to_string();
+    validate_site_name(&new_name)?;
+    if new_name == site.name {
+        return Err(AppError::InvalidInput(format!("Site is already named '{}'", new_name)));
+    }
+    if storage.sites.get_latest_by_name(&new_name).await?.is_some() {
+        return Err(AppError::SiteNameConflict(new_name));
+    }
+
+    let old_name = site.name.clone();
+    let was_latest = storage.sites.get_all_by_name(&old_name).await?.first().map(|s| s.id) == Some(site_id);
+
+    let _permit = storage.extractions.acquire().await
+        .map_err(|e| AppError::Internal(e.to_string()))?;
+
+    let mut renamed = site;
+    renamed.name = new_name;
+    let new_dir = rebuild_name_dir(&storage, &renamed)?;
+    if let Err(e) = storage.sites.update(renamed.clone()).await {
+        warn!("Saving renamed site {} failed; removing {:?}: {}", site_id, new_dir, e);
+        std::fs::remove_dir_all(&new_dir).ok();
+        storage.sizes.invalidate(&new_dir);
+        return Err(e);
+    }
+
+    // 旧名称的目录跟随其剩余的最新版本
+    let old_dir = storage.sites.get_site_files_path_str(&old_name);
+    match storage.sites.get_all_by_name(&old_name).await?.first() {
+        None => {
+            if old_dir.exists() {
+                std::fs::remove_dir_all(&old_dir)?;
+            }
+            storage.sizes.invalidate(&old_dir);
+        }
+        Some(newest) if was_latest => {
+            rebuild_name_dir(&storage, newest)?;
+        }
+        Some(_) => {}
+    }
+
+    storage.audit.append(AuditEvent::new(
+        AuditAction::SiteRename,
+        Some(user.id),
+        Some(format!("{}->{}:{}", old_name, renamed.name, site_id)),
+    )).await?;
+
+    Ok(Json(SiteResponse::from_site(renamed, config.server.url.as_ref(), config.server.site_url_style)))
+}
+
+/// DELETE /api/sites/by-name/{name} - 删除调用者拥有的该名称下的全部版本
+pub async fn delete_sites_by_name(
+    State((storage, _config)): State<(Arc<Storage>, Arc<Config>)>,
+    Path(site_name): Path<String>,
+    AuthenticatedUser(user): AuthenticatedUser,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let user_id = user.id;
+
+    let versions = storage.sites.get_all_by_name(&site_name).await?;
+    if versions.is_empty() {
+        return Err(AppError::SiteNotFound);
+    }
+
+    // 只处理调用者自己的版本
+    let (owned, foreign): (Vec<Site>, Vec<Site>) = versions
+        .into_iter()
+        .partition(|site| site.owner_id == user_id);
+    if owned.is_empty() {
+        return Err(AppError::AuthorizationFailed);
+    }
+
+    for site in &owned {
+        // sites.delete 同时删除 UUID 目录；最后一个版本被删除时也删除 siteName 目录
+        storage.sites.delete(site.id).await?;
+        storage.sizes.invalidate(&storage.sites.get_site_files_path(site.id));
+        storage.audit.append(AuditEvent::new(
+            AuditAction::SiteDelete,
+            Some(user_id),
+            Some(format!("{}:{}", site.name, site.id)),
+        )).await?;
+    }
+
+    // siteName 目录仍可能服务于其他用户的同名版本，存储层仅在没有剩余版本时删除它
+    if foreign.is_empty() {
+        storage.sizes.invalidate(&storage.sites.get_site_files_path_str(&site_name));
+    }
+
+    Ok(Json(serde_json::json!({
+        "message": "Sites deleted successfully",
+        "deleted": owned.len()
+    })))
+}
+
+/// DELETE /api/sites/mine - 删除调用者拥有的全部站点（所有名称、所有版本），返回删除数量
+/// 账号保留；其他用户的同名版本及其 siteName 目录不受影响
+pub async fn delete_my_sites(
+    State((storage, _config)): State<(Arc<Storage>, Arc<Config>)>,
+    AuthenticatedUser(user): AuthenticatedUser,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let owned = storage.sites.list_by_owner(user.id).await?;
+
+    for site in &owned {
+        // sites.delete 同时删除 UUID 目录；该名称没有剩余版本时也删除 siteName 目录
+        storage.sites.delete(site.id).await?;
+        storage.sizes.invalidate(&storage.sites.get_site_files_path(site.id));
+        storage.sizes.invalidate(&storage.sites.get_site_files_path_str(&site.name));
+        storage.audit.append(AuditEvent::new(
+            AuditAction::SiteDelete,
+            Some(user.id),
+            Some(format!("{}:{}", site.name, site.id)),
+        )).await?;
+    }
+
+    Ok(Json(serde_json::json!({
+        "message": "Sites deleted successfully",
+        "deleted": owned.len()
+    })))
+}
+
+/// GET /api/sites/resolve?name= - 站点名 -> UUID
+/// 普通用户只能解析自己拥有的版本；管理员（见 `is_admin`）可解析任意名称
+pub async fn resolve_site_name(
+    State((storage, config)): State<(Arc<Storage>, Arc<Config>)>,
+    Query(params): Query<HashMap<String, String>>,
+    AuthenticatedUser(user): AuthenticatedUser,
+) -> Result<Json<ResolveSiteResponse>, AppError> {
+    let name = params
+        .get("name")
+        .ok_or_else(|| AppError::InvalidInput("Missing name".to_string()))?;
+    let is_admin = is_admin(&storage, &config, &params, Some(&user)).await?;
+
+    // get_all_by_name 已按 created_at 倒序
+    let versions: Vec<Uuid> = storage.sites.get_all_by_name(name).await?
+        .into_iter()
+        .filter(|site| is_admin || site.owner_id == user.id)
+        .map(|site| site.id)
+        .collect();
+
+    // 不区分“不存在”和“无权限”，避免泄露他人的站点名
+    let latest = *versions.first().ok_or(AppError::SiteNotFound)?;
+
+    Ok(Json(ResolveSiteResponse {
+        name: name.clone(),
+        latest,
+        versions,
+    }))
+}
+
+/// `/sites/{uuid|name}/...` 静态文件服务前的中间件：
+/// 设置了分享密码的站点要求 HTTP Basic 认证（用户名任意）；随后应用站点 `_redirects` 规则，
+/// 相对路径的目标保留在同一站点前缀下（UUID 或名称），外部 URL 原样返回；
+/// auto_sitemap 站点在归档没有 sitemap.xml 时按站点内的 HTML 页面生成；
+/// spa_mode 站点中不存在的无扩展名路径改为返回站点的 index.html；
+/// 站点的 custom_headers 加在以上所有响应上（密码质询除外）；
+/// 不存在的站点返回 404（配置了 `server.sites_not_found_page` 时为该页面）
+pub async fn guard_site_files(
+    State((storage, config)): State<(Arc<Storage>, Arc<Config>)>,
+    mut request: Request,
+    next: Next,
+) -> Response {
+    // nest_service 已去掉 /sites 前缀
+    let path = request.uri().path().trim_start_matches('/');
+    let (site_key, rest) = match path.split_once('/') {
+        Some((key, rest)) => (key, format!("/{}", rest)),
+        None => (path, "/".to_string()),
+    };
+    if site_key.is_empty() || site_key.starts_with('.') {
+        return next.run(request).await;
+    }
+
+    let site = match Uuid::parse_str(site_key) {
+        Ok(id) => storage.sites.get(id).await,
+        Err(_) => storage.sites.get_latest_by_name(site_key).await,
+    };
+    let site = match site {
+        Ok(Some(site)) => site,
+        // Directories without a record (e.g. orphans) are still served as before
+        Ok(None) if storage.sites.get_site_files_path_str(site_key).exists() => return next.run(request).await,
+        Ok(None) => return site_not_found(&config).await,
+        // Can't tell whether the site is protected, so don't serve it
+        Err(e) => return e.into_response(),
+    };
+
+    if let Some(hash) = &site.password_hash {
+        let authorized = request
+            .headers()
+            .typed_get::<Authorization<Basic>>()
+            .is_some_and(|auth| bcrypt::verify(auth.password(), hash).unwrap_or(false));
+        if !authorized {
+            let challenge = format!("Basic realm=\"{}\", charset=\"UTF-8\"", site.name);
+            return (StatusCode::UNAUTHORIZED, [(WWW_AUTHENTICATE, challenge)]).into_response();
+        }
+    }
+
+    let mut response = 'serve: {
+        if let Some(rule) = find_redirect(&site.redirects, &rest) {
+            let location = if rule.to.starts_with('/') {
+                format!("/sites/{}{}", site_key, rule.to)
+            } else {
+                rule.to.clone()
+            };
+            debug!("Redirecting /sites/{}{} -> {} ({})", site_key, rest, location, rule.status);
+            let status = StatusCode::from_u16(rule.status).unwrap_or(StatusCode::MOVED_PERMANENTLY);
+            break 'serve (status, [(LOCATION, location)]).into_response();
+        }
+
+        // robots.txt / sitemap.xml shipped in the archive are plain files and served as such
+        if rest == "/sitemap.xml" && site.auto_sitemap {
+            let site_dir = storage.sites.get_site_files_path_str(site_key);
+            if !site_dir.join("sitemap.xml").exists() {
+                let site_url = format!("{}/sites/{}/", config.server.url, site_key);
+                break 'serve match build_sitemap(&site_dir, &site_url) {
+                    Ok(xml) => ([(CONTENT_TYPE, "application/xml; charset=utf-8")], xml).into_response(),
+                    Err(e) => e.into_response(),
+                };
+            }
+        }
+
+        if site.spa_mode && is_spa_route(&storage.sites.get_site_files_path_str(site_key), &rest) {
+            debug!("SPA fallback: /sites/{}{} -> index.html", site_key, rest);
+            if let Ok(uri) = format!("/{}/index.html", site_key).parse() {
+                *request.uri_mut() = uri;
+            }
+        }
+        next.run(request).await
+    };
+
+    // Custom headers were checked when set; one that no longer parses is skipped
+    for (name, value) in &site.custom_headers {
+        if let (Ok(name), Ok(value)) = (HeaderName::from_bytes(name.as_bytes()), HeaderValue::from_str(value)) {
+            response.headers_mut().insert(name, value);
+        }
+    }
+    response
+}
+
+/// 404 for an unknown site: the configured `sites_not_found_page`, else the JSON error
+async fn site_not_found(config: &Config) -> Response {
+    if let Some(page) = &config.server.sites_not_found_page {
+        match tokio::fs::read(page).await {
+            Ok(html) => {
+                return (StatusCode::NOT_FOUND, [(CONTENT_TYPE, "text/html; charset=utf-8")], html).into_response();
+            }
+            Err(e) => warn!("Can't read sites_not_found_page {:?}: {}", page, e),
+        }
+    }
+    AppError::SiteNotFound.into_response()
+}
+
+/// A client-side route: no file or directory at `rest`, and the last segment has no
+/// extension. Missing assets such as `app.js` or `style.css` still 404.
+fn is_spa_route(site_dir: &std::path::Path, rest: &str) -> bool {
+    let relative = std::path::Path::new(rest.trim_start_matches('/'));
+    if !relative.components().all(|c| matches!(c, std::path::Component::Normal(_))) {
+        return false;
+    }
+    let has_extension = relative.file_name().is_some_and(|name| name.to_string_lossy().contains('.'));
+    !has_extension && !site_dir.join(relative).exists()
+}
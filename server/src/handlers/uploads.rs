@@ -0,0 +1,191 @@
+use crate::{
+    auth::AuthenticatedUser,
+    error::AppError,
+    models::{CompleteUploadRequest, SiteResponse, StartUploadRequest, UploadStatusResponse},
+    storage::{Storage, CHUNKED_UPLOADS_DIR},
+    config::Config,
+    handlers::{json::JsonBody, sites::{accept_site_name, parse_upload_mode, publish_archive, SiteUploadParams, UploadOrigin}},
+    utils::archive,
+};
+use axum::{
+    body::Body,
+    extract::{Path, Query, State},
+    Json,
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::Arc;
+use uuid::Uuid;
+
+use tracing::debug;
+
+const META_FILE: &str = "meta.json";
+
+/// Session metadata kept next to the partial archive
+#[derive(Debug, Serialize, Deserialize)]
+struct UploadMeta {
+    owner_id: Uuid,
+    filename: String,
+    created_at: DateTime<Utc>,
+}
+
+/// An upload session on disk: `<sites>/.chunked_uploads/<id>/{meta.json,<filename>}`
+struct UploadSession {
+    id: Uuid,
+    dir: PathBuf,
+    meta: UploadMeta,
+}
+
+impl UploadSession {
+    fn dir_for(storage: &Storage, id: Uuid) -> PathBuf {
+        storage.sites.get_site_files_path_str(&format!("{}/{}", CHUNKED_UPLOADS_DIR, id))
+    }
+
+    /// Load a session owned by `user_id`
+    async fn open(storage: &Storage, id: Uuid, user_id: Uuid) -> Result<Self, AppError> {
+        let dir = Self::dir_for(storage, id);
+        let raw = match tokio::fs::read(dir.join(META_FILE)).await {
+            Ok(raw) => raw,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Err(AppError::UploadNotFound),
+            Err(e) => return Err(e.into()),
+        };
+        let meta: UploadMeta = serde_json::from_slice(&raw)?;
+        if meta.owner_id != user_id {
+            return Err(AppError::AuthorizationFailed);
+        }
+        Ok(Self { id, dir, meta })
+    }
+
+    fn data_path(&self) -> PathBuf {
+        self.dir.join(&self.meta.filename)
+    }
+
+    /// Bytes received so far
+    async fn received(&self) -> Result<u64, AppError> {
+        match tokio::fs::metadata(self.data_path()).await {
+            Ok(m) => Ok(m.len()),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(0),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn status(&self, offset: u64) -> UploadStatusResponse {
+        UploadStatusResponse { upload_id: self.id, offset }
+    }
+}
+
+/// The archive name becomes a file inside the session dir, so it must be a bare file name
+fn validate_upload_filename(filename: &str) -> Result<(), AppError> {
+    let is_bare = std::path::Path::new(filename).file_name().and_then(|n| n.to_str()) == Some(filename);
+    if !is_bare || filename == META_FILE {
+        return Err(AppError::InvalidInput(format!("Invalid archive filename '{}'", filename)));
+    }
+    Ok(())
+}
+
+/// POST /api/uploads - 开始一次分片上传，返回 upload_id
+pub async fn start_upload(
+    State((storage, _config)): State<(Arc<Storage>, Arc<Config>)>,
+    AuthenticatedUser(user): AuthenticatedUser,
+    JsonBody(req): JsonBody<StartUploadRequest>,
+) -> Result<Json<UploadStatusResponse>, AppError> {
+    validate_upload_filename(&req.filename)?;
+
+    let id = Uuid::new_v4();
+    let dir = UploadSession::dir_for(&storage, id);
+    tokio::fs::create_dir_all(&dir).await?;
+
+    let meta = UploadMeta {
+        owner_id: user.id,
+        filename: req.filename,
+        created_at: Utc::now(),
+    };
+    tokio::fs::write(dir.join(META_FILE), serde_json::to_vec(&meta)?).await?;
+    debug!("Started chunked upload {} for user {}", id, user.id);
+
+    Ok(Json(UploadStatusResponse { upload_id: id, offset: 0 }))
+}
+
+/// GET /api/uploads/{id} - 查询已接收字节数，用于断点续传
+pub async fn upload_status(
+    State((storage, _config)): State<(Arc<Storage>, Arc<Config>)>,
+    AuthenticatedUser(user): AuthenticatedUser,
+    Path(id): Path<Uuid>,
+) -> Result<Json<UploadStatusResponse>, AppError> {
+    let session = UploadSession::open(&storage, id, user.id).await?;
+    let offset = session.received().await?;
+    Ok(Json(session.status(offset)))
+}
+
+/// PUT /api/uploads/{id}?offset=N - 追加一个分片
+/// `offset` 必须等于已接收的字节数，否则返回 409 并在 details 中给出期望的 offset
+/// 累计大小超过 `storage.archive.max_total_bytes` 的分片被丢弃并返回 413
+pub async fn upload_chunk(
+    State((storage, config)): State<(Arc<Storage>, Arc<Config>)>,
+    AuthenticatedUser(user): AuthenticatedUser,
+    Path(id): Path<Uuid>,
+    Query(params): Query<HashMap<String, String>>,
+    body: Body,
+) -> Result<Json<UploadStatusResponse>, AppError> {
+    let offset: u64 = params
+        .get("offset")
+        .ok_or_else(|| AppError::InvalidInput("Missing offset".to_string()))?
+        .parse()
+        .map_err(|_| AppError::InvalidInput("offset must be a non-negative integer".to_string()))?;
+
+    let session = UploadSession::open(&storage, id, user.id).await?;
+    let received = session.received().await?;
+    if offset != received {
+        return Err(AppError::UploadOffsetMismatch(received));
+    }
+
+    let max_len = config.storage.archive.max_total_bytes;
+    let offset = archive::append_stream(body.into_data_stream(), &session.data_path(), max_len).await?;
+    debug!("Chunked upload {} now at {} bytes", id, offset);
+    Ok(Json(session.status(offset)))
+}
+
+/// POST /api/uploads/{id}/complete - 结束上传并发布站点（与 multipart 上传走同一流程）
+pub async fn complete_upload(
+    State((storage, config)): State<(Arc<Storage>, Arc<Config>)>,
+    AuthenticatedUser(user): AuthenticatedUser,
+    Path(id): Path<Uuid>,
+    origin: UploadOrigin,
+    JsonBody(req): JsonBody<CompleteUploadRequest>,
+) -> Result<Json<SiteResponse>, AppError> {
+    let site_name = accept_site_name(req.site_name, config.server.allow_slugify)?;
+    let create_only = req.mode.as_deref().map(parse_upload_mode).transpose()?.unwrap_or(false);
+    let root_dir = req.root_dir.as_deref().map(archive::parse_root_dir).transpose()?.flatten();
+
+    let session = UploadSession::open(&storage, id, user.id).await?;
+    let data_path = session.data_path();
+    if session.received().await? == 0 {
+        return Err(AppError::InvalidInput("Upload has no data".to_string()));
+    }
+
+    // 超出每用户并发上限时保留会话，客户端稍后可重试 complete
+    let _slot = storage.uploads.acquire(user.id)?;
+
+    let params = SiteUploadParams {
+        site_id: req.uuid,
+        site_name,
+        user_id: user.id,
+        archive_filename: session.meta.filename.clone(),
+        archive_path: data_path.clone(),
+        root_dir,
+        spa_mode: req.spa_mode,
+        fingerprint_assets: req.fingerprint_assets,
+        auto_sitemap: req.auto_sitemap,
+        origin,
+    };
+    let result = publish_archive(&storage, &config, params, create_only, Some(&session.dir)).await;
+
+    // publish_archive removes the session dir on success; a rejected upload whose archive
+    // was already discarded (e.g. name conflict) can't be completed again either
+    if result.is_err() && !data_path.exists() {
+        tokio::fs::remove_dir_all(&session.dir).await.ok();
+    }
+    result.map(Json)
+}
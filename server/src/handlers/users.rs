@@ -1,9 +1,10 @@
 use crate::{
-    auth::AuthenticatedUser,
+    auth::{validation::{normalize_email, validate_email, validate_username}, AuthenticatedUser},
     error::AppError,
+    handlers::json::JsonBody,
     models::{SiteResponse, UserResponse},
     storage::Storage,
-    config::Config,
+    config::{Config, SiteUrlStyle},
 };
 use axum::{
     extract::State,
@@ -28,7 +29,7 @@ pub async fn get_user_profile(
     
     let site_responses: Vec<SiteResponse> = sites
         .into_iter()
-        .map(|site| SiteResponse::from_site(site, "http://localhost:8080"))
+        .map(|site| SiteResponse::from_site(site, "http://localhost:8080", SiteUrlStyle::default()))
         .collect();
 
     let profile = UserProfileResponse {
@@ -44,7 +45,7 @@ pub async fn get_user_profile(
 pub async fn update_user_profile(
     State(storage): State<Arc<Storage>>,
     AuthenticatedUser(auth_user): AuthenticatedUser,
-    Json(req): Json<UpdateUserRequest>,
+    JsonBody(req): JsonBody<UpdateUserRequest>,
 ) -> Result<Json<UserResponse>, AppError> {
     let user_id = auth_user.id;
 
@@ -53,6 +54,7 @@ pub async fn update_user_profile(
     // 更新用户名（如果提供且不为空）
     if let Some(username) = req.username {
         if !username.trim().is_empty() {
+            validate_username(&username)?;
             // 检查用户名是否已被其他用户使用
             if let Some(existing_user) = storage.users.get_by_username(&username).await? {
                 if existing_user.id != user_id {
@@ -63,6 +65,17 @@ pub async fn update_user_profile(
         }
     }
 
+    // 更新邮箱：空字符串表示移除；冲突由存储层的唯一约束报告为 EmailAlreadyExists
+    if let Some(email) = req.email {
+        let email = normalize_email(&email);
+        if email.is_empty() {
+            user.email = None;
+        } else {
+            validate_email(&email)?;
+            user.email = Some(email);
+        }
+    }
+
     storage.users.update(user.clone()).await?;
     Ok(Json(UserResponse::from(user)))
 }
@@ -102,7 +115,7 @@ pub async fn get_user_stats(
 
     let site_responses: Vec<SiteResponse> = sites
         .into_iter()
-        .map(|site| SiteResponse::from_site(site, config.server.url.as_ref()))
+        .map(|site| SiteResponse::from_site(site, config.server.url.as_ref(), config.server.site_url_style))
         .collect();
 
     let stats = UserStatsResponse {
@@ -130,8 +143,9 @@ pub struct UserProfileResponse {
 #[derive(Debug, Deserialize)]
 pub struct UpdateUserRequest {
     pub username: Option<String>,
+    /// 传空字符串可移除邮箱
+    pub email: Option<String>,
     // 可以添加其他可更新的字段
-    // pub email: Option<String>,
     // pub display_name: Option<String>,
 }
 
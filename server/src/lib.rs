@@ -5,6 +5,7 @@ pub mod config;
 pub mod error;
 pub mod handlers;
 pub mod models;
+pub mod routes;
 pub mod storage;
 pub mod utils;
 
@@ -12,4 +13,6 @@ pub mod utils;
 pub use config::Config;
 pub use error::AppError;
 pub use models::{User, Site};
+pub use routes::build as build_app;
 pub use storage::Storage;
+pub use utils::archive::{extract_archive, ArchiveConfig, ArchiveError};
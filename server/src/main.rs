@@ -4,130 +4,155 @@ mod error;
 mod utils;
 mod handlers;
 mod models;
+mod routes;
 mod storage;
 
-use auth::{auth_middleware, AuthService, TokenService};
-use axum::{
-    extract::DefaultBodyLimit,
-    http::StatusCode,
-    middleware,
-    routing::{delete, get, get_service, post, put},
-    Router,
-};
 use config::Config;
-use handlers::{auth as auth_handlers, sites as site_handlers, users as user_handlers, admin as admin_handlers};
 use std::sync::Arc;
 use storage::Storage;
-use tower_http::{cors::CorsLayer, limit::RequestBodyLimitLayer, services::{ServeDir, ServeFile}, trace::TraceLayer};
-use tracing::info;
+use tracing::{info, warn};
+use tracing_subscriber::{layer::SubscriberExt, reload, util::SubscriberInitExt, EnvFilter};
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
-    tracing_subscriber::fmt()
-        .with_env_filter("debug")
+    // 先用环境变量/默认值初始化，加载配置后再按 server.log_filter 重新设置
+    let env_filter = std::env::var("RUST_LOG").ok();
+    let (filter_layer, filter_handle) = reload::Layer::new(EnvFilter::new(
+        config::resolve_log_filter(None, env_filter.as_deref()),
+    ));
+    tracing_subscriber::registry()
+        .with(filter_layer)
+        .with(tracing_subscriber::fmt::layer())
         .init();
 
     let args: Vec<String> = std::env::args().collect();
-    let (show_help, config_path) = utils::parse_args::parse_args(&args);
-    if show_help {
+    let cli = utils::parse_args::parse_args(&args);
+    if cli.show_help {
         let prog = args.get(0).map(|s| s.as_str()).unwrap_or("server");
-        println!("Usage: {} --config <path>\n\nOptions:\n  --config <path>    Specify config file (default: config.json)\n  -h, --help         Show this help\n", prog);
+        println!("Usage: {} --config <path>\n\nOptions:\n  --config <path>       Specify config file (default: $OBSIDIAN_CONFIG_PATH, then config.json)\n  --print-config        Print a complete default config to stdout and exit\n  --init-config <path>  Write a complete default config to <path> and exit\n  --create-admin <name> Create an admin user in the configured storage and exit\n                        (password from --admin-password-file or $ADMIN_PASSWORD)\n  --admin-password-file <path>\n                        File holding the --create-admin password\n  -h, --help            Show this help\n", prog);
+        return Ok(());
+    }
+
+    // 生成配置模板后直接退出，不启动服务
+    if cli.print_config {
+        println!("{}", Config::template()?);
+        return Ok(());
+    }
+    if let Some(path) = &cli.init_config {
+        if std::path::Path::new(path).exists() {
+            anyhow::bail!("{} already exists; refusing to overwrite it", path);
+        }
+        std::fs::write(path, Config::template()?)?;
+        println!("Wrote default config to {}", path);
         return Ok(());
     }
 
-    let config = Arc::new(Config::load_from(&config_path)?);
-    info!("🔧 Configuration loaded");
+    let config = Arc::new(Config::load_from(&cli.config_path)?);
+    let log_filter = config::resolve_log_filter(config.server.log_filter.as_deref(), env_filter.as_deref());
+    filter_handle.modify(|f| *f = EnvFilter::new(&log_filter))?;
+    info!("🔧 Configuration loaded (log filter: {})", log_filter);
 
     // 初始化存储 (async to support ORM connection)
     let storage = Arc::new(Storage::new(&config.storage).await?);
     info!("💾 Storage initialized");
 
-    // 初始化服务
-    let token_service = Arc::new(TokenService::new(
-        config.server.jwt_secret.clone(),
-        config.auth.token_expiration_hours,
-    ));
-    let auth_service = Arc::new(AuthService::new(
-        storage.users.clone(),
-        (*token_service).clone(),
-        config.auth.allow_plaintext_password,
-    ));
-    info!("🔒 Services initialized");
-
-    // 公开路由（不需要认证）
-    let public_routes = Router::new()
-        .route("/api/admin/all", get(admin_handlers::admin_all))
-        .route("/api/admin/sites", get(admin_handlers::admin_sites))
-        .route("/api/admin/storage", get(admin_handlers::admin_storage))
-        .route("/api/sites", get(site_handlers::list_all))
-        .with_state((storage.clone(), config.clone()))
-        .route("/auth/register", post(auth_handlers::register))
-        .route("/auth/login", post(auth_handlers::login))
-        .with_state(auth_service.clone());
-
-    // 需要认证的路由
-    let protected_routes = Router::new()
-        .route("/auth/me", get(auth_handlers::me))
-        .with_state(auth_service.clone())
-        .route("/api/sites", post(site_handlers::upload_site))
-        .route("/api/sites/{id}", put(site_handlers::update_site))
-        .route("/api/sites/{id}", delete(site_handlers::delete_site))
-        .route("/user/stats", get(user_handlers::get_user_stats))
-        .with_state((storage.clone(), config.clone()))
-        .route("/user/profile", get(user_handlers::get_user_profile))
-        .route("/user/profile", put(user_handlers::update_user_profile))
-        .route("/user/account", delete(user_handlers::delete_user_account))
-        .with_state(storage.clone());
-
-    let auth_middleware_layer =
-        middleware::from_fn_with_state(
-            token_service.clone(),
-            auth_middleware,
+    // 离线创建管理员：不需要服务在运行，也不受 require_invite 限制
+    if let Some(username) = cli.create_admin {
+        let password = read_admin_password(cli.admin_password_file.as_deref())?;
+        let auth_service = auth::AuthService::new(
+            storage.users.clone(),
+            storage.audit.clone(),
+            auth::TokenService::new(config.server.jwt_secret.clone(), config.auth.token_expiration_hours),
+            config.auth.allow_plaintext_password,
         );
+        let admin = auth_service.create_admin(username, password).await?;
+        println!("Created admin user {} ({})", admin.username, admin.id);
+        return Ok(());
+    }
+    let pruned = storage.cleanup_temp().await?;
+    if !pruned.is_empty() {
+        info!("🧹 Removed leftover temp directories: {}", pruned.join(", "));
+    }
 
-    // Web UI
-    let static_service = if let Some(root) = config.server.static_root.clone() {
-        get_service(
-            ServeDir::new(root.clone())
-                .fallback(ServeFile::new(root.join("index.html")))
-        )
-    } else {
-        get(|| async { StatusCode::NOT_FOUND })
-    };
+    let retention_days = config.storage.audit_retention_days;
+    if retention_days > 0 {
+        let storage = storage.clone();
+        tokio::spawn(async move {
+            // 启动时先清理一次，之后每小时一次
+            let mut ticker = tokio::time::interval(std::time::Duration::from_secs(3600));
+            loop {
+                ticker.tick().await;
+                match storage.prune_audit(retention_days).await {
+                    Ok(0) => {}
+                    Ok(n) => info!("🧹 Pruned {} audit events older than {} days", n, retention_days),
+                    Err(e) => warn!("Pruning audit events failed: {}", e),
+                }
+            }
+        });
+    }
 
-    let app = Router::new()
-        .merge(protected_routes)
-        .route_layer(auth_middleware_layer)
-        .merge(public_routes)
-        .nest_service("/sites", ServeDir::new(storage.sites.get_site_files_path_str("")))
-        .fallback_service(static_service)
-        .layer(CorsLayer::permissive())
-        .layer(TraceLayer::new_for_http())
-        .layer(DefaultBodyLimit::disable())
-        .layer(RequestBodyLimitLayer::new(
-            250 * 1024 * 1024, /* 250mb */
-        ));
+    if config.auth.expose_reset_token {
+        warn!("auth.expose_reset_token is enabled: password reset tokens are returned to any caller");
+    }
+
+    let app = routes::build(storage.clone(), config.clone());
 
     let listener = tokio::net::TcpListener::bind(config.server.bind_url()).await?;
     info!("🚀 Server running on {}", config.server.bind_url());
     info!("📚 API endpoints:");
-    info!("  GET    /api/admin/all    - Debugging");
-    info!("  GET    /api/admin/sites  - DB <-> disk mismatch check (requires ?key=JWT_SECRET)");
-    info!("  GET    /api/admin/storage - Storage usage summary (requires ?key=JWT_SECRET)");
-    info!("  GET    /api/sites        - 列出站点");
+    if config.server.require_auth_for_listing {
+        info!("  (require_auth_for_listing: /api/admin/* require an admin-role token, GET /api/sites requires auth)");
+    } else {
+        info!("  GET    /api/admin/all    - Debugging (requires ?key=JWT_SECRET or admin role)");
+        info!("  GET    /api/admin/sites  - DB <-> disk mismatch check (requires ?key=JWT_SECRET or admin role)");
+        info!("  GET    /api/admin/storage - Storage usage summary (requires ?key=JWT_SECRET or admin role)");
+        info!("  GET    /api/admin/report - Usage aggregates for sites created in a window (requires ?key=JWT_SECRET or admin role, optional ?from=&to=&top=)");
+        info!("  GET    /api/admin/audit  - Recent audit events (requires ?key=JWT_SECRET or admin role, optional ?limit=)");
+        info!("  GET    /api/admin/export - NDJSON export of users and sites (requires ?key=JWT_SECRET or admin role)");
+        info!("  POST   /api/admin/prune-temp - Remove leftover upload/extraction temp dirs (requires ?key=JWT_SECRET or admin role)");
+        info!("  POST   /api/admin/invites - Mint a registration invite code (requires ?key=JWT_SECRET or admin role, optional ?max_uses=&expires_in_hours=)");
+        info!("  GET    /api/sites        - 列出站点");
+    }
+    info!("  GET    /ready            - 就绪检查（数据库、站点目录可写）");
     info!("  POST   /auth/register    - 用户注册");
     info!("  POST   /auth/login       - 用户登录");
+    info!("  POST   /auth/password-reset/request - 申请密码重置令牌");
+    info!("  POST   /auth/password-reset/confirm - 用令牌设置新密码");
+    info!("  POST   /auth/introspect  - 解析 token");
     info!("  ------------------------------  ");
     info!("  GET    /auth/me          - 获取当前用户信息");
     info!("  POST   /api/sites        - 上传站点");
+    info!("  POST   /api/uploads      - 开始分片上传");
+    info!("  PUT    /api/uploads/:id?offset= - 追加分片 (GET 查询已接收字节数)");
+    info!("  POST   /api/uploads/:id/complete - 完成分片上传并发布站点");
     info!("  PUT    /api/sites/:id    - 更新站点信息");
+    info!("  PATCH  /api/sites/:id    - 部分更新站点信息");
     info!("  DELETE /api/sites/:id    - 删除站点");
+    info!("  PUT    /api/sites/:id/password - 设置/清除站点分享密码");
+    info!("  POST   /api/sites/:id/rebuild - 由 UUID 目录重建 siteName 目录");
+    info!("  POST   /api/sites/:id/rename - 修改某个版本的站点名");
+    info!("  POST   /api/sites/stats  - 批量获取版本统计（最多 100 个 id）");
+    info!("  DELETE /api/sites/by-name/:name - 按名称删除自己的全部版本");
+    info!("  DELETE /api/sites/mine   - 删除自己的全部站点");
+    info!("  GET    /api/sites/resolve?name= - 站点名解析为 UUID");
+    info!("  GET    /api/sites/names  - 去重后的站点名列表 (?mine=true 需要认证)");
     info!("  GET    /user/profile     - 获取用户详细信息");
     info!("  PUT    /user/profile     - 更新用户信息");
     info!("  GET    /user/stats       - 获取用户统计");
     info!("  DELETE /user/account     - 删除用户账户");
 
-    axum::serve(listener, app).await?;
+    axum::serve(listener, app.into_make_service_with_connect_info::<std::net::SocketAddr>()).await?;
 
     Ok(())
-}
\ No newline at end of file
+}
+
+/// `--create-admin` 的密码：--admin-password-file 的首行，否则 ADMIN_PASSWORD；
+/// 不在终端提示输入，避免密码回显
+fn read_admin_password(file: Option<&str>) -> anyhow::Result<String> {
+    if let Some(path) = file {
+        let contents = std::fs::read_to_string(path)?;
+        return Ok(contents.lines().next().unwrap_or_default().to_string());
+    }
+    std::env::var("ADMIN_PASSWORD")
+        .map_err(|_| anyhow::anyhow!("--create-admin needs a password from --admin-password-file or $ADMIN_PASSWORD"))
+}
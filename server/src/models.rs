@@ -1,3 +1,4 @@
+use crate::{config::SiteUrlStyle, error::AppError};
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
@@ -8,6 +9,15 @@ pub struct User {
     pub username: String,
     pub password: String, // 生产环境应该hash
     pub created_at: DateTime<Utc>,
+    /// 最近一次登录成功的时间；从未登录为 None
+    #[serde(default)]
+    pub last_login: Option<DateTime<Utc>>,
+    /// 写入 JWT `roles` claim，供反向代理等下游做授权判断
+    #[serde(default)]
+    pub roles: Vec<String>,
+    /// 联系邮箱（小写保存，全局唯一），为找回密码预留；不出现在公开列表中
+    #[serde(default)]
+    pub email: Option<String>,
 }
 
 impl User {
@@ -17,6 +27,9 @@ impl User {
             username,
             password,
             created_at: Utc::now(),
+            last_login: None,
+            roles: Vec::new(),
+            email: None,
         }
     }
 }
@@ -29,6 +42,37 @@ pub struct Site {
     pub domain: Option<String>,
     pub description: String,
     pub created_at: DateTime<Utc>,
+    /// SHA-256 (hex) of the uploaded archive; used to skip identical re-uploads
+    #[serde(default)]
+    pub content_hash: Option<String>,
+    /// Archive subdirectory published as the site root (`rootDir`); with `content_hash`
+    /// it decides whether a re-upload is identical
+    #[serde(default)]
+    pub root_dir: Option<String>,
+    /// Rules parsed from the site's `_redirects` file at upload time
+    #[serde(default)]
+    pub redirects: Vec<RedirectRule>,
+    /// bcrypt hash of the share password; `/sites` asks for HTTP Basic auth when set
+    #[serde(default)]
+    pub password_hash: Option<String>,
+    /// Single-page app: `/sites` serves `index.html` for extensionless paths with no file
+    #[serde(default)]
+    pub spa_mode: bool,
+    /// Local asset links in the siteName copy's HTML carry `?v=<content hash>`
+    #[serde(default)]
+    pub fingerprint_assets: bool,
+    /// `/sites/<site>/sitemap.xml` is generated from the site's HTML pages when the archive
+    /// doesn't ship one
+    #[serde(default)]
+    pub auto_sitemap: bool,
+    /// Extra `(name, value)` response headers for everything served under `/sites/<site>`
+    #[serde(default)]
+    pub custom_headers: Vec<(String, String)>,
+    /// Uploader's address and User-Agent, for abuse reports; only shown to admins
+    #[serde(default)]
+    pub source_ip: Option<String>,
+    #[serde(default)]
+    pub user_agent: Option<String>,
 }
 
 impl Site {
@@ -40,8 +84,149 @@ impl Site {
             domain: None,
             description,
             created_at: Utc::now(),
+            content_hash: None,
+            root_dir: None,
+            redirects: Vec::new(),
+            password_hash: None,
+            spa_mode: false,
+            fingerprint_assets: false,
+            auto_sitemap: false,
+            custom_headers: Vec::new(),
+            source_ip: None,
+            user_agent: None,
         }
     }
+
+    /// `AuthorizationFailed` unless `user_id` owns this version
+    pub fn ensure_owned_by(&self, user_id: Uuid) -> Result<(), AppError> {
+        if self.owner_id == user_id {
+            Ok(())
+        } else {
+            Err(AppError::AuthorizationFailed)
+        }
+    }
+}
+
+/// `_redirects` 中的一条规则；`from` 为相对站点根目录的路径
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RedirectRule {
+    pub from: String,
+    /// Site-relative path (starting with `/`) or absolute http(s) URL
+    pub to: String,
+    /// 301 or 302
+    pub status: u16,
+}
+
+/// 审计日志条目（只追加）
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AuditEvent {
+    pub id: Uuid,
+    pub timestamp: DateTime<Utc>,
+    pub action: String,
+    pub actor_id: Option<Uuid>,
+    pub target: Option<String>,
+}
+
+impl AuditEvent {
+    pub fn new(action: AuditAction, actor_id: Option<Uuid>, target: Option<String>) -> Self {
+        Self {
+            id: Uuid::new_v4(),
+            timestamp: Utc::now(),
+            action: action.as_str().to_string(),
+            actor_id,
+            target,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AuditAction {
+    LoginSuccess,
+    LoginFailure,
+    Register,
+    SiteUpload,
+    SiteDelete,
+    SiteRename,
+    AdminAccess,
+    PasswordResetRequest,
+    PasswordReset,
+}
+
+impl AuditAction {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AuditAction::LoginSuccess => "login_success",
+            AuditAction::LoginFailure => "login_failure",
+            AuditAction::Register => "register",
+            AuditAction::SiteUpload => "site_upload",
+            AuditAction::SiteDelete => "site_delete",
+            AuditAction::SiteRename => "site_rename",
+            AuditAction::AdminAccess => "admin_access",
+            AuditAction::PasswordResetRequest => "password_reset_request",
+            AuditAction::PasswordReset => "password_reset",
+        }
+    }
+}
+
+/// 注册邀请码：可使用 `max_uses` 次，过期或用完后失效
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Invite {
+    pub code: String,
+    pub created_at: DateTime<Utc>,
+    pub expires_at: Option<DateTime<Utc>>,
+    pub max_uses: u32,
+    pub uses: u32,
+}
+
+impl Invite {
+    pub fn new(max_uses: u32, expires_at: Option<DateTime<Utc>>) -> Self {
+        Self {
+            code: Uuid::new_v4().simple().to_string(),
+            created_at: Utc::now(),
+            expires_at,
+            max_uses,
+            uses: 0,
+        }
+    }
+
+    pub fn is_usable(&self, now: DateTime<Utc>) -> bool {
+        self.uses < self.max_uses && self.expires_at.is_none_or(|t| now < t)
+    }
+}
+
+/// 密码重置令牌：只保存令牌的 SHA-256，单次有效，过期后失效
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PasswordReset {
+    pub token_hash: String,
+    pub user_id: Uuid,
+    pub created_at: DateTime<Utc>,
+    pub expires_at: DateTime<Utc>,
+    pub used: bool,
+}
+
+impl PasswordReset {
+    /// Mint a token for `user_id`; returns the record to store and the plain token to hand out
+    pub fn new(user_id: Uuid, ttl: chrono::Duration) -> (Self, String) {
+        let token = format!("{}{}", Uuid::new_v4().simple(), Uuid::new_v4().simple());
+        let created_at = Utc::now();
+        let reset = Self {
+            token_hash: Self::hash_token(&token),
+            user_id,
+            created_at,
+            expires_at: created_at + ttl,
+            used: false,
+        };
+        (reset, token)
+    }
+
+    pub fn hash_token(token: &str) -> String {
+        use sha2::{Digest, Sha256};
+        Sha256::digest(token.as_bytes()).iter().map(|b| format!("{:02x}", b)).collect()
+    }
+
+    pub fn is_usable(&self, now: DateTime<Utc>) -> bool {
+        !self.used && now < self.expires_at
+    }
 }
 
 // API 请求/响应模型
@@ -49,6 +234,11 @@ impl Site {
 pub struct RegisterRequest {
     pub username: String,
     pub password: String,
+    /// 仅在 `auth.require_invite` 开启时要求
+    #[serde(default)]
+    pub invite_code: Option<String>,
+    #[serde(default)]
+    pub email: Option<String>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -59,15 +249,44 @@ pub struct LoginRequest {
 
 #[derive(Debug, Serialize)]
 pub struct LoginResponse {
-    pub token: String,
+    /// Omitted when the token is delivered as a cookie (`server.auth_cookie`)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub token: Option<String>,
     pub user: UserResponse,
 }
 
+/// `POST /auth/password-reset/request`：按用户名或邮箱查找账户
+#[derive(Debug, Default, Deserialize)]
+pub struct PasswordResetRequest {
+    #[serde(default)]
+    pub username: Option<String>,
+    #[serde(default)]
+    pub email: Option<String>,
+}
+
+/// 无论账户是否存在都返回同样的内容
+#[derive(Debug, Serialize)]
+pub struct PasswordResetRequestResponse {
+    pub message: String,
+    /// Only with `auth.expose_reset_token` (no mail delivery yet), and only when an account matched
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub token: Option<String>,
+}
+
+/// `POST /auth/password-reset/confirm`
+#[derive(Debug, Deserialize)]
+pub struct PasswordResetConfirm {
+    pub token: String,
+    pub new_password: String,
+}
+
 #[derive(Debug, Serialize)]
 pub struct UserResponse {
     pub id: Uuid,
     pub username: String,
     pub created_at: DateTime<Utc>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub email: Option<String>,
 }
 
 impl From<User> for UserResponse {
@@ -76,13 +295,118 @@ impl From<User> for UserResponse {
             id: user.id,
             username: user.username,
             created_at: user.created_at,
+            email: user.email,
         }
     }
 }
 
+/// `/auth/me` 响应：用户信息 + 当前 token 的过期信息
+#[derive(Debug, Serialize)]
+pub struct MeResponse {
+    #[serde(flatten)]
+    pub user: UserResponse,
+    pub exp: usize,
+    pub seconds_remaining: i64,
+}
+
+/// `POST /api/uploads` 请求：开始一次分片上传
+#[derive(Debug, Deserialize)]
+pub struct StartUploadRequest {
+    /// Archive file name; its extension selects the archive format
+    pub filename: String,
+}
+
+/// 分片上传状态：`offset` 为已接收的字节数，即下一个分片的起始位置
+#[derive(Debug, Serialize)]
+pub struct UploadStatusResponse {
+    pub upload_id: Uuid,
+    pub offset: u64,
+}
+
+/// `POST /api/uploads/{id}/complete` 请求，字段含义与 multipart 上传相同
+#[derive(Debug, Deserialize)]
+pub struct CompleteUploadRequest {
+    pub uuid: Uuid,
+    #[serde(rename = "siteName")]
+    pub site_name: String,
+    #[serde(default)]
+    pub mode: Option<String>,
+    #[serde(default, rename = "rootDir")]
+    pub root_dir: Option<String>,
+    #[serde(default, rename = "spaMode")]
+    pub spa_mode: Option<bool>,
+    #[serde(default, rename = "fingerprintAssets")]
+    pub fingerprint_assets: Option<bool>,
+    #[serde(default, rename = "autoSitemap")]
+    pub auto_sitemap: Option<bool>,
+}
+
+/// `PUT /api/sites/{id}/password`：空值或 null 表示取消密码保护
+#[derive(Debug, Deserialize)]
+pub struct SetSitePasswordRequest {
+    #[serde(default)]
+    pub password: Option<String>,
+}
+
 #[derive(Debug, Deserialize)]
 pub struct UpdateSiteRequest {
     pub description: String,
+    /// Omitted: leave SPA fallback as it is
+    #[serde(default, rename = "spaMode")]
+    pub spa_mode: Option<bool>,
+}
+
+/// `POST /api/sites/{id}/rename`
+#[derive(Debug, Deserialize)]
+pub struct RenameSiteRequest {
+    pub new_name: String,
+}
+
+/// `PATCH /api/sites/{id}`：只修改出现的字段
+#[derive(Debug, Default, Deserialize)]
+pub struct PatchSiteRequest {
+    #[serde(default)]
+    pub description: Option<String>,
+    #[serde(default, rename = "spaMode")]
+    pub spa_mode: Option<bool>,
+    #[serde(default, rename = "autoSitemap")]
+    pub auto_sitemap: Option<bool>,
+    /// Replaces the whole list; `[]` removes every custom header
+    #[serde(default, rename = "customHeaders")]
+    pub custom_headers: Option<Vec<(String, String)>>,
+}
+
+/// Timings and sizes of one archive extraction, for telling CPU-bound (decompression)
+/// from IO-bound (writes) uploads apart
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ExtractionMetrics {
+    /// Validating the archive and extracting the original (UUID) copy
+    pub decompress_us: u64,
+    /// Extracting the siteName copy with `/sites/{uuid}/` replaced
+    pub replace_us: u64,
+    /// Size of the uploaded (compressed) archive
+    pub archive_bytes: u64,
+    /// Bytes and files written to the UUID directory
+    pub extracted_bytes: u64,
+    pub extracted_files: u64,
+}
+
+/// `POST /api/sites/stats`
+#[derive(Debug, Deserialize)]
+pub struct BulkSiteStatsRequest {
+    pub ids: Vec<Uuid>,
+}
+
+/// One version's entry in the `POST /api/sites/stats` response
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct SiteStats {
+    /// Bytes and files in this version's UUID directory
+    pub size_bytes: u64,
+    pub file_count: u64,
+    /// Versions published under this version's name
+    pub version_count: usize,
+    /// When the newest version of the name was published
+    pub last_modified: DateTime<Utc>,
 }
 
 #[derive(Debug, Serialize, Clone)]
@@ -96,25 +420,112 @@ pub struct SiteResponse {
     pub url: String,
     /// URL using site UUID (alternative access path)
     pub url_by_id: String,
+    /// Upload only: whether the siteName copy of index.html no longer references the UUID path
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub replacement_verified: Option<bool>,
+    /// `?with_stats=true` only: number of stored versions for this name
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub version_count: Option<usize>,
+    /// `?with_stats=true` only: bytes on disk across all versions plus the siteName directory
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub total_bytes: Option<u64>,
+    /// Upload only: archive entries that were skipped instead of extracted verbatim
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub warnings: Vec<String>,
+    /// `?include_owner=true` only: username of the site's owner
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub owner_username: Option<String>,
+    /// Upload only: the archive matched the latest version, so no new version was created
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub deduplicated: Option<bool>,
+    /// Upload only, with `server.upload_metrics`: how long extraction took
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub metrics: Option<ExtractionMetrics>,
+    /// Whether `/sites` asks visitors for the share password
+    pub password_protected: bool,
+    /// Whether unknown extensionless paths fall back to `index.html`
+    pub spa_mode: bool,
+    /// Whether asset links in the served HTML carry a `?v=` content hash
+    pub fingerprint_assets: bool,
+    /// Whether a missing `sitemap.xml` is generated from the site's pages
+    pub auto_sitemap: bool,
+    /// Extra response headers sent with the site's files
+    pub custom_headers: Vec<(String, String)>,
 }
 
 impl SiteResponse {
-    pub fn from_site(site: Site, base_url: &str) -> Self {
+    pub fn from_site(site: Site, base_url: &str, style: SiteUrlStyle) -> Self {
         Self {
             id: site.id,
             name: site.name.clone(),
             domain: site.domain,
             description: site.description,
             created_at: site.created_at,
-            url: format!("{}/sites/{}/", base_url, site.name),
-            url_by_id: format!("{}/sites/{}/", base_url, site.id),
+            url: style.site_url(base_url, &site.name),
+            url_by_id: style.site_url(base_url, &site.id.to_string()),
+            replacement_verified: None,
+            version_count: None,
+            total_bytes: None,
+            warnings: Vec::new(),
+            owner_username: None,
+            deduplicated: None,
+            metrics: None,
+            password_protected: site.password_hash.is_some(),
+            spa_mode: site.spa_mode,
+            fingerprint_assets: site.fingerprint_assets,
+            auto_sitemap: site.auto_sitemap,
+            custom_headers: site.custom_headers,
         }
     }
 }
 
+#[derive(Debug, Default, Deserialize)]
+pub struct IntrospectRequest {
+    pub token: Option<String>,
+}
+
+/// Token 自省结果；无效或过期的 token 只返回 `{ "active": false }`
+#[derive(Debug, Serialize)]
+pub struct IntrospectResponse {
+    pub active: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sub: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub username: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub exp: Option<usize>,
+    /// Remaining validity in seconds
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub expires_in: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub roles: Option<Vec<String>>,
+}
+
+impl IntrospectResponse {
+    pub fn inactive() -> Self {
+        Self { active: false, sub: None, username: None, exp: None, expires_in: None, roles: None }
+    }
+}
+
+/// `GET /api/sites/resolve?name=` 响应
+#[derive(Debug, Serialize)]
+pub struct ResolveSiteResponse {
+    pub name: String,
+    /// UUID of the newest version
+    pub latest: Uuid,
+    /// All version UUIDs, newest first
+    pub versions: Vec<Uuid>,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Claims {
     pub sub: String, // user_id
     pub username: String,
     pub exp: usize,
+    /// Roles stored on the user at login; absent in older tokens
+    #[serde(default)]
+    pub roles: Vec<String>,
+    /// `auth.custom_claims` from the config, passed through unchanged
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub custom: Option<serde_json::Value>,
 }
\ No newline at end of file
@@ -0,0 +1,190 @@
+use crate::{
+    auth::{auth_middleware, optional_auth_middleware, AuthService, TokenService},
+    config::Config,
+    error,
+    handlers::{auth as auth_handlers, health as health_handlers, sites as site_handlers, uploads as upload_handlers, users as user_handlers, admin as admin_handlers},
+    storage::Storage,
+    utils::request_id::{request_id, RequestId},
+};
+use axum::{
+    extract::{DefaultBodyLimit, Request},
+    http::{Extensions, HeaderMap, StatusCode, Version},
+    middleware,
+    routing::{delete, get, get_service, patch, post, put},
+    Router,
+};
+use std::sync::Arc;
+use tower::ServiceBuilder;
+use tower_http::{compression::{predicate::{NotForContentType, Predicate, SizeAbove}, CompressionLayer}, cors::CorsLayer, decompression::RequestDecompressionLayer, limit::RequestBodyLimitLayer, services::{ServeDir, ServeFile}, trace::TraceLayer};
+
+/// JSON bodies smaller than this are sent uncompressed
+pub const API_COMPRESSION_MIN_BYTES: u16 = 1024;
+
+/// gzip for API responses when the client sends `Accept-Encoding: gzip`; only successful
+/// bodies of at least `API_COMPRESSION_MIN_BYTES` (streamed ones always qualify)
+fn api_compression() -> CompressionLayer<impl Predicate> {
+    let predicate = SizeAbove::new(API_COMPRESSION_MIN_BYTES)
+        .and(NotForContentType::IMAGES)
+        .and(NotForContentType::SSE)
+        .and(|status: StatusCode, _: Version, _: &HeaderMap, _: &Extensions| status.is_success());
+    CompressionLayer::new().gzip(true).compress_when(predicate)
+}
+
+/// 组装完整的应用路由（main 与集成测试共用）
+pub fn build(storage: Arc<Storage>, config: Arc<Config>) -> Router {
+    // 初始化服务
+    let token_service = Arc::new(TokenService::new(
+        config.server.jwt_secret.clone(),
+        config.auth.token_expiration_hours,
+    )
+    .with_custom_claims(config.auth.custom_claims.clone())
+    .with_leeway(config.server.jwt_leeway_secs));
+    let mut auth_service = AuthService::new(
+        storage.users.clone(),
+        storage.audit.clone(),
+        (*token_service).clone(),
+        config.auth.allow_plaintext_password,
+    )
+    .with_auth_cookie(config.server.auth_cookie)
+    .with_secure_cookie(config.server.cookie_secure());
+    if config.auth.require_invite {
+        auth_service = auth_service.with_required_invites(storage.invites.clone());
+    }
+    auth_service = auth_service.with_password_resets(
+        storage.password_resets.clone(),
+        config.auth.password_reset_ttl_minutes,
+        config.auth.expose_reset_token,
+    );
+    let auth_service = Arc::new(auth_service);
+
+    // 私有部署：站点列表需要登录，管理接口只认 admin 角色（不接受 ?key=）
+    let require_auth_for_listing = config.server.require_auth_for_listing;
+
+    // 管理接口：?key=<jwt_secret> 或持有 admin 角色的登录用户；私有部署时挂在需要认证的路由上
+    let admin_routes = Router::new()
+        .route("/api/admin/all", get(admin_handlers::admin_all))
+        .route("/api/admin/sites", get(admin_handlers::admin_sites))
+        .route("/api/admin/storage", get(admin_handlers::admin_storage))
+        .route("/api/admin/report", get(admin_handlers::admin_report))
+        .route("/api/admin/audit", get(admin_handlers::admin_audit))
+        .route("/api/admin/export", get(admin_handlers::admin_export))
+        .route("/api/admin/invites", post(admin_handlers::admin_create_invite))
+        .route("/api/admin/prune-temp", post(admin_handlers::admin_prune_temp));
+
+    // 公开路由（不需要认证）；列表接口带了 token 时校验（include_owner / mine 需要登录）
+    let optional_auth = middleware::from_fn_with_state(token_service.clone(), optional_auth_middleware);
+    let mut listing_routes = Router::new()
+        .route("/api/sites/names", get(site_handlers::list_names));
+    if !require_auth_for_listing {
+        listing_routes = listing_routes.route("/api/sites", get(site_handlers::list_all));
+    }
+    let mut listing_routes = listing_routes.route_layer(optional_auth.clone());
+    if !require_auth_for_listing {
+        listing_routes = listing_routes.merge(admin_routes.clone().route_layer(optional_auth));
+    }
+    let public_routes = listing_routes
+        .route("/ready", get(health_handlers::ready))
+        .with_state((storage.clone(), config.clone()))
+        .route("/auth/register", post(auth_handlers::register))
+        .route("/auth/login", post(auth_handlers::login))
+        .route("/auth/password-reset/request", post(auth_handlers::request_password_reset))
+        .route("/auth/password-reset/confirm", post(auth_handlers::confirm_password_reset))
+        .with_state(auth_service.clone())
+        .route("/auth/introspect", post(auth_handlers::introspect))
+        .with_state(token_service.clone())
+        .layer(RequestDecompressionLayer::new())
+        .layer(api_compression());
+
+    // 需要认证的路由
+    let mut protected_routes = Router::new()
+        .route("/auth/me", get(auth_handlers::me))
+        .with_state(auth_service.clone())
+        .route("/api/sites/{id}", put(site_handlers::update_site))
+        .route("/api/sites/{id}", patch(site_handlers::patch_site))
+        .route("/api/sites/{id}", delete(site_handlers::delete_site))
+        .route("/api/sites/{id}/password", put(site_handlers::set_site_password))
+        .route("/api/sites/{id}/rebuild", post(site_handlers::rebuild_site))
+        .route("/api/sites/{id}/rename", post(site_handlers::rename_site))
+        .route("/api/sites/by-name/{name}", delete(site_handlers::delete_sites_by_name))
+        .route("/api/sites/mine", delete(site_handlers::delete_my_sites))
+        .route("/api/sites/resolve", get(site_handlers::resolve_site_name))
+        .route("/api/sites/stats", post(site_handlers::bulk_site_stats))
+        .route("/user/stats", get(user_handlers::get_user_stats));
+    if require_auth_for_listing {
+        protected_routes = protected_routes
+            .merge(admin_routes)
+            .route("/api/sites", get(site_handlers::list_own));
+    }
+    let protected_routes = protected_routes
+        .with_state((storage.clone(), config.clone()))
+        .route("/user/profile", get(user_handlers::get_user_profile))
+        .route("/user/profile", put(user_handlers::update_user_profile))
+        .route("/user/account", delete(user_handlers::delete_user_account))
+        .with_state(storage.clone())
+        .layer(RequestDecompressionLayer::new())
+        .layer(api_compression());
+
+    // 上传路由单独存放：multipart 流式写盘，不经过请求体解压
+    let upload_routes = Router::new()
+        .route("/api/sites", post(site_handlers::upload_site))
+        .route("/api/uploads", post(upload_handlers::start_upload))
+        .route("/api/uploads/{id}", get(upload_handlers::upload_status).put(upload_handlers::upload_chunk))
+        .route("/api/uploads/{id}/complete", post(upload_handlers::complete_upload))
+        .with_state((storage.clone(), config.clone()));
+
+    let auth_middleware_layer =
+        middleware::from_fn_with_state(
+            token_service.clone(),
+            auth_middleware,
+        );
+
+    // 站点静态文件；先检查分享密码并应用各站点的 _redirects 规则
+    let site_files = ServiceBuilder::new()
+        .layer(middleware::from_fn_with_state((storage.clone(), config.clone()), site_handlers::guard_site_files))
+        .service(ServeDir::new(storage.sites.get_site_files_path_str("")));
+
+    // Web UI
+    let static_service = if let Some(root) = config.server.static_root.clone() {
+        get_service(
+            ServeDir::new(root.clone())
+                .fallback(ServeFile::new(root.join("index.html")))
+        )
+    } else {
+        get(|| async { StatusCode::NOT_FOUND })
+    };
+
+    let app = Router::new()
+        .merge(protected_routes)
+        .merge(upload_routes)
+        .route_layer(auth_middleware_layer)
+        .merge(public_routes)
+        .nest_service("/sites", site_files)
+        .fallback_service(static_service);
+
+    // `Router::layer` wraps each route on its own, inside the point where axum adds `Allow`
+    // to a 405; serving `app` as a fallback puts these layers around the whole router
+    Router::new()
+        .fallback_service(app)
+        .layer(middleware::map_response(error::method_not_allowed_json))
+        .layer(middleware::map_response_with_state(config.clone(), error::custom_error_messages))
+        .layer(CorsLayer::permissive())
+        .layer(TraceLayer::new_for_http().make_span_with(request_span))
+        .layer(DefaultBodyLimit::disable())
+        .layer(RequestBodyLimitLayer::new(
+            usize::try_from(config.server.max_body_bytes).unwrap_or(usize::MAX),
+        ))
+        .layer(middleware::map_response_with_state(config.clone(), error::payload_too_large_json))
+        .layer(middleware::from_fn(request_id))
+}
+
+/// `TraceLayer`'s default span plus the id set by `request_id`
+fn request_span(request: &Request) -> tracing::Span {
+    let id = request.extensions().get::<RequestId>().map_or("-", |id| id.0.as_str());
+    tracing::debug_span!(
+        "request",
+        method = %request.method(),
+        uri = %request.uri(),
+        version = ?request.version(),
+        request_id = %id,
+    )
+}
@@ -1,5 +1,5 @@
 use crate::error::AppError;
-use crate::models::{User, Site};
+use crate::models::{AuditEvent, Invite, PasswordReset, User, Site};
 use uuid::Uuid;
 use tracing::warn;
 
@@ -8,12 +8,35 @@ use tracing::warn;
 pub struct UserStorage {
     sled: crate::storage::sled::UserStorage,
     orm: crate::storage::orm::UserStorage,
+    concurrent_writes: bool,
 }
 
 #[derive(Clone)]
 pub struct SiteStorage {
     sled: crate::storage::sled::SiteStorage,
     orm: crate::storage::orm::SiteStorage,
+    concurrent_writes: bool,
+}
+
+#[derive(Clone)]
+pub struct AuditStorage {
+    sled: crate::storage::sled::AuditStorage,
+    orm: crate::storage::orm::AuditStorage,
+    concurrent_writes: bool,
+}
+
+#[derive(Clone)]
+pub struct InviteStorage {
+    sled: crate::storage::sled::InviteStorage,
+    orm: crate::storage::orm::InviteStorage,
+    concurrent_writes: bool,
+}
+
+#[derive(Clone)]
+pub struct PasswordResetStorage {
+    sled: crate::storage::sled::PasswordResetStorage,
+    orm: crate::storage::orm::PasswordResetStorage,
+    concurrent_writes: bool,
 }
 
 macro_rules! read_compare {
@@ -55,11 +78,32 @@ macro_rules! read_list_compare {
     };
 }
 
+// Writes go to sled then orm, or to both at once with `concurrent_writes`; either way both
+// results are awaited before they're compared
+macro_rules! on_both {
+    ($self:ident.$name:ident($($arg:expr),*)) => {
+        if $self.concurrent_writes {
+            tokio::join!($self.sled.$name($($arg),*), $self.orm.$name($($arg),*))
+        } else {
+            ($self.sled.$name($($arg),*).await, $self.orm.$name($($arg),*).await)
+        }
+    };
+}
+
+macro_rules! with_concurrent_writes {
+    () => {
+        /// Issue writes to both backends concurrently (`storage.debug_concurrent_writes`)
+        pub fn with_concurrent_writes(mut self, concurrent_writes: bool) -> Self {
+            self.concurrent_writes = concurrent_writes;
+            self
+        }
+    };
+}
+
 macro_rules! write_both {
     ($vis:vis fn $name:ident(&self $(, $arg:ident : $argty:ty)*) -> Result<(), AppError>) => {
         $vis async fn $name(&self $(, $arg : $argty)*) -> Result<(), AppError> {
-            let res_sled = self.sled.$name($($arg.clone()),*).await;
-            let res_orm = self.orm.$name($($arg.clone()),*).await;
+            let (res_sled, res_orm) = on_both!(self.$name($($arg.clone()),*));
             if res_sled.is_err() || res_orm.is_err() {
                 warn!(concat!(stringify!($name), " mismatch: sled={:?} orm={:?}"), res_sled, res_orm);
             }
@@ -68,16 +112,47 @@ macro_rules! write_both {
     };
 }
 
+/// Check that a record just created on both backends reads back identically from each, so an
+/// id, timestamp or field that one backend dropped or regenerated fails the create that caused it
+fn verify_created<T: serde::Serialize + std::fmt::Debug>(what: &str, sled: Option<T>, orm: Option<T>) -> Result<(), AppError> {
+    let (Some(a), Some(b)) = (&sled, &orm) else {
+        warn!("{} missing after create: sled={:?} orm={:?}", what, sled, orm);
+        return Err(AppError::Database(format!("{} missing from a backend after create", what)));
+    };
+    let sa = serde_json::to_string(a)?;
+    let sb = serde_json::to_string(b)?;
+    if sa != sb {
+        warn!("{} diverged after create: sled={}, orm={}", what, sa, sb);
+        return Err(AppError::Database(format!("{} differs between backends after create", what)));
+    }
+    Ok(())
+}
+
 impl UserStorage {
     pub async fn new(sled: crate::storage::sled::UserStorage, orm: crate::storage::orm::UserStorage) -> Result<Self, AppError> {
-        Ok(Self { sled, orm })
+        Ok(Self { sled, orm, concurrent_writes: false })
     }
 
+    with_concurrent_writes!();
+
     // Macros will generate the repetitive wrappers below
     read_compare!{ pub fn get(&self, id: Uuid) -> Result<Option<User>, AppError> }
     read_compare!{ pub fn get_by_username(&self, username: &str) -> Result<Option<User>, AppError> }
+    read_compare!{ pub fn get_by_email(&self, email: &str) -> Result<Option<User>, AppError> }
     read_list_compare!{ pub fn list_all(&self) -> Result<Vec<User>, AppError> }
-    write_both!{ pub fn create(&self, user: User) -> Result<(), AppError> }
+    read_list_compare!{ pub fn list_page(&self, after: Option<Uuid>, limit: usize) -> Result<Vec<User>, AppError> }
+    // create returns the stored user, so it can't use write_both!; as there, sled's failure
+    // is returned when both fail (e.g. a UserAlreadyExists conflict)
+    pub async fn create(&self, user: User) -> Result<User, AppError> {
+        let id = user.id;
+        let (res_sled, res_orm) = on_both!(self.create(user.clone()));
+        if res_sled.is_err() || res_orm.is_err() {
+            warn!("create mismatch: sled={:?} orm={:?}", res_sled, res_orm);
+        }
+        let created = res_sled.and(res_orm)?;
+        verify_created("user", self.sled.get(id).await?, self.orm.get(id).await?)?;
+        Ok(created)
+    }
     write_both!{ pub fn update(&self, user: User) -> Result<(), AppError> }
     write_both!{ pub fn delete(&self, id: Uuid) -> Result<(), AppError> }
     // count is special: compare numbers then return sled's count
@@ -94,15 +169,28 @@ impl UserStorage {
 // Generate SiteStorage methods
 impl SiteStorage {
     pub async fn new(sled: crate::storage::sled::SiteStorage, orm: crate::storage::orm::SiteStorage) -> Result<Self, AppError> {
-        Ok(Self { sled, orm })
+        Ok(Self { sled, orm, concurrent_writes: false })
     }
 
+    with_concurrent_writes!();
+
     read_compare!{ pub fn get(&self, id: Uuid) -> Result<Option<Site>, AppError> }
     read_compare!{ pub fn get_latest_by_name(&self, name: &str) -> Result<Option<Site>, AppError> }
     read_list_compare!{ pub fn get_all_by_name(&self, name: &str) -> Result<Vec<Site>, AppError> }
     read_list_compare!{ pub fn list_all(&self) -> Result<Vec<Site>, AppError> }
+    read_list_compare!{ pub fn list_page(&self, after: Option<Uuid>, limit: usize) -> Result<Vec<Site>, AppError> }
     read_list_compare!{ pub fn list_by_owner(&self, owner_id: Uuid) -> Result<Vec<Site>, AppError> }
-    write_both!{ pub fn create(&self, site: Site) -> Result<(), AppError> }
+    read_list_compare!{ pub fn list_latest_per_name(&self) -> Result<Vec<Site>, AppError> }
+    read_list_compare!{ pub fn list_distinct_names(&self) -> Result<Vec<String>, AppError> }
+    pub async fn create(&self, site: Site) -> Result<(), AppError> {
+        let id = site.id;
+        let (res_sled, res_orm) = on_both!(self.create(site.clone()));
+        if res_sled.is_err() || res_orm.is_err() {
+            warn!("create mismatch: sled={:?} orm={:?}", res_sled, res_orm);
+        }
+        res_sled.and(res_orm)?;
+        verify_created("site", self.sled.get(id).await?, self.orm.get(id).await?)
+    }
     write_both!{ pub fn update(&self, site: Site) -> Result<(), AppError> }
     write_both!{ pub fn delete(&self, id: Uuid) -> Result<(), AppError> }
 
@@ -114,4 +202,74 @@ impl SiteStorage {
     pub fn get_site_files_path_str(&self, site_id: &str) -> std::path::PathBuf {
         self.sled.get_site_files_path_str(site_id)
     }
-}
\ No newline at end of file
+}
+
+impl AuditStorage {
+    pub async fn new(sled: crate::storage::sled::AuditStorage, orm: crate::storage::orm::AuditStorage) -> Result<Self, AppError> {
+        Ok(Self { sled, orm, concurrent_writes: false })
+    }
+
+    with_concurrent_writes!();
+
+    read_list_compare!{ pub fn recent(&self, limit: usize) -> Result<Vec<AuditEvent>, AppError> }
+    write_both!{ pub fn append(&self, event: AuditEvent) -> Result<(), AppError> }
+
+    // Returns sled's count; the orm count should match
+    pub async fn prune_before(&self, cutoff: chrono::DateTime<chrono::Utc>, limit: usize) -> Result<usize, AppError> {
+        let (res_sled, res_orm) = on_both!(self.prune_before(cutoff, limit));
+        match (&res_sled, &res_orm) {
+            (Ok(a), Ok(b)) if a == b => {}
+            _ => warn!("prune_before mismatch: sled={:?} orm={:?}", res_sled, res_orm),
+        }
+        let removed = res_sled?;
+        res_orm?;
+        Ok(removed)
+    }
+}
+
+impl InviteStorage {
+    pub async fn new(sled: crate::storage::sled::InviteStorage, orm: crate::storage::orm::InviteStorage) -> Result<Self, AppError> {
+        Ok(Self { sled, orm, concurrent_writes: false })
+    }
+
+    with_concurrent_writes!();
+
+    read_compare!{ pub fn get(&self, code: &str) -> Result<Option<Invite>, AppError> }
+    write_both!{ pub fn create(&self, invite: Invite) -> Result<(), AppError> }
+    write_both!{ pub fn release(&self, code: &str) -> Result<(), AppError> }
+
+    // Returns sled's answer; orm should agree
+    pub async fn claim(&self, code: &str, now: chrono::DateTime<chrono::Utc>) -> Result<bool, AppError> {
+        let (res_sled, res_orm) = on_both!(self.claim(code, now));
+        match (&res_sled, &res_orm) {
+            (Ok(a), Ok(b)) if a == b => {}
+            _ => warn!("claim mismatch: sled={:?} orm={:?}", res_sled, res_orm),
+        }
+        let claimed = res_sled?;
+        res_orm?;
+        Ok(claimed)
+    }
+}
+
+impl PasswordResetStorage {
+    pub async fn new(sled: crate::storage::sled::PasswordResetStorage, orm: crate::storage::orm::PasswordResetStorage) -> Result<Self, AppError> {
+        Ok(Self { sled, orm, concurrent_writes: false })
+    }
+
+    with_concurrent_writes!();
+
+    read_compare!{ pub fn get(&self, token_hash: &str) -> Result<Option<PasswordReset>, AppError> }
+    write_both!{ pub fn create(&self, reset: PasswordReset) -> Result<(), AppError> }
+
+    // Returns sled's answer; orm should agree on whether the token was spent
+    pub async fn redeem(&self, token_hash: &str, now: chrono::DateTime<chrono::Utc>) -> Result<Option<PasswordReset>, AppError> {
+        let (res_sled, res_orm) = on_both!(self.redeem(token_hash, now));
+        match (&res_sled, &res_orm) {
+            (Ok(a), Ok(b)) if a.is_some() == b.is_some() => {}
+            _ => warn!("redeem mismatch: sled={:?} orm={:?}", res_sled, res_orm),
+        }
+        let reset = res_sled?;
+        res_orm?;
+        Ok(reset)
+    }
+}
@@ -0,0 +1,262 @@
+use crate::config::StorageEntry;
+use crate::error::AppError;
+use crate::models::{AuditEvent, Invite, PasswordReset, Site, User};
+use crate::storage::{RetryPolicy, SiteStore, UserStore};
+use std::path::PathBuf;
+use std::sync::Arc;
+use uuid::Uuid;
+
+// Runtime selection between the compiled-in implementations, so users and sites
+// can live in different backends (see `StorageEntry::role`).
+
+/// Users in whichever `UserStore` the config selected
+#[derive(Clone)]
+pub struct UserStorage(Arc<dyn UserStore>);
+
+/// Sites in whichever `SiteStore` the config selected
+#[derive(Clone)]
+pub struct SiteStorage(Arc<dyn SiteStore>);
+
+#[derive(Clone)]
+pub enum AuditStorage {
+    #[cfg(feature = "sled")]
+    Sled(crate::storage::sled::AuditStorage),
+    #[cfg(feature = "orm")]
+    Orm(crate::storage::orm::AuditStorage),
+    #[cfg(feature = "debug_sled_and_orm")]
+    Debug(crate::storage::debug::AuditStorage),
+    Memory(crate::storage::memory::AuditStorage),
+}
+
+#[derive(Clone)]
+pub enum InviteStorage {
+    #[cfg(feature = "sled")]
+    Sled(crate::storage::sled::InviteStorage),
+    #[cfg(feature = "orm")]
+    Orm(crate::storage::orm::InviteStorage),
+    #[cfg(feature = "debug_sled_and_orm")]
+    Debug(crate::storage::debug::InviteStorage),
+    Memory(crate::storage::memory::InviteStorage),
+}
+
+#[derive(Clone)]
+pub enum PasswordResetStorage {
+    #[cfg(feature = "sled")]
+    Sled(crate::storage::sled::PasswordResetStorage),
+    #[cfg(feature = "orm")]
+    Orm(crate::storage::orm::PasswordResetStorage),
+    #[cfg(feature = "debug_sled_and_orm")]
+    Debug(crate::storage::debug::PasswordResetStorage),
+    Memory(crate::storage::memory::PasswordResetStorage),
+}
+
+macro_rules! forward {
+    ($vis:vis async fn $name:ident(&self $(, $arg:ident : $argty:ty)*) -> $ret:ty) => {
+        $vis async fn $name(&self $(, $arg : $argty)*) -> $ret {
+            match self {
+                #[cfg(feature = "sled")]
+                Self::Sled(s) => s.$name($($arg),*).await,
+                #[cfg(feature = "orm")]
+                Self::Orm(s) => s.$name($($arg),*).await,
+                #[cfg(feature = "debug_sled_and_orm")]
+                Self::Debug(s) => s.$name($($arg),*).await,
+                Self::Memory(s) => s.$name($($arg),*).await,
+            }
+        }
+    };
+    ($vis:vis fn $name:ident(&self $(, $arg:ident : $argty:ty)*) -> $ret:ty) => {
+        $vis fn $name(&self $(, $arg : $argty)*) -> $ret {
+            match self {
+                #[cfg(feature = "sled")]
+                Self::Sled(s) => s.$name($($arg),*),
+                #[cfg(feature = "orm")]
+                Self::Orm(s) => s.$name($($arg),*),
+                #[cfg(feature = "debug_sled_and_orm")]
+                Self::Debug(s) => s.$name($($arg),*),
+                Self::Memory(s) => s.$name($($arg),*),
+            }
+        }
+    };
+}
+
+macro_rules! delegate {
+    ($vis:vis async fn $name:ident(&self $(, $arg:ident : $argty:ty)*) -> $ret:ty) => {
+        $vis async fn $name(&self $(, $arg : $argty)*) -> $ret {
+            self.0.$name($($arg),*).await
+        }
+    };
+    ($vis:vis fn $name:ident(&self $(, $arg:ident : $argty:ty)*) -> $ret:ty) => {
+        $vis fn $name(&self $(, $arg : $argty)*) -> $ret {
+            self.0.$name($($arg),*)
+        }
+    };
+}
+
+macro_rules! backend_name {
+    () => {
+        /// Which implementation this handle dispatches to: "sled", "orm", "debug" or "memory"
+        pub fn backend(&self) -> &'static str {
+            match self {
+                #[cfg(feature = "sled")]
+                Self::Sled(_) => "sled",
+                #[cfg(feature = "orm")]
+                Self::Orm(_) => "orm",
+                #[cfg(feature = "debug_sled_and_orm")]
+                Self::Debug(_) => "debug",
+                Self::Memory(_) => "memory",
+            }
+        }
+    };
+}
+
+fn backend_not_compiled(entry: &StorageEntry) -> AppError {
+    AppError::Config(format!(
+        "storage backend '{}' is not available in this build",
+        entry.backend
+    ))
+}
+
+fn sled_path(entry: &StorageEntry) -> Result<&PathBuf, AppError> {
+    entry.path.as_ref().ok_or_else(|| AppError::Config(format!(
+        "storage backend '{}' requires a 'path' field",
+        entry.backend
+    )))
+}
+
+impl UserStorage {
+    /// Wrap an already opened backend
+    pub fn new(store: impl UserStore + 'static) -> Self {
+        Self(Arc::new(store))
+    }
+
+    /// Open a single backend described by `entry`; `retry` applies to orm backends
+    #[cfg_attr(not(feature = "orm"), allow(unused_variables))]
+    pub async fn open(entry: &StorageEntry, retry: RetryPolicy) -> Result<Self, AppError> {
+        match entry.backend.as_str() {
+            #[cfg(feature = "sled")]
+            "sled" => Ok(Self::new(crate::storage::sled::UserStorage::new(sled_path(entry)?).await?)),
+            #[cfg(feature = "orm")]
+            "sqlite" | "postgres" => Ok(Self::new(crate::storage::orm::UserStorage::new(&crate::storage::get_database_url(entry)).await?.with_retry(retry))),
+            "memory" => Ok(Self::new(crate::storage::memory::UserStorage::new())),
+            _ => Err(backend_not_compiled(entry)),
+        }
+    }
+
+    /// Which implementation this handle dispatches to: "sled", "orm", "debug" or "memory"
+    pub fn backend(&self) -> &'static str {
+        self.0.backend()
+    }
+
+    delegate!{ pub async fn get(&self, id: Uuid) -> Result<Option<User>, AppError> }
+    delegate!{ pub async fn get_by_username(&self, username: &str) -> Result<Option<User>, AppError> }
+    delegate!{ pub async fn get_by_email(&self, email: &str) -> Result<Option<User>, AppError> }
+    delegate!{ pub async fn list_all(&self) -> Result<Vec<User>, AppError> }
+    delegate!{ pub async fn list_page(&self, after: Option<Uuid>, limit: usize) -> Result<Vec<User>, AppError> }
+    delegate!{ pub async fn create(&self, user: User) -> Result<User, AppError> }
+    delegate!{ pub async fn update(&self, user: User) -> Result<(), AppError> }
+    delegate!{ pub async fn delete(&self, id: Uuid) -> Result<(), AppError> }
+    delegate!{ pub async fn count(&self) -> Result<usize, AppError> }
+}
+
+impl SiteStorage {
+    /// Wrap an already opened backend
+    pub fn new(store: impl SiteStore + 'static) -> Self {
+        Self(Arc::new(store))
+    }
+
+    /// Open a single backend described by `entry`; `retry` applies to orm backends
+    #[cfg_attr(not(feature = "orm"), allow(unused_variables))]
+    pub async fn open(entry: &StorageEntry, site_files_path: PathBuf, retry: RetryPolicy) -> Result<Self, AppError> {
+        match entry.backend.as_str() {
+            #[cfg(feature = "sled")]
+            "sled" => Ok(Self::new(crate::storage::sled::SiteStorage::new(sled_path(entry)?, site_files_path).await?)),
+            #[cfg(feature = "orm")]
+            "sqlite" | "postgres" => Ok(Self::new(crate::storage::orm::SiteStorage::new(&crate::storage::get_database_url(entry), site_files_path).await?.with_retry(retry))),
+            "memory" => Ok(Self::new(crate::storage::memory::SiteStorage::new(site_files_path)?)),
+            _ => Err(backend_not_compiled(entry)),
+        }
+    }
+
+    /// Which implementation this handle dispatches to: "sled", "orm", "debug" or "memory"
+    pub fn backend(&self) -> &'static str {
+        self.0.backend()
+    }
+
+    delegate!{ pub async fn get(&self, id: Uuid) -> Result<Option<Site>, AppError> }
+    delegate!{ pub async fn get_latest_by_name(&self, name: &str) -> Result<Option<Site>, AppError> }
+    delegate!{ pub async fn get_all_by_name(&self, name: &str) -> Result<Vec<Site>, AppError> }
+    delegate!{ pub async fn list_all(&self) -> Result<Vec<Site>, AppError> }
+    delegate!{ pub async fn list_page(&self, after: Option<Uuid>, limit: usize) -> Result<Vec<Site>, AppError> }
+    delegate!{ pub async fn list_by_owner(&self, owner_id: Uuid) -> Result<Vec<Site>, AppError> }
+    delegate!{ pub async fn list_latest_per_name(&self) -> Result<Vec<Site>, AppError> }
+    delegate!{ pub async fn list_distinct_names(&self) -> Result<Vec<String>, AppError> }
+    delegate!{ pub async fn create(&self, site: Site) -> Result<(), AppError> }
+    delegate!{ pub async fn update(&self, site: Site) -> Result<(), AppError> }
+    delegate!{ pub async fn delete(&self, id: Uuid) -> Result<(), AppError> }
+    delegate!{ pub fn get_site_files_path(&self, site_id: Uuid) -> PathBuf }
+    delegate!{ pub fn get_site_files_path_str(&self, site_id: &str) -> PathBuf }
+}
+
+impl AuditStorage {
+    /// Open a single backend described by `entry`; `retry` applies to orm backends
+    #[cfg_attr(not(feature = "orm"), allow(unused_variables))]
+    pub async fn open(entry: &StorageEntry, retry: RetryPolicy) -> Result<Self, AppError> {
+        match entry.backend.as_str() {
+            #[cfg(feature = "sled")]
+            "sled" => Ok(Self::Sled(crate::storage::sled::AuditStorage::new(sled_path(entry)?).await?)),
+            #[cfg(feature = "orm")]
+            "sqlite" | "postgres" => Ok(Self::Orm(crate::storage::orm::AuditStorage::new(&crate::storage::get_database_url(entry)).await?.with_retry(retry))),
+            "memory" => Ok(Self::Memory(crate::storage::memory::AuditStorage::new())),
+            _ => Err(backend_not_compiled(entry)),
+        }
+    }
+
+    backend_name!();
+
+    forward!{ pub async fn append(&self, event: AuditEvent) -> Result<(), AppError> }
+    forward!{ pub async fn recent(&self, limit: usize) -> Result<Vec<AuditEvent>, AppError> }
+    forward!{ pub async fn prune_before(&self, cutoff: chrono::DateTime<chrono::Utc>, limit: usize) -> Result<usize, AppError> }
+}
+
+impl InviteStorage {
+    /// Open a single backend described by `entry`; `retry` applies to orm backends
+    #[cfg_attr(not(feature = "orm"), allow(unused_variables))]
+    pub async fn open(entry: &StorageEntry, retry: RetryPolicy) -> Result<Self, AppError> {
+        match entry.backend.as_str() {
+            #[cfg(feature = "sled")]
+            "sled" => Ok(Self::Sled(crate::storage::sled::InviteStorage::new(sled_path(entry)?).await?)),
+            #[cfg(feature = "orm")]
+            "sqlite" | "postgres" => Ok(Self::Orm(crate::storage::orm::InviteStorage::new(&crate::storage::get_database_url(entry)).await?.with_retry(retry))),
+            "memory" => Ok(Self::Memory(crate::storage::memory::InviteStorage::new())),
+            _ => Err(backend_not_compiled(entry)),
+        }
+    }
+
+    backend_name!();
+
+    forward!{ pub async fn get(&self, code: &str) -> Result<Option<Invite>, AppError> }
+    forward!{ pub async fn create(&self, invite: Invite) -> Result<(), AppError> }
+    forward!{ pub async fn claim(&self, code: &str, now: chrono::DateTime<chrono::Utc>) -> Result<bool, AppError> }
+    forward!{ pub async fn release(&self, code: &str) -> Result<(), AppError> }
+}
+
+impl PasswordResetStorage {
+    /// Open a single backend described by `entry`; `retry` applies to orm backends
+    #[cfg_attr(not(feature = "orm"), allow(unused_variables))]
+    pub async fn open(entry: &StorageEntry, retry: RetryPolicy) -> Result<Self, AppError> {
+        match entry.backend.as_str() {
+            #[cfg(feature = "sled")]
+            "sled" => Ok(Self::Sled(crate::storage::sled::PasswordResetStorage::new(sled_path(entry)?).await?)),
+            #[cfg(feature = "orm")]
+            "sqlite" | "postgres" => Ok(Self::Orm(crate::storage::orm::PasswordResetStorage::new(&crate::storage::get_database_url(entry)).await?.with_retry(retry))),
+            "memory" => Ok(Self::Memory(crate::storage::memory::PasswordResetStorage::new())),
+            _ => Err(backend_not_compiled(entry)),
+        }
+    }
+
+    backend_name!();
+
+    forward!{ pub async fn get(&self, token_hash: &str) -> Result<Option<PasswordReset>, AppError> }
+    forward!{ pub async fn create(&self, reset: PasswordReset) -> Result<(), AppError> }
+    forward!{ pub async fn redeem(&self, token_hash: &str, now: chrono::DateTime<chrono::Utc>) -> Result<Option<PasswordReset>, AppError> }
+}
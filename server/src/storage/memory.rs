@@ -0,0 +1,341 @@
+use crate::error::AppError;
+use crate::models::{AuditEvent, Invite, PasswordReset, Site, User};
+use crate::storage::{SiteStore, UserStore};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::path::PathBuf;
+use std::sync::Arc;
+use tokio::sync::RwLock;
+use uuid::Uuid;
+
+// 纯内存实现：进程退出即丢失，用于测试和临时预览部署。
+// 语义与 sled 保持一致（用户名/邮箱唯一索引、按 owner 倒序、同名取最新版本）。
+
+#[derive(Default)]
+struct Users {
+    by_id: HashMap<Uuid, User>,
+    by_username: HashMap<String, Uuid>,
+    by_email: HashMap<String, Uuid>,
+}
+
+#[derive(Clone, Default)]
+pub struct UserStorage {
+    inner: Arc<RwLock<Users>>,
+}
+
+impl UserStorage {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+/// Newest first; equal timestamps fall back to the id so the order is stable
+fn newest_first<T>(items: &mut [T], key: impl Fn(&T) -> (DateTime<Utc>, Uuid)) {
+    items.sort_by_key(|item| std::cmp::Reverse(key(item)));
+}
+
+#[async_trait]
+impl UserStore for UserStorage {
+    fn backend(&self) -> &'static str { "memory" }
+
+    async fn get(&self, id: Uuid) -> Result<Option<User>, AppError> {
+        Ok(self.inner.read().await.by_id.get(&id).cloned())
+    }
+
+    async fn get_by_username(&self, username: &str) -> Result<Option<User>, AppError> {
+        let users = self.inner.read().await;
+        Ok(users.by_username.get(username).and_then(|id| users.by_id.get(id)).cloned())
+    }
+
+    async fn get_by_email(&self, email: &str) -> Result<Option<User>, AppError> {
+        let users = self.inner.read().await;
+        Ok(users.by_email.get(email).and_then(|id| users.by_id.get(id)).cloned())
+    }
+
+    async fn list_all(&self) -> Result<Vec<User>, AppError> {
+        let mut all: Vec<User> = self.inner.read().await.by_id.values().cloned().collect();
+        newest_first(&mut all, |u| (u.created_at, u.id));
+        Ok(all)
+    }
+
+    async fn list_page(&self, after: Option<Uuid>, limit: usize) -> Result<Vec<User>, AppError> {
+        let mut page: Vec<User> = self.inner.read().await.by_id.values()
+            .filter(|u| after.is_none_or(|a| u.id > a))
+            .cloned()
+            .collect();
+        page.sort_by_key(|u| u.id);
+        page.truncate(limit);
+        Ok(page)
+    }
+
+    async fn create(&self, user: User) -> Result<User, AppError> {
+        let mut users = self.inner.write().await;
+        if users.by_username.contains_key(&user.username) {
+            return Err(AppError::UserAlreadyExists);
+        }
+        if let Some(email) = &user.email {
+            if users.by_email.get(email).is_some_and(|owner| *owner != user.id) {
+                return Err(AppError::EmailAlreadyExists);
+            }
+            users.by_email.insert(email.clone(), user.id);
+        }
+        users.by_username.insert(user.username.clone(), user.id);
+        users.by_id.insert(user.id, user.clone());
+        Ok(user)
+    }
+
+    async fn update(&self, user: User) -> Result<(), AppError> {
+        let mut users = self.inner.write().await;
+        let previous_email = users.by_id.get(&user.id).and_then(|u| u.email.clone());
+        if previous_email != user.email {
+            if let Some(email) = &user.email {
+                if users.by_email.get(email).is_some_and(|owner| *owner != user.id) {
+                    return Err(AppError::EmailAlreadyExists);
+                }
+                users.by_email.insert(email.clone(), user.id);
+            }
+            if let Some(old) = previous_email {
+                users.by_email.remove(&old);
+            }
+        }
+        users.by_id.insert(user.id, user);
+        Ok(())
+    }
+
+    async fn delete(&self, id: Uuid) -> Result<(), AppError> {
+        let mut users = self.inner.write().await;
+        if let Some(user) = users.by_id.remove(&id) {
+            users.by_username.remove(&user.username);
+            if let Some(email) = &user.email {
+                users.by_email.remove(email);
+            }
+        }
+        Ok(())
+    }
+
+    async fn count(&self) -> Result<usize, AppError> {
+        Ok(self.inner.read().await.by_id.len())
+    }
+}
+
+#[derive(Clone)]
+pub struct SiteStorage {
+    sites: Arc<RwLock<HashMap<Uuid, Site>>>,
+    site_files_path: PathBuf,
+}
+
+impl SiteStorage {
+    /// Records live in memory; site files still go under `site_static_files_path`
+    pub fn new(site_static_files_path: PathBuf) -> Result<Self, AppError> {
+        std::fs::create_dir_all(&site_static_files_path)?;
+        Ok(Self { sites: Arc::default(), site_files_path: site_static_files_path })
+    }
+}
+
+#[async_trait]
+impl SiteStore for SiteStorage {
+    fn backend(&self) -> &'static str { "memory" }
+
+    async fn get(&self, id: Uuid) -> Result<Option<Site>, AppError> {
+        Ok(self.sites.read().await.get(&id).cloned())
+    }
+
+    async fn get_latest_by_name(&self, name: &str) -> Result<Option<Site>, AppError> {
+        Ok(self.get_all_by_name(name).await?.into_iter().next())
+    }
+
+    async fn get_all_by_name(&self, name: &str) -> Result<Vec<Site>, AppError> {
+        let mut sites: Vec<Site> = self.sites.read().await.values().filter(|s| s.name == name).cloned().collect();
+        newest_first(&mut sites, |s| (s.created_at, s.id));
+        Ok(sites)
+    }
+
+    async fn list_all(&self) -> Result<Vec<Site>, AppError> {
+        let mut sites: Vec<Site> = self.sites.read().await.values().cloned().collect();
+        newest_first(&mut sites, |s| (s.created_at, s.id));
+        Ok(sites)
+    }
+
+    async fn list_page(&self, after: Option<Uuid>, limit: usize) -> Result<Vec<Site>, AppError> {
+        let mut page: Vec<Site> = self.sites.read().await.values()
+            .filter(|s| after.is_none_or(|a| s.id > a))
+            .cloned()
+            .collect();
+        page.sort_by_key(|s| s.id);
+        page.truncate(limit);
+        Ok(page)
+    }
+
+    async fn list_by_owner(&self, owner_id: Uuid) -> Result<Vec<Site>, AppError> {
+        let mut sites: Vec<Site> = self.sites.read().await.values().filter(|s| s.owner_id == owner_id).cloned().collect();
+        newest_first(&mut sites, |s| (s.created_at, s.id));
+        Ok(sites)
+    }
+
+    async fn list_latest_per_name(&self) -> Result<Vec<Site>, AppError> {
+        let mut latest: HashMap<String, Site> = HashMap::new();
+        for site in self.sites.read().await.values() {
+            match latest.get(&site.name) {
+                Some(current) if (current.created_at, current.id) >= (site.created_at, site.id) => {}
+                _ => {
+                    latest.insert(site.name.clone(), site.clone());
+                }
+            }
+        }
+        let mut sites: Vec<Site> = latest.into_values().collect();
+        newest_first(&mut sites, |s| (s.created_at, s.id));
+        Ok(sites)
+    }
+
+    async fn list_distinct_names(&self) -> Result<Vec<String>, AppError> {
+        let names: BTreeSet<String> = self.sites.read().await.values().map(|s| s.name.clone()).collect();
+        Ok(names.into_iter().collect())
+    }
+
+    async fn create(&self, site: Site) -> Result<(), AppError> {
+        let mut sites = self.sites.write().await;
+        if sites.contains_key(&site.id) {
+            return Err(AppError::Database(format!("site {} already exists", site.id)));
+        }
+        sites.insert(site.id, site);
+        Ok(())
+    }
+
+    async fn update(&self, site: Site) -> Result<(), AppError> {
+        self.sites.write().await.insert(site.id, site);
+        Ok(())
+    }
+
+    async fn delete(&self, id: Uuid) -> Result<(), AppError> {
+        let (removed, name_left) = {
+            let mut sites = self.sites.write().await;
+            let removed = sites.remove(&id);
+            let name_left = removed.as_ref().is_some_and(|r| sites.values().any(|s| s.name == r.name));
+            (removed, name_left)
+        };
+
+        let site_dir = self.site_files_path.join(id.to_string());
+        if site_dir.exists() {
+            std::fs::remove_dir_all(site_dir)?;
+        }
+        if let Some(name) = removed.map(|s| s.name).filter(|n| !n.is_empty() && !name_left) {
+            let name_dir = self.site_files_path.join(&name);
+            if name_dir.exists() {
+                std::fs::remove_dir_all(name_dir)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn get_site_files_path(&self, site_id: Uuid) -> PathBuf {
+        self.site_files_path.join(site_id.to_string())
+    }
+
+    fn get_site_files_path_str(&self, site_id: &str) -> PathBuf {
+        self.site_files_path.join(site_id)
+    }
+}
+
+/// 按 (时间, id) 排序，与 sled 的键顺序一致
+type AuditEvents = BTreeMap<(DateTime<Utc>, Uuid), AuditEvent>;
+
+#[derive(Clone, Default)]
+pub struct AuditStorage {
+    events: Arc<RwLock<AuditEvents>>,
+}
+
+impl AuditStorage {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub async fn append(&self, event: AuditEvent) -> Result<(), AppError> {
+        self.events.write().await.insert((event.timestamp, event.id), event);
+        Ok(())
+    }
+
+    /// 最近的 `limit` 条事件，最新在前
+    pub async fn recent(&self, limit: usize) -> Result<Vec<AuditEvent>, AppError> {
+        Ok(self.events.read().await.values().rev().take(limit).cloned().collect())
+    }
+
+    /// 删除最多 `limit` 条早于 `cutoff` 的事件（最旧的先删），返回删除条数
+    pub async fn prune_before(&self, cutoff: DateTime<Utc>, limit: usize) -> Result<usize, AppError> {
+        let mut events = self.events.write().await;
+        let doomed: Vec<_> = events.keys().take_while(|(ts, _)| *ts < cutoff).take(limit).copied().collect();
+        for key in &doomed {
+            events.remove(key);
+        }
+        Ok(doomed.len())
+    }
+}
+
+#[derive(Clone, Default)]
+pub struct InviteStorage {
+    invites: Arc<RwLock<HashMap<String, Invite>>>,
+}
+
+impl InviteStorage {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub async fn create(&self, invite: Invite) -> Result<(), AppError> {
+        self.invites.write().await.insert(invite.code.clone(), invite);
+        Ok(())
+    }
+
+    pub async fn get(&self, code: &str) -> Result<Option<Invite>, AppError> {
+        Ok(self.invites.read().await.get(code).cloned())
+    }
+
+    pub async fn claim(&self, code: &str, now: chrono::DateTime<chrono::Utc>) -> Result<bool, AppError> {
+        let mut invites = self.invites.write().await;
+        match invites.get_mut(code) {
+            Some(invite) if invite.is_usable(now) => {
+                invite.uses += 1;
+                Ok(true)
+            }
+            _ => Ok(false),
+        }
+    }
+
+    pub async fn release(&self, code: &str) -> Result<(), AppError> {
+        if let Some(invite) = self.invites.write().await.get_mut(code) {
+            invite.uses = invite.uses.saturating_sub(1);
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone, Default)]
+pub struct PasswordResetStorage {
+    resets: Arc<RwLock<HashMap<String, PasswordReset>>>,
+}
+
+impl PasswordResetStorage {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub async fn create(&self, reset: PasswordReset) -> Result<(), AppError> {
+        self.resets.write().await.insert(reset.token_hash.clone(), reset);
+        Ok(())
+    }
+
+    pub async fn get(&self, token_hash: &str) -> Result<Option<PasswordReset>, AppError> {
+        Ok(self.resets.read().await.get(token_hash).cloned())
+    }
+
+    pub async fn redeem(&self, token_hash: &str, now: chrono::DateTime<chrono::Utc>) -> Result<Option<PasswordReset>, AppError> {
+        let mut resets = self.resets.write().await;
+        match resets.get_mut(token_hash) {
+            Some(reset) if reset.is_usable(now) => {
+                reset.used = true;
+                Ok(Some(reset.clone()))
+            }
+            _ => Ok(None),
+        }
+    }
+}
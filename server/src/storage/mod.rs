@@ -1,11 +1,24 @@
 #![cfg_attr(debug_assertions, allow(dead_code))]
 
-use crate::config::{StorageConfig, StorageEntry};
+use crate::config::{StorageConfig, StorageEntry, StorageRole};
+use std::path::PathBuf;
+use tokio::sync::Semaphore;
 use anyhow::Result;
 use crate::error::AppError;
 
+/// Audit events deleted per batch by `Storage::prune_audit`
+pub const AUDIT_PRUNE_BATCH: usize = 500;
+
+/// Resumable upload sessions, one directory per upload (see `handlers::uploads`)
+pub const CHUNKED_UPLOADS_DIR: &str = ".chunked_uploads";
+
+/// Chunked upload sessions nothing was written to for this long are removed by
+/// `Storage::cleanup_temp`
+pub const CHUNKED_UPLOAD_TTL: std::time::Duration = std::time::Duration::from_secs(24 * 3600);
+
 // Two implementations live side-by-side. Default feature is `sled` so existing behavior
 // is preserved. When compiled with `--features orm` the ORM implementation will be used.
+// Individual entries in `storage.db` can pin users or sites to a backend via `role`.
 
 #[cfg(feature = "sled")]
 pub mod sled;
@@ -17,22 +30,52 @@ pub mod orm;
 #[cfg(feature = "debug_sled_and_orm")]
 pub mod debug;
 
-#[cfg(all(feature = "sled", not(feature = "debug_sled_and_orm")))]
-pub use sled::*;
+// In-memory backend (`backend: "memory"`), for tests and throwaway deployments
+pub mod memory;
 
-#[cfg(all(feature = "orm", not(feature = "debug_sled_and_orm")))]
-pub use orm::*;
+// Backend-independent users/sites interface
+mod traits;
+pub use traits::{SiteStore, UserStore};
 
-#[cfg(feature = "debug_sled_and_orm")]
-pub use debug::*;
+// Runtime dispatch over the compiled-in implementations
+mod dispatch;
+pub use dispatch::*;
+
+// Retrying transient database errors (`storage.db_retries`)
+pub mod retry;
+pub use retry::RetryPolicy;
+
+mod size_cache;
+pub use size_cache::SizeCache;
+
+mod upload_slots;
+pub use upload_slots::UploadSlots;
 
 pub struct Storage {
     pub users: UserStorage,
     pub sites: SiteStorage,
+    pub audit: AuditStorage,
+    /// Registration invite codes (`auth.require_invite`)
+    pub invites: InviteStorage,
+    /// Hashed password reset tokens
+    pub password_resets: PasswordResetStorage,
+    /// Slots for archive extraction (`storage.max_concurrent_extractions`)
+    pub extractions: Semaphore,
+    /// Per-directory size totals, kept up to date by uploads and deletes
+    pub sizes: SizeCache,
+    /// Uploads in flight per user (`storage.max_concurrent_uploads_per_user`)
+    pub uploads: UploadSlots,
+    /// Permit count of `extractions`, so the temp sweep can hold all of them
+    extraction_slots: u32,
 }
 
 impl Storage {
     pub async fn new(config: &StorageConfig) -> Result<Self> {
+        let conflicts = config.conflicts();
+        if !conflicts.is_empty() {
+            return Err(AppError::Config(format!("unusable storage.db configuration: {}", conflicts.join("; "))).into());
+        }
+
         std::fs::create_dir_all(&config.sites.path)?;
 
         for entry in &config.db {
@@ -42,27 +85,123 @@ impl Storage {
         }
 
         let site_files_path = config.sites.path.clone();
+        let retry = RetryPolicy::from_config(config);
 
-        #[cfg(all(feature = "sled", not(feature = "debug_sled_and_orm")))]
-        {
-            let sled_entry = config.first_db_with_backend(&["sled"])
-                .ok_or_else(|| AppError::Config("Missing 'sled' backend in storage.db config".to_string()))?;
-            let sled_db_path = sled_entry.path.as_ref().unwrap();
-            let sled_users = sled::UserStorage::new(sled_db_path).await?;
-            let sled_sites = sled::SiteStorage::new(sled_db_path, site_files_path.clone()).await?;
-            Ok(Self { users: sled_users, sites: sled_sites })
+        // Entries with an explicit role are routed independently; the audit log, invites and reset tokens follow users.
+        // Anything without a role falls back to the feature-selected default (opened once,
+        // since sled holds an exclusive lock on its directory).
+        let users_entry = config.db_for_role(StorageRole::Users);
+        let sites_entry = config.db_for_role(StorageRole::Sites);
+        let mut default = match (users_entry, sites_entry) {
+            (Some(_), Some(_)) => None,
+            _ => Some(Self::open_default(config, site_files_path.clone(), retry).await?),
+        };
+
+        let (users, audit, invites, password_resets) = match users_entry {
+            Some(entry) => (
+                UserStorage::open(entry, retry).await?,
+                AuditStorage::open(entry, retry).await?,
+                InviteStorage::open(entry, retry).await?,
+                PasswordResetStorage::open(entry, retry).await?,
+            ),
+            None => {
+                let (users, _, audit, invites, password_resets) = default.clone().expect("default storage opened above");
+                (users, audit, invites, password_resets)
+            }
+        };
+        let sites = match sites_entry {
+            Some(entry) => SiteStorage::open(entry, site_files_path.clone(), retry).await?,
+            None => default.take().expect("default storage opened above").1,
+        };
+
+        let extraction_slots = config.max_concurrent_extractions.clamp(1, Semaphore::MAX_PERMITS) as u32;
+        let extractions = Semaphore::new(extraction_slots as usize);
+        let uploads = UploadSlots::new(config.max_concurrent_uploads_per_user);
+
+        Ok(Self { users, sites, audit, invites, password_resets, extractions, sizes: SizeCache::default(), uploads, extraction_slots })
+    }
+
+    /// Delete audit events older than `retention_days` and return how many went. Deletes in
+    /// batches of `AUDIT_PRUNE_BATCH`, yielding between them, so a large backlog doesn't hold
+    /// up other requests.
+    pub async fn prune_audit(&self, retention_days: u64) -> Result<usize, AppError> {
+        let cutoff = chrono::Duration::try_days(retention_days.try_into().unwrap_or(i64::MAX))
+            .and_then(|age| chrono::Utc::now().checked_sub_signed(age))
+            .unwrap_or(chrono::DateTime::<chrono::Utc>::MIN_UTC);
+        let mut total = 0;
+        loop {
+            let removed = self.audit.prune_before(cutoff, AUDIT_PRUNE_BATCH).await?;
+            total += removed;
+            if removed < AUDIT_PRUNE_BATCH {
+                return Ok(total);
+            }
+            tokio::task::yield_now().await;
         }
+    }
 
-        #[cfg(all(feature = "orm", not(feature = "debug_sled_and_orm")))]
-        {
-            let orm_entry = config.first_db_with_backend(&["postgres", "sqlite"])
-                .ok_or_else(|| AppError::Config("Missing ORM-compatible backend (postgres or sqlite) in storage.db config".to_string()))?;
-            let orm_database_url = &get_database_url(orm_entry);
-            let orm_users = orm::UserStorage::new(orm_database_url).await?;
-            let orm_sites = orm::SiteStorage::new(orm_database_url, site_files_path.clone()).await?;
-            Ok(Self { users: orm_users, sites: orm_sites })
+    /// Write and delete a small probe file in the sites root, so a read-only or full disk
+    /// shows up before an upload fails on it
+    pub fn check_writable(&self) -> Result<(), AppError> {
+        let root = self.sites.get_site_files_path_str("");
+        let probe = root.join(format!(".ready_probe_{}", uuid::Uuid::new_v4().simple()));
+        std::fs::write(&probe, b"ok")?;
+        std::fs::remove_file(&probe)?;
+        Ok(())
+    }
+
+    /// Remove leftover upload/extraction/rebuild staging directories from the sites root and
+    /// return their names, sorted. Resumable uploads (`.chunked_uploads`) are kept unless they
+    /// outlived `CHUNKED_UPLOAD_TTL`. Waits for every extraction slot so no extraction or
+    /// rebuild is staging meanwhile, and keeps the shared `.upload_temp` and every upload
+    /// session while any upload is in flight.
+    pub async fn cleanup_temp(&self) -> Result<Vec<String>, AppError> {
+        let _permits = self.extractions.acquire_many(self.extraction_slots).await
+            .map_err(|e| AppError::Internal(e.to_string()))?;
+        self.uploads.paused(|idle| self.remove_temp_dirs(idle))
+    }
+
+    fn remove_temp_dirs(&self, uploads_idle: bool) -> Result<Vec<String>, AppError> {
+        let root = self.sites.get_site_files_path_str("");
+        let mut removed = Vec::new();
+        let entries = match std::fs::read_dir(&root) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(removed),
+            Err(e) => return Err(e.into()),
+        };
+        for entry in entries {
+            let entry = entry?;
+            let name = entry.file_name().to_string_lossy().into_owned();
+            if !is_temp_dir_name(&name) || !entry.file_type()?.is_dir() {
+                continue;
+            }
+            if name == UPLOAD_TEMP_DIR && !uploads_idle {
+                continue;
+            }
+            match std::fs::remove_dir_all(entry.path()) {
+                Ok(()) => removed.push(name),
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+                Err(e) => return Err(e.into()),
+            }
+        }
+        if uploads_idle {
+            expire_chunked_uploads(&root, &mut removed)?;
         }
+        removed.sort();
+        Ok(removed)
+    }
 
+    /// Feature-selected default: both backends compared (debug), else sled, else orm.
+    /// A `memory` entry without a role takes precedence over all of them.
+    async fn open_default(config: &StorageConfig, site_files_path: PathBuf, retry: RetryPolicy) -> Result<(UserStorage, SiteStorage, AuditStorage, InviteStorage, PasswordResetStorage)> {
+        if let Some(entry) = config.first_db_with_backend(&["memory"]) {
+            return Ok((
+                UserStorage::open(entry, retry).await?,
+                SiteStorage::open(entry, site_files_path, retry).await?,
+                AuditStorage::open(entry, retry).await?,
+                InviteStorage::open(entry, retry).await?,
+                PasswordResetStorage::open(entry, retry).await?,
+            ));
+        }
 
         #[cfg(feature = "debug_sled_and_orm")]
         {
@@ -71,18 +210,101 @@ impl Storage {
             let sled_db_path = sled_entry.path.as_ref().unwrap();
             let sled_users = sled::UserStorage::new(sled_db_path).await?;
             let sled_sites = sled::SiteStorage::new(sled_db_path, site_files_path.clone()).await?;
+            let sled_audit = sled::AuditStorage::new(sled_db_path).await?;
+            let sled_invites = sled::InviteStorage::new(sled_db_path).await?;
+            let sled_resets = sled::PasswordResetStorage::new(sled_db_path).await?;
             let orm_entry = config.first_db_with_backend(&["postgres", "sqlite"])
                 .ok_or_else(|| AppError::Config("Missing ORM-compatible backend (postgres or sqlite) in storage.db config".to_string()))?;
             let orm_database_url = &get_database_url(orm_entry);
-            let orm_users = orm::UserStorage::new(orm_database_url).await?;
-            let orm_sites = orm::SiteStorage::new(orm_database_url, site_files_path.clone()).await?;
+            let orm_users = orm::UserStorage::new(orm_database_url).await?.with_retry(retry);
+            let orm_sites = orm::SiteStorage::new(orm_database_url, site_files_path.clone()).await?.with_retry(retry);
+            let orm_audit = orm::AuditStorage::new(orm_database_url).await?.with_retry(retry);
+            let orm_invites = orm::InviteStorage::new(orm_database_url).await?.with_retry(retry);
+            let orm_resets = orm::PasswordResetStorage::new(orm_database_url).await?.with_retry(retry);
             // Each underlying implementation exposes the same public async constructors.
-            let users = UserStorage::new(sled_users, orm_users).await?;
-            let sites = SiteStorage::new(sled_sites, orm_sites).await?;
-            Ok(Self { users, sites })
+            let concurrent = config.debug_concurrent_writes;
+            let users = debug::UserStorage::new(sled_users, orm_users).await?.with_concurrent_writes(concurrent);
+            let sites = debug::SiteStorage::new(sled_sites, orm_sites).await?.with_concurrent_writes(concurrent);
+            let audit = debug::AuditStorage::new(sled_audit, orm_audit).await?.with_concurrent_writes(concurrent);
+            let invites = debug::InviteStorage::new(sled_invites, orm_invites).await?.with_concurrent_writes(concurrent);
+            let resets = debug::PasswordResetStorage::new(sled_resets, orm_resets).await?.with_concurrent_writes(concurrent);
+            Ok((
+                UserStorage::new(users),
+                SiteStorage::new(sites),
+                AuditStorage::Debug(audit),
+                InviteStorage::Debug(invites),
+                PasswordResetStorage::Debug(resets),
+            ))
+        }
+
+        #[cfg(all(feature = "sled", not(feature = "debug_sled_and_orm")))]
+        {
+            let sled_entry = config.first_db_with_backend(&["sled"])
+                .ok_or_else(|| AppError::Config("Missing 'sled' backend in storage.db config".to_string()))?;
+            let users = UserStorage::open(sled_entry, retry).await?;
+            let sites = SiteStorage::open(sled_entry, site_files_path, retry).await?;
+            let audit = AuditStorage::open(sled_entry, retry).await?;
+            let invites = InviteStorage::open(sled_entry, retry).await?;
+            let resets = PasswordResetStorage::open(sled_entry, retry).await?;
+            Ok((users, sites, audit, invites, resets))
+        }
+
+        #[cfg(all(feature = "orm", not(feature = "sled")))]
+        {
+            let orm_entry = config.first_db_with_backend(&["postgres", "sqlite"])
+                .ok_or_else(|| AppError::Config("Missing ORM-compatible backend (postgres or sqlite) in storage.db config".to_string()))?;
+            let users = UserStorage::open(orm_entry, retry).await?;
+            let sites = SiteStorage::open(orm_entry, site_files_path, retry).await?;
+            let audit = AuditStorage::open(orm_entry, retry).await?;
+            let invites = InviteStorage::open(orm_entry, retry).await?;
+            let resets = PasswordResetStorage::open(orm_entry, retry).await?;
+            Ok((users, sites, audit, invites, resets))
         }
+    }
+}
 
+/// Remove the upload sessions under `root` that outlived `CHUNKED_UPLOAD_TTL`, adding them to
+/// `removed` as `.chunked_uploads/<id>`
+fn expire_chunked_uploads(root: &std::path::Path, removed: &mut Vec<String>) -> Result<(), AppError> {
+    let sessions = match std::fs::read_dir(root.join(CHUNKED_UPLOADS_DIR)) {
+        Ok(sessions) => sessions,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(e.into()),
+    };
+    let now = std::time::SystemTime::now();
+    for entry in sessions {
+        let entry = entry?;
+        if !entry.file_type()?.is_dir() {
+            continue;
+        }
+        let stale = now.duration_since(last_write(&entry.path())?).is_ok_and(|age| age > CHUNKED_UPLOAD_TTL);
+        if !stale {
+            continue;
+        }
+        match std::fs::remove_dir_all(entry.path()) {
+            Ok(()) => removed.push(format!("{}/{}", CHUNKED_UPLOADS_DIR, entry.file_name().to_string_lossy())),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => return Err(e.into()),
+        }
+    }
+    Ok(())
+}
+
+/// Newest modification time of `dir` and the files directly inside it
+fn last_write(dir: &std::path::Path) -> std::io::Result<std::time::SystemTime> {
+    let mut newest = std::fs::metadata(dir)?.modified()?;
+    for entry in std::fs::read_dir(dir)? {
+        newest = newest.max(entry?.metadata()?.modified()?);
     }
+    Ok(newest)
+}
+
+/// Staging directory shared by multipart uploads
+const UPLOAD_TEMP_DIR: &str = ".upload_temp";
+
+/// Staging directories created under the sites root by uploads, extraction and rebuilds
+fn is_temp_dir_name(name: &str) -> bool {
+    name == UPLOAD_TEMP_DIR || name.starts_with(".extract_temp_") || name.starts_with(".rebuild_temp_")
 }
 
 pub fn get_database_url(db_entry: &StorageEntry) -> String {
@@ -0,0 +1,88 @@
+use crate::{error::AppError, models::AuditEvent};
+use chrono::{DateTime, SecondsFormat, Utc};
+use sea_orm::{ColumnTrait, Database, EntityTrait, QueryFilter, Set, ConnectionTrait, QueryOrder, QuerySelect};
+use crate::storage::orm::retry::{with_retry, RetryingConnection};
+use uuid::Uuid;
+use crate::storage::orm::entities::audit_log as audit_entity;
+
+#[derive(Clone)]
+pub struct AuditStorage {
+    conn: RetryingConnection,
+}
+
+impl AuditStorage {
+    with_retry!();
+
+    pub async fn new(database_url: &str) -> Result<Self, AppError> {
+        eprintln!("Connecting to DB; database_url='{}'", database_url);
+        let conn = Database::connect(database_url).await.map_err(|e| AppError::Database(e.to_string()))?;
+
+        // timestamp 以固定纳秒精度的 RFC3339 存储，保证字符串排序与时间顺序一致
+        let sql = r#"CREATE TABLE IF NOT EXISTS audit_log (
+            id TEXT PRIMARY KEY,
+            timestamp TEXT NOT NULL,
+            action TEXT NOT NULL,
+            actor_id TEXT,
+            target TEXT
+        );"#;
+        let backend = if database_url.starts_with("sqlite") {
+            sea_orm::DbBackend::Sqlite
+        } else {
+            sea_orm::DbBackend::Postgres
+        };
+        conn.execute(sea_orm::Statement::from_string(backend, sql.to_owned())).await.map_err(|e| AppError::Database(e.to_string()))?;
+
+        Ok(Self { conn: RetryingConnection::new(conn) })
+    }
+
+    pub async fn append(&self, event: AuditEvent) -> Result<(), AppError> {
+        let am = audit_entity::ActiveModel {
+            id: Set(event.id.to_string()),
+            timestamp: Set(event.timestamp.to_rfc3339_opts(SecondsFormat::Nanos, true)),
+            action: Set(event.action),
+            actor_id: Set(event.actor_id.map(|id| id.to_string())),
+            target: Set(event.target),
+        };
+
+        audit_entity::Entity::insert(am).exec(&self.conn).await.map_err(|e| AppError::Database(e.to_string()))?;
+        Ok(())
+    }
+
+    /// 最近的 `limit` 条事件，按时间倒序（最新在前）
+    pub async fn recent(&self, limit: usize) -> Result<Vec<AuditEvent>, AppError> {
+        let models = audit_entity::Entity::find()
+            .order_by_desc(audit_entity::Column::Timestamp)
+            // SQL 的 LIMIT 是有符号 64 位，`usize::MAX`（“全部”）需截断
+            .limit(limit.min(i64::MAX as usize) as u64)
+            .all(&self.conn).await.map_err(|e| AppError::Database(e.to_string()))?;
+        let mut events = Vec::new();
+        for m in models {
+            let timestamp = chrono::DateTime::parse_from_rfc3339(&m.timestamp)?.with_timezone(&chrono::Utc);
+            let actor_id = match m.actor_id {
+                Some(id) => Some(Uuid::parse_str(&id)?),
+                None => None,
+            };
+            events.push(AuditEvent { id: Uuid::parse_str(&m.id)?, timestamp, action: m.action, actor_id, target: m.target });
+        }
+        Ok(events)
+    }
+
+    /// 删除最多 `limit` 条早于 `cutoff` 的事件（最旧的先删），返回删除条数
+    pub async fn prune_before(&self, cutoff: DateTime<Utc>, limit: usize) -> Result<usize, AppError> {
+        let ids: Vec<String> = audit_entity::Entity::find()
+            .select_only()
+            .column(audit_entity::Column::Id)
+            .filter(audit_entity::Column::Timestamp.lt(cutoff.to_rfc3339_opts(SecondsFormat::Nanos, true)))
+            .order_by_asc(audit_entity::Column::Timestamp)
+            .limit(limit.min(i64::MAX as usize) as u64)
+            .into_tuple()
+            .all(&self.conn).await.map_err(|e| AppError::Database(e.to_string()))?;
+        if ids.is_empty() {
+            return Ok(0);
+        }
+        let res = audit_entity::Entity::delete_many()
+            .filter(audit_entity::Column::Id.is_in(ids))
+            .exec(&self.conn).await.map_err(|e| AppError::Database(e.to_string()))?;
+        Ok(res.rows_affected as usize)
+    }
+}
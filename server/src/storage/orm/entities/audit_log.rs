@@ -0,0 +1,25 @@
+use sea_orm::entity::prelude::*;
+use strum_macros::EnumIter;
+
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel)]
+#[sea_orm(table_name = "audit_log")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    pub id: String,
+    pub timestamp: String,
+    pub action: String,
+    pub actor_id: Option<String>,
+    pub target: Option<String>,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter)]
+pub enum Relation {}
+
+impl RelationTrait for Relation {
+    fn def(&self) -> RelationDef {
+        match *self {}
+    }
+}
+
+#[derive(Copy, Clone, Default, Debug, DeriveActiveModelBehavior)]
+pub struct ActiveModelBehavior;
@@ -0,0 +1,25 @@
+use sea_orm::entity::prelude::*;
+use strum_macros::EnumIter;
+
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel)]
+#[sea_orm(table_name = "invites")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    pub code: String,
+    pub created_at: String,
+    pub expires_at: Option<String>,
+    pub max_uses: i64,
+    pub uses: i64,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter)]
+pub enum Relation {}
+
+impl RelationTrait for Relation {
+    fn def(&self) -> RelationDef {
+        match *self {}
+    }
+}
+
+#[derive(Copy, Clone, Default, Debug, DeriveActiveModelBehavior)]
+pub struct ActiveModelBehavior;
@@ -2,7 +2,13 @@
 pub mod prelude {
     pub use super::users::Entity as Users;
     pub use super::sites::Entity as Sites;
+    pub use super::audit_log::Entity as AuditLog;
+    pub use super::invites::Entity as Invites;
+    pub use super::password_resets::Entity as PasswordResets;
 }
 
 pub mod users;
 pub mod sites;
+pub mod audit_log;
+pub mod invites;
+pub mod password_resets;
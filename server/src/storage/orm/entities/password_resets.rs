@@ -0,0 +1,26 @@
+use sea_orm::entity::prelude::*;
+use strum_macros::EnumIter;
+
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel)]
+#[sea_orm(table_name = "password_resets")]
+pub struct Model {
+    /// SHA-256 of the token; the token itself is never stored
+    #[sea_orm(primary_key)]
+    pub token_hash: String,
+    pub user_id: String,
+    pub created_at: String,
+    pub expires_at: String,
+    pub used: bool,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter)]
+pub enum Relation {}
+
+impl RelationTrait for Relation {
+    fn def(&self) -> RelationDef {
+        match *self {}
+    }
+}
+
+#[derive(Copy, Clone, Default, Debug, DeriveActiveModelBehavior)]
+pub struct ActiveModelBehavior;
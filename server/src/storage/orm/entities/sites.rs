@@ -11,6 +11,16 @@ pub struct Model {
     pub domain: Option<String>,
     pub description: String,
     pub created_at: String,
+    pub content_hash: Option<String>,
+    pub root_dir: Option<String>,
+    pub redirects: Option<String>,
+    pub password_hash: Option<String>,
+    pub spa_mode: bool,
+    pub fingerprint_assets: bool,
+    pub auto_sitemap: bool,
+    pub custom_headers: Option<String>,
+    pub source_ip: Option<String>,
+    pub user_agent: Option<String>,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter)]
@@ -9,6 +9,11 @@ pub struct Model {
     pub username: String,
     pub password: String,
     pub created_at: String,
+    pub last_login: Option<String>,
+    /// JSON array; NULL when the user has no roles
+    pub roles: Option<String>,
+    /// Unique when present
+    pub email: Option<String>,
     // sites field removed: sites are now indexed in `sites` table and queried by owner/date
 }
 
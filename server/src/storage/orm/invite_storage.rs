@@ -0,0 +1,92 @@
+use crate::{error::AppError, models::Invite};
+use sea_orm::{sea_query::Expr, ColumnTrait, Database, EntityTrait, QueryFilter, Set, ConnectionTrait};
+use crate::storage::orm::retry::{with_retry, RetryingConnection};
+use crate::storage::orm::entities::invites as invite_entity;
+
+fn parse_time(raw: &str) -> Result<chrono::DateTime<chrono::Utc>, AppError> {
+    Ok(chrono::DateTime::parse_from_rfc3339(raw)?.with_timezone(&chrono::Utc))
+}
+
+fn to_invite(m: invite_entity::Model) -> Result<Invite, AppError> {
+    Ok(Invite {
+        code: m.code,
+        created_at: parse_time(&m.created_at)?,
+        expires_at: m.expires_at.as_deref().map(parse_time).transpose()?,
+        max_uses: m.max_uses as u32,
+        uses: m.uses as u32,
+    })
+}
+
+#[derive(Clone)]
+pub struct InviteStorage {
+    conn: RetryingConnection,
+}
+
+impl InviteStorage {
+    with_retry!();
+
+    pub async fn new(database_url: &str) -> Result<Self, AppError> {
+        eprintln!("Connecting to DB; database_url='{}'", database_url);
+        let conn = Database::connect(database_url).await.map_err(|e| AppError::Database(e.to_string()))?;
+
+        let sql = r#"CREATE TABLE IF NOT EXISTS invites (
+            code TEXT PRIMARY KEY,
+            created_at TEXT NOT NULL,
+            expires_at TEXT,
+            max_uses BIGINT NOT NULL,
+            uses BIGINT NOT NULL
+        );"#;
+        let backend = if database_url.starts_with("sqlite") {
+            sea_orm::DbBackend::Sqlite
+        } else {
+            sea_orm::DbBackend::Postgres
+        };
+        conn.execute(sea_orm::Statement::from_string(backend, sql.to_owned())).await.map_err(|e| AppError::Database(e.to_string()))?;
+
+        Ok(Self { conn: RetryingConnection::new(conn) })
+    }
+
+    pub async fn create(&self, invite: Invite) -> Result<(), AppError> {
+        let am = invite_entity::ActiveModel {
+            code: Set(invite.code),
+            created_at: Set(invite.created_at.to_rfc3339()),
+            expires_at: Set(invite.expires_at.map(|t| t.to_rfc3339())),
+            max_uses: Set(invite.max_uses as i64),
+            uses: Set(invite.uses as i64),
+        };
+
+        invite_entity::Entity::insert(am).exec(&self.conn).await.map_err(|e| AppError::Database(e.to_string()))?;
+        Ok(())
+    }
+
+    pub async fn get(&self, code: &str) -> Result<Option<Invite>, AppError> {
+        invite_entity::Entity::find_by_id(code.to_string()).one(&self.conn).await.map_err(|e| AppError::Database(e.to_string()))?
+            .map(to_invite)
+            .transpose()
+    }
+
+    /// Take one use of `code` if it is usable at `now`. The increment is a single conditional
+    /// UPDATE, so concurrent registrations can't both spend the last use; false when unknown or used up
+    pub async fn claim(&self, code: &str, now: chrono::DateTime<chrono::Utc>) -> Result<bool, AppError> {
+        match self.get(code).await? {
+            Some(invite) if invite.is_usable(now) => {}
+            _ => return Ok(false),
+        }
+        let res = invite_entity::Entity::update_many()
+            .col_expr(invite_entity::Column::Uses, Expr::col(invite_entity::Column::Uses).add(1))
+            .filter(invite_entity::Column::Code.eq(code))
+            .filter(Expr::col(invite_entity::Column::Uses).lt(Expr::col(invite_entity::Column::MaxUses)))
+            .exec(&self.conn).await.map_err(|e| AppError::Database(e.to_string()))?;
+        Ok(res.rows_affected == 1)
+    }
+
+    /// Give back a use taken by `claim` (the registration it was for failed)
+    pub async fn release(&self, code: &str) -> Result<(), AppError> {
+        invite_entity::Entity::update_many()
+            .col_expr(invite_entity::Column::Uses, Expr::col(invite_entity::Column::Uses).sub(1))
+            .filter(invite_entity::Column::Code.eq(code))
+            .filter(invite_entity::Column::Uses.gt(0))
+            .exec(&self.conn).await.map_err(|e| AppError::Database(e.to_string()))?;
+        Ok(())
+    }
+}
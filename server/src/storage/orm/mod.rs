@@ -1,6 +1,13 @@
 pub mod user_storage;
 pub mod site_storage;
+pub mod audit_storage;
+pub mod invite_storage;
+pub mod password_reset_storage;
 pub mod entities;
+pub mod retry;
 
 pub use user_storage::UserStorage;
 pub use site_storage::SiteStorage;
+pub use audit_storage::AuditStorage;
+pub use invite_storage::InviteStorage;
+pub use password_reset_storage::PasswordResetStorage;
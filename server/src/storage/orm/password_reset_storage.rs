@@ -0,0 +1,87 @@
+use crate::{error::AppError, models::PasswordReset};
+use sea_orm::{sea_query::Expr, ColumnTrait, Database, EntityTrait, QueryFilter, Set, ConnectionTrait};
+use crate::storage::orm::retry::{with_retry, RetryingConnection};
+use uuid::Uuid;
+use crate::storage::orm::entities::password_resets as reset_entity;
+
+fn parse_time(raw: &str) -> Result<chrono::DateTime<chrono::Utc>, AppError> {
+    Ok(chrono::DateTime::parse_from_rfc3339(raw)?.with_timezone(&chrono::Utc))
+}
+
+fn to_reset(m: reset_entity::Model) -> Result<PasswordReset, AppError> {
+    Ok(PasswordReset {
+        token_hash: m.token_hash,
+        user_id: Uuid::parse_str(&m.user_id)?,
+        created_at: parse_time(&m.created_at)?,
+        expires_at: parse_time(&m.expires_at)?,
+        used: m.used,
+    })
+}
+
+#[derive(Clone)]
+pub struct PasswordResetStorage {
+    conn: RetryingConnection,
+}
+
+impl PasswordResetStorage {
+    with_retry!();
+
+    pub async fn new(database_url: &str) -> Result<Self, AppError> {
+        eprintln!("Connecting to DB; database_url='{}'", database_url);
+        let conn = Database::connect(database_url).await.map_err(|e| AppError::Database(e.to_string()))?;
+
+        let sql = r#"CREATE TABLE IF NOT EXISTS password_resets (
+            token_hash TEXT PRIMARY KEY,
+            user_id TEXT NOT NULL,
+            created_at TEXT NOT NULL,
+            expires_at TEXT NOT NULL,
+            used BOOLEAN NOT NULL
+        );"#;
+        let backend = if database_url.starts_with("sqlite") {
+            sea_orm::DbBackend::Sqlite
+        } else {
+            sea_orm::DbBackend::Postgres
+        };
+        conn.execute(sea_orm::Statement::from_string(backend, sql.to_owned())).await.map_err(|e| AppError::Database(e.to_string()))?;
+
+        Ok(Self { conn: RetryingConnection::new(conn) })
+    }
+
+    pub async fn create(&self, reset: PasswordReset) -> Result<(), AppError> {
+        let am = reset_entity::ActiveModel {
+            token_hash: Set(reset.token_hash),
+            user_id: Set(reset.user_id.to_string()),
+            created_at: Set(reset.created_at.to_rfc3339()),
+            expires_at: Set(reset.expires_at.to_rfc3339()),
+            used: Set(reset.used),
+        };
+
+        reset_entity::Entity::insert(am).exec(&self.conn).await.map_err(|e| AppError::Database(e.to_string()))?;
+        Ok(())
+    }
+
+    pub async fn get(&self, token_hash: &str) -> Result<Option<PasswordReset>, AppError> {
+        reset_entity::Entity::find_by_id(token_hash.to_string()).one(&self.conn).await.map_err(|e| AppError::Database(e.to_string()))?
+            .map(to_reset)
+            .transpose()
+    }
+
+    /// Mark the token used if it is usable at `now`, returning it only to the caller that did.
+    /// The flag flips in one `UPDATE ... WHERE used = false`, so two concurrent confirms can't both spend it
+    pub async fn redeem(&self, token_hash: &str, now: chrono::DateTime<chrono::Utc>) -> Result<Option<PasswordReset>, AppError> {
+        let mut reset = match self.get(token_hash).await? {
+            Some(reset) if reset.is_usable(now) => reset,
+            _ => return Ok(None),
+        };
+        let res = reset_entity::Entity::update_many()
+            .col_expr(reset_entity::Column::Used, Expr::value(true))
+            .filter(reset_entity::Column::TokenHash.eq(token_hash))
+            .filter(reset_entity::Column::Used.eq(false))
+            .exec(&self.conn).await.map_err(|e| AppError::Database(e.to_string()))?;
+        if res.rows_affected != 1 {
+            return Ok(None);
+        }
+        reset.used = true;
+        Ok(Some(reset))
+    }
+}
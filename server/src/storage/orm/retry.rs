@@ -0,0 +1,97 @@
+use crate::storage::retry::{retry, RetryPolicy};
+use std::sync::Arc;
+use sea_orm::{ConnectionTrait, DatabaseConnection, DbBackend, DbErr, ExecResult, QueryResult, RuntimeErr, Statement};
+
+/// Database error codes worth another attempt: sqlite busy / locked (plain and extended),
+/// postgres serialization failure, deadlock and "cannot connect now". Postgres class 08
+/// (connection exceptions) is matched by prefix.
+const TRANSIENT_SQL_CODES: &[&str] = &["5", "6", "261", "517", "40001", "40P01", "57P03"];
+
+/// Whether `err` means the database was briefly unreachable or busy, as opposed to the
+/// statement being rejected. Constraint violations and missing rows never count.
+pub fn is_transient(err: &DbErr) -> bool {
+    if err.sql_err().is_some() {
+        return false;
+    }
+    match err {
+        DbErr::ConnectionAcquire(_) | DbErr::Conn(_) => true,
+        DbErr::Exec(RuntimeErr::SqlxError(e)) | DbErr::Query(RuntimeErr::SqlxError(e)) => match e {
+            sqlx::Error::Io(_) | sqlx::Error::PoolTimedOut | sqlx::Error::WorkerCrashed => true,
+            sqlx::Error::Database(db) => db.code().is_some_and(|code| code.starts_with("08") || TRANSIENT_SQL_CODES.contains(&&*code)),
+            _ => false,
+        },
+        _ => false,
+    }
+}
+
+/// Whether `sql` may run again after failing with `err`. Reads may after any transient error;
+/// writes only when no connection could be had, since otherwise the database may already
+/// have applied them and a replayed insert would report a conflict for its own row.
+fn may_retry(sql: &str, err: &DbErr) -> bool {
+    match err {
+        DbErr::ConnectionAcquire(_) => true,
+        _ => is_read(sql) && is_transient(err),
+    }
+}
+
+fn is_read(sql: &str) -> bool {
+    sql.trim_start().get(..6).is_some_and(|keyword| keyword.eq_ignore_ascii_case("select"))
+}
+
+/// `DatabaseConnection` that runs each statement under a `RetryPolicy`, so every query the
+/// orm storages build gets the same treatment (see `may_retry` for what is run again)
+#[derive(Clone)]
+pub struct RetryingConnection {
+    // Arc: `DatabaseConnection` is not `Clone` once sea-orm's `mock` feature is on (tests)
+    inner: Arc<DatabaseConnection>,
+    policy: RetryPolicy,
+}
+
+impl RetryingConnection {
+    pub fn new(inner: DatabaseConnection) -> Self {
+        Self { inner: Arc::new(inner), policy: RetryPolicy::default() }
+    }
+
+    pub fn with_policy(self, policy: RetryPolicy) -> Self {
+        Self { policy, ..self }
+    }
+}
+
+#[async_trait::async_trait]
+impl ConnectionTrait for RetryingConnection {
+    fn get_database_backend(&self) -> DbBackend {
+        self.inner.get_database_backend()
+    }
+
+    async fn execute(&self, stmt: Statement) -> Result<ExecResult, DbErr> {
+        retry(self.policy, |e| may_retry(&stmt.sql, e), || self.inner.execute(stmt.clone())).await
+    }
+
+    async fn execute_unprepared(&self, sql: &str) -> Result<ExecResult, DbErr> {
+        retry(self.policy, |e| may_retry(sql, e), || self.inner.execute_unprepared(sql)).await
+    }
+
+    async fn query_one(&self, stmt: Statement) -> Result<Option<QueryResult>, DbErr> {
+        retry(self.policy, |e| may_retry(&stmt.sql, e), || self.inner.query_one(stmt.clone())).await
+    }
+
+    async fn query_all(&self, stmt: Statement) -> Result<Vec<QueryResult>, DbErr> {
+        retry(self.policy, |e| may_retry(&stmt.sql, e), || self.inner.query_all(stmt.clone())).await
+    }
+
+    fn support_returning(&self) -> bool {
+        self.inner.support_returning()
+    }
+}
+
+/// `with_retry` for a storage holding a `RetryingConnection` in `conn`
+macro_rules! with_retry {
+    () => {
+        /// Retry transient database errors per `policy` (`storage.db_retries`)
+        pub fn with_retry(mut self, policy: crate::storage::retry::RetryPolicy) -> Self {
+            self.conn = self.conn.with_policy(policy);
+            self
+        }
+    };
+}
+pub(crate) use with_retry;
@@ -1,16 +1,36 @@
 use crate::{error::AppError, models::Site};
-use sea_orm::{Database, DatabaseConnection, EntityTrait, Set, ConnectionTrait, QueryFilter, ColumnTrait, QueryOrder};
+use sea_orm::{Database, EntityTrait, Set, ConnectionTrait, QueryFilter, ColumnTrait, QueryOrder, QuerySelect};
+use crate::storage::orm::retry::{with_retry, RetryingConnection};
+use std::collections::HashSet;
 use std::path::PathBuf;
 use uuid::Uuid;
 use crate::storage::orm::entities::sites as sites_entity;
 
+/// 重定向规则以 JSON 文本存储；没有规则时为 NULL
+/// List columns (`redirects`, `custom_headers`) hold JSON; an empty list is stored as NULL
+fn encode_list<T: serde::Serialize>(items: &[T]) -> Result<Option<String>, AppError> {
+    if items.is_empty() {
+        return Ok(None);
+    }
+    Ok(Some(serde_json::to_string(items)?))
+}
+
+fn decode_list<T: serde::de::DeserializeOwned>(raw: Option<&str>) -> Result<Vec<T>, AppError> {
+    match raw {
+        Some(json) => Ok(serde_json::from_str(json)?),
+        None => Ok(Vec::new()),
+    }
+}
+
 #[derive(Clone)]
 pub struct SiteStorage {
-    conn: DatabaseConnection,
+    conn: RetryingConnection,
     site_files_path: PathBuf,
 }
 
 impl SiteStorage {
+    with_retry!();
+
     pub async fn new(database_url: &str, site_static_files_path: PathBuf) -> Result<Self, AppError> {
         eprintln!("Connecting to DB; database_url='{}'", database_url);
         let conn = Database::connect(database_url).await.map_err(|e| AppError::Database(e.to_string()))?;
@@ -23,7 +43,17 @@ impl SiteStorage {
                 name TEXT NOT NULL,
                 domain TEXT,
                 description TEXT NOT NULL,
-                created_at TEXT NOT NULL
+                created_at TEXT NOT NULL,
+                content_hash TEXT,
+                root_dir TEXT,
+                redirects TEXT,
+                password_hash TEXT,
+                spa_mode BOOLEAN NOT NULL DEFAULT FALSE,
+                fingerprint_assets BOOLEAN NOT NULL DEFAULT FALSE,
+                auto_sitemap BOOLEAN NOT NULL DEFAULT FALSE,
+                custom_headers TEXT,
+                source_ip TEXT,
+                user_agent TEXT
             );"#;
             conn.execute(sea_orm::Statement::from_string(sea_orm::DbBackend::Sqlite, sql.to_owned())).await.map_err(|e| AppError::Database(e.to_string()))?;
         } else {
@@ -33,14 +63,41 @@ impl SiteStorage {
                 name TEXT NOT NULL,
                 domain TEXT,
                 description TEXT NOT NULL,
-                created_at TEXT NOT NULL
+                created_at TEXT NOT NULL,
+                content_hash TEXT,
+                root_dir TEXT,
+                redirects TEXT,
+                password_hash TEXT,
+                spa_mode BOOLEAN NOT NULL DEFAULT FALSE,
+                fingerprint_assets BOOLEAN NOT NULL DEFAULT FALSE,
+                auto_sitemap BOOLEAN NOT NULL DEFAULT FALSE,
+                custom_headers TEXT,
+                source_ip TEXT,
+                user_agent TEXT
             );"#;
             conn.execute(sea_orm::Statement::from_string(sea_orm::DbBackend::Postgres, sql.to_owned())).await.map_err(|e| AppError::Database(e.to_string()))?;
         }
 
+        // 旧库没有 content_hash / root_dir / redirects / password_hash / spa_mode / fingerprint_assets / auto_sitemap / custom_headers / source_ip / user_agent 列；列已存在时 ALTER 会报错，忽略即可
+        let backend = if database_url.starts_with("sqlite") {
+            sea_orm::DbBackend::Sqlite
+        } else {
+            sea_orm::DbBackend::Postgres
+        };
+        conn.execute(sea_orm::Statement::from_string(backend, "ALTER TABLE sites ADD COLUMN content_hash TEXT;".to_owned())).await.ok();
+        conn.execute(sea_orm::Statement::from_string(backend, "ALTER TABLE sites ADD COLUMN root_dir TEXT;".to_owned())).await.ok();
+        conn.execute(sea_orm::Statement::from_string(backend, "ALTER TABLE sites ADD COLUMN redirects TEXT;".to_owned())).await.ok();
+        conn.execute(sea_orm::Statement::from_string(backend, "ALTER TABLE sites ADD COLUMN password_hash TEXT;".to_owned())).await.ok();
+        conn.execute(sea_orm::Statement::from_string(backend, "ALTER TABLE sites ADD COLUMN spa_mode BOOLEAN NOT NULL DEFAULT FALSE;".to_owned())).await.ok();
+        conn.execute(sea_orm::Statement::from_string(backend, "ALTER TABLE sites ADD COLUMN fingerprint_assets BOOLEAN NOT NULL DEFAULT FALSE;".to_owned())).await.ok();
+        conn.execute(sea_orm::Statement::from_string(backend, "ALTER TABLE sites ADD COLUMN auto_sitemap BOOLEAN NOT NULL DEFAULT FALSE;".to_owned())).await.ok();
+        conn.execute(sea_orm::Statement::from_string(backend, "ALTER TABLE sites ADD COLUMN custom_headers TEXT;".to_owned())).await.ok();
+        conn.execute(sea_orm::Statement::from_string(backend, "ALTER TABLE sites ADD COLUMN source_ip TEXT;".to_owned())).await.ok();
+        conn.execute(sea_orm::Statement::from_string(backend, "ALTER TABLE sites ADD COLUMN user_agent TEXT;".to_owned())).await.ok();
+
         std::fs::create_dir_all(&site_static_files_path)?;
 
-        Ok(Self { conn, site_files_path: site_static_files_path })
+        Ok(Self { conn: RetryingConnection::new(conn), site_files_path: site_static_files_path })
     }
 
     pub async fn create(&self, site: Site) -> Result<(), AppError> {
@@ -51,7 +108,16 @@ impl SiteStorage {
             domain: Set(site.domain),
             description: Set(site.description),
             created_at: Set(site.created_at.to_rfc3339()),
-            ..Default::default()
+            content_hash: Set(site.content_hash),
+            root_dir: Set(site.root_dir),
+            redirects: Set(encode_list(&site.redirects)?),
+            password_hash: Set(site.password_hash),
+            spa_mode: Set(site.spa_mode),
+            fingerprint_assets: Set(site.fingerprint_assets),
+            auto_sitemap: Set(site.auto_sitemap),
+            custom_headers: Set(encode_list(&site.custom_headers)?),
+            source_ip: Set(site.source_ip),
+            user_agent: Set(site.user_agent),
         };
 
         sites_entity::Entity::insert(am).exec(&self.conn).await.map_err(|e| AppError::Database(e.to_string()))?;
@@ -62,7 +128,7 @@ impl SiteStorage {
         let key = id.to_string();
         if let Some(m) = sites_entity::Entity::find_by_id(key).one(&self.conn).await.map_err(|e| AppError::Database(e.to_string()))? {
             let created_at = chrono::DateTime::parse_from_rfc3339(&m.created_at)?.with_timezone(&chrono::Utc);
-            Ok(Some(Site { id: Uuid::parse_str(&m.id)?, owner_id: Uuid::parse_str(&m.owner_id)?, name: m.name, domain: m.domain, description: m.description, created_at }))
+            Ok(Some(Site { id: Uuid::parse_str(&m.id)?, owner_id: Uuid::parse_str(&m.owner_id)?, name: m.name, domain: m.domain, description: m.description, created_at, content_hash: m.content_hash, root_dir: m.root_dir, redirects: decode_list(m.redirects.as_deref())?, password_hash: m.password_hash, spa_mode: m.spa_mode, fingerprint_assets: m.fingerprint_assets, auto_sitemap: m.auto_sitemap, custom_headers: decode_list(m.custom_headers.as_deref())?, source_ip: m.source_ip, user_agent: m.user_agent }))
         } else {
             Ok(None)
         }
@@ -76,7 +142,7 @@ impl SiteStorage {
             .one(&self.conn).await.map_err(|e| AppError::Database(e.to_string()))? 
         {
             let created_at = chrono::DateTime::parse_from_rfc3339(&m.created_at)?.with_timezone(&chrono::Utc);
-            Ok(Some(Site { id: Uuid::parse_str(&m.id)?, owner_id: Uuid::parse_str(&m.owner_id)?, name: m.name, domain: m.domain, description: m.description, created_at }))
+            Ok(Some(Site { id: Uuid::parse_str(&m.id)?, owner_id: Uuid::parse_str(&m.owner_id)?, name: m.name, domain: m.domain, description: m.description, created_at, content_hash: m.content_hash, root_dir: m.root_dir, redirects: decode_list(m.redirects.as_deref())?, password_hash: m.password_hash, spa_mode: m.spa_mode, fingerprint_assets: m.fingerprint_assets, auto_sitemap: m.auto_sitemap, custom_headers: decode_list(m.custom_headers.as_deref())?, source_ip: m.source_ip, user_agent: m.user_agent }))
         } else {
             Ok(None)
         }
@@ -91,7 +157,7 @@ impl SiteStorage {
         let mut sites = Vec::new();
         for m in models {
             let created_at = chrono::DateTime::parse_from_rfc3339(&m.created_at)?.with_timezone(&chrono::Utc);
-            sites.push(Site { id: Uuid::parse_str(&m.id)?, owner_id: Uuid::parse_str(&m.owner_id)?, name: m.name, domain: m.domain, description: m.description, created_at });
+            sites.push(Site { id: Uuid::parse_str(&m.id)?, owner_id: Uuid::parse_str(&m.owner_id)?, name: m.name, domain: m.domain, description: m.description, created_at, content_hash: m.content_hash, root_dir: m.root_dir, redirects: decode_list(m.redirects.as_deref())?, password_hash: m.password_hash, spa_mode: m.spa_mode, fingerprint_assets: m.fingerprint_assets, auto_sitemap: m.auto_sitemap, custom_headers: decode_list(m.custom_headers.as_deref())?, source_ip: m.source_ip, user_agent: m.user_agent });
         }
         Ok(sites)
     }
@@ -105,6 +171,16 @@ impl SiteStorage {
             am.domain = Set(site.domain);
             am.description = Set(site.description);
             am.created_at = Set(site.created_at.to_rfc3339());
+            am.content_hash = Set(site.content_hash);
+            am.root_dir = Set(site.root_dir);
+            am.redirects = Set(encode_list(&site.redirects)?);
+            am.password_hash = Set(site.password_hash);
+            am.spa_mode = Set(site.spa_mode);
+            am.fingerprint_assets = Set(site.fingerprint_assets);
+            am.auto_sitemap = Set(site.auto_sitemap);
+            am.custom_headers = Set(encode_list(&site.custom_headers)?);
+            am.source_ip = Set(site.source_ip);
+            am.user_agent = Set(site.user_agent);
             sites_entity::Entity::update(am).exec(&self.conn).await.map_err(|e| AppError::Database(e.to_string()))?;
             Ok(())
         } else {
@@ -112,8 +188,10 @@ impl SiteStorage {
         }
     }
 
+    /// 删除记录与 UUID 目录；该名称没有其他版本时一并删除 siteName 目录
     pub async fn delete(&self, id: Uuid) -> Result<(), AppError> {
         let key = id.to_string();
+        let name = self.get(id).await?.map(|site| site.name);
         sites_entity::Entity::delete_by_id(key.clone()).exec(&self.conn).await.map_err(|e| AppError::Database(e.to_string()))?;
 
         // delete files
@@ -122,6 +200,15 @@ impl SiteStorage {
             std::fs::remove_dir_all(site_dir)?;
         }
 
+        if let Some(name) = name.filter(|n| !n.is_empty())
+            && self.get_all_by_name(&name).await?.is_empty()
+        {
+            let name_dir = self.site_files_path.join(&name);
+            if name_dir.exists() {
+                std::fs::remove_dir_all(name_dir)?;
+            }
+        }
+
         Ok(())
     }
 
@@ -130,17 +217,66 @@ impl SiteStorage {
         let mut sites = Vec::new();
         for m in models {
             let created_at = chrono::DateTime::parse_from_rfc3339(&m.created_at)?.with_timezone(&chrono::Utc);
-            sites.push(Site { id: Uuid::parse_str(&m.id)?, owner_id: Uuid::parse_str(&m.owner_id)?, name: m.name, domain: m.domain, description: m.description, created_at });
+            sites.push(Site { id: Uuid::parse_str(&m.id)?, owner_id: Uuid::parse_str(&m.owner_id)?, name: m.name, domain: m.domain, description: m.description, created_at, content_hash: m.content_hash, root_dir: m.root_dir, redirects: decode_list(m.redirects.as_deref())?, password_hash: m.password_hash, spa_mode: m.spa_mode, fingerprint_assets: m.fingerprint_assets, auto_sitemap: m.auto_sitemap, custom_headers: decode_list(m.custom_headers.as_deref())?, source_ip: m.source_ip, user_agent: m.user_agent });
+        }
+        Ok(sites)
+    }
+
+    /// Up to `limit` versions with ids after `after`; hyphenated ids sort like the Uuids
+    pub async fn list_page(&self, after: Option<Uuid>, limit: usize) -> Result<Vec<Site>, AppError> {
+        let mut query = sites_entity::Entity::find().order_by_asc(sites_entity::Column::Id);
+        if let Some(after) = after {
+            query = query.filter(sites_entity::Column::Id.gt(after.to_string()));
+        }
+        let models = query.limit(limit as u64).all(&self.conn).await.map_err(|e| AppError::Database(e.to_string()))?;
+        let mut sites = Vec::new();
+        for m in models {
+            let created_at = chrono::DateTime::parse_from_rfc3339(&m.created_at)?.with_timezone(&chrono::Utc);
+            sites.push(Site { id: Uuid::parse_str(&m.id)?, owner_id: Uuid::parse_str(&m.owner_id)?, name: m.name, domain: m.domain, description: m.description, created_at, content_hash: m.content_hash, root_dir: m.root_dir, redirects: decode_list(m.redirects.as_deref())?, password_hash: m.password_hash, spa_mode: m.spa_mode, fingerprint_assets: m.fingerprint_assets, auto_sitemap: m.auto_sitemap, custom_headers: decode_list(m.custom_headers.as_deref())?, source_ip: m.source_ip, user_agent: m.user_agent });
+        }
+        Ok(sites)
+    }
+
+    /// Newest version of every site name, sorted by created_at descending
+    pub async fn list_latest_per_name(&self) -> Result<Vec<Site>, AppError> {
+        // rows arrive newest-first, so the first row seen for each name is its latest version
+        let models = sites_entity::Entity::find()
+            .order_by_desc(sites_entity::Column::CreatedAt)
+            .all(&self.conn).await.map_err(|e| AppError::Database(e.to_string()))?;
+        let mut seen = HashSet::new();
+        let mut sites = Vec::new();
+        for m in models {
+            if !seen.insert(m.name.clone()) {
+                continue;
+            }
+            let created_at = chrono::DateTime::parse_from_rfc3339(&m.created_at)?.with_timezone(&chrono::Utc);
+            sites.push(Site { id: Uuid::parse_str(&m.id)?, owner_id: Uuid::parse_str(&m.owner_id)?, name: m.name, domain: m.domain, description: m.description, created_at, content_hash: m.content_hash, root_dir: m.root_dir, redirects: decode_list(m.redirects.as_deref())?, password_hash: m.password_hash, spa_mode: m.spa_mode, fingerprint_assets: m.fingerprint_assets, auto_sitemap: m.auto_sitemap, custom_headers: decode_list(m.custom_headers.as_deref())?, source_ip: m.source_ip, user_agent: m.user_agent });
         }
         Ok(sites)
     }
 
+    /// 所有站点名（SELECT DISTINCT name，升序）
+    pub async fn list_distinct_names(&self) -> Result<Vec<String>, AppError> {
+        sites_entity::Entity::find()
+            .select_only()
+            .column(sites_entity::Column::Name)
+            .distinct()
+            .order_by_asc(sites_entity::Column::Name)
+            .into_tuple::<String>()
+            .all(&self.conn).await.map_err(|e| AppError::Database(e.to_string()))
+    }
+
+    /// Newest first, like the sled backend's date-keyed index (ties broken by id, also descending)
     pub async fn list_by_owner(&self, owner_id: Uuid) -> Result<Vec<Site>, AppError> {
-        let models = sites_entity::Entity::find().filter(sites_entity::Column::OwnerId.eq(owner_id.to_string())).all(&self.conn).await.map_err(|e| AppError::Database(e.to_string()))?;
+        let models = sites_entity::Entity::find()
+            .filter(sites_entity::Column::OwnerId.eq(owner_id.to_string()))
+            .order_by_desc(sites_entity::Column::CreatedAt)
+            .order_by_desc(sites_entity::Column::Id)
+            .all(&self.conn).await.map_err(|e| AppError::Database(e.to_string()))?;
         let mut sites = Vec::new();
         for m in models {
             let created_at = chrono::DateTime::parse_from_rfc3339(&m.created_at)?.with_timezone(&chrono::Utc);
-            sites.push(Site { id: Uuid::parse_str(&m.id)?, owner_id: Uuid::parse_str(&m.owner_id)?, name: m.name, domain: m.domain, description: m.description, created_at });
+            sites.push(Site { id: Uuid::parse_str(&m.id)?, owner_id: Uuid::parse_str(&m.owner_id)?, name: m.name, domain: m.domain, description: m.description, created_at, content_hash: m.content_hash, root_dir: m.root_dir, redirects: decode_list(m.redirects.as_deref())?, password_hash: m.password_hash, spa_mode: m.spa_mode, fingerprint_assets: m.fingerprint_assets, auto_sitemap: m.auto_sitemap, custom_headers: decode_list(m.custom_headers.as_deref())?, source_ip: m.source_ip, user_agent: m.user_agent });
         }
         Ok(sites)
     }
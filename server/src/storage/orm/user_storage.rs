@@ -1,14 +1,57 @@
 use crate::{error::AppError, models::User};
-use sea_orm::{Database, DatabaseConnection, EntityTrait, Set, ConnectionTrait, QueryFilter, ColumnTrait, QueryOrder, PaginatorTrait};
+use sea_orm::{Database, EntityTrait, Set, ConnectionTrait, QueryFilter, ColumnTrait, QueryOrder, QuerySelect, PaginatorTrait, SqlErr};
+use crate::storage::orm::retry::{with_retry, RetryingConnection};
 use uuid::Uuid;
 use crate::storage::orm::entities::users as users_entity;
 
+fn parse_time(raw: &str) -> Result<chrono::DateTime<chrono::Utc>, AppError> {
+    Ok(chrono::DateTime::parse_from_rfc3339(raw)?.with_timezone(&chrono::Utc))
+}
+
+/// 角色以 JSON 数组文本存储；没有角色时为 NULL
+fn encode_roles(roles: &[String]) -> Result<Option<String>, AppError> {
+    if roles.is_empty() {
+        return Ok(None);
+    }
+    Ok(Some(serde_json::to_string(roles)?))
+}
+
+fn decode_roles(raw: Option<&str>) -> Result<Vec<String>, AppError> {
+    match raw {
+        Some(json) => Ok(serde_json::from_str(json)?),
+        None => Ok(Vec::new()),
+    }
+}
+
+/// 唯一约束冲突：约束名/列名含 email 的是邮箱冲突，其余是用户名冲突
+fn map_write_err(e: sea_orm::DbErr) -> AppError {
+    match e.sql_err() {
+        Some(SqlErr::UniqueConstraintViolation(msg)) if msg.contains("email") => AppError::EmailAlreadyExists,
+        Some(SqlErr::UniqueConstraintViolation(_)) => AppError::UserAlreadyExists,
+        _ => AppError::Database(e.to_string()),
+    }
+}
+
+fn to_user(m: users_entity::Model) -> Result<User, AppError> {
+    Ok(User {
+        id: Uuid::parse_str(&m.id)?,
+        username: m.username,
+        password: m.password,
+        created_at: parse_time(&m.created_at)?,
+        last_login: m.last_login.as_deref().map(parse_time).transpose()?,
+        roles: decode_roles(m.roles.as_deref())?,
+        email: m.email,
+    })
+}
+
 #[derive(Clone)]
 pub struct UserStorage {
-    conn: DatabaseConnection,
+    conn: RetryingConnection,
 }
 
 impl UserStorage {
+    with_retry!();
+
     pub async fn new(database_url: &str) -> Result<Self, AppError> {
         eprintln!("Connecting to DB; database_url='{}'", database_url);
         let conn = Database::connect(database_url).await.map_err(|e| AppError::Database(e.to_string()))?;
@@ -19,7 +62,10 @@ impl UserStorage {
                 id TEXT PRIMARY KEY,
                 username TEXT NOT NULL UNIQUE,
                 password TEXT NOT NULL,
-                created_at TEXT NOT NULL
+                created_at TEXT NOT NULL,
+                last_login TEXT,
+                roles TEXT,
+                email TEXT
             );"#;
             conn.execute(sea_orm::Statement::from_string(sea_orm::DbBackend::Sqlite, sql.to_owned())).await.map_err(|e| AppError::Database(e.to_string()))?;
         } else {
@@ -27,44 +73,58 @@ impl UserStorage {
                 id TEXT PRIMARY KEY,
                 username TEXT NOT NULL UNIQUE,
                 password TEXT NOT NULL,
-                created_at TEXT NOT NULL
+                created_at TEXT NOT NULL,
+                last_login TEXT,
+                roles TEXT,
+                email TEXT
             );"#;
             conn.execute(sea_orm::Statement::from_string(sea_orm::DbBackend::Postgres, sql.to_owned())).await.map_err(|e| AppError::Database(e.to_string()))?;
         }
 
-        Ok(Self { conn })
+        // 旧库没有 last_login / roles / email 列；列已存在时 ALTER 会报错，忽略即可
+        let backend = if database_url.starts_with("sqlite") { sea_orm::DbBackend::Sqlite } else { sea_orm::DbBackend::Postgres };
+        conn.execute(sea_orm::Statement::from_string(backend, "ALTER TABLE users ADD COLUMN last_login TEXT;".to_owned())).await.ok();
+        conn.execute(sea_orm::Statement::from_string(backend, "ALTER TABLE users ADD COLUMN roles TEXT;".to_owned())).await.ok();
+        conn.execute(sea_orm::Statement::from_string(backend, "ALTER TABLE users ADD COLUMN email TEXT;".to_owned())).await.ok();
+        // NULL 不参与唯一性比较，未设置邮箱的用户互不冲突
+        conn.execute(sea_orm::Statement::from_string(backend, "CREATE UNIQUE INDEX IF NOT EXISTS users_email_idx ON users(email);".to_owned())).await.map_err(|e| AppError::Database(e.to_string()))?;
+
+        Ok(Self { conn: RetryingConnection::new(conn) })
     }
 
-    pub async fn create(&self, user: User) -> Result<(), AppError> {
+    /// 返回写入的用户；违反 username / email 唯一约束时返回 `UserAlreadyExists` / `EmailAlreadyExists`
+    pub async fn create(&self, user: User) -> Result<User, AppError> {
         let am = users_entity::ActiveModel {
             id: Set(user.id.to_string()),
-            username: Set(user.username),
-            password: Set(user.password),
+            username: Set(user.username.clone()),
+            password: Set(user.password.clone()),
             created_at: Set(user.created_at.to_rfc3339()),
-            ..Default::default()
+            last_login: Set(user.last_login.map(|t| t.to_rfc3339())),
+            roles: Set(encode_roles(&user.roles)?),
+            email: Set(user.email.clone()),
         };
 
-        users_entity::Entity::insert(am).exec(&self.conn).await.map_err(|e| AppError::Database(e.to_string()))?;
-        Ok(())
+        users_entity::Entity::insert(am).exec(&self.conn).await.map_err(map_write_err)?;
+        Ok(user)
     }
 
     pub async fn get(&self, id: Uuid) -> Result<Option<User>, AppError> {
         let key = id.to_string();
-        if let Some(m) = users_entity::Entity::find_by_id(key).one(&self.conn).await.map_err(|e| AppError::Database(e.to_string()))? {
-            let created_at = chrono::DateTime::parse_from_rfc3339(&m.created_at)?.with_timezone(&chrono::Utc);
-            Ok(Some(User { id: Uuid::parse_str(&m.id)?, username: m.username, password: m.password, created_at }))
-        } else {
-            Ok(None)
-        }
+        users_entity::Entity::find_by_id(key).one(&self.conn).await.map_err(|e| AppError::Database(e.to_string()))?
+            .map(to_user)
+            .transpose()
     }
 
     pub async fn get_by_username(&self, username: &str) -> Result<Option<User>, AppError> {
-        if let Some(m) = users_entity::Entity::find().filter(users_entity::Column::Username.eq(username.to_string())).one(&self.conn).await.map_err(|e| AppError::Database(e.to_string()))? {
-            let created_at = chrono::DateTime::parse_from_rfc3339(&m.created_at)?.with_timezone(&chrono::Utc);
-            Ok(Some(User { id: Uuid::parse_str(&m.id)?, username: m.username, password: m.password, created_at }))
-        } else {
-            Ok(None)
-        }
+        users_entity::Entity::find().filter(users_entity::Column::Username.eq(username.to_string())).one(&self.conn).await.map_err(|e| AppError::Database(e.to_string()))?
+            .map(to_user)
+            .transpose()
+    }
+
+    pub async fn get_by_email(&self, email: &str) -> Result<Option<User>, AppError> {
+        users_entity::Entity::find().filter(users_entity::Column::Email.eq(email.to_string())).one(&self.conn).await.map_err(|e| AppError::Database(e.to_string()))?
+            .map(to_user)
+            .transpose()
     }
 
     pub async fn update(&self, user: User) -> Result<(), AppError> {
@@ -74,7 +134,10 @@ impl UserStorage {
             am.username = Set(user.username);
             am.password = Set(user.password);
             am.created_at = Set(user.created_at.to_rfc3339());
-            users_entity::Entity::update(am).exec(&self.conn).await.map_err(|e| AppError::Database(e.to_string()))?;
+            am.last_login = Set(user.last_login.map(|t| t.to_rfc3339()));
+            am.roles = Set(encode_roles(&user.roles)?);
+            am.email = Set(user.email);
+            users_entity::Entity::update(am).exec(&self.conn).await.map_err(map_write_err)?;
             Ok(())
         } else {
             Err(AppError::UserNotFound)
@@ -89,12 +152,17 @@ impl UserStorage {
 
     pub async fn list_all(&self) -> Result<Vec<User>, AppError> {
         let models = users_entity::Entity::find().order_by_desc(users_entity::Column::CreatedAt).all(&self.conn).await.map_err(|e| AppError::Database(e.to_string()))?;
-        let mut users = Vec::new();
-        for m in models {
-            let created_at = chrono::DateTime::parse_from_rfc3339(&m.created_at)?.with_timezone(&chrono::Utc);
-            users.push(User { id: Uuid::parse_str(&m.id)?, username: m.username, password: m.password, created_at });
+        models.into_iter().map(to_user).collect()
+    }
+
+    /// Up to `limit` users with ids after `after`; hyphenated ids sort like the Uuids
+    pub async fn list_page(&self, after: Option<Uuid>, limit: usize) -> Result<Vec<User>, AppError> {
+        let mut query = users_entity::Entity::find().order_by_asc(users_entity::Column::Id);
+        if let Some(after) = after {
+            query = query.filter(users_entity::Column::Id.gt(after.to_string()));
         }
-        Ok(users)
+        let models = query.limit(limit as u64).all(&self.conn).await.map_err(|e| AppError::Database(e.to_string()))?;
+        models.into_iter().map(to_user).collect()
     }
 
     pub async fn count(&self) -> Result<usize, AppError> {
@@ -0,0 +1,47 @@
+use crate::config::StorageConfig;
+use std::future::Future;
+use std::time::Duration;
+
+/// How often a database call is run again after a transient failure
+/// (`storage.db_retries`, `storage.db_retry_backoff_ms`). The default never retries.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// Attempts after the first one
+    pub retries: u32,
+    /// Pause before the first retry; doubled for each one after it
+    pub backoff: Duration,
+}
+
+impl RetryPolicy {
+    pub fn from_config(config: &StorageConfig) -> Self {
+        Self { retries: config.db_retries, backoff: Duration::from_millis(config.db_retry_backoff_ms) }
+    }
+
+    /// Pause before retry number `attempt` (1-based)
+    pub fn delay(&self, attempt: u32) -> Duration {
+        let factor = 1u32.checked_shl(attempt.saturating_sub(1)).unwrap_or(u32::MAX);
+        self.backoff.saturating_mul(factor)
+    }
+}
+
+/// Run `op`, and run it again after `policy.delay` while it fails with an error `is_transient`
+/// accepts and retries are left. Any other error, or the last transient one, is returned as is.
+pub async fn retry<T, E, F, Fut>(policy: RetryPolicy, is_transient: impl Fn(&E) -> bool, mut op: F) -> Result<T, E>
+where
+    E: std::fmt::Display,
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T, E>>,
+{
+    let mut attempt = 0;
+    loop {
+        match op().await {
+            Err(e) if attempt < policy.retries && is_transient(&e) => {
+                attempt += 1;
+                let delay = policy.delay(attempt);
+                tracing::warn!("transient database error, retry {}/{} in {:?}: {}", attempt, policy.retries, delay, e);
+                tokio::time::sleep(delay).await;
+            }
+            result => return result,
+        }
+    }
+}
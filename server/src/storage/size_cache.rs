@@ -0,0 +1,43 @@
+use crate::{error::AppError, utils::fs::dir_size_and_count};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::RwLock;
+
+/// In-process cache of `(bytes, files)` per site directory (UUID or siteName).
+///
+/// Entries are recorded right after extraction and dropped when the directory is
+/// deleted, so size reads don't walk the tree. A miss falls back to a walk, whose
+/// result is kept unless the directory is a dot-prefixed temp directory.
+#[derive(Default)]
+pub struct SizeCache {
+    entries: RwLock<HashMap<PathBuf, (u64, u64)>>,
+}
+
+impl SizeCache {
+    /// Walk `dir` now and remember the result
+    pub fn record(&self, dir: &Path) -> Result<(u64, u64), AppError> {
+        let totals = dir_size_and_count(dir)?;
+        self.entries.write().unwrap_or_else(|e| e.into_inner()).insert(dir.to_path_buf(), totals);
+        Ok(totals)
+    }
+
+    pub fn cached(&self, dir: &Path) -> Option<(u64, u64)> {
+        self.entries.read().unwrap_or_else(|e| e.into_inner()).get(dir).copied()
+    }
+
+    pub fn invalidate(&self, dir: &Path) {
+        self.entries.write().unwrap_or_else(|e| e.into_inner()).remove(dir);
+    }
+
+    /// Cached totals, or a walk when the cache is cold
+    pub fn get_or_walk(&self, dir: &Path) -> Result<(u64, u64), AppError> {
+        if let Some(totals) = self.cached(dir) {
+            return Ok(totals);
+        }
+        let is_temp = dir.file_name().and_then(|n| n.to_str()).is_some_and(|n| n.starts_with('.'));
+        if is_temp || !dir.exists() {
+            return dir_size_and_count(dir);
+        }
+        self.record(dir)
+    }
+}
@@ -0,0 +1,53 @@
+use crate::{error::AppError, models::AuditEvent};
+use chrono::{DateTime, Utc};
+use sled::Db;
+use std::path::Path;
+use super::dbs::*;
+
+// key = 时间戳(纳秒, 大端) + 事件 id，保证按时间有序，倒序遍历即为最新事件
+
+#[derive(Clone)]
+pub struct AuditStorage {
+    db: Db,
+}
+
+impl AuditStorage {
+    pub async fn new(path: &Path) -> Result<Self, AppError> {
+        let db = sled::open(path.join(DB_AUDIT))?;
+        Ok(Self { db })
+    }
+
+    pub async fn append(&self, event: AuditEvent) -> Result<(), AppError> {
+        let nanos = event.timestamp.timestamp_nanos_opt().unwrap_or(0);
+        let mut key = nanos.to_be_bytes().to_vec();
+        key.extend_from_slice(event.id.as_bytes());
+        let value = serde_json::to_vec(&event)?;
+        self.db.insert(key, value)?;
+        Ok(())
+    }
+
+    /// 最近的 `limit` 条事件，按时间倒序（最新在前）
+    pub async fn recent(&self, limit: usize) -> Result<Vec<AuditEvent>, AppError> {
+        let mut events = Vec::new();
+        for result in self.db.iter().rev().take(limit) {
+            let (_, value) = result?;
+            let event: AuditEvent = serde_json::from_slice(&value)?;
+            events.push(event);
+        }
+        Ok(events)
+    }
+
+    /// 删除最多 `limit` 条早于 `cutoff` 的事件（最旧的先删），返回删除条数
+    pub async fn prune_before(&self, cutoff: DateTime<Utc>, limit: usize) -> Result<usize, AppError> {
+        let end = cutoff.timestamp_nanos_opt().unwrap_or(0).to_be_bytes();
+        let mut batch = sled::Batch::default();
+        let mut removed = 0;
+        for result in self.db.range(..end.to_vec()).take(limit) {
+            let (key, _) = result?;
+            batch.remove(key);
+            removed += 1;
+        }
+        self.db.apply_batch(batch)?;
+        Ok(removed)
+    }
+}
@@ -1,3 +1,6 @@
 pub const DB_USERS: &str = "users.db";
 pub const DB_SITES: &str = "sites.db";
-pub const DB_USER_SITES: &str = "user_sites.db";
\ No newline at end of file
+pub const DB_USER_SITES: &str = "user_sites.db";
+pub const DB_AUDIT: &str = "audit.db";
+pub const DB_INVITES: &str = "invites.db";
+pub const DB_PASSWORD_RESETS: &str = "password_resets.db";
@@ -0,0 +1,69 @@
+use crate::{error::AppError, models::Invite};
+use chrono::{DateTime, Utc};
+use sled::Db;
+use std::path::Path;
+use super::dbs::*;
+
+// key = 邀请码，value = Invite 的 JSON
+
+#[derive(Clone)]
+pub struct InviteStorage {
+    db: Db,
+}
+
+impl InviteStorage {
+    pub async fn new(path: &Path) -> Result<Self, AppError> {
+        let db = sled::open(path.join(DB_INVITES))?;
+        Ok(Self { db })
+    }
+
+    pub async fn create(&self, invite: Invite) -> Result<(), AppError> {
+        let value = serde_json::to_vec(&invite)?;
+        self.db.insert(invite.code.as_bytes(), value)?;
+        Ok(())
+    }
+
+    pub async fn get(&self, code: &str) -> Result<Option<Invite>, AppError> {
+        match self.db.get(code.as_bytes())? {
+            Some(value) => Ok(Some(serde_json::from_slice(&value)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Take one use of `code` if it is usable at `now`. A compare-and-swap, so concurrent
+    /// registrations can't both spend the last use; false when unknown or used up
+    pub async fn claim(&self, code: &str, now: DateTime<Utc>) -> Result<bool, AppError> {
+        self.swap(code, |invite| {
+            if !invite.is_usable(now) {
+                return false;
+            }
+            invite.uses += 1;
+            true
+        })
+    }
+
+    /// Give back a use taken by `claim` (the registration it was for failed)
+    pub async fn release(&self, code: &str) -> Result<(), AppError> {
+        self.swap(code, |invite| {
+            invite.uses = invite.uses.saturating_sub(1);
+            true
+        })?;
+        Ok(())
+    }
+
+    // Apply `change` until the stored invite is swapped without a concurrent write in between;
+    // false (nothing written) when the code is unknown or `change` declines
+    fn swap(&self, code: &str, change: impl Fn(&mut Invite) -> bool) -> Result<bool, AppError> {
+        loop {
+            let Some(old) = self.db.get(code.as_bytes())? else { return Ok(false) };
+            let mut invite: Invite = serde_json::from_slice(&old)?;
+            if !change(&mut invite) {
+                return Ok(false);
+            }
+            let new = serde_json::to_vec(&invite)?;
+            if self.db.compare_and_swap(code.as_bytes(), Some(old), Some(new))?.is_ok() {
+                return Ok(true);
+            }
+        }
+    }
+}
@@ -0,0 +1,49 @@
+use crate::{error::AppError, models::PasswordReset};
+use chrono::{DateTime, Utc};
+use sled::Db;
+use std::path::Path;
+use super::dbs::*;
+
+// key = 令牌的 SHA-256，value = PasswordReset 的 JSON
+
+#[derive(Clone)]
+pub struct PasswordResetStorage {
+    db: Db,
+}
+
+impl PasswordResetStorage {
+    pub async fn new(path: &Path) -> Result<Self, AppError> {
+        let db = sled::open(path.join(DB_PASSWORD_RESETS))?;
+        Ok(Self { db })
+    }
+
+    pub async fn create(&self, reset: PasswordReset) -> Result<(), AppError> {
+        let value = serde_json::to_vec(&reset)?;
+        self.db.insert(reset.token_hash.as_bytes(), value)?;
+        Ok(())
+    }
+
+    pub async fn get(&self, token_hash: &str) -> Result<Option<PasswordReset>, AppError> {
+        match self.db.get(token_hash.as_bytes())? {
+            Some(value) => Ok(Some(serde_json::from_slice(&value)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Mark the token used if it is usable at `now`, returning it only to the caller that did.
+    /// A compare-and-swap, so two concurrent confirms can't both spend one token
+    pub async fn redeem(&self, token_hash: &str, now: DateTime<Utc>) -> Result<Option<PasswordReset>, AppError> {
+        loop {
+            let Some(old) = self.db.get(token_hash.as_bytes())? else { return Ok(None) };
+            let mut reset: PasswordReset = serde_json::from_slice(&old)?;
+            if !reset.is_usable(now) {
+                return Ok(None);
+            }
+            reset.used = true;
+            let new = serde_json::to_vec(&reset)?;
+            if self.db.compare_and_swap(token_hash.as_bytes(), Some(old), Some(new))?.is_ok() {
+                return Ok(Some(reset));
+            }
+        }
+    }
+}
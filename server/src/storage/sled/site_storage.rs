@@ -1,5 +1,7 @@
 use crate::{error::AppError, models::Site};
 use sled::Db;
+use std::collections::HashMap;
+use std::ops::Bound;
 use std::path::PathBuf;
 use uuid::Uuid;
 use super::dbs::*;
@@ -28,7 +30,10 @@ impl SiteStorage {
     pub async fn create(&self, site: Site) -> Result<(), AppError> {
         let key = site.id.as_bytes();
         let value = serde_json::to_vec(&site)?;
-        self.db.insert(key, value)?;
+        // 与 orm 的主键约束一致：已存在的 id 不覆盖
+        if self.db.compare_and_swap(key, None as Option<&[u8]>, Some(value))?.is_err() {
+            return Err(AppError::Database(format!("site {} already exists", site.id)));
+        }
         // insert index entry for owner->(date)->site
         let idx_key = format!("user:{}:{}:{}", site.owner_id, site.created_at.to_rfc3339(), site.id);
         self.user_sites_db.insert(idx_key.as_bytes(), site.id.as_bytes())?;
@@ -96,13 +101,16 @@ impl SiteStorage {
         Ok(())
     }
 
+    /// 删除记录与 UUID 目录；该名称没有其他版本时一并删除 siteName 目录
     pub async fn delete(&self, id: Uuid) -> Result<(), AppError> {
         let key = id.as_bytes();
         // remove index entry
+        let mut name = None;
         if let Some(value) = self.db.get(key)? {
             let site: Site = serde_json::from_slice(&value)?;
             let idx_key = format!("user:{}:{}:{}", site.owner_id, site.created_at.to_rfc3339(), site.id);
             let _ = self.user_sites_db.remove(idx_key.as_bytes());
+            name = Some(site.name);
         }
         self.db.remove(key)?;
         
@@ -111,6 +119,15 @@ impl SiteStorage {
         if site_dir.exists() {
             std::fs::remove_dir_all(site_dir)?;
         }
+
+        if let Some(name) = name.filter(|n| !n.is_empty())
+            && self.get_all_by_name(&name).await?.is_empty()
+        {
+            let name_dir = self.site_files_path.join(&name);
+            if name_dir.exists() {
+                std::fs::remove_dir_all(name_dir)?;
+            }
+        }
         
         Ok(())
     }
@@ -127,6 +144,49 @@ impl SiteStorage {
         Ok(sites)
     }
 
+    /// Up to `limit` versions with ids after `after`, by ascending id (keys are the id bytes)
+    pub async fn list_page(&self, after: Option<Uuid>, limit: usize) -> Result<Vec<Site>, AppError> {
+        let start = match after {
+            Some(id) => Bound::Excluded(id.as_bytes().to_vec()),
+            None => Bound::Unbounded,
+        };
+        let mut sites = Vec::new();
+        for result in self.db.range((start, Bound::Unbounded)).take(limit) {
+            let (_, value) = result?;
+            sites.push(serde_json::from_slice(&value)?);
+        }
+        Ok(sites)
+    }
+
+    /// Newest version of every site name, sorted by created_at descending
+    pub async fn list_latest_per_name(&self) -> Result<Vec<Site>, AppError> {
+        let mut latest: HashMap<String, Site> = HashMap::new();
+        for result in self.db.iter() {
+            let (_, value) = result?;
+            let site: Site = serde_json::from_slice(&value)?;
+            match latest.get(&site.name) {
+                Some(current) if current.created_at >= site.created_at => {}
+                _ => {
+                    latest.insert(site.name.clone(), site);
+                }
+            }
+        }
+        let mut sites: Vec<Site> = latest.into_values().collect();
+        sites.sort_by_key(|site| std::cmp::Reverse(site.created_at));
+        Ok(sites)
+    }
+
+    /// 所有站点名（去重，升序）
+    pub async fn list_distinct_names(&self) -> Result<Vec<String>, AppError> {
+        let mut names = std::collections::BTreeSet::new();
+        for result in self.db.iter() {
+            let (_, value) = result?;
+            let site: Site = serde_json::from_slice(&value)?;
+            names.insert(site.name);
+        }
+        Ok(names.into_iter().collect())
+    }
+
     pub async fn list_by_owner(&self, owner_id: Uuid) -> Result<Vec<Site>, AppError> {
         let mut sites = Vec::new();
 
@@ -1,10 +1,31 @@
 use crate::{error::AppError, models::User};
 use sled::Db;
+use std::ops::Bound;
 use std::path::PathBuf;
 use uuid::Uuid;
 use super::dbs::*;
 
 // 现在会在一个db里同时存储(id, 用户)和(username: name, 用户id)两种键值对，之后考虑优化
+// 另有一个 USER_COUNT_KEY 保存用户数（u64 大端），create/delete 时原子增减
+// 设置了邮箱的用户还有 (email: 地址, 用户id) 索引，保证邮箱唯一
+
+const USERNAME_PREFIX: &str = "username:";
+const EMAIL_PREFIX: &str = "email:";
+const USER_COUNT_KEY: &str = "meta:user_count";
+
+/// 用户名/邮箱索引与计数器之外的键才是用户记录
+fn is_user_key(key: &[u8]) -> bool {
+    let key = std::str::from_utf8(key).unwrap_or("");
+    !key.starts_with(USERNAME_PREFIX) && !key.starts_with(EMAIL_PREFIX) && key != USER_COUNT_KEY
+}
+
+fn email_key(email: &str) -> String {
+    format!("{}{}", EMAIL_PREFIX, email)
+}
+
+fn decode_count(raw: &[u8]) -> u64 {
+    raw.try_into().map(u64::from_be_bytes).unwrap_or(0)
+}
 
 #[derive(Clone)]
 pub struct UserStorage {
@@ -15,19 +36,67 @@ impl UserStorage {
     pub async fn new(path: &PathBuf) -> Result<Self, AppError> {
         // sled is synchronous; opening here is cheap and acceptable in async fn
         let db = sled::open(path.join(DB_USERS))?;
-        Ok(Self { db })
+        let storage = Self { db };
+        // 旧库没有计数器：启动时（尚无并发写入）扫描一次补上
+        storage.count().await?;
+        Ok(storage)
+    }
+
+    /// Apply `delta` to the stored count. A missing counter stays missing; `count` rebuilds it.
+    fn adjust_count(&self, delta: i64) -> Result<(), AppError> {
+        self.db.update_and_fetch(USER_COUNT_KEY, |old| {
+            old.map(|raw| decode_count(raw).saturating_add_signed(delta).to_be_bytes().to_vec())
+        })?;
+        Ok(())
+    }
+
+    /// Atomically point `index_key` at `id`; false when it already belongs to another user
+    fn claim(&self, index_key: &str, id: Uuid) -> Result<bool, AppError> {
+        match self.db.compare_and_swap(index_key.as_bytes(), None as Option<&[u8]>, Some(&id.as_bytes()[..]))? {
+            Ok(()) => Ok(true),
+            Err(e) => Ok(e.current.as_deref() == Some(&id.as_bytes()[..])),
+        }
+    }
+
+    /// Count user records by walking the whole tree
+    fn scan_count(&self) -> Result<u64, AppError> {
+        let mut count = 0;
+        for result in self.db.iter() {
+            let (key, _) = result?;
+            if is_user_key(&key) {
+                count += 1;
+            }
+        }
+        Ok(count)
     }
 
-    pub async fn create(&self, user: User) -> Result<(), AppError> {
+    /// 返回写入的用户；用户名已被占用时返回 `UserAlreadyExists`，邮箱已被占用时返回 `EmailAlreadyExists`
+    pub async fn create(&self, user: User) -> Result<User, AppError> {
+        // 先用 CAS 原子地占用用户名索引，并发注册同名用户时只有一个能成功
+        let username_key = format!("{}{}", USERNAME_PREFIX, user.username);
+        let claimed = self.db.compare_and_swap(
+            username_key.as_bytes(),
+            None as Option<&[u8]>,
+            Some(&user.id.as_bytes()[..]),
+        )?;
+        if claimed.is_err() {
+            return Err(AppError::UserAlreadyExists);
+        }
+        if let Some(email) = &user.email
+            && !self.claim(&email_key(email), user.id)?
+        {
+            // 释放刚占用的用户名
+            self.db.remove(username_key.as_bytes())?;
+            return Err(AppError::EmailAlreadyExists);
+        }
+
         let key = user.id.as_bytes();
         let value = serde_json::to_vec(&user)?;
-        self.db.insert(key, value)?;
-        
-        // 创建用户名索引
-        let username_key = format!("username:{}", user.username);
-        self.db.insert(username_key.as_bytes(), user.id.as_bytes())?;
-        
-        Ok(())
+        if self.db.insert(key, value)?.is_none() {
+            self.adjust_count(1)?;
+        }
+
+        Ok(user)
     }
 
     pub async fn get(&self, id: Uuid) -> Result<Option<User>, AppError> {
@@ -41,7 +110,7 @@ impl UserStorage {
     }
 
     pub async fn get_by_username(&self, username: &str) -> Result<Option<User>, AppError> {
-        let username_key = format!("username:{}", username);
+        let username_key = format!("{}{}", USERNAME_PREFIX, username);
         if let Some(user_id_bytes) = self.db.get(username_key.as_bytes())? {
             let user_id = Uuid::from_slice(&user_id_bytes)
                 .map_err(|e| AppError::Internal(e.to_string()))?;
@@ -51,7 +120,31 @@ impl UserStorage {
         }
     }
 
+    /// `email` must already be normalized (see `auth::validation::normalize_email`)
+    pub async fn get_by_email(&self, email: &str) -> Result<Option<User>, AppError> {
+        if let Some(user_id_bytes) = self.db.get(email_key(email).as_bytes())? {
+            let user_id = Uuid::from_slice(&user_id_bytes)
+                .map_err(|e| AppError::Internal(e.to_string()))?;
+            self.get(user_id).await
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// 邮箱变化时先占用新邮箱索引，已被他人占用返回 `EmailAlreadyExists`
     pub async fn update(&self, user: User) -> Result<(), AppError> {
+        let previous_email = self.get(user.id).await?.and_then(|u| u.email);
+        if previous_email != user.email {
+            if let Some(email) = &user.email
+                && !self.claim(&email_key(email), user.id)?
+            {
+                return Err(AppError::EmailAlreadyExists);
+            }
+            if let Some(old) = previous_email {
+                self.db.remove(email_key(&old).as_bytes())?;
+            }
+        }
+
         let key = user.id.as_bytes();
         let value = serde_json::to_vec(&user)?;
         self.db.insert(key, value)?;
@@ -61,12 +154,18 @@ impl UserStorage {
     pub async fn delete(&self, id: Uuid) -> Result<(), AppError> {
         // 先获取用户信息以删除用户名索引
         if let Some(user) = self.get(id).await? {
-            let username_key = format!("username:{}", user.username);
+            let username_key = format!("{}{}", USERNAME_PREFIX, user.username);
             self.db.remove(username_key.as_bytes())?;
+            if let Some(email) = &user.email {
+                self.db.remove(email_key(email).as_bytes())?;
+            }
         }
         
+        // 并发删除同一用户时只有真正移除记录的一方减计数
         let key = id.as_bytes();
-        self.db.remove(key)?;
+        if self.db.remove(key)?.is_some() {
+            self.adjust_count(-1)?;
+        }
         Ok(())
     }
     
@@ -76,8 +175,8 @@ impl UserStorage {
         for result in self.db.iter() {
             let (key, value) = result?;
             
-            // 跳过用户名索引键
-            if std::str::from_utf8(&key).unwrap_or("").starts_with("username:") {
+            // 跳过索引与计数器
+            if !is_user_key(&key) {
                 continue;
             }
             
@@ -91,20 +190,37 @@ impl UserStorage {
         Ok(users)
     }
 
-    pub async fn count(&self) -> Result<usize, AppError> {
-        let mut count = 0;
-        
-        for result in self.db.iter() {
-            let (key, _) = result?;
-            
-            // 跳过用户名索引键
-            if std::str::from_utf8(&key).unwrap_or("").starts_with("username:") {
+    /// 按 id 升序返回 `after` 之后的至多 `limit` 个用户；键即 id 字节，顺序与 Uuid 一致
+    pub async fn list_page(&self, after: Option<Uuid>, limit: usize) -> Result<Vec<User>, AppError> {
+        let start = match after {
+            Some(id) => Bound::Excluded(id.as_bytes().to_vec()),
+            None => Bound::Unbounded,
+        };
+        let mut users = Vec::new();
+        for result in self.db.range((start, Bound::Unbounded)) {
+            if users.len() >= limit {
+                break;
+            }
+            let (key, value) = result?;
+            if !is_user_key(&key) {
                 continue;
             }
-            
-            count += 1;
+            users.push(serde_json::from_slice(&value)?);
+        }
+        Ok(users)
+    }
+
+    /// 直接读取计数器；计数器缺失时全量扫描重建
+    pub async fn count(&self) -> Result<usize, AppError> {
+        if let Some(raw) = self.db.get(USER_COUNT_KEY)? {
+            return Ok(decode_count(&raw) as usize);
+        }
+
+        let scanned = self.scan_count()?;
+        // 另一方已先重建时以其结果为准
+        match self.db.compare_and_swap(USER_COUNT_KEY, None as Option<&[u8]>, Some(&scanned.to_be_bytes()[..]))? {
+            Ok(()) => Ok(scanned as usize),
+            Err(e) => Ok(e.current.as_deref().map(decode_count).unwrap_or(scanned) as usize),
         }
-        
-        Ok(count)
     }
 }
@@ -0,0 +1,120 @@
+use crate::error::AppError;
+use crate::models::{Site, User};
+use async_trait::async_trait;
+use std::path::PathBuf;
+use uuid::Uuid;
+
+// What a users/sites backend has to provide. `UserStorage` / `SiteStorage` hold one of these
+// chosen at runtime from `storage.db`, so a new backend only needs these impls and an arm in
+// their `open`.
+
+#[async_trait]
+pub trait UserStore: Send + Sync {
+    /// Short name for logs and tests: "sled", "orm", "debug", "memory"
+    fn backend(&self) -> &'static str;
+
+    async fn get(&self, id: Uuid) -> Result<Option<User>, AppError>;
+    async fn get_by_username(&self, username: &str) -> Result<Option<User>, AppError>;
+    /// `email` must already be normalized (see `auth::validation::normalize_email`)
+    async fn get_by_email(&self, email: &str) -> Result<Option<User>, AppError>;
+    /// Newest first
+    async fn list_all(&self) -> Result<Vec<User>, AppError>;
+    /// Up to `limit` users with ids greater than `after`, by ascending id; walks every user
+    /// a page at a time
+    async fn list_page(&self, after: Option<Uuid>, limit: usize) -> Result<Vec<User>, AppError>;
+    /// Returns the stored user; `UserAlreadyExists` / `EmailAlreadyExists` when the username
+    /// or email is taken, in which case nothing is written
+    async fn create(&self, user: User) -> Result<User, AppError>;
+    /// `EmailAlreadyExists` when the new email belongs to another user
+    async fn update(&self, user: User) -> Result<(), AppError>;
+    /// Deleting a missing user is not an error
+    async fn delete(&self, id: Uuid) -> Result<(), AppError>;
+    async fn count(&self) -> Result<usize, AppError>;
+}
+
+#[async_trait]
+pub trait SiteStore: Send + Sync {
+    /// Short name for logs and tests: "sled", "orm", "debug", "memory"
+    fn backend(&self) -> &'static str;
+
+    async fn get(&self, id: Uuid) -> Result<Option<Site>, AppError>;
+    /// Newest version (by `created_at`) with this name
+    async fn get_latest_by_name(&self, name: &str) -> Result<Option<Site>, AppError>;
+    /// Every version with this name, newest first
+    async fn get_all_by_name(&self, name: &str) -> Result<Vec<Site>, AppError>;
+    async fn list_all(&self) -> Result<Vec<Site>, AppError>;
+    /// Up to `limit` versions with ids greater than `after`, by ascending id; walks every
+    /// version a page at a time
+    async fn list_page(&self, after: Option<Uuid>, limit: usize) -> Result<Vec<Site>, AppError>;
+    /// Newest first
+    async fn list_by_owner(&self, owner_id: Uuid) -> Result<Vec<Site>, AppError>;
+    /// Newest version of every name, newest first
+    async fn list_latest_per_name(&self) -> Result<Vec<Site>, AppError>;
+    /// Distinct names, ascending
+    async fn list_distinct_names(&self) -> Result<Vec<String>, AppError>;
+    async fn create(&self, site: Site) -> Result<(), AppError>;
+    async fn update(&self, site: Site) -> Result<(), AppError>;
+    /// Removes the record and its UUID directory, and the name directory once no version
+    /// with that name is left
+    async fn delete(&self, id: Uuid) -> Result<(), AppError>;
+    fn get_site_files_path(&self, site_id: Uuid) -> PathBuf;
+    fn get_site_files_path_str(&self, site_id: &str) -> PathBuf;
+}
+
+// The sled, orm and debug implementations keep their inherent methods (debug.rs and the
+// tests call them directly); these impls just forward to them.
+
+macro_rules! impl_user_store {
+    ($ty:ty, $backend:literal) => {
+        #[async_trait]
+        impl UserStore for $ty {
+            fn backend(&self) -> &'static str { $backend }
+            async fn get(&self, id: Uuid) -> Result<Option<User>, AppError> { <$ty>::get(self, id).await }
+            async fn get_by_username(&self, username: &str) -> Result<Option<User>, AppError> { <$ty>::get_by_username(self, username).await }
+            async fn get_by_email(&self, email: &str) -> Result<Option<User>, AppError> { <$ty>::get_by_email(self, email).await }
+            async fn list_all(&self) -> Result<Vec<User>, AppError> { <$ty>::list_all(self).await }
+            async fn list_page(&self, after: Option<Uuid>, limit: usize) -> Result<Vec<User>, AppError> { <$ty>::list_page(self, after, limit).await }
+            async fn create(&self, user: User) -> Result<User, AppError> { <$ty>::create(self, user).await }
+            async fn update(&self, user: User) -> Result<(), AppError> { <$ty>::update(self, user).await }
+            async fn delete(&self, id: Uuid) -> Result<(), AppError> { <$ty>::delete(self, id).await }
+            async fn count(&self) -> Result<usize, AppError> { <$ty>::count(self).await }
+        }
+    };
+}
+
+macro_rules! impl_site_store {
+    ($ty:ty, $backend:literal) => {
+        #[async_trait]
+        impl SiteStore for $ty {
+            fn backend(&self) -> &'static str { $backend }
+            async fn get(&self, id: Uuid) -> Result<Option<Site>, AppError> { <$ty>::get(self, id).await }
+            async fn get_latest_by_name(&self, name: &str) -> Result<Option<Site>, AppError> { <$ty>::get_latest_by_name(self, name).await }
+            async fn get_all_by_name(&self, name: &str) -> Result<Vec<Site>, AppError> { <$ty>::get_all_by_name(self, name).await }
+            async fn list_all(&self) -> Result<Vec<Site>, AppError> { <$ty>::list_all(self).await }
+            async fn list_page(&self, after: Option<Uuid>, limit: usize) -> Result<Vec<Site>, AppError> { <$ty>::list_page(self, after, limit).await }
+            async fn list_by_owner(&self, owner_id: Uuid) -> Result<Vec<Site>, AppError> { <$ty>::list_by_owner(self, owner_id).await }
+            async fn list_latest_per_name(&self) -> Result<Vec<Site>, AppError> { <$ty>::list_latest_per_name(self).await }
+            async fn list_distinct_names(&self) -> Result<Vec<String>, AppError> { <$ty>::list_distinct_names(self).await }
+            async fn create(&self, site: Site) -> Result<(), AppError> { <$ty>::create(self, site).await }
+            async fn update(&self, site: Site) -> Result<(), AppError> { <$ty>::update(self, site).await }
+            async fn delete(&self, id: Uuid) -> Result<(), AppError> { <$ty>::delete(self, id).await }
+            fn get_site_files_path(&self, site_id: Uuid) -> PathBuf { <$ty>::get_site_files_path(self, site_id) }
+            fn get_site_files_path_str(&self, site_id: &str) -> PathBuf { <$ty>::get_site_files_path_str(self, site_id) }
+        }
+    };
+}
+
+#[cfg(feature = "sled")]
+impl_user_store!(crate::storage::sled::UserStorage, "sled");
+#[cfg(feature = "sled")]
+impl_site_store!(crate::storage::sled::SiteStorage, "sled");
+
+#[cfg(feature = "orm")]
+impl_user_store!(crate::storage::orm::UserStorage, "orm");
+#[cfg(feature = "orm")]
+impl_site_store!(crate::storage::orm::SiteStorage, "orm");
+
+#[cfg(feature = "debug_sled_and_orm")]
+impl_user_store!(crate::storage::debug::UserStorage, "debug");
+#[cfg(feature = "debug_sled_and_orm")]
+impl_site_store!(crate::storage::debug::SiteStorage, "debug");
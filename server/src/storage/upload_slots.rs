@@ -0,0 +1,59 @@
+use crate::error::AppError;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use uuid::Uuid;
+
+/// Per-user count of uploads being published (queued for or in archive extraction).
+///
+/// Keeps one user from taking every extraction slot: once a user has `limit` uploads in
+/// flight, further ones are refused instead of queued. A limit of 0 disables the check.
+#[derive(Default)]
+pub struct UploadSlots {
+    limit: usize,
+    in_flight: Mutex<HashMap<Uuid, usize>>,
+}
+
+/// Held for the duration of one upload; dropping it (on any exit path) frees the slot
+pub struct UploadSlot<'a> {
+    slots: &'a UploadSlots,
+    user_id: Uuid,
+}
+
+impl UploadSlots {
+    pub fn new(limit: usize) -> Self {
+        Self { limit, in_flight: Mutex::new(HashMap::new()) }
+    }
+
+    /// Take one of `user_id`'s slots, or `TooManyUploads` when they are all in use
+    pub fn acquire(&self, user_id: Uuid) -> Result<UploadSlot<'_>, AppError> {
+        let mut in_flight = self.in_flight.lock().unwrap_or_else(|e| e.into_inner());
+        let count = in_flight.entry(user_id).or_insert(0);
+        if self.limit > 0 && *count >= self.limit {
+            return Err(AppError::TooManyUploads(self.limit));
+        }
+        *count += 1;
+        Ok(UploadSlot { slots: self, user_id })
+    }
+
+    pub fn in_flight(&self, user_id: Uuid) -> usize {
+        self.in_flight.lock().unwrap_or_else(|e| e.into_inner()).get(&user_id).copied().unwrap_or(0)
+    }
+
+    /// Run `f` with new uploads held off; it is told whether none are in flight
+    pub fn paused<T>(&self, f: impl FnOnce(bool) -> T) -> T {
+        let in_flight = self.in_flight.lock().unwrap_or_else(|e| e.into_inner());
+        f(in_flight.is_empty())
+    }
+}
+
+impl Drop for UploadSlot<'_> {
+    fn drop(&mut self) {
+        let mut in_flight = self.slots.in_flight.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(count) = in_flight.get_mut(&self.user_id) {
+            *count -= 1;
+            if *count == 0 {
+                in_flight.remove(&self.user_id);
+            }
+        }
+    }
+}
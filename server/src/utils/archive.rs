@@ -1,6 +1,12 @@
-use crate::error::AppError;
-use std::{io, pin::pin, path::Path};
-use tokio::{fs::File, io::BufWriter};
+//! Archive validation and extraction (.tar.gz / .tgz / .zip).
+//!
+//! Usable without the server: entry paths are checked against traversal and the
+//! `ArchiveConfig` limits, and failures surface as `AppError::Archive(ArchiveError)`.
+
+pub use crate::{config::{ArchiveConfig, ForbiddenExtensionMode}, error::ArchiveError};
+use crate::{error::AppError, utils::fs::walk_dir};
+use std::{borrow::Cow, io, pin::pin, path::{Component, Path, PathBuf}};
+use tokio::{fs::File, io::{AsyncReadExt, AsyncWriteExt, BufWriter}};
 use tokio_util::io::StreamReader;
 use axum::{
     body::Bytes,
@@ -34,21 +40,320 @@ where
     .map_err(|e| AppError::Internal(e.to_string()))
 }
 
-pub async fn extract_archive(archive_path: &Path, extract_to: &Path) -> Result<(), AppError> {
-    let file_name = archive_path.file_name()
-        .and_then(|n| n.to_str())
-        .unwrap_or("");
+/// Append a `Stream` to the end of a file (created if missing) and return the new file length.
+/// Used for resumable uploads, where each chunk lands after the bytes received so far.
+/// A chunk that would grow the file past `max_len` is dropped again (`PayloadTooLarge`).
+pub async fn append_stream<S, E>(stream: S, path: &Path, max_len: u64) -> Result<u64, AppError>
+where
+    S: Stream<Item = Result<Bytes, E>>,
+    E: Into<BoxError>,
+{
+    let len = async {
+        let body_with_io_error = stream.map_err(io::Error::other);
+        let body_reader = pin!(StreamReader::new(body_with_io_error));
+
+        let file = tokio::fs::OpenOptions::new().create(true).append(true).open(path).await?;
+        let start = file.metadata().await?.len();
+        let mut file = BufWriter::new(file);
+        // One byte past the limit is enough to tell the chunk doesn't fit
+        let mut limited = body_reader.take(max_len.saturating_sub(start).saturating_add(1));
+        tokio::io::copy(&mut limited, &mut file).await?;
+        file.flush().await?;
+
+        let len = file.get_ref().metadata().await?.len();
+        if len > max_len {
+            file.get_ref().set_len(start).await?;
+        }
+        Ok::<_, io::Error>(len)
+    }
+    .await
+    .map_err(|e| AppError::Internal(e.to_string()))?;
+    if len > max_len {
+        return Err(AppError::PayloadTooLarge(max_len));
+    }
+    Ok(len)
+}
+
+/// Reject entries whose path is nested too deeply or has an over-long component,
+/// before they hit OS path limits mid-extraction
+pub fn check_entry_path(path: &Path, limits: &ArchiveConfig) -> Result<(), AppError> {
+    let depth = path.components().count();
+    if depth > limits.max_path_depth {
+        return Err(ArchiveError::PathTooLong(format!(
+            "Archive entry '{}' is nested too deeply ({} > {} levels)",
+            path.display(), depth, limits.max_path_depth
+        )).into());
+    }
+    for component in path.components() {
+        let len = component.as_os_str().len();
+        if len > limits.max_component_length {
+            return Err(ArchiveError::PathTooLong(format!(
+                "Archive entry '{}' has a path component longer than {} bytes",
+                path.display(), limits.max_component_length
+            )).into());
+        }
+    }
+    Ok(())
+}
+
+/// SHA-256 (hex) of the archive file, streamed so large uploads aren't read into memory
+pub fn content_hash(archive_path: &Path) -> Result<String, AppError> {
+    use sha2::{Digest, Sha256};
+
+    let mut file = std::fs::File::open(archive_path)?;
+    let mut hasher = Sha256::new();
+    io::copy(&mut file, &mut hasher)?;
+    Ok(hasher.finalize().iter().map(|b| format!("{:02x}", b)).collect())
+}
+
+/// Entry path relative to the extraction root (leading `./` dropped), or `None`
+/// when it is absolute or climbs out via `..`
+fn contained_path(path: &Path) -> Option<PathBuf> {
+    let mut out = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::Normal(p) => out.push(p),
+            Component::CurDir => {}
+            _ => return None,
+        }
+    }
+    Some(out)
+}
+
+/// Decide whether an entry gets extracted. Links and paths escaping the root are
+/// skipped and explained in `warnings`; limit violations still fail the upload.
+/// Forbidden file types fail it or are skipped, per `forbidden_extension_mode`.
+fn admit_entry(
+    raw: &Path,
+    is_link: bool,
+    limits: &ArchiveConfig,
+    warnings: &mut Vec<String>,
+) -> Result<Option<PathBuf>, AppError> {
+    if is_link {
+        warnings.push(format!("Skipped '{}': links are not extracted", raw.display()));
+        return Ok(None);
+    }
+    let Some(path) = contained_path(raw) else {
+        warnings.push(format!("Skipped '{}': path points outside the site directory", raw.display()));
+        return Ok(None);
+    };
+    if path.as_os_str().is_empty() {
+        return Ok(None);
+    }
+    check_entry_path(&path, limits)?;
+    if limits.is_forbidden(&path) {
+        if limits.forbidden_extension_mode == ForbiddenExtensionMode::Reject {
+            return Err(ArchiveError::ForbiddenFile(path.display().to_string()).into());
+        }
+        warnings.push(format!("Skipped '{}': file type may not be published", path.display()));
+        return Ok(None);
+    }
+    Ok(Some(path))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArchiveFormat {
+    TarGz,
+    Zip,
+}
+
+impl ArchiveFormat {
+    /// Format implied by the file extension
+    pub fn from_file_name(archive_path: &Path) -> Result<Self, AppError> {
+        let file_name = archive_path.file_name()
+            .and_then(|n| n.to_str())
+            .unwrap_or("");
+
+        if file_name.ends_with(".tar.gz") || file_name.ends_with(".tgz") {
+            Ok(ArchiveFormat::TarGz)
+        } else if file_name.ends_with(".zip") {
+            Ok(ArchiveFormat::Zip)
+        } else {
+            Err(ArchiveError::UnsupportedFormat("Unsupported archive format; expected .tar.gz, .tgz or .zip".to_string()).into())
+        }
+    }
+
+    /// Format detected from the leading magic bytes, if recognised
+    pub fn detect(archive_path: &Path) -> Option<Self> {
+        use std::io::Read;
+
+        let mut magic = [0u8; 4];
+        let mut file = std::fs::File::open(archive_path).ok()?;
+        let n = file.read(&mut magic).ok()?;
+        match &magic[..n] {
+            [0x1f, 0x8b, ..] => Some(ArchiveFormat::TarGz),
+            [b'P', b'K', 0x03, 0x04] | [b'P', b'K', 0x05, 0x06] => Some(ArchiveFormat::Zip),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            ArchiveFormat::TarGz => "tar.gz",
+            ArchiveFormat::Zip => "zip",
+        }
+    }
+}
+
+/// Decoding failure inside the archive (as opposed to writing the extracted files)
+fn corrupt(err: impl std::fmt::Display) -> AppError {
+    ArchiveError::Corrupt(err.to_string()).into()
+}
+
+/// When decoding fails and the content looks like a different format than the
+/// extension claims, say so instead of reporting a corrupt archive
+fn explain_format_mismatch(archive_path: &Path, expected: ArchiveFormat, err: AppError) -> AppError {
+    if !matches!(err, AppError::Archive(ArchiveError::Corrupt(_)) | AppError::Io(_)) {
+        return err;
+    }
+    match ArchiveFormat::detect(archive_path) {
+        Some(detected) if detected != expected => ArchiveError::UnsupportedFormat(format!(
+            "Archive '{}' has a .{} extension but its content looks like {}; rename the file or re-create the archive",
+            archive_path.file_name().map(|n| n.to_string_lossy()).unwrap_or_default(),
+            expected.name(),
+            detected.name(),
+        )).into(),
+        _ => err,
+    }
+}
+
+/// Read through the archive without writing anything: it must decode in the format its
+/// extension names, every entry must pass the same checks as extraction, and it must hold
+/// at least one file including a top-level `index.html`, within the entry count and
+/// declared size limits. Runs before any live site directory is touched, so a bad upload
+/// leaves the current site as it was.
+/// With `root_dir`, that subdirectory must exist and hold the `index.html` instead.
+pub fn validate_archive(archive_path: &Path, limits: &ArchiveConfig, root_dir: Option<&Path>) -> Result<(), AppError> {
+    let format = ArchiveFormat::from_file_name(archive_path)?;
+    let res = match format {
+        ArchiveFormat::TarGz => list_tar_gz_files(archive_path, limits),
+        ArchiveFormat::Zip => list_zip_files(archive_path, limits),
+    };
+    let files = res.map_err(|e| explain_format_mismatch(archive_path, format, e))?;
+
+    if files.is_empty() {
+        return Err(ArchiveError::Empty.into());
+    }
+    let Some(root_dir) = root_dir else {
+        if !files.iter().any(|p| p == Path::new("index.html")) {
+            return Err(ArchiveError::NoIndexHtml("archive has no index.html at its top level".to_string()).into());
+        }
+        return Ok(());
+    };
+    if !files.iter().any(|p| p.starts_with(root_dir)) {
+        return Err(ArchiveError::RootDirNotFound(root_dir.display().to_string()).into());
+    }
+    if !files.iter().any(|p| *p == root_dir.join("index.html")) {
+        return Err(ArchiveError::NoIndexHtml(format!("rootDir '{}' has no index.html", root_dir.display())).into());
+    }
+    Ok(())
+}
+
+/// Running entry count and declared uncompressed size while listing an archive
+struct EntryBudget<'a> {
+    limits: &'a ArchiveConfig,
+    entries: usize,
+    bytes: u64,
+}
+
+impl<'a> EntryBudget<'a> {
+    fn new(limits: &'a ArchiveConfig) -> Self {
+        Self { limits, entries: 0, bytes: 0 }
+    }
+
+    fn charge(&mut self, size: u64) -> Result<(), AppError> {
+        self.entries += 1;
+        if self.entries > self.limits.max_entries {
+            return Err(ArchiveError::TooManyEntries(self.limits.max_entries).into());
+        }
+        self.bytes = self.bytes.saturating_add(size);
+        if self.bytes > self.limits.max_total_bytes {
+            return Err(ArchiveError::TooLarge(self.limits.max_total_bytes).into());
+        }
+        Ok(())
+    }
+}
+
+/// Parse the `rootDir` upload field into a relative path inside the archive.
+/// Blank or `.` means the archive root; absolute paths and `..` are rejected.
+pub fn parse_root_dir(raw: &str) -> Result<Option<PathBuf>, AppError> {
+    let raw = raw.trim();
+    let path = contained_path(Path::new(raw))
+        .ok_or_else(|| ArchiveError::PathTraversal(raw.to_string()))?;
+    Ok((!path.as_os_str().is_empty()).then_some(path))
+}
+
+/// Paths of the regular files extraction would write, relative to the site root
+/// Pax headers and GNU long name/link records describe the entry after them; `tar` folds
+/// them into that entry's path, so they are skipped here rather than written out as files
+fn is_tar_meta_entry(entry_type: tar::EntryType) -> bool {
+    entry_type.is_pax_local_extensions()
+        || entry_type.is_pax_global_extensions()
+        || entry_type.is_gnu_longname()
+        || entry_type.is_gnu_longlink()
+}
+
+fn list_tar_gz_files(archive_path: &Path, limits: &ArchiveConfig) -> Result<Vec<PathBuf>, AppError> {
+    use flate2::read::GzDecoder;
+    use tar::Archive;
 
-    if file_name.ends_with(".tar.gz") || file_name.ends_with(".tgz") {
-        extract_tar_gz(archive_path, extract_to).await
-    } else if file_name.ends_with(".zip") {
-        extract_zip(archive_path, extract_to).await
-    } else {
-        Err(AppError::InvalidInput("Unsupported archive format".to_string()))
+    let mut archive = Archive::new(GzDecoder::new(std::fs::File::open(archive_path)?));
+    let mut budget = EntryBudget::new(limits);
+    let mut skipped = Vec::new();
+    let mut files = Vec::new();
+    for entry_res in archive.entries().map_err(corrupt)? {
+        let entry = entry_res.map_err(corrupt)?;
+        if is_tar_meta_entry(entry.header().entry_type()) {
+            continue;
+        }
+        budget.charge(entry.size())?;
+        let raw = entry.path()
+            .map_err(corrupt)?
+            .into_owned();
+        let entry_type = entry.header().entry_type();
+        let is_link = entry_type.is_symlink() || entry_type.is_hard_link();
+        if let Some(path) = admit_entry(&raw, is_link, limits, &mut skipped)?
+            && !entry_type.is_dir()
+        {
+            files.push(path);
+        }
     }
+    Ok(files)
 }
 
-pub async fn extract_tar_gz(archive_path: &Path, extract_to: &Path) -> Result<(), AppError> {
+fn list_zip_files(archive_path: &Path, limits: &ArchiveConfig) -> Result<Vec<PathBuf>, AppError> {
+    use zip::ZipArchive;
+
+    let mut archive = ZipArchive::new(std::fs::File::open(archive_path)?)
+        .map_err(corrupt)?;
+    let mut budget = EntryBudget::new(limits);
+    let mut skipped = Vec::new();
+    let mut files = Vec::new();
+    for i in 0..archive.len() {
+        let file = archive.by_index(i)
+            .map_err(corrupt)?;
+        budget.charge(file.size())?;
+        if let Some(path) = admit_entry(Path::new(file.name()), file.is_symlink(), limits, &mut skipped)?
+            && !file.name().ends_with('/')
+        {
+            files.push(path);
+        }
+    }
+    Ok(files)
+}
+
+/// Extract into `extract_to` (created if missing), format chosen by the file extension.
+/// Returns one warning per entry that was skipped instead of extracted verbatim
+pub async fn extract_archive(archive_path: &Path, extract_to: &Path, limits: &ArchiveConfig) -> Result<Vec<String>, AppError> {
+    let format = ArchiveFormat::from_file_name(archive_path)?;
+    let res = match format {
+        ArchiveFormat::TarGz => extract_tar_gz(archive_path, extract_to, limits).await,
+        ArchiveFormat::Zip => extract_zip(archive_path, extract_to, limits).await,
+    };
+    res.map_err(|e| explain_format_mismatch(archive_path, format, e))
+}
+
+pub async fn extract_tar_gz(archive_path: &Path, extract_to: &Path, limits: &ArchiveConfig) -> Result<Vec<String>, AppError> {
     debug!("Extracting tar.gz archive {:?} to {:?}", archive_path, extract_to);
     use flate2::read::GzDecoder;
     use std::fs::File;
@@ -57,25 +362,48 @@ pub async fn extract_tar_gz(archive_path: &Path, extract_to: &Path) -> Result<()
     let file = File::open(archive_path)?;
     let gz = GzDecoder::new(file);
     let mut archive = Archive::new(gz);
-    
-    archive.unpack(extract_to)
-        .map_err(|e| AppError::Internal(e.to_string()))?;
-    Ok(())
+
+    std::fs::create_dir_all(extract_to)?;
+    let mut warnings = Vec::new();
+    for entry_res in archive.entries()? {
+        let mut entry = entry_res.map_err(corrupt)?;
+        if is_tar_meta_entry(entry.header().entry_type()) {
+            continue;
+        }
+        let raw = entry.path()
+            .map_err(corrupt)?
+            .into_owned();
+        let entry_type = entry.header().entry_type();
+        let is_link = entry_type.is_symlink() || entry_type.is_hard_link();
+        if admit_entry(&raw, is_link, limits, &mut warnings)?.is_none() {
+            continue;
+        }
+        let unpacked = entry.unpack_in(extract_to)
+            .map_err(|e| AppError::Internal(e.to_string()))?;
+        if !unpacked {
+            warnings.push(format!("Skipped '{}': path points outside the site directory", raw.display()));
+        }
+    }
+    Ok(warnings)
 }
 
-async fn extract_zip(archive_path: &Path, extract_to: &Path) -> Result<(), AppError> {
+async fn extract_zip(archive_path: &Path, extract_to: &Path, limits: &ArchiveConfig) -> Result<Vec<String>, AppError> {
     debug!("Extracting zip archive {:?} to {:?}", archive_path, extract_to);
     use std::fs::File;
     use zip::ZipArchive;
 
     let file = File::open(archive_path)?;
     let mut archive = ZipArchive::new(file)
-        .map_err(|e| AppError::Internal(e.to_string()))?;
+        .map_err(corrupt)?;
 
+    let mut warnings = Vec::new();
     for i in 0..archive.len() {
         let mut file = archive.by_index(i)
-            .map_err(|e| AppError::Internal(e.to_string()))?;
-        let outpath = extract_to.join(file.name());
+            .map_err(corrupt)?;
+        let Some(path) = admit_entry(Path::new(file.name()), file.is_symlink(), limits, &mut warnings)? else {
+            continue;
+        };
+        let outpath = extract_to.join(path);
 
         if file.name().ends_with('/') {
             tokio::fs::create_dir_all(&outpath).await?;
@@ -88,32 +416,54 @@ async fn extract_zip(archive_path: &Path, extract_to: &Path) -> Result<(), AppEr
                 .map_err(|e| AppError::Internal(e.to_string()))?;
         }
     }
-    Ok(())
+    Ok(warnings)
+}
+
+/// The siteName copy of a file: UTF-8 text has `pattern` replaced, binary files are kept as-is
+fn replaced_bytes<'a>(buf: &'a [u8], replacement: Option<&(String, String)>) -> Cow<'a, [u8]> {
+    match (replacement, std::str::from_utf8(buf)) {
+        (Some((pattern, replacement)), Ok(text)) => Cow::Owned(text.replace(pattern.as_str(), replacement).into_bytes()),
+        _ => Cow::Borrowed(buf),
+    }
+}
+
+/// Rebuild `dst` from an already-extracted tree at `src` with the same replacement
+/// rules as `extract_archive_with_replace`; `dst` must not exist yet
+pub fn replace_in_directory(src: &Path, dst: &Path, replacement: (String, String)) -> Result<(), AppError> {
+    std::fs::create_dir(dst)?;
+    walk_dir(src, |entry| {
+        let Ok(relative) = entry.path.strip_prefix(src) else { return Ok(()) };
+        let target = dst.join(relative);
+        if entry.metadata.is_dir() {
+            std::fs::create_dir_all(&target)?;
+        } else if entry.metadata.is_file() {
+            let buf = std::fs::read(&entry.path)?;
+            std::fs::write(&target, replaced_bytes(&buf, Some(&replacement)))?;
+        }
+        Ok(())
+    })
 }
 
 pub async fn extract_archive_with_replace(
     archive_path: &Path,
     extract_to: &Path,
     replacement: Option<(String, String)>,
-) -> Result<(), AppError> {
-    let file_name = archive_path.file_name()
-        .and_then(|n| n.to_str())
-        .unwrap_or("");
-
-    if file_name.ends_with(".tar.gz") || file_name.ends_with(".tgz") {
-        extract_tar_gz_with_replace(archive_path, extract_to, replacement).await
-    } else if file_name.ends_with(".zip") {
-        extract_zip_with_replace(archive_path, extract_to, replacement).await
-    } else {
-        Err(AppError::InvalidInput("Unsupported archive format".to_string()))
-    }
+    limits: &ArchiveConfig,
+) -> Result<Vec<String>, AppError> {
+    let format = ArchiveFormat::from_file_name(archive_path)?;
+    let res = match format {
+        ArchiveFormat::TarGz => extract_tar_gz_with_replace(archive_path, extract_to, replacement, limits).await,
+        ArchiveFormat::Zip => extract_zip_with_replace(archive_path, extract_to, replacement, limits).await,
+    };
+    res.map_err(|e| explain_format_mismatch(archive_path, format, e))
 }
 
 pub async fn extract_tar_gz_with_replace(
     archive_path: &Path,
     extract_to: &Path,
     replacement: Option<(String, String)>,
-) -> Result<(), AppError> {
+    limits: &ArchiveConfig,
+) -> Result<Vec<String>, AppError> {
     debug!("Extracting tar.gz archive with optional replace {:?} to {:?}", archive_path, extract_to);
 
     use flate2::read::GzDecoder;
@@ -131,11 +481,20 @@ pub async fn extract_tar_gz_with_replace(
     std::fs::create_dir_all(&original_dir)?;
     std::fs::create_dir_all(&replaced_dir)?;
 
+    let mut warnings = Vec::new();
     for entry_res in archive.entries()? {
-        let mut entry = entry_res.map_err(|e| AppError::Internal(e.to_string()))?;
-        let path = match entry.path() {
+        let mut entry = entry_res.map_err(corrupt)?;
+        if is_tar_meta_entry(entry.header().entry_type()) {
+            continue;
+        }
+        let raw = match entry.path() {
             Ok(p) => p.into_owned(),
-            Err(e) => return Err(AppError::Internal(e.to_string())),
+            Err(e) => return Err(corrupt(e)),
+        };
+        let entry_type = entry.header().entry_type();
+        let is_link = entry_type.is_symlink() || entry_type.is_hard_link();
+        let Some(path) = admit_entry(&raw, is_link, limits, &mut warnings)? else {
+            continue;
         };
 
         let out_original = original_dir.join(&path);
@@ -157,34 +516,23 @@ pub async fn extract_tar_gz_with_replace(
         // Read entry into memory (per-file streaming)
         let mut buf = Vec::new();
         entry.read_to_end(&mut buf)
-            .map_err(|e| AppError::Internal(e.to_string()))?;
+            .map_err(corrupt)?;
 
         // write original bytes
         std::fs::write(&out_original, &buf)?;
 
-        // if replacement provided and file is valid UTF-8, do text replace
-        if let Some((ref pattern, ref replacement)) = replacement {
-            if let Ok(text) = String::from_utf8(buf.clone()) {
-                let replaced_text = text.replace(pattern, replacement);
-                std::fs::write(&out_replaced, replaced_text.as_bytes())?;
-            } else {
-                // binary file, just write original bytes into replaced folder as well
-                std::fs::write(&out_replaced, &buf)?;
-            }
-        } else {
-            // no replacement requested, just copy original to replaced folder as-is
-            std::fs::write(&out_replaced, &buf)?;
-        }
+        std::fs::write(&out_replaced, replaced_bytes(&buf, replacement.as_ref()))?;
     }
 
-    Ok(())
+    Ok(warnings)
 }
 
 async fn extract_zip_with_replace(
     archive_path: &Path,
     extract_to: &Path,
     replacement: Option<(String, String)>,
-) -> Result<(), AppError> {
+    limits: &ArchiveConfig,
+) -> Result<Vec<String>, AppError> {
     debug!("Extracting zip archive with optional replace {:?} to {:?}", archive_path, extract_to);
     use std::fs::File;
     use zip::ZipArchive;
@@ -192,19 +540,22 @@ async fn extract_zip_with_replace(
 
     let file = File::open(archive_path)?;
     let mut archive = ZipArchive::new(file)
-        .map_err(|e| AppError::Internal(e.to_string()))?;
+        .map_err(corrupt)?;
 
     let original_dir = extract_to.join("original");
     let replaced_dir = extract_to.join("replaced");
     std::fs::create_dir_all(&original_dir)?;
     std::fs::create_dir_all(&replaced_dir)?;
 
+    let mut warnings = Vec::new();
     for i in 0..archive.len() {
         let mut file = archive.by_index(i)
-            .map_err(|e| AppError::Internal(e.to_string()))?;
+            .map_err(corrupt)?;
 
-        // Use sanitized name when available to avoid absolute paths
-        let name = file.name().to_string();
+        // Absolute paths, `..` and links are skipped rather than written outside extract_to
+        let Some(name) = admit_entry(Path::new(file.name()), file.is_symlink(), limits, &mut warnings)? else {
+            continue;
+        };
         let out_original = original_dir.join(&name);
         let out_replaced = replaced_dir.join(&name);
 
@@ -223,21 +574,12 @@ async fn extract_zip_with_replace(
 
         let mut buf = Vec::new();
         file.read_to_end(&mut buf)
-            .map_err(|e| AppError::Internal(e.to_string()))?;
+            .map_err(corrupt)?;
 
         // write original
         std::fs::write(&out_original, &buf)?;
 
-        if let Some((ref pattern, ref replacement)) = replacement {
-            if let Ok(text) = String::from_utf8(buf.clone()) {
-                let replaced_text = text.replace(pattern, replacement);
-                std::fs::write(&out_replaced, replaced_text.as_bytes())?;
-            } else {
-                std::fs::write(&out_replaced, &buf)?;
-            }
-        } else {
-            std::fs::write(&out_replaced, &buf)?;
-        }
+        std::fs::write(&out_replaced, replaced_bytes(&buf, replacement.as_ref()))?;
     }
-    Ok(())
+    Ok(warnings)
 }
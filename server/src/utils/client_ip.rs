@@ -0,0 +1,34 @@
+use axum::http::HeaderMap;
+use std::net::IpAddr;
+
+/// The address a request came from. Forwarding headers are only believed when `peer` (the
+/// socket address) is one of `trusted_proxies`; anyone else could set them to anything.
+///
+/// `X-Forwarded-For` is read right to left, skipping further trusted proxies, so the result is
+/// the last hop no trusted proxy vouches for. `X-Real-IP` is used when there is no
+/// `X-Forwarded-For`. Unparsable headers fall back to `peer`.
+pub fn client_ip(peer: Option<IpAddr>, headers: &HeaderMap, trusted_proxies: &[IpAddr]) -> Option<IpAddr> {
+    let peer = peer?;
+    if !trusted_proxies.contains(&peer) {
+        return Some(peer);
+    }
+
+    let forwarded: Vec<IpAddr> = headers
+        .get_all("x-forwarded-for")
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','))
+        .filter_map(|hop| hop.trim().parse().ok())
+        .collect();
+    if let Some(first) = forwarded.first() {
+        // Every hop is a trusted proxy: the leftmost is the closest we have to a client
+        let hop = forwarded.iter().rev().find(|ip| !trusted_proxies.contains(ip)).unwrap_or(first);
+        return Some(*hop);
+    }
+
+    headers
+        .get("x-real-ip")
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.trim().parse().ok())
+        .or(Some(peer))
+}
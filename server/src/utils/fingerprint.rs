@@ -0,0 +1,128 @@
+use crate::{error::AppError, utils::fs::walk_dir};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::path::{Component, Path, PathBuf};
+
+/// Hex characters of the asset's SHA-256 kept in `?v=`
+pub const FINGERPRINT_LEN: usize = 12;
+
+/// Attributes whose quoted value is treated as a link
+const LINK_ATTRIBUTES: [&str; 2] = ["href", "src"];
+
+/// Rewrite local asset links in every `.html` file below `site_dir` to `<link>?v=<hash>`,
+/// the hash being taken from the linked file's content. Absolute links count as local when
+/// they start with `url_prefix` (e.g. `/sites/notes/`); links to pages, other absolute or
+/// external URLs, links that already carry a query or fragment, and links to missing files
+/// are left alone. Returns the number of links rewritten.
+pub fn fingerprint_assets(site_dir: &Path, url_prefix: &str) -> Result<usize, AppError> {
+    let mut pages = Vec::new();
+    walk_dir(site_dir, |entry| {
+        if entry.metadata.is_file() && is_page(&entry.path) {
+            pages.push(entry.path.clone());
+        }
+        Ok(())
+    })?;
+
+    let mut hashes: HashMap<PathBuf, String> = HashMap::new();
+    let mut rewritten = 0;
+    for page in pages {
+        // Non-UTF-8 pages are served as uploaded
+        let Ok(html) = std::fs::read_to_string(&page) else { continue };
+        let page_dir = page.parent().unwrap_or(site_dir);
+        let mut count = 0;
+        let updated = rewrite_links(&html, |url| {
+            let asset = resolve_asset(site_dir, page_dir, url_prefix, url)?;
+            let hash = match hashes.get(&asset) {
+                Some(hash) => hash.clone(),
+                None => {
+                    let hash = file_fingerprint(&asset).ok()?;
+                    hashes.insert(asset, hash.clone());
+                    hash
+                }
+            };
+            count += 1;
+            Some(format!("{}?v={}", url, hash))
+        });
+        if count > 0 {
+            std::fs::write(&page, updated)?;
+            rewritten += count;
+        }
+    }
+    Ok(rewritten)
+}
+
+fn is_page(path: &Path) -> bool {
+    path.extension()
+        .and_then(|e| e.to_str())
+        .is_some_and(|e| e.eq_ignore_ascii_case("html") || e.eq_ignore_ascii_case("htm"))
+}
+
+/// First `FINGERPRINT_LEN` hex characters of the file's SHA-256
+fn file_fingerprint(path: &Path) -> Result<String, AppError> {
+    let mut file = std::fs::File::open(path)?;
+    let mut hasher = Sha256::new();
+    std::io::copy(&mut file, &mut hasher)?;
+    let hex: String = hasher.finalize().iter().map(|b| format!("{:02x}", b)).collect();
+    Ok(hex[..FINGERPRINT_LEN].to_string())
+}
+
+/// Replace every quoted `href`/`src` value for which `rewrite` returns `Some`
+fn rewrite_links(html: &str, mut rewrite: impl FnMut(&str) -> Option<String>) -> String {
+    let mut out = String::with_capacity(html.len());
+    let mut rest = html;
+    while let Some(value_start) = next_attribute_value(rest) {
+        let quote = rest.as_bytes()[value_start - 1] as char;
+        let Some(len) = rest[value_start..].find(quote) else { break };
+        let url = &rest[value_start..value_start + len];
+        out.push_str(&rest[..value_start]);
+        match rewrite(url) {
+            Some(replaced) => out.push_str(&replaced),
+            None => out.push_str(url),
+        }
+        rest = &rest[value_start + len..];
+    }
+    out.push_str(rest);
+    out
+}
+
+/// Byte offset just past the opening quote of the next `href="…"` or `src='…'` value
+fn next_attribute_value(html: &str) -> Option<usize> {
+    let bytes = html.as_bytes();
+    html.match_indices('=').map(|(eq, _)| eq).find(|&eq| {
+        let quoted = matches!(bytes.get(eq + 1), Some(b'"' | b'\''));
+        // The name must start right after whitespace: `data-src=` is not a link the browser loads
+        quoted && LINK_ATTRIBUTES.iter().any(|attr| {
+            eq > attr.len()
+                && bytes[eq - attr.len()..eq].eq_ignore_ascii_case(attr.as_bytes())
+                && bytes[eq - attr.len() - 1].is_ascii_whitespace()
+        })
+    }).map(|eq| eq + 2)
+}
+
+/// The file a link points at, if it is a local, non-page asset inside `site_dir`
+fn resolve_asset(site_dir: &Path, page_dir: &Path, url_prefix: &str, url: &str) -> Option<PathBuf> {
+    if url.is_empty() || url.starts_with("//") || url.contains([':', '?', '#']) {
+        return None;
+    }
+    let (base, relative) = match url.strip_prefix(url_prefix) {
+        Some(relative) => (site_dir, relative),
+        None if url.starts_with('/') => return None,
+        None => (page_dir, url),
+    };
+    if relative.ends_with('/') || is_page(Path::new(relative)) {
+        return None;
+    }
+
+    let mut path = base.to_path_buf();
+    for component in Path::new(relative).components() {
+        match component {
+            Component::Normal(part) => path.push(part),
+            Component::CurDir => {}
+            Component::ParentDir => {
+                path.pop();
+            }
+            _ => return None,
+        }
+    }
+    (path.starts_with(site_dir) && path.is_file()).then_some(path)
+}
@@ -0,0 +1,129 @@
+use crate::error::AppError;
+use std::fs::Metadata;
+use std::path::{Path, PathBuf};
+
+use tracing::debug;
+
+/// One file or directory found by `walk_dir`
+pub struct WalkEntry {
+    pub path: PathBuf,
+    /// Metadata of the entry itself; symlinks are reported, not followed
+    pub metadata: Metadata,
+}
+
+/// Visit every entry below `root` (not `root` itself), each directory before its contents.
+/// Entries deleted mid-walk (e.g. a concurrent site delete) are skipped rather than failing
+/// the walk, and a missing `root` is an empty tree.
+pub fn walk_dir(root: &Path, mut visit: impl FnMut(&WalkEntry) -> Result<(), AppError>) -> Result<(), AppError> {
+    let mut stack = vec![root.to_path_buf()];
+    while let Some(dir) = stack.pop() {
+        let Some(entries) = unless_vanished(&dir, std::fs::read_dir(&dir))? else { continue };
+        for entry in entries {
+            let Some(entry) = unless_vanished(&dir, entry)? else { continue };
+            let path = entry.path();
+            let Some(metadata) = unless_vanished(&path, entry.metadata())? else { continue };
+            let entry = WalkEntry { path, metadata };
+            visit(&entry)?;
+            if entry.metadata.is_dir() {
+                stack.push(entry.path);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// NotFound means the entry disappeared while walking; anything else is a real error
+fn unless_vanished<T>(path: &Path, res: std::io::Result<T>) -> Result<Option<T>, AppError> {
+    match res {
+        Ok(v) => Ok(Some(v)),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            debug!("{:?} vanished during directory walk; skipping", path);
+            Ok(None)
+        }
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Total bytes and number of regular files below `root`
+pub fn dir_size_and_count(root: &Path) -> Result<(u64, u64), AppError> {
+    let mut total: u64 = 0;
+    let mut count: u64 = 0;
+    walk_dir(root, |entry| {
+        if entry.metadata.is_file() {
+            total += entry.metadata.len();
+            count += 1;
+        }
+        Ok(())
+    })?;
+    Ok((total, count))
+}
+
+/// Regular files below `root`, relative to it and sorted
+pub fn list_files(root: &Path) -> Result<Vec<PathBuf>, AppError> {
+    let mut files = Vec::new();
+    walk_dir(root, |entry| {
+        if entry.metadata.is_file()
+            && let Ok(relative) = entry.path.strip_prefix(root)
+        {
+            files.push(relative.to_path_buf());
+        }
+        Ok(())
+    })?;
+    files.sort();
+    Ok(files)
+}
+
+/// Copy the directories and regular files below `src` into `dst` (created if missing).
+/// Symlinks are not copied; files that vanish mid-copy are skipped.
+pub fn copy_dir_recursive(src: &Path, dst: &Path) -> Result<(), AppError> {
+    std::fs::create_dir_all(dst)?;
+    walk_dir(src, |entry| {
+        let Ok(relative) = entry.path.strip_prefix(src) else { return Ok(()) };
+        let target = dst.join(relative);
+        if entry.metadata.is_dir() {
+            std::fs::create_dir_all(&target)?;
+        } else if entry.metadata.is_file() {
+            unless_vanished(&entry.path, std::fs::copy(&entry.path, &target))?;
+        }
+        Ok(())
+    })
+}
+
+/// Removes the files and directories it tracks when dropped, so an early return or a
+/// cancelled future (client gone, timeout) doesn't leave upload temp artifacts behind.
+/// Removal is synchronous and best-effort; `keep` disarms it.
+#[derive(Debug, Default)]
+pub struct TempGuard {
+    paths: Vec<PathBuf>,
+}
+
+impl TempGuard {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn track(&mut self, path: impl Into<PathBuf>) {
+        self.paths.push(path.into());
+    }
+
+    /// Stop tracking everything and hand the paths back, e.g. to log what was kept
+    pub fn keep(mut self) -> Vec<PathBuf> {
+        std::mem::take(&mut self.paths)
+    }
+}
+
+impl Drop for TempGuard {
+    fn drop(&mut self) {
+        for path in self.paths.drain(..) {
+            let removed = match std::fs::symlink_metadata(&path) {
+                Ok(meta) if meta.is_dir() => std::fs::remove_dir_all(&path),
+                Ok(_) => std::fs::remove_file(&path),
+                Err(_) => continue,
+            };
+            match removed {
+                Ok(()) => debug!("Removed temp path {:?}", path),
+                Err(e) => debug!("Could not remove temp path {:?}: {}", path, e),
+            }
+        }
+    }
+}
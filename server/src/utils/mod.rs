@@ -1,4 +1,12 @@
 #![cfg_attr(debug_assertions, allow(dead_code))]
 pub mod archive;
+pub mod client_ip;
+pub mod fingerprint;
+pub mod fs;
+pub mod pagination;
 pub mod parse_args;
-pub mod secrets;
\ No newline at end of file
+pub mod redirects;
+pub mod request_id;
+pub mod secrets;
+pub mod sitemap;
+pub mod text;
\ No newline at end of file
@@ -0,0 +1,93 @@
+use axum::http::{HeaderMap, HeaderValue};
+use std::collections::HashMap;
+
+/// 分页参数，从查询字符串中的 `offset` / `limit` 解析
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Page {
+    pub offset: usize,
+    pub limit: Option<usize>,
+}
+
+impl Page {
+    /// 从查询参数中解析分页信息；无法解析的值按未提供处理
+    pub fn from_params(params: &HashMap<String, String>) -> Self {
+        let offset = params.get("offset").and_then(|v| v.parse().ok()).unwrap_or(0);
+        let limit = params.get("limit").and_then(|v| v.parse().ok()).filter(|l| *l > 0);
+        Self { offset, limit }
+    }
+
+    /// 对已加载的完整列表切片，返回当前页
+    pub fn apply<T>(&self, items: Vec<T>) -> Vec<T> {
+        let iter = items.into_iter().skip(self.offset);
+        match self.limit {
+            Some(limit) => iter.take(limit).collect(),
+            None => iter.collect(),
+        }
+    }
+}
+
+/// 构建分页响应头：`X-Total-Count`、`X-Offset` 以及（如有）`Link` rel=next/prev
+///
+/// `Link` 中保留请求里的其它查询参数（例如 admin 接口的 `key`），仅替换 offset/limit。
+pub fn pagination_headers(
+    base_url: &str,
+    path: &str,
+    params: &HashMap<String, String>,
+    page: &Page,
+    total: usize,
+) -> HeaderMap {
+    let mut headers = HeaderMap::new();
+    headers.insert("X-Total-Count", HeaderValue::from(total));
+    headers.insert("X-Offset", HeaderValue::from(page.offset));
+
+    let Some(limit) = page.limit else {
+        return headers;
+    };
+
+    let mut links = Vec::new();
+    if page.offset + limit < total {
+        links.push(format!("<{}>; rel=\"next\"", page_url(base_url, path, params, page.offset + limit, limit)));
+    }
+    if page.offset > 0 {
+        let prev = page.offset.saturating_sub(limit);
+        links.push(format!("<{}>; rel=\"prev\"", page_url(base_url, path, params, prev, limit)));
+    }
+
+    if !links.is_empty()
+        && let Ok(v) = HeaderValue::from_str(&links.join(", "))
+    {
+        headers.insert(axum::http::header::LINK, v);
+    }
+
+    headers
+}
+
+fn page_url(base_url: &str, path: &str, params: &HashMap<String, String>, offset: usize, limit: usize) -> String {
+    // 按 key 排序，保证生成的链接稳定
+    let mut pairs: Vec<(&String, &String)> = params
+        .iter()
+        .filter(|(k, _)| k.as_str() != "offset" && k.as_str() != "limit")
+        .collect();
+    pairs.sort();
+
+    let mut query: Vec<String> = pairs
+        .into_iter()
+        .map(|(k, v)| format!("{}={}", encode_query_component(k), encode_query_component(v)))
+        .collect();
+    query.push(format!("offset={}", offset));
+    query.push(format!("limit={}", limit));
+
+    format!("{}{}?{}", base_url.trim_end_matches('/'), path, query.join("&"))
+}
+
+/// 最小化的 percent-encoding，仅保留 RFC 3986 unreserved 字符
+fn encode_query_component(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for b in s.bytes() {
+        match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => out.push(b as char),
+            _ => out.push_str(&format!("%{:02X}", b)),
+        }
+    }
+    out
+}
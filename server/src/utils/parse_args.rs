@@ -1,25 +1,91 @@
-/// 解析命令行，仅支持 --config <path> 和 --help/-h
-pub fn parse_args(args: &[String]) -> (bool, String) {
-    // 默认配置路径
-    let mut config_path = "config.json".to_string();
-    let mut show_help = false;
+/// 指定配置文件路径的环境变量（容器里不方便传参时使用）
+pub const CONFIG_PATH_ENV: &str = "OBSIDIAN_CONFIG_PATH";
+pub const DEFAULT_CONFIG_PATH: &str = "config.json";
+
+/// 命令行参数
+#[derive(Debug)]
+pub struct Args {
+    pub show_help: bool,
+    pub config_path: String,
+    /// --print-config：把完整的默认配置模板打印到 stdout 后退出
+    pub print_config: bool,
+    /// --init-config <path>：把默认配置模板写到该文件后退出（文件已存在时报错）
+    pub init_config: Option<String>,
+    /// --create-admin <username>：用配置里的存储创建管理员后退出（密码取 ADMIN_PASSWORD 或 --admin-password-file）
+    pub create_admin: Option<String>,
+    /// --admin-password-file <path>：--create-admin 的密码文件（首行，去掉行尾换行）
+    pub admin_password_file: Option<String>,
+}
+
+/// 配置路径优先级：--config > OBSIDIAN_CONFIG_PATH 环境变量 > config.json（空串视为未设置）
+pub fn resolve_config_path(flag_path: Option<&str>, env_path: Option<&str>) -> String {
+    flag_path
+        .filter(|p| !p.trim().is_empty())
+        .or(env_path.filter(|p| !p.trim().is_empty()))
+        .unwrap_or(DEFAULT_CONFIG_PATH)
+        .to_string()
+}
+
+/// 解析命令行，支持 --config <path>、--print-config、--init-config <path>、--create-admin <username>、
+/// --admin-password-file <path> 和 --help/-h；
+/// 没有 --config 时读取 OBSIDIAN_CONFIG_PATH
+pub fn parse_args(args: &[String]) -> Args {
+    let mut flag_path = None;
+    let mut parsed = Args {
+        config_path: String::new(),
+        show_help: false,
+        print_config: false,
+        init_config: None,
+        create_admin: None,
+        admin_password_file: None,
+    };
 
     let mut i = 1; // 跳过可执行文件名
     while i < args.len() {
         match args[i].as_str() {
             "--help" | "-h" => {
-                show_help = true;
+                parsed.show_help = true;
                 break;
             }
             "--config" => {
                 if i + 1 < args.len() {
-                    config_path = args[i + 1].clone();
+                    flag_path = Some(args[i + 1].clone());
                     i += 1; // 跳过路径参数
                 } else {
                     eprintln!("--config requires a path");
                     std::process::exit(1);
                 }
             }
+            "--print-config" => {
+                parsed.print_config = true;
+            }
+            "--init-config" => {
+                if i + 1 < args.len() {
+                    parsed.init_config = Some(args[i + 1].clone());
+                    i += 1; // 跳过路径参数
+                } else {
+                    eprintln!("--init-config requires a path");
+                    std::process::exit(1);
+                }
+            }
+            "--create-admin" => {
+                if i + 1 < args.len() {
+                    parsed.create_admin = Some(args[i + 1].clone());
+                    i += 1; // 跳过用户名参数
+                } else {
+                    eprintln!("--create-admin requires a username");
+                    std::process::exit(1);
+                }
+            }
+            "--admin-password-file" => {
+                if i + 1 < args.len() {
+                    parsed.admin_password_file = Some(args[i + 1].clone());
+                    i += 1; // 跳过路径参数
+                } else {
+                    eprintln!("--admin-password-file requires a path");
+                    std::process::exit(1);
+                }
+            }
             _ => {
                 // 忽略未知参数
             }
@@ -27,5 +93,45 @@ pub fn parse_args(args: &[String]) -> (bool, String) {
         i += 1;
     }
 
-    (show_help, config_path)
-}
\ No newline at end of file
+    let env_path = std::env::var(CONFIG_PATH_ENV).ok();
+    parsed.config_path = resolve_config_path(flag_path.as_deref(), env_path.as_deref());
+    parsed
+}
+
+#[cfg(test)]
+mod config_path_tests {
+    use super::*;
+
+    #[test]
+    fn flag_wins_over_env() {
+        assert_eq!(resolve_config_path(Some("flag.json"), Some("env.json")), "flag.json");
+        assert_eq!(resolve_config_path(Some("flag.json"), None), "flag.json");
+    }
+
+    #[test]
+    fn env_used_without_flag() {
+        assert_eq!(resolve_config_path(None, Some("/etc/obsidian/config.json")), "/etc/obsidian/config.json");
+        assert_eq!(resolve_config_path(Some(" "), Some("env.json")), "env.json");
+    }
+
+    #[test]
+    fn default_when_neither_set() {
+        assert_eq!(resolve_config_path(None, None), DEFAULT_CONFIG_PATH);
+        assert_eq!(resolve_config_path(Some(""), Some("")), DEFAULT_CONFIG_PATH);
+    }
+
+    #[test]
+    fn create_admin_takes_a_username() {
+        let args: Vec<String> = ["server", "--config", "x.json", "--create-admin", "root", "--admin-password-file", "pw.txt"].iter().map(|s| s.to_string()).collect();
+        let parsed = parse_args(&args);
+        assert_eq!(parsed.create_admin.as_deref(), Some("root"));
+        assert_eq!(parsed.admin_password_file.as_deref(), Some("pw.txt"));
+        assert_eq!(parsed.config_path, "x.json");
+    }
+
+    #[test]
+    fn help_still_stops_parsing() {
+        let args: Vec<String> = ["server", "--help", "--config", "x.json"].iter().map(|s| s.to_string()).collect();
+        assert!(parse_args(&args).show_help);
+    }
+}
@@ -0,0 +1,85 @@
+use crate::models::RedirectRule;
+use std::path::Path;
+
+/// Netlify-style redirect file at the site root
+pub const REDIRECTS_FILE: &str = "_redirects";
+
+/// Rules beyond this count are dropped with a warning
+pub const MAX_REDIRECT_RULES: usize = 1000;
+
+/// Read and parse `<site_dir>/_redirects`. A missing file yields no rules and no warnings.
+pub fn load_redirects(site_dir: &Path) -> (Vec<RedirectRule>, Vec<String>) {
+    match std::fs::read_to_string(site_dir.join(REDIRECTS_FILE)) {
+        Ok(text) => parse_redirects(&text),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => (Vec::new(), Vec::new()),
+        Err(e) => (Vec::new(), vec![format!("{} could not be read and was ignored: {}", REDIRECTS_FILE, e)]),
+    }
+}
+
+/// Parse `from to [status]` lines; blank lines and `#` comments are skipped.
+/// Malformed or unsupported lines are left out and explained in the returned warnings.
+pub fn parse_redirects(text: &str) -> (Vec<RedirectRule>, Vec<String>) {
+    let mut rules = Vec::new();
+    let mut warnings = Vec::new();
+    for (i, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        match parse_rule(line) {
+            Ok(_) if rules.len() >= MAX_REDIRECT_RULES => {
+                warnings.push(format!("{} line {}: more than {} rules; ignored", REDIRECTS_FILE, i + 1, MAX_REDIRECT_RULES));
+            }
+            Ok(rule) => rules.push(rule),
+            Err(reason) => warnings.push(format!("{} line {}: {}; ignored", REDIRECTS_FILE, i + 1, reason)),
+        }
+    }
+    (rules, warnings)
+}
+
+fn parse_rule(line: &str) -> Result<RedirectRule, String> {
+    let fields: Vec<&str> = line.split_whitespace().collect();
+    let (from, to, status) = match fields.as_slice() {
+        [from, to] => (*from, *to, 301),
+        [from, to, status] => {
+            // 规则总是先于文件生效，Netlify 的强制标记 `!` 不影响行为
+            let code = status.trim_end_matches('!');
+            match code {
+                "301" => (*from, *to, 301),
+                "302" => (*from, *to, 302),
+                _ => return Err(format!("unsupported status '{}' (only 301 and 302)", status)),
+            }
+        }
+        _ => return Err("expected 'from to [status]'".to_string()),
+    };
+
+    if !from.starts_with('/') {
+        return Err(format!("source '{}' must start with '/'", from));
+    }
+    if from.contains('*') || from.split('/').any(|seg| seg.starts_with(':')) {
+        return Err(format!("source '{}' uses splats or placeholders, which are not supported", from));
+    }
+    if !(to.starts_with('/') || to.starts_with("http://") || to.starts_with("https://")) {
+        return Err(format!("target '{}' must be a site path or an http(s) URL", to));
+    }
+
+    Ok(RedirectRule {
+        from: normalize(from).to_string(),
+        to: to.to_string(),
+        status,
+    })
+}
+
+/// `/a/` and `/a` name the same page
+fn normalize(path: &str) -> &str {
+    match path.trim_end_matches('/') {
+        "" => "/",
+        trimmed => trimmed,
+    }
+}
+
+/// First rule whose source matches the site-relative request path
+pub fn find_redirect<'a>(rules: &'a [RedirectRule], path: &str) -> Option<&'a RedirectRule> {
+    let path = normalize(path);
+    rules.iter().find(|r| r.from == path)
+}
@@ -0,0 +1,36 @@
+use axum::{
+    extract::Request,
+    http::{HeaderName, HeaderValue},
+    middleware::Next,
+    response::Response,
+};
+use uuid::Uuid;
+
+pub const REQUEST_ID_HEADER: HeaderName = HeaderName::from_static("x-request-id");
+
+/// Longest incoming id that is kept; anything longer gets a fresh one
+pub const MAX_REQUEST_ID_LEN: usize = 128;
+
+/// The request's id, as a request extension (also recorded on the `TraceLayer` span)
+#[derive(Debug, Clone)]
+pub struct RequestId(pub String);
+
+/// Keep the caller's `X-Request-Id` (printable ASCII, at most `MAX_REQUEST_ID_LEN`) or
+/// make a UUID, and echo it on the response. Sits outside `TraceLayer` so the span sees it.
+pub async fn request_id(mut request: Request, next: Next) -> Response {
+    let id = request
+        .headers()
+        .get(&REQUEST_ID_HEADER)
+        .and_then(|v| v.to_str().ok())
+        .map(str::trim)
+        .filter(|v| !v.is_empty() && v.len() <= MAX_REQUEST_ID_LEN && v.bytes().all(|b| b.is_ascii_graphic()))
+        .map(str::to_string)
+        .unwrap_or_else(|| Uuid::new_v4().to_string());
+    request.extensions_mut().insert(RequestId(id.clone()));
+
+    let mut response = next.run(request).await;
+    if let Ok(value) = HeaderValue::from_str(&id) {
+        response.headers_mut().insert(REQUEST_ID_HEADER, value);
+    }
+    response
+}
@@ -0,0 +1,44 @@
+use crate::{error::AppError, utils::fs::list_files};
+use std::path::Path;
+
+/// `sitemap.xml` listing every HTML page below `site_dir`, in path order. `site_url` is the
+/// site root and ends with `/`; `index.html` pages are listed by their directory URL.
+/// Hidden paths (`.foo/...`) and non-UTF-8 names are left out.
+pub fn build_sitemap(site_dir: &Path, site_url: &str) -> Result<String, AppError> {
+    let mut xml = String::from(concat!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n",
+        "<urlset xmlns=\"http://www.sitemaps.org/schemas/sitemap/0.9\">\n",
+    ));
+    for file in list_files(site_dir)? {
+        let Some(path) = file.to_str().map(|p| p.replace('\\', "/")) else { continue };
+        let lower = path.to_ascii_lowercase();
+        if !(lower.ends_with(".html") || lower.ends_with(".htm")) || path.split('/').any(|s| s.starts_with('.')) {
+            continue;
+        }
+        let page = match path.strip_suffix("index.html") {
+            Some(dir) if dir.is_empty() || dir.ends_with('/') => dir,
+            _ => path.as_str(),
+        };
+        xml.push_str(&format!("  <url><loc>{}{}</loc></url>\n", escape_xml(site_url), escape_xml(&encode_path(page))));
+    }
+    xml.push_str("</urlset>\n");
+    Ok(xml)
+}
+
+/// Percent-encode everything but unreserved characters and `/`
+fn encode_path(path: &str) -> String {
+    path.bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' | b'/' => (b as char).to_string(),
+            _ => format!("%{:02X}", b),
+        })
+        .collect()
+}
+
+fn escape_xml(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
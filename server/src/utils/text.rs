@@ -0,0 +1,31 @@
+/// Maximum stored length (in chars) of a site description
+pub const MAX_DESCRIPTION_LEN: usize = 500;
+
+/// Clean user-supplied free text before storing it: control characters are dropped,
+/// runs of whitespace collapse to a single space, and the result is trimmed and cut to
+/// at most `max_chars` characters. HTML is left as-is; escaping is up to the client.
+pub fn sanitize_text(input: &str, max_chars: usize) -> String {
+    let mut out = String::with_capacity(input.len().min(max_chars));
+    let mut pending_space = false;
+    let mut len = 0;
+    for c in input.chars() {
+        if c.is_whitespace() {
+            pending_space = !out.is_empty();
+            continue;
+        }
+        if c.is_control() {
+            continue;
+        }
+        let needed = if pending_space { 2 } else { 1 };
+        if len + needed > max_chars {
+            break;
+        }
+        if pending_space {
+            out.push(' ');
+            pending_space = false;
+        }
+        out.push(c);
+        len += needed;
+    }
+    out
+}
@@ -0,0 +1,244 @@
+/// Admin handler tests
+///
+/// These tests exercise the admin handlers and helpers directly, without HTTP.
+
+mod utils;
+
+use axum::body::to_bytes;
+use axum::extract::{Query, State};
+use obsidian_publisher_server::{
+    config::Config,
+    error::AppError,
+    handlers::admin::{admin_export, admin_prune_temp, admin_report, admin_sites},
+    models::{Site, User},
+};
+use std::collections::HashMap;
+use std::sync::Arc;
+use utils::storage::create_test_storage;
+use uuid::Uuid;
+
+// ===== admin_export Tests =====
+
+#[tokio::test]
+async fn test_admin_export_streams_one_record_per_line() {
+    let (storage, _temp) = create_test_storage().await;
+    let config = Config::default();
+
+    let mut owner_ids = Vec::new();
+    for i in 0..100 {
+        let user = User::new(format!("user-{}", i), "pw".to_string());
+        owner_ids.push(user.id);
+        storage.users.create(user).await.unwrap();
+    }
+    for i in 0..200 {
+        let site = Site::new(Uuid::new_v4(), owner_ids[i % 100], format!("site-{}", i), "d".to_string());
+        storage.sites.create(site).await.unwrap();
+    }
+
+    let mut params = HashMap::new();
+    params.insert("key".to_string(), config.server.jwt_secret.clone());
+    let res = admin_export(State((Arc::new(storage), Arc::new(config))), None, Query(params))
+        .await
+        .expect("admin_export failed");
+    assert_eq!(res.headers()["content-type"], "application/x-ndjson");
+
+    let body = to_bytes(res.into_body(), usize::MAX).await.unwrap();
+    let text = String::from_utf8(body.to_vec()).unwrap();
+    assert!(text.ends_with('\n'));
+
+    let (mut users, mut sites) = (0, 0);
+    for line in text.lines() {
+        let v: serde_json::Value = serde_json::from_str(line).expect("each line should be a JSON object");
+        match v["type"].as_str() {
+            Some("user") => users += 1,
+            Some("site") => sites += 1,
+            other => panic!("unexpected record type {:?}", other),
+        }
+        assert!(v["record"]["id"].is_string());
+    }
+    assert_eq!((users, sites), (100, 200));
+}
+
+#[tokio::test]
+async fn test_admin_export_requires_key() {
+    let (storage, _temp) = create_test_storage().await;
+    let res = admin_export(State((Arc::new(storage), Arc::new(Config::default()))), None, Query(HashMap::new())).await;
+    assert!(res.is_err());
+}
+
+// ===== admin_sites Tests =====
+
+#[tokio::test]
+async fn test_admin_sites_report_is_sorted() {
+    let (storage, temp) = create_test_storage().await;
+    let mut config = Config::default();
+    config.storage.sites.path = temp.path().join("sites");
+
+    // Orphans created out of order
+    for name in ["zeta", "alpha", "mu", "beta"] {
+        std::fs::create_dir_all(config.storage.sites.path.join(name)).unwrap();
+    }
+    // Sites without a directory
+    let owner = User::new("owner".to_string(), "pw".to_string());
+    for i in 0..5 {
+        storage.sites.create(Site::new(Uuid::new_v4(), owner.id, format!("missing-{}", i), "d".to_string())).await.unwrap();
+    }
+
+    let mut params = HashMap::new();
+    params.insert("key".to_string(), config.server.jwt_secret.clone());
+    let report = admin_sites(State((Arc::new(storage), Arc::new(config))), None, Query(params)).await.unwrap().0;
+
+    let is_sorted = |v: &[String]| v.windows(2).all(|w| w[0] <= w[1]);
+    for name in ["zeta", "alpha", "mu", "beta"] {
+        assert!(report.orphan_site_dirs.contains(&name.to_string()), "{} missing from {:?}", name, report.orphan_site_dirs);
+    }
+    assert_eq!(report.missing_site_dirs.len(), 5);
+    assert!(is_sorted(&report.orphan_site_dirs), "{:?}", report.orphan_site_dirs);
+    assert!(is_sorted(&report.missing_site_dirs), "{:?}", report.missing_site_dirs);
+    assert!(is_sorted(&report.db_site_ids), "{:?}", report.db_site_ids);
+    assert!(is_sorted(&report.disk_site_dirs), "{:?}", report.disk_site_dirs);
+}
+
+// ===== admin_report Tests =====
+
+#[tokio::test]
+async fn test_admin_report_aggregates_sites_in_window() {
+    let (storage, _temp) = create_test_storage().await;
+    let config = Config::default();
+    let alice = storage.users.create(User::new("alice".to_string(), "pw".to_string())).await.unwrap();
+    let bob = storage.users.create(User::new("bob".to_string(), "pw".to_string())).await.unwrap();
+    let ghost = Uuid::new_v4();
+
+    let at = |s: &str| chrono::DateTime::parse_from_rfc3339(s).unwrap().with_timezone(&chrono::Utc);
+    let mut ids = HashMap::new();
+    for (label, owner, created_at, bytes) in [
+        ("a1", alice.id, "2026-01-05T12:00:00Z", 300),
+        ("a2", alice.id, "2026-01-20T08:00:00Z", 100),
+        ("a-old", alice.id, "2025-12-31T23:00:00Z", 1000),
+        ("b1", bob.id, "2026-01-10T00:00:00Z", 500),
+        ("b-late", bob.id, "2026-02-01T00:00:00Z", 50),
+        ("g1", ghost, "2026-01-15T00:00:00Z", 10),
+    ] {
+        let mut site = Site::new(Uuid::new_v4(), owner, label.to_string(), "d".to_string());
+        site.created_at = at(created_at);
+        let dir = storage.sites.get_site_files_path(site.id);
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("index.html"), vec![b'x'; bytes]).unwrap();
+        ids.insert(label, site.id);
+        storage.sites.create(site).await.unwrap();
+    }
+
+    let params: HashMap<String, String> = [
+        ("key", config.server.jwt_secret.as_str()),
+        ("from", "2026-01-01"),
+        ("to", "2026-02-01T00:00:00Z"),
+        ("top", "2"),
+    ].into_iter().map(|(k, v)| (k.to_string(), v.to_string())).collect();
+    let storage = Arc::new(storage);
+    let report = admin_report(State((storage.clone(), Arc::new(config))), None, Query(params)).await.unwrap().0;
+
+    // a-old is before the window and b-late on its (exclusive) end
+    assert_eq!(report.site_count, 4);
+    assert_eq!(report.total_bytes, 910);
+
+    let per_user: Vec<_> = report.per_user.iter()
+        .map(|u| (u.owner_id, u.username.as_deref(), u.site_count, u.total_bytes))
+        .collect();
+    assert_eq!(per_user, vec![
+        (bob.id, Some("bob"), 1, 500),
+        (alice.id, Some("alice"), 2, 400),
+        (ghost, None, 1, 10),
+    ]);
+
+    let largest: Vec<_> = report.largest_sites.iter().map(|s| (s.id, s.size_bytes)).collect();
+    assert_eq!(largest, vec![(ids["b1"], 500), (ids["a1"], 300)]);
+}
+
+#[tokio::test]
+async fn test_admin_report_rejects_bad_bounds() {
+    let (storage, _temp) = create_test_storage().await;
+    let config = Config::default();
+    let storage = Arc::new(storage);
+    let config = Arc::new(config);
+    for (from, to) in [("last tuesday", "2026-02-01"), ("2026-03-01", "2026-02-01")] {
+        let params: HashMap<String, String> = [("key", config.server.jwt_secret.as_str()), ("from", from), ("to", to)]
+            .into_iter().map(|(k, v)| (k.to_string(), v.to_string())).collect();
+        let res = admin_report(State((storage.clone(), config.clone())), None, Query(params)).await;
+        assert!(matches!(res, Err(AppError::InvalidInput(_))), "{} .. {}", from, to);
+    }
+    let res = admin_report(State((storage, config)), None, Query(HashMap::new())).await;
+    assert!(matches!(res, Err(AppError::AuthorizationFailed)));
+}
+
+// ===== admin_prune_temp Tests =====
+
+#[tokio::test]
+async fn test_admin_prune_temp_removes_only_staging_dirs() {
+    let (storage, temp) = create_test_storage().await;
+    let sites = temp.path().join("sites");
+
+    let stale_extract = format!(".extract_temp_{}", Uuid::new_v4());
+    let stale_rebuild = format!(".rebuild_temp_{}", Uuid::new_v4());
+    for dir in [".upload_temp", stale_extract.as_str(), stale_rebuild.as_str()] {
+        std::fs::create_dir_all(sites.join(dir).join("nested")).unwrap();
+        std::fs::write(sites.join(dir).join("nested/leftover.bin"), b"x").unwrap();
+    }
+    let site_id = Uuid::new_v4().to_string();
+    for dir in [site_id.as_str(), "my-notes", ".chunked_uploads"] {
+        std::fs::create_dir_all(sites.join(dir)).unwrap();
+        std::fs::write(sites.join(dir).join("index.html"), b"<html></html>").unwrap();
+    }
+
+    let config = Config::default();
+    let mut params = HashMap::new();
+    params.insert("key".to_string(), config.server.jwt_secret.clone());
+    let report = admin_prune_temp(State((Arc::new(storage), Arc::new(config))), None, Query(params)).await.unwrap().0;
+
+    let mut expected = vec![".upload_temp".to_string(), stale_extract, stale_rebuild];
+    expected.sort();
+    assert_eq!(report.removed, expected);
+    for dir in &expected {
+        assert!(!sites.join(dir).exists(), "{} should be removed", dir);
+    }
+    for dir in [site_id.as_str(), "my-notes", ".chunked_uploads"] {
+        assert!(sites.join(dir).join("index.html").exists(), "{} should be left alone", dir);
+    }
+}
+
+#[tokio::test]
+async fn test_admin_prune_temp_spares_in_flight_staging() {
+    let (storage, temp) = create_test_storage().await;
+    let storage = Arc::new(storage);
+    let sites = temp.path().join("sites");
+    let staging = format!(".extract_temp_{}", Uuid::new_v4());
+    for dir in [".upload_temp", staging.as_str()] {
+        std::fs::create_dir_all(sites.join(dir)).unwrap();
+        std::fs::write(sites.join(dir).join("site.tar.gz"), b"x").unwrap();
+    }
+
+    // An upload is in flight and its extraction holds a slot
+    let slot = storage.uploads.acquire(Uuid::new_v4()).unwrap();
+    let extraction = storage.extractions.acquire().await.unwrap();
+
+    let config = Config::default();
+    let mut params = HashMap::new();
+    params.insert("key".to_string(), config.server.jwt_secret.clone());
+    let prune = tokio::spawn(admin_prune_temp(State((storage.clone(), Arc::new(config))), None, Query(params)));
+    tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+    assert!(!prune.is_finished(), "pruning should wait for running extractions");
+    assert!(sites.join(&staging).exists());
+
+    // Once the extraction is done its staging dir is fair game; the upload's is not
+    drop(extraction);
+    let report = prune.await.unwrap().unwrap().0;
+    assert_eq!(report.removed, vec![staging.clone()]);
+    assert!(sites.join(".upload_temp/site.tar.gz").exists(), "in-flight upload staging should survive");
+    drop(slot);
+}
+
+#[tokio::test]
+async fn test_admin_prune_temp_requires_key() {
+    let (storage, _temp) = create_test_storage().await;
+    let res = admin_prune_temp(State((Arc::new(storage), Arc::new(Config::default()))), None, Query(HashMap::new())).await;
+    assert!(res.is_err());
+}
@@ -0,0 +1,150 @@
+/// End-to-end tests
+///
+/// These tests boot the assembled app (`build_app`) on a local port and talk to it
+/// over real HTTP, so routing, middleware and state wiring are covered together.
+
+mod utils;
+
+use reqwest::{multipart, StatusCode};
+use uuid::Uuid;
+use utils::app::TestApp;
+use utils::storage::create_test_archive_file;
+
+#[tokio::test]
+async fn test_register_login_upload_list_delete() {
+    let app = TestApp::spawn().await;
+    let token = app.register_and_login("e2e-user", "e2e-password").await;
+
+    // Protected routes reject anonymous callers
+    let res = app.client.get(app.url("/auth/me")).send().await.unwrap();
+    assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+
+    let res = app.client.get(app.url("/auth/me")).bearer_auth(&token).send().await.unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+    let me: serde_json::Value = res.json().await.unwrap();
+    assert_eq!(me["username"], "e2e-user");
+
+    // Upload
+    let site_id = Uuid::new_v4();
+    let temp = tempfile::TempDir::new().unwrap();
+    let archive = std::fs::read(create_test_archive_file(temp.path(), &site_id)).unwrap();
+    let form = multipart::Form::new()
+        .text("uuid", site_id.to_string())
+        .text("siteName", "e2e-site")
+        .part("site", multipart::Part::bytes(archive).file_name("site.tar.gz"));
+    let res = app.client.post(app.url("/api/sites")).bearer_auth(&token).multipart(form).send().await.unwrap();
+    assert_eq!(res.status(), StatusCode::OK, "upload failed: {}", res.text().await.unwrap_or_default());
+    let uploaded: serde_json::Value = res.json().await.unwrap();
+    assert_eq!(uploaded["id"], site_id.to_string());
+    assert_eq!(uploaded["name"], "e2e-site");
+
+    // The site is served under both its UUID and its name
+    for path in [format!("/sites/{}/index.html", site_id), "/sites/e2e-site/index.html".to_string()] {
+        let res = app.client.get(app.url(&path)).send().await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK, "GET {}", path);
+        assert!(res.text().await.unwrap().contains("Test Site"));
+    }
+
+    // List
+    let res = app.client.get(app.url("/api/sites")).send().await.unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+    let sites: Vec<serde_json::Value> = res.json().await.unwrap();
+    assert_eq!(sites.len(), 1);
+    assert_eq!(sites[0]["id"], site_id.to_string());
+
+    // Only the owner may delete
+    let stranger = app.register_and_login("e2e-stranger", "e2e-password").await;
+    let res = app.client.delete(app.url(&format!("/api/sites/{}", site_id))).bearer_auth(&stranger).send().await.unwrap();
+    assert_eq!(res.status(), StatusCode::FORBIDDEN);
+
+    let res = app.client.delete(app.url(&format!("/api/sites/{}", site_id))).bearer_auth(&token).send().await.unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+
+    let res = app.client.get(app.url("/api/sites")).send().await.unwrap();
+    let sites: Vec<serde_json::Value> = res.json().await.unwrap();
+    assert!(sites.is_empty());
+    let res = app.client.get(app.url(&format!("/sites/{}/index.html", site_id))).send().await.unwrap();
+    assert_eq!(res.status(), StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn test_oversize_upload_gets_json_413() {
+    let app = TestApp::spawn_with(|config| config.server.max_body_bytes = 1024).await;
+    let token = app.register_and_login("big-uploader", "big-password").await;
+
+    let form = multipart::Form::new()
+        .text("uuid", Uuid::new_v4().to_string())
+        .text("siteName", "too-big")
+        .part("site", multipart::Part::bytes(vec![0u8; 16 * 1024]).file_name("site.tar.gz"));
+    let res = app.client.post(app.url("/api/sites")).bearer_auth(&token).multipart(form).send().await.unwrap();
+    assert_eq!(res.status(), StatusCode::PAYLOAD_TOO_LARGE);
+    let body: serde_json::Value = res.json().await.expect("413 body should be JSON");
+    assert_eq!(body["code"], "PAYLOAD_TOO_LARGE");
+    assert_eq!(body["error"], "Payload too large");
+    assert!(body["details"].as_str().unwrap().contains("1024 bytes"), "{}", body);
+}
+
+#[tokio::test]
+async fn test_admin_role_opens_admin_endpoints() {
+    use obsidian_publisher_server::auth::{AuthService, TokenService};
+
+    let app = TestApp::spawn().await;
+    let service = AuthService::new(
+        app.storage.users.clone(),
+        app.storage.audit.clone(),
+        TokenService::new(app.config.server.jwt_secret.clone(), 1),
+        app.config.auth.allow_plaintext_password,
+    );
+    let admin = service.create_admin("root".to_string(), "root-password".to_string()).await.expect("create_admin failed");
+
+    let credentials = serde_json::json!({ "username": "root", "password": "root-password" });
+    let res = app.client.post(app.url("/auth/login")).json(&credentials).send().await.unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+    let login: serde_json::Value = res.json().await.unwrap();
+    let admin_token = login["token"].as_str().unwrap().to_string();
+    let user_token = app.register_and_login("plain-user", "plain-password").await;
+
+    for path in ["/api/admin/storage", "/api/admin/sites", "/api/admin/audit"] {
+        let res = app.client.get(app.url(path)).bearer_auth(&admin_token).send().await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK, "{}: {}", path, res.text().await.unwrap_or_default());
+
+        let res = app.client.get(app.url(path)).bearer_auth(&user_token).send().await.unwrap();
+        assert_eq!(res.status(), StatusCode::FORBIDDEN, "{}", path);
+
+        let res = app.client.get(app.url(path)).send().await.unwrap();
+        assert_eq!(res.status(), StatusCode::FORBIDDEN, "{}", path);
+    }
+
+    // Role-based access is audited under the admin's id
+    let events = app.storage.audit.recent(usize::MAX).await.unwrap();
+    assert!(events.iter().any(|e| e.actor_id == Some(admin.id) && e.target.as_deref() == Some("/api/admin/storage")));
+}
+
+#[tokio::test]
+async fn test_private_invite_only_instance_can_mint_invites() {
+    use obsidian_publisher_server::auth::{AuthService, TokenService};
+
+    let app = TestApp::spawn_with(|config| {
+        config.auth.require_invite = true;
+        config.server.require_auth_for_listing = true;
+    }).await;
+    let service = AuthService::new(
+        app.storage.users.clone(),
+        app.storage.audit.clone(),
+        TokenService::new(app.config.server.jwt_secret.clone(), 1),
+        app.config.auth.allow_plaintext_password,
+    );
+    service.create_admin("root".to_string(), "root-password".to_string()).await.expect("create_admin failed");
+    let credentials = serde_json::json!({ "username": "root", "password": "root-password" });
+    let res = app.client.post(app.url("/auth/login")).json(&credentials).send().await.unwrap();
+    let login: serde_json::Value = res.json().await.unwrap();
+    let admin_token = login["token"].as_str().unwrap().to_string();
+
+    let res = app.client.post(app.url("/api/admin/invites")).bearer_auth(&admin_token).send().await.unwrap();
+    assert_eq!(res.status(), StatusCode::OK, "{}", res.text().await.unwrap_or_default());
+    let invite: serde_json::Value = res.json().await.unwrap();
+
+    let registration = serde_json::json!({ "username": "invited", "password": "invited-password", "invite_code": invite["code"] });
+    let res = app.client.post(app.url("/auth/register")).json(&registration).send().await.unwrap();
+    assert_eq!(res.status(), StatusCode::OK, "{}", res.text().await.unwrap_or_default());
+}
@@ -1,7 +1,7 @@
 use std::{fs, fs::File, io::Write};
 use tempfile::tempdir;
 
-use obsidian_publisher_server::utils::archive;
+use obsidian_publisher_server::{config::ArchiveConfig, error::{AppError, ArchiveError}, utils::archive};
 
 #[tokio::test]
 async fn test_zip_extract_with_replace() {
@@ -39,6 +39,7 @@ async fn test_zip_extract_with_replace() {
         &zip_path,
         &outdir,
         Some(("target".to_string(), "repl".to_string())),
+        &ArchiveConfig::default(),
     )
     .await
     .expect("extract zip");
@@ -86,6 +87,7 @@ async fn test_tar_gz_extract_with_replace() {
         &tar_gz_path,
         &outdir,
         Some(("target".to_string(), "repl".to_string())),
+        &ArchiveConfig::default(),
     )
     .await
     .expect("extract tar.gz");
@@ -100,3 +102,260 @@ async fn test_tar_gz_extract_with_replace() {
     let repl_bin = fs::read(outdir.join("replaced").join("b.bin")).expect("read repl bin");
     assert_eq!(orig_bin, repl_bin);
 }
+
+#[tokio::test]
+async fn test_zip_rejects_overlong_filename() {
+    let td = tempdir().expect("tempdir");
+    let zip_path = td.path().join("site.zip");
+    let mut zip = zip::ZipWriter::new(File::create(&zip_path).expect("create zip"));
+    let options: zip::write::FileOptions<'_, ()> = zip::write::FileOptions::default();
+
+    let long_name = format!("notes/{}.md", "x".repeat(40));
+    zip.start_file(long_name.as_str(), options).expect("start long file");
+    zip.write_all(b"content").expect("write long file");
+    zip.finish().expect("finish zip");
+
+    let limits = ArchiveConfig { max_path_depth: 8, max_component_length: 32, ..ArchiveConfig::default() };
+    let err = archive::extract_archive(&zip_path, &td.path().join("out"), &limits)
+        .await
+        .expect_err("over-long filename should be rejected");
+
+    match err {
+        AppError::Archive(ArchiveError::PathTooLong(msg)) => assert!(msg.contains(&long_name), "message should name the path: {}", msg),
+        other => panic!("expected PathTooLong, got {:?}", other),
+    }
+}
+
+#[tokio::test]
+async fn test_tar_gz_rejects_excessive_nesting() {
+    let td = tempdir().expect("tempdir");
+    let tar_gz_path = td.path().join("site.tar.gz");
+    let enc = flate2::write::GzEncoder::new(File::create(&tar_gz_path).expect("create tar.gz"), flate2::Compression::default());
+    let mut tar = tar::Builder::new(enc);
+
+    let deep_path = "a/b/c/d/e/f/index.html";
+    let data = b"<html></html>";
+    let mut header = tar::Header::new_gnu();
+    header.set_size(data.len() as u64);
+    header.set_mode(0o644);
+    tar.append_data(&mut header, deep_path, &data[..]).expect("append deep entry");
+    tar.into_inner().expect("into_inner").finish().expect("finish encoder");
+
+    let limits = ArchiveConfig { max_path_depth: 4, max_component_length: 255, ..ArchiveConfig::default() };
+    let err = archive::extract_archive_with_replace(&tar_gz_path, &td.path().join("out"), None, &limits)
+        .await
+        .expect_err("deeply nested entry should be rejected");
+
+    match err {
+        AppError::Archive(ArchiveError::PathTooLong(msg)) => assert!(msg.contains(deep_path), "message should name the path: {}", msg),
+        other => panic!("expected PathTooLong, got {:?}", other),
+    }
+}
+
+#[tokio::test]
+async fn test_tar_gz_long_names_extract_without_meta_files() {
+    let td = tempdir().expect("tempdir");
+    let tar_gz_path = td.path().join("site.tar.gz");
+    let enc = flate2::write::GzEncoder::new(File::create(&tar_gz_path).expect("create tar.gz"), flate2::Compression::default());
+    let mut tar = tar::Builder::new(enc);
+
+    // pax global header, as written by `git archive`
+    let pax = b"16 comment=test\n";
+    let mut header = tar::Header::new_ustar();
+    header.set_entry_type(tar::EntryType::XGlobalHeader);
+    header.set_size(pax.len() as u64);
+    header.set_mode(0o644);
+    tar.append_data(&mut header, "pax_global_header", &pax[..]).expect("append pax header");
+
+    // Over 100 bytes, so the GNU header needs a LongName ('L') record before it
+    let long_name = format!("notes/{}.html", "n".repeat(150));
+    for (name, data) in [("index.html", &b"<a href=\"/sites/old/x\">x</a>"[..]), (long_name.as_str(), &b"long"[..])] {
+        let mut header = tar::Header::new_gnu();
+        header.set_size(data.len() as u64);
+        header.set_mode(0o644);
+        tar.append_data(&mut header, name, data).expect("append entry");
+    }
+    tar.into_inner().expect("into_inner").finish().expect("finish encoder");
+
+    archive::validate_archive(&tar_gz_path, &ArchiveConfig::default(), None).expect("archive should validate");
+
+    let out = td.path().join("out");
+    let warnings = archive::extract_archive_with_replace(
+        &tar_gz_path,
+        &out,
+        Some(("/sites/old/".to_string(), "/sites/new/".to_string())),
+        &ArchiveConfig::default(),
+    ).await.expect("extract_archive_with_replace failed");
+    assert!(warnings.is_empty(), "warnings: {:?}", warnings);
+
+    for dir in ["original", "replaced"] {
+        let files = obsidian_publisher_server::utils::fs::list_files(&out.join(dir)).unwrap();
+        assert_eq!(files, vec![std::path::PathBuf::from("index.html"), std::path::PathBuf::from(&long_name)], "in {}", dir);
+    }
+    assert_eq!(fs::read(out.join("replaced").join(&long_name)).unwrap(), b"long");
+
+    let plain = td.path().join("plain");
+    archive::extract_archive(&tar_gz_path, &plain, &ArchiveConfig::default()).await.expect("extract_archive failed");
+    assert!(plain.join(&long_name).exists());
+    assert!(!plain.join("pax_global_header").exists());
+    assert!(!plain.join("././@LongLink").exists());
+}
+
+#[tokio::test]
+async fn test_zip_renamed_to_tar_gz_is_client_error() {
+    use axum::{http::StatusCode, response::IntoResponse};
+
+    let td = tempdir().expect("tempdir");
+    // A valid zip archive with a misleading extension
+    let zip_path = td.path().join("site.tar.gz");
+    let mut zip = zip::ZipWriter::new(File::create(&zip_path).expect("create zip"));
+    let options: zip::write::FileOptions<'_, ()> = zip::write::FileOptions::default();
+    zip.start_file("index.html", options).expect("start index.html");
+    zip.write_all(b"<html></html>").expect("write index.html");
+    zip.finish().expect("finish zip");
+
+    let err = archive::extract_archive(&zip_path, &td.path().join("out"), &ArchiveConfig::default())
+        .await
+        .expect_err("zip content with .tar.gz extension should fail");
+
+    match &err {
+        AppError::Archive(ArchiveError::UnsupportedFormat(msg)) => {
+            assert!(msg.contains(".tar.gz"), "message should mention the extension: {}", msg);
+            assert!(msg.contains("zip"), "message should mention the detected format: {}", msg);
+        }
+        other => panic!("expected UnsupportedFormat, got {:?}", other),
+    }
+    assert_eq!(err.into_response().status(), StatusCode::BAD_REQUEST);
+}
+
+// ===== typed archive failure Tests =====
+
+/// tar.gz with the given entries; a path ending in '/' becomes a directory entry
+fn write_tar_gz(path: &std::path::Path, entries: &[(&str, &[u8])]) {
+    let enc = flate2::write::GzEncoder::new(File::create(path).expect("create tar.gz"), flate2::Compression::default());
+    let mut tar = tar::Builder::new(enc);
+    for (name, data) in entries {
+        let mut header = tar::Header::new_gnu();
+        if name.ends_with('/') {
+            header.set_entry_type(tar::EntryType::Directory);
+            header.set_size(0);
+            header.set_mode(0o755);
+        } else {
+            header.set_size(data.len() as u64);
+            header.set_mode(0o644);
+        }
+        tar.append_data(&mut header, name, *data).expect("append entry");
+    }
+    tar.into_inner().expect("into_inner").finish().expect("finish encoder");
+}
+
+/// Code of the archive error `validate_archive` reports
+fn failure_code(archive_path: &std::path::Path, limits: &ArchiveConfig, root_dir: Option<&str>) -> &'static str {
+    match archive::validate_archive(archive_path, limits, root_dir.map(std::path::Path::new)) {
+        Err(AppError::Archive(e)) => e.code(),
+        other => panic!("expected an archive error, got {:?}", other),
+    }
+}
+
+#[test]
+fn test_archive_failure_codes() {
+    let td = tempdir().expect("tempdir");
+    let site: &[(&str, &[u8])] = &[("index.html", b"<html></html>"), ("notes/a.md", b"# a")];
+    let good = td.path().join("site.tar.gz");
+    write_tar_gz(&good, site);
+    let limits = ArchiveConfig::default();
+    archive::validate_archive(&good, &limits, None).expect("good archive should validate");
+
+    let rar = td.path().join("site.rar");
+    fs::copy(&good, &rar).expect("copy");
+    assert_eq!(failure_code(&rar, &limits, None), "ARCHIVE_UNSUPPORTED_FORMAT");
+
+    let small = ArchiveConfig { max_total_bytes: 8, ..ArchiveConfig::default() };
+    assert_eq!(failure_code(&good, &small, None), "ARCHIVE_TOO_LARGE");
+
+    let few = ArchiveConfig { max_entries: 1, ..ArchiveConfig::default() };
+    assert_eq!(failure_code(&good, &few, None), "ARCHIVE_TOO_MANY_ENTRIES");
+
+    let shallow = ArchiveConfig { max_path_depth: 1, ..ArchiveConfig::default() };
+    assert_eq!(failure_code(&good, &shallow, None), "ARCHIVE_PATH_TOO_LONG");
+
+    let no_index = td.path().join("no-index.tar.gz");
+    write_tar_gz(&no_index, &[("readme.md", b"hi")]);
+    assert_eq!(failure_code(&no_index, &limits, None), "ARCHIVE_NO_INDEX_HTML");
+    assert_eq!(failure_code(&good, &limits, Some("dist")), "ARCHIVE_ROOT_DIR_NOT_FOUND");
+
+    let empty = td.path().join("empty.tar.gz");
+    write_tar_gz(&empty, &[("assets/", b"")]);
+    assert_eq!(failure_code(&empty, &limits, None), "ARCHIVE_EMPTY");
+
+    let garbage = td.path().join("garbage.tar.gz");
+    fs::write(&garbage, b"definitely not gzip").expect("write garbage");
+    assert_eq!(failure_code(&garbage, &limits, None), "ARCHIVE_CORRUPT");
+}
+
+#[test]
+fn test_root_dir_traversal_is_typed() {
+    for raw in ["../outside", "/etc", "dist/../../x"] {
+        match archive::parse_root_dir(raw) {
+            Err(AppError::Archive(e @ ArchiveError::PathTraversal(_))) => assert_eq!(e.code(), "ARCHIVE_PATH_TRAVERSAL"),
+            other => panic!("expected PathTraversal for {:?}, got {:?}", raw, other),
+        }
+    }
+    assert_eq!(archive::parse_root_dir(" dist/ ").unwrap(), Some(std::path::PathBuf::from("dist")));
+    assert_eq!(archive::parse_root_dir("").unwrap(), None);
+}
+
+#[tokio::test]
+async fn test_archive_error_response_carries_code() {
+    use axum::{body::to_bytes, http::StatusCode, response::IntoResponse};
+
+    let res = AppError::from(ArchiveError::Empty).into_response();
+    assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+    let body = to_bytes(res.into_body(), usize::MAX).await.unwrap();
+    let json: serde_json::Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(json["error"], "Invalid archive");
+    assert_eq!(json["code"], "ARCHIVE_EMPTY");
+}
+
+#[tokio::test]
+async fn test_forbidden_extension_rejects_upload() {
+    let td = tempdir().expect("tempdir");
+    let tar_gz_path = td.path().join("site.tar.gz");
+    write_tar_gz(&tar_gz_path, &[("index.html", b"<html></html>"), ("uploads/shell.php", b"<?php system($_GET['c']); ?>")]);
+
+    let limits = ArchiveConfig { forbidden_extensions: vec![".PHP".to_string()], ..ArchiveConfig::default() };
+    match archive::validate_archive(&tar_gz_path, &limits, None) {
+        Err(AppError::Archive(e @ ArchiveError::ForbiddenFile(_))) => {
+            assert_eq!(e.code(), "ARCHIVE_FORBIDDEN_FILE");
+            assert!(e.to_string().contains("uploads/shell.php"), "message should name the file: {}", e);
+        }
+        other => panic!("expected ForbiddenFile, got {:?}", other),
+    }
+
+    let outdir = td.path().join("out");
+    let err = archive::extract_archive(&tar_gz_path, &outdir, &limits)
+        .await
+        .expect_err("forbidden file should fail extraction");
+    assert!(matches!(err, AppError::Archive(ArchiveError::ForbiddenFile(_))), "got {:?}", err);
+}
+
+#[tokio::test]
+async fn test_forbidden_extension_skip_mode_leaves_file_out() {
+    let td = tempdir().expect("tempdir");
+    let tar_gz_path = td.path().join("site.tar.gz");
+    write_tar_gz(&tar_gz_path, &[("index.html", b"<html></html>"), ("uploads/shell.php", b"<?php ?>"), ("notes.phps", b"ok")]);
+
+    let limits = ArchiveConfig {
+        forbidden_extensions: vec!["php".to_string()],
+        forbidden_extension_mode: archive::ForbiddenExtensionMode::Skip,
+        ..ArchiveConfig::default()
+    };
+    archive::validate_archive(&tar_gz_path, &limits, None).expect("skip mode should validate");
+
+    let outdir = td.path().join("out");
+    let warnings = archive::extract_archive(&tar_gz_path, &outdir, &limits).await.expect("extract");
+    assert!(outdir.join("index.html").exists());
+    assert!(outdir.join("notes.phps").exists(), "only exact extensions are forbidden");
+    assert!(!outdir.join("uploads/shell.php").exists());
+    assert!(warnings.iter().any(|w| w.contains("uploads/shell.php")), "warnings: {:?}", warnings);
+}
@@ -0,0 +1,60 @@
+/// Archive extraction used as a library
+///
+/// These tests only touch the crate-root exports, the way external tooling would.
+
+use obsidian_publisher_server::{extract_archive, AppError, ArchiveConfig, ArchiveError};
+use std::io::Write;
+use std::path::Path;
+use tempfile::tempdir;
+
+fn write_zip(path: &Path, entries: &[(&str, &[u8])]) {
+    let mut zip = zip::ZipWriter::new(std::fs::File::create(path).expect("create zip"));
+    let options: zip::write::FileOptions<'_, ()> = zip::write::FileOptions::default();
+    for (name, data) in entries {
+        zip.start_file(*name, options).expect("start entry");
+        zip.write_all(data).expect("write entry");
+    }
+    zip.finish().expect("finish zip");
+}
+
+#[tokio::test]
+async fn test_extract_archive_as_library() {
+    let td = tempdir().expect("tempdir");
+    let zip_path = td.path().join("site.zip");
+    write_zip(&zip_path, &[
+        ("index.html", b"<h1>home</h1>"),
+        ("notes/a.html", b"<p>a</p>"),
+        ("../escape.txt", b"nope"),
+    ]);
+
+    let out = td.path().join("out");
+    let warnings = extract_archive(&zip_path, &out, &ArchiveConfig::default())
+        .await
+        .expect("extract_archive failed");
+
+    assert_eq!(std::fs::read(out.join("index.html")).unwrap(), b"<h1>home</h1>");
+    assert_eq!(std::fs::read(out.join("notes/a.html")).unwrap(), b"<p>a</p>");
+    // zip-slip entries are skipped and reported, never written
+    assert_eq!(warnings.len(), 1, "warnings: {:?}", warnings);
+    assert!(warnings[0].contains("escape.txt"));
+    assert!(!td.path().join("escape.txt").exists());
+}
+
+#[tokio::test]
+async fn test_extract_archive_limits_are_typed() {
+    let td = tempdir().expect("tempdir");
+    let zip_path = td.path().join("site.zip");
+    write_zip(&zip_path, &[("index.html", b"x"), ("notes/deep/a.html", b"y")]);
+
+    let limits = ArchiveConfig { max_path_depth: 2, ..ArchiveConfig::default() };
+    let err = extract_archive(&zip_path, &td.path().join("out"), &limits).await.unwrap_err();
+    match err {
+        AppError::Archive(e @ ArchiveError::PathTooLong(_)) => assert_eq!(e.code(), "ARCHIVE_PATH_TOO_LONG"),
+        other => panic!("expected PathTooLong, got {:?}", other),
+    }
+
+    let rar = td.path().join("site.rar");
+    std::fs::write(&rar, b"not an archive").unwrap();
+    let err = extract_archive(&rar, &td.path().join("out"), &ArchiveConfig::default()).await.unwrap_err();
+    assert!(matches!(err, AppError::Archive(ArchiveError::UnsupportedFormat(_))), "got {:?}", err);
+}
@@ -0,0 +1,698 @@
+/// Auth tests
+///
+/// These tests exercise token handling and the auth service without HTTP.
+
+mod utils;
+
+use axum::{body::to_bytes, extract::{Query, State}, http::StatusCode, response::IntoResponse};
+use obsidian_publisher_server::{
+    auth::{authenticate_headers, AuthService, AuthUser, AuthenticatedUser, TokenService, ADMIN_ROLE},
+    config::Config,
+    error::AppError,
+    handlers::{admin::admin_create_invite, auth::me, json::JsonBody, users::{update_user_profile, UpdateUserRequest}},
+    models::{Invite, LoginRequest, PasswordReset, PasswordResetConfirm, PasswordResetRequest, RegisterRequest, User},
+};
+use std::collections::HashMap;
+use std::sync::Arc;
+use uuid::Uuid;
+use utils::storage::create_test_storage;
+
+// ===== token expiration Tests =====
+
+#[test]
+fn test_generated_token_exp_matches_configured_hours() {
+    let hours = 72;
+    let service = TokenService::new("test-secret".to_string(), hours);
+    let token = service.generate_token(Uuid::new_v4(), "erin".to_string()).expect("generate_token failed");
+
+    let claims = service.verify_token(&token).expect("verify_token failed");
+    let expected = chrono::Utc::now().timestamp() + hours * 3600;
+    let diff = (claims.exp as i64 - expected).abs();
+    assert!(diff <= 5, "exp should be ~now + {}h, off by {}s", hours, diff);
+}
+
+#[test]
+fn test_recently_expired_token_is_accepted_within_leeway() {
+    // Expired 30s ago, as seen by a server whose clock runs slightly ahead
+    let exp = (chrono::Utc::now().timestamp() - 30) as usize;
+    let token = jsonwebtoken::encode(
+        &jsonwebtoken::Header::default(),
+        &serde_json::json!({ "sub": Uuid::new_v4().to_string(), "username": "skew", "exp": exp }),
+        &jsonwebtoken::EncodingKey::from_secret(b"test-secret"),
+    )
+    .unwrap();
+
+    let lenient = TokenService::new("test-secret".to_string(), 1).with_leeway(120);
+    let claims = lenient.verify_token(&token).expect("token should verify within the leeway");
+    assert_eq!(claims.username, "skew");
+
+    let strict = TokenService::new("test-secret".to_string(), 1).with_leeway(0);
+    assert!(strict.verify_token(&token).is_err());
+}
+
+#[tokio::test]
+async fn test_expired_and_garbage_tokens_get_distinct_401s() {
+    let service = TokenService::new("test-secret".to_string(), 1).with_leeway(0);
+    let expired = jsonwebtoken::encode(
+        &jsonwebtoken::Header::default(),
+        &serde_json::json!({ "sub": Uuid::new_v4().to_string(), "username": "late", "exp": chrono::Utc::now().timestamp() - 600 }),
+        &jsonwebtoken::EncodingKey::from_secret(b"test-secret"),
+    )
+    .unwrap();
+    let forged = TokenService::new("other-secret".to_string(), 1).generate_token(Uuid::new_v4(), "mallory".to_string()).unwrap();
+
+    let error_body = |token: &str| {
+        let mut headers = axum::http::HeaderMap::new();
+        headers.insert(axum::http::header::AUTHORIZATION, format!("Bearer {}", token).parse().unwrap());
+        authenticate_headers(&service, &headers).unwrap_err()
+    };
+
+    let err = error_body(&expired);
+    let AppError::TokenExpired(ago) = err else { panic!("expected TokenExpired, got {:?}", err) };
+    assert!((600..=605).contains(&ago), "expired {}s ago", ago);
+    let res = err.into_response();
+    assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+    let body: serde_json::Value = serde_json::from_slice(&to_bytes(res.into_body(), usize::MAX).await.unwrap()).unwrap();
+    assert_eq!(body["code"], "TOKEN_EXPIRED");
+
+    for garbage in ["not-a-jwt", forged.as_str()] {
+        let res = error_body(garbage).into_response();
+        assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+        let body: serde_json::Value = serde_json::from_slice(&to_bytes(res.into_body(), usize::MAX).await.unwrap()).unwrap();
+        assert_eq!(body["code"], "AUTH_FAILED", "{}", garbage);
+    }
+}
+
+// ===== introspect Tests =====
+
+#[test]
+fn test_introspect_valid_token() {
+    let service = TokenService::new("test-secret".to_string(), 1);
+    let user_id = Uuid::new_v4();
+    let token = service.generate_token(user_id, "alice".to_string()).expect("generate_token failed");
+
+    let res = service.introspect(&token);
+    assert!(res.active);
+    assert_eq!(res.sub, Some(user_id.to_string()));
+    assert_eq!(res.username.as_deref(), Some("alice"));
+    assert!(res.exp.is_some());
+    let expires_in = res.expires_in.unwrap();
+    assert!(expires_in > 0 && expires_in <= 3600);
+}
+
+#[test]
+fn test_introspect_expired_or_garbage_token() {
+    // Negative lifetime produces an already-expired token
+    let expired_service = TokenService::new("test-secret".to_string(), -1);
+    let token = expired_service.generate_token(Uuid::new_v4(), "bob".to_string()).expect("generate_token failed");
+
+    let res = expired_service.introspect(&token);
+    assert!(!res.active);
+    assert!(res.sub.is_none());
+
+    let res = expired_service.introspect("not-a-jwt");
+    assert!(!res.active);
+
+    let json = serde_json::to_value(&res).unwrap();
+    assert_eq!(json, serde_json::json!({ "active": false }));
+}
+
+// ===== claims Tests =====
+
+#[tokio::test]
+async fn test_login_token_carries_roles_claim() {
+    let (storage, _temp) = create_test_storage().await;
+
+    let mut user = User::new("gina".to_string(), "right-pass".to_string());
+    user.roles = vec!["editor".to_string(), "admin".to_string()];
+    storage.users.create(user).await.expect("Failed to create user");
+
+    let tokens = TokenService::new("test-secret".to_string(), 1)
+        .with_custom_claims(Some(serde_json::json!({ "tenant": "docs" })));
+    let service = AuthService::new(storage.users.clone(), storage.audit.clone(), tokens.clone(), true);
+
+    let res = service.login(LoginRequest { username: "gina".to_string(), password: "right-pass".to_string() })
+        .await
+        .expect("login failed");
+    let claims = tokens.verify_token(&res.token.unwrap()).expect("token should verify");
+    assert_eq!(claims.username, "gina");
+    assert_eq!(claims.roles, vec!["editor", "admin"]);
+    assert_eq!(claims.custom, Some(serde_json::json!({ "tenant": "docs" })));
+}
+
+#[test]
+fn test_tokens_without_extra_claims_still_verify() {
+    // Token shaped like those issued before roles/custom existed
+    let exp = (chrono::Utc::now().timestamp() + 3600) as usize;
+    let legacy = jsonwebtoken::encode(
+        &jsonwebtoken::Header::default(),
+        &serde_json::json!({ "sub": Uuid::new_v4().to_string(), "username": "old", "exp": exp }),
+        &jsonwebtoken::EncodingKey::from_secret(b"test-secret"),
+    )
+    .unwrap();
+
+    // A non-object custom_claims is dropped rather than emitted
+    let service = TokenService::new("test-secret".to_string(), 1).with_custom_claims(Some(serde_json::json!(["x"])));
+    let claims = service.verify_token(&legacy).expect("legacy token should verify");
+    assert!(claims.roles.is_empty());
+    assert!(claims.custom.is_none());
+
+    let fresh = service.generate_token(Uuid::new_v4(), "new".to_string()).unwrap();
+    assert!(service.verify_token(&fresh).unwrap().custom.is_none());
+}
+
+// ===== audit Tests =====
+
+#[tokio::test]
+async fn test_login_failure_appends_audit_event() {
+    let (storage, _temp) = create_test_storage().await;
+
+    let user = User::new("carol".to_string(), "right".to_string());
+    let user_id = user.id;
+    storage.users.create(user).await.expect("Failed to create user");
+
+    let service = AuthService::new(
+        storage.users.clone(),
+        storage.audit.clone(),
+        TokenService::new("test-secret".to_string(), 1),
+        true,
+    );
+
+    let res = service.login(LoginRequest { username: "carol".to_string(), password: "wrong".to_string() }).await;
+    assert!(matches!(res, Err(AppError::AuthenticationFailed)));
+
+    let events = storage.audit.recent(10).await.expect("recent failed");
+    assert_eq!(events.len(), 1);
+    assert_eq!(events[0].action, "login_failure");
+    assert_eq!(events[0].actor_id, Some(user_id));
+    assert_eq!(events[0].target.as_deref(), Some("carol"));
+}
+
+#[tokio::test]
+async fn test_login_records_last_login() {
+    let (storage, _temp) = create_test_storage().await;
+
+    let user = User::new("frank".to_string(), "right-pass".to_string());
+    let user_id = user.id;
+    storage.users.create(user).await.expect("Failed to create user");
+    let idle = User::new("idle".to_string(), "right-pass".to_string());
+    let idle_id = idle.id;
+    storage.users.create(idle).await.expect("Failed to create user");
+
+    let service = AuthService::new(
+        storage.users.clone(),
+        storage.audit.clone(),
+        TokenService::new("test-secret".to_string(), 1),
+        true,
+    );
+    let login = || service.login(LoginRequest { username: "frank".to_string(), password: "right-pass".to_string() });
+
+    login().await.expect("first login failed");
+    let first = storage.users.get(user_id).await.unwrap().unwrap().last_login.expect("last_login not set");
+
+    tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+    login().await.expect("second login failed");
+    let second = storage.users.get(user_id).await.unwrap().unwrap().last_login.expect("last_login not set");
+    assert!(second > first, "last_login should advance: {} -> {}", first, second);
+
+    // A failed login leaves it alone
+    let res = service.login(LoginRequest { username: "frank".to_string(), password: "wrong".to_string() }).await;
+    assert!(res.is_err());
+    assert_eq!(storage.users.get(user_id).await.unwrap().unwrap().last_login, Some(second));
+
+    assert!(storage.users.get(idle_id).await.unwrap().unwrap().last_login.is_none());
+}
+
+// ===== validation Tests =====
+
+#[tokio::test]
+async fn test_register_reports_every_invalid_field() {
+    let (storage, _temp) = create_test_storage().await;
+    let service = AuthService::new(
+        storage.users.clone(),
+        storage.audit.clone(),
+        TokenService::new("test-secret".to_string(), 1),
+        true,
+    );
+
+    let err = service
+        .register(RegisterRequest { username: "".to_string(), password: "pw".to_string(), invite_code: None, email: None })
+        .await
+        .unwrap_err();
+    let AppError::Validation(fields) = &err else { panic!("expected Validation, got {:?}", err) };
+    let names: Vec<&str> = fields.iter().map(|f| f.field.as_str()).collect();
+    assert_eq!(names, ["username", "password"]);
+
+    let res = err.into_response();
+    assert_eq!(res.status(), StatusCode::UNPROCESSABLE_ENTITY);
+    let body = to_bytes(res.into_body(), usize::MAX).await.unwrap();
+    let json: serde_json::Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(json["error"], "Validation failed");
+    assert_eq!(json["fields"].as_array().unwrap().len(), 2);
+    assert_eq!(json["fields"][0]["field"], "username");
+    assert_eq!(json["fields"][1]["field"], "password");
+    assert!(json["fields"][1]["message"].as_str().unwrap().contains("at least"));
+
+    // Nothing was stored
+    assert!(storage.users.list_all().await.unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn test_register_rejects_malformed_email() {
+    let (storage, _temp) = create_test_storage().await;
+    let service = AuthService::new(
+        storage.users.clone(),
+        storage.audit.clone(),
+        TokenService::new("test-secret".to_string(), 1),
+        true,
+    );
+
+    for email in ["not-an-email", "a@b", "@example.com", "two@@example.com", "sp ace@example.com"] {
+        let err = service
+            .register(RegisterRequest {
+                username: "mallory".to_string(),
+                password: "long-enough".to_string(),
+                invite_code: None,
+                email: Some(email.to_string()),
+            })
+            .await
+            .unwrap_err();
+        let AppError::Validation(fields) = &err else { panic!("{}: expected Validation, got {:?}", email, err) };
+        assert_eq!(fields.len(), 1, "{}", email);
+        assert_eq!(fields[0].field, "email");
+    }
+    assert!(storage.users.list_all().await.unwrap().is_empty());
+}
+
+// ===== /auth/me Tests =====
+
+#[tokio::test]
+async fn test_me_reports_seconds_remaining() {
+    let (storage, _temp) = create_test_storage().await;
+
+    let user = User::new("dave".to_string(), "pass".to_string());
+    let user_id = user.id;
+    storage.users.create(user).await.expect("Failed to create user");
+
+    let service = Arc::new(AuthService::new(
+        storage.users.clone(),
+        storage.audit.clone(),
+        TokenService::new("test-secret".to_string(), 1),
+        true,
+    ));
+
+    let exp = (chrono::Utc::now().timestamp() + 3600) as usize;
+    let auth = AuthenticatedUser(AuthUser { id: user_id, username: "dave".to_string(), exp });
+    let res = me(State(service), auth).await.expect("me failed");
+
+    assert_eq!(res.0.exp, exp);
+    assert!(res.0.seconds_remaining > 0 && res.0.seconds_remaining <= 3600);
+
+    let json = serde_json::to_value(&res.0).unwrap();
+    assert_eq!(json["username"], "dave");
+    assert!(json["seconds_remaining"].as_i64().unwrap() > 0);
+}
+
+#[tokio::test]
+async fn test_email_set_at_register_is_shown_by_me() {
+    let (storage, _temp) = create_test_storage().await;
+    let service = Arc::new(AuthService::new(
+        storage.users.clone(),
+        storage.audit.clone(),
+        TokenService::new("test-secret".to_string(), 1),
+        true,
+    ));
+
+    let user = service
+        .register(RegisterRequest {
+            username: "erin".to_string(),
+            password: "long-enough".to_string(),
+            invite_code: None,
+            email: Some("  Erin@Example.COM ".to_string()),
+        })
+        .await
+        .expect("register with email failed");
+    assert_eq!(user.email.as_deref(), Some("erin@example.com"));
+
+    let exp = (chrono::Utc::now().timestamp() + 3600) as usize;
+    let auth = AuthenticatedUser(AuthUser { id: user.id, username: "erin".to_string(), exp });
+    let json = serde_json::to_value(me(State(service), auth).await.expect("me failed").0).unwrap();
+    assert_eq!(json["email"], "erin@example.com");
+
+    // Accounts without an email don't carry the key at all
+    let plain = serde_json::to_value(obsidian_publisher_server::models::UserResponse::from(
+        User::new("plain".to_string(), "pw".to_string()),
+    )).unwrap();
+    assert!(plain.get("email").is_none());
+}
+
+#[tokio::test]
+async fn test_profile_update_sets_validates_and_clears_email() {
+    let (storage, _temp) = create_test_storage().await;
+    let storage = Arc::new(storage);
+    let alice = storage.users.create(User::new("alice".to_string(), "pw".to_string())).await.unwrap();
+    let bob = storage.users.create(User::new("bob".to_string(), "pw".to_string())).await.unwrap();
+    let auth = |user: &User| AuthenticatedUser(AuthUser { id: user.id, username: user.username.clone(), exp: usize::MAX });
+    let email = |value: &str| UpdateUserRequest { username: None, email: Some(value.to_string()) };
+
+    let updated = update_user_profile(State(storage.clone()), auth(&alice), JsonBody(email("Alice@Example.com")))
+        .await
+        .expect("setting a valid email failed")
+        .0;
+    assert_eq!(updated.email.as_deref(), Some("alice@example.com"));
+    let stored = storage.users.get(alice.id).await.unwrap().unwrap();
+    assert_eq!(stored.email.as_deref(), Some("alice@example.com"));
+
+    let err = update_user_profile(State(storage.clone()), auth(&bob), JsonBody(email("bob-at-example.com")))
+        .await
+        .unwrap_err();
+    let AppError::Validation(fields) = &err else { panic!("expected Validation, got {:?}", err) };
+    assert_eq!(fields[0].field, "email");
+
+    // Another account can't take alice's address, whatever its case
+    let err = update_user_profile(State(storage.clone()), auth(&bob), JsonBody(email("ALICE@example.com")))
+        .await
+        .unwrap_err();
+    assert!(matches!(err, AppError::EmailAlreadyExists), "got {:?}", err);
+    assert_eq!(err.into_response().status(), StatusCode::CONFLICT);
+    assert!(storage.users.get(bob.id).await.unwrap().unwrap().email.is_none());
+
+    // Clearing frees the address for bob
+    let cleared = update_user_profile(State(storage.clone()), auth(&alice), JsonBody(email("")))
+        .await
+        .expect("clearing email failed")
+        .0;
+    assert!(cleared.email.is_none());
+    assert!(storage.users.get(alice.id).await.unwrap().unwrap().email.is_none());
+    let updated = update_user_profile(State(storage.clone()), auth(&bob), JsonBody(email("alice@example.com")))
+        .await
+        .expect("reusing a released email failed")
+        .0;
+    assert_eq!(updated.email.as_deref(), Some("alice@example.com"));
+}
+
+// ===== invite Tests =====
+
+fn invite_register(username: &str, invite_code: Option<String>) -> RegisterRequest {
+    RegisterRequest { username: username.to_string(), password: "long-enough".to_string(), invite_code, email: None }
+}
+
+fn invite_field_message(err: AppError) -> String {
+    match err {
+        AppError::Validation(fields) => {
+            assert_eq!(fields.len(), 1);
+            assert_eq!(fields[0].field, "invite_code");
+            fields[0].message.clone()
+        }
+        other => panic!("expected invite_code validation error, got {:?}", other),
+    }
+}
+
+#[tokio::test]
+async fn test_register_consumes_invite_code() {
+    let (storage, _temp) = create_test_storage().await;
+    let storage = Arc::new(storage);
+    let config = Arc::new(Config::default());
+
+    let mut params = HashMap::new();
+    params.insert("key".to_string(), config.server.jwt_secret.clone());
+    let invite = admin_create_invite(State((storage.clone(), config.clone())), None, Query(params))
+        .await
+        .expect("minting an invite failed")
+        .0;
+    assert_eq!((invite.max_uses, invite.uses), (1, 0));
+    assert!(invite.expires_at.is_none());
+
+    let service = AuthService::new(
+        storage.users.clone(),
+        storage.audit.clone(),
+        TokenService::new("test-secret".to_string(), 1),
+        true,
+    ).with_required_invites(storage.invites.clone());
+
+    let user = service.register(invite_register("ivy", Some(invite.code.clone())))
+        .await
+        .expect("registering with a fresh invite failed");
+    assert_eq!(user.username, "ivy");
+
+    let stored = storage.invites.get(&invite.code).await.unwrap().expect("invite should still exist");
+    assert_eq!(stored.uses, 1);
+
+    // The code is used up now
+    let err = service.register(invite_register("jack", Some(invite.code))).await.unwrap_err();
+    assert_eq!(invite_field_message(err), "is invalid, expired or already used");
+    assert!(storage.users.get_by_username("jack").await.unwrap().is_none());
+}
+
+#[tokio::test]
+async fn test_register_requires_invite_code_when_enabled() {
+    let (storage, _temp) = create_test_storage().await;
+    let service = AuthService::new(
+        storage.users.clone(),
+        storage.audit.clone(),
+        TokenService::new("test-secret".to_string(), 1),
+        true,
+    ).with_required_invites(storage.invites.clone());
+
+    let err = service.register(invite_register("kim", None)).await.unwrap_err();
+    assert_eq!(invite_field_message(err), "is required");
+
+    let err = service.register(invite_register("kim", Some("no-such-code".to_string()))).await.unwrap_err();
+    assert_eq!(invite_field_message(err), "is invalid, expired or already used");
+    assert_eq!(storage.users.count().await.unwrap(), 0);
+}
+
+#[tokio::test]
+async fn test_expired_invite_is_rejected() {
+    let (storage, _temp) = create_test_storage().await;
+    let mut invite = Invite::new(3, None);
+    invite.expires_at = Some(chrono::Utc::now() - chrono::Duration::minutes(1));
+    storage.invites.create(invite.clone()).await.unwrap();
+
+    let service = AuthService::new(
+        storage.users.clone(),
+        storage.audit.clone(),
+        TokenService::new("test-secret".to_string(), 1),
+        true,
+    ).with_required_invites(storage.invites.clone());
+
+    let err = service.register(invite_register("lee", Some(invite.code))).await.unwrap_err();
+    assert_eq!(invite_field_message(err), "is invalid, expired or already used");
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn test_single_use_invite_redeemed_once_under_concurrency() {
+    let (storage, _temp) = create_test_storage().await;
+    let invite = Invite::new(1, None);
+    storage.invites.create(invite.clone()).await.unwrap();
+
+    let service = Arc::new(AuthService::new(
+        storage.users.clone(),
+        storage.audit.clone(),
+        TokenService::new("test-secret".to_string(), 1),
+        true,
+    ).with_required_invites(storage.invites.clone()));
+
+    let tasks: Vec<_> = (0..8)
+        .map(|i| {
+            let service = service.clone();
+            let code = invite.code.clone();
+            tokio::spawn(async move { service.register(invite_register(&format!("racer-{}", i), Some(code))).await })
+        })
+        .collect();
+    let mut registered = 0;
+    for task in tasks {
+        match task.await.unwrap() {
+            Ok(_) => registered += 1,
+            Err(err) => assert_eq!(invite_field_message(err), "is invalid, expired or already used"),
+        }
+    }
+    assert_eq!(registered, 1);
+    assert_eq!(storage.invites.get(&invite.code).await.unwrap().unwrap().uses, 1);
+    assert_eq!(storage.users.count().await.unwrap(), 1);
+}
+
+#[tokio::test]
+async fn test_failed_registration_gives_the_invite_use_back() {
+    let (storage, _temp) = create_test_storage().await;
+    storage.users.create(User::new("taken".to_string(), "pw".to_string())).await.unwrap();
+    let invite = Invite::new(1, None);
+    storage.invites.create(invite.clone()).await.unwrap();
+
+    let service = AuthService::new(
+        storage.users.clone(),
+        storage.audit.clone(),
+        TokenService::new("test-secret".to_string(), 1),
+        true,
+    ).with_required_invites(storage.invites.clone());
+
+    let err = service.register(invite_register("taken", Some(invite.code.clone()))).await.unwrap_err();
+    assert!(matches!(err, AppError::UserAlreadyExists), "got {:?}", err);
+    assert_eq!(storage.invites.get(&invite.code).await.unwrap().unwrap().uses, 0);
+
+    service.register(invite_register("fresh", Some(invite.code.clone()))).await.expect("the invite should still be usable");
+}
+
+// ===== create-admin Tests =====
+
+#[tokio::test]
+async fn test_create_admin_persists_admin_with_hashed_password() {
+    let (storage, _temp) = create_test_storage().await;
+    let service = AuthService::new(
+        storage.users.clone(),
+        storage.audit.clone(),
+        TokenService::new("test-secret".to_string(), 1),
+        false,
+    );
+
+    let created = service.create_admin("root".to_string(), "bootstrap-pass".to_string()).await.expect("create_admin failed");
+    let stored = storage.users.get_by_username("root").await.unwrap().expect("admin should be persisted");
+    assert_eq!(stored.id, created.id);
+    assert_eq!(stored.roles, vec![ADMIN_ROLE.to_string()]);
+    assert_ne!(stored.password, "bootstrap-pass");
+    assert!(bcrypt::verify("bootstrap-pass", &stored.password).unwrap());
+
+    // The admin logs in like anyone else and gets the role in its token
+    let res = service.login(LoginRequest { username: "root".to_string(), password: "bootstrap-pass".to_string() })
+        .await
+        .expect("admin login failed");
+    let claims = TokenService::new("test-secret".to_string(), 1).verify_token(&res.token.unwrap()).unwrap();
+    assert_eq!(claims.roles, vec![ADMIN_ROLE.to_string()]);
+}
+
+#[tokio::test]
+async fn test_create_admin_keeps_plaintext_password_in_plaintext_mode() {
+    let (storage, _temp) = create_test_storage().await;
+    let service = AuthService::new(
+        storage.users.clone(),
+        storage.audit.clone(),
+        TokenService::new("test-secret".to_string(), 1),
+        true,
+    );
+
+    service.create_admin("root".to_string(), "bootstrap-pass".to_string()).await.expect("create_admin failed");
+    let stored = storage.users.get_by_username("root").await.unwrap().unwrap();
+    assert_eq!(stored.password, "bootstrap-pass");
+    assert_eq!(stored.roles, vec![ADMIN_ROLE.to_string()]);
+
+    let err = service.create_admin("root".to_string(), "another-pass".to_string()).await.unwrap_err();
+    assert!(matches!(err, AppError::UserAlreadyExists), "got {:?}", err);
+    let err = service.create_admin("second".to_string(), "short".to_string()).await.unwrap_err();
+    assert!(matches!(err, AppError::Validation(_)), "got {:?}", err);
+}
+
+// ===== password reset Tests =====
+
+fn reset_service(storage: &obsidian_publisher_server::storage::Storage, expose_token: bool) -> AuthService {
+    AuthService::new(
+        storage.users.clone(),
+        storage.audit.clone(),
+        TokenService::new("test-secret".to_string(), 1),
+        true,
+    ).with_password_resets(storage.password_resets.clone(), 30, expose_token)
+}
+
+fn token_field_message(err: AppError) -> String {
+    match err {
+        AppError::Validation(fields) => {
+            assert_eq!(fields.len(), 1);
+            assert_eq!(fields[0].field, "token");
+            fields[0].message.clone()
+        }
+        other => panic!("expected token validation error, got {:?}", other),
+    }
+}
+
+#[tokio::test]
+async fn test_password_reset_by_username_stores_only_the_hash() {
+    let (storage, _temp) = create_test_storage().await;
+    let user = storage.users.create(User::new("hana".to_string(), "old-password".to_string())).await.unwrap();
+    let service = reset_service(&storage, true);
+
+    let res = service
+        .request_password_reset(PasswordResetRequest { username: Some("hana".to_string()), email: None })
+        .await
+        .expect("requesting a reset failed");
+    let token = res.token.expect("token should be exposed");
+
+    assert!(storage.password_resets.get(&token).await.unwrap().is_none(), "plain token must not be a key");
+    let stored = storage.password_resets.get(&PasswordReset::hash_token(&token)).await.unwrap().expect("reset should be stored");
+    assert_eq!(stored.user_id, user.id);
+    assert!(!stored.used);
+
+    service
+        .confirm_password_reset(PasswordResetConfirm { token: token.clone(), new_password: "new-password".to_string() })
+        .await
+        .expect("confirming the reset failed");
+    assert_eq!(storage.users.get(user.id).await.unwrap().unwrap().password, "new-password");
+    assert!(storage.password_resets.get(&PasswordReset::hash_token(&token)).await.unwrap().unwrap().used);
+
+    // Without `expose_reset_token` the token stays server-side
+    let res = reset_service(&storage, false)
+        .request_password_reset(PasswordResetRequest { username: Some("hana".to_string()), email: None })
+        .await
+        .unwrap();
+    assert!(res.token.is_none());
+}
+
+#[tokio::test]
+async fn test_password_reset_rejects_expired_used_and_unknown_tokens() {
+    let (storage, _temp) = create_test_storage().await;
+    let user = storage.users.create(User::new("ines".to_string(), "old-password".to_string())).await.unwrap();
+    let service = reset_service(&storage, true);
+    let confirm = |token: &str| PasswordResetConfirm { token: token.to_string(), new_password: "new-password".to_string() };
+
+    let (expired, expired_token) = PasswordReset::new(user.id, chrono::Duration::minutes(-1));
+    storage.password_resets.create(expired).await.unwrap();
+    let err = service.confirm_password_reset(confirm(&expired_token)).await.unwrap_err();
+    assert_eq!(token_field_message(err), "is invalid, expired or already used");
+
+    let (mut used, used_token) = PasswordReset::new(user.id, chrono::Duration::minutes(30));
+    used.used = true;
+    storage.password_resets.create(used).await.unwrap();
+    let err = service.confirm_password_reset(confirm(&used_token)).await.unwrap_err();
+    assert_eq!(token_field_message(err), "is invalid, expired or already used");
+
+    let err = service.confirm_password_reset(confirm("not-a-token")).await.unwrap_err();
+    assert_eq!(token_field_message(err), "is invalid, expired or already used");
+
+    // A weak new password is refused before the token is looked at
+    let (fresh, fresh_token) = PasswordReset::new(user.id, chrono::Duration::minutes(30));
+    storage.password_resets.create(fresh).await.unwrap();
+    let err = service
+        .confirm_password_reset(PasswordResetConfirm { token: fresh_token.clone(), new_password: "x".to_string() })
+        .await
+        .unwrap_err();
+    let AppError::Validation(fields) = &err else { panic!("expected Validation, got {:?}", err) };
+    assert_eq!(fields[0].field, "new_password");
+    assert!(!storage.password_resets.get(&PasswordReset::hash_token(&fresh_token)).await.unwrap().unwrap().used);
+
+    assert_eq!(storage.users.get(user.id).await.unwrap().unwrap().password, "old-password");
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn test_password_reset_token_spent_once_under_concurrency() {
+    let (storage, _temp) = create_test_storage().await;
+    let user = storage.users.create(User::new("jo".to_string(), "old-password".to_string())).await.unwrap();
+    let service = Arc::new(reset_service(&storage, true));
+    let (reset, token) = PasswordReset::new(user.id, chrono::Duration::minutes(30));
+    storage.password_resets.create(reset).await.unwrap();
+
+    let tasks: Vec<_> = (0..8)
+        .map(|i| {
+            let service = service.clone();
+            let confirm = PasswordResetConfirm { token: token.clone(), new_password: format!("new-password-{}", i) };
+            tokio::spawn(async move { service.confirm_password_reset(confirm).await.map(|_| i) })
+        })
+        .collect();
+    let mut winners = Vec::new();
+    for task in tasks {
+        match task.await.unwrap() {
+            Ok(i) => winners.push(i),
+            Err(err) => assert_eq!(token_field_message(err), "is invalid, expired or already used"),
+        }
+    }
+    assert_eq!(winners.len(), 1, "exactly one confirm should spend the token");
+    let password = storage.users.get(user.id).await.unwrap().unwrap().password;
+    assert_eq!(password, format!("new-password-{}", winners[0]));
+}
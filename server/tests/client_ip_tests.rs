@@ -0,0 +1,57 @@
+/// Client address resolution tests
+///
+/// These tests check when `utils::client_ip` believes forwarding headers.
+
+use axum::http::HeaderMap;
+use obsidian_publisher_server::utils::client_ip::client_ip;
+use std::net::IpAddr;
+
+fn ip(s: &str) -> IpAddr {
+    s.parse().unwrap()
+}
+
+fn headers(pairs: &[(&'static str, &str)]) -> HeaderMap {
+    let mut map = HeaderMap::new();
+    for (name, value) in pairs {
+        map.append(*name, value.parse().unwrap());
+    }
+    map
+}
+
+#[test]
+fn test_untrusted_peer_ignores_forwarding_headers() {
+    let spoofed = headers(&[("x-forwarded-for", "1.2.3.4"), ("x-real-ip", "5.6.7.8")]);
+    let got = client_ip(Some(ip("203.0.113.9")), &spoofed, &[ip("10.0.0.1")]);
+    assert_eq!(got, Some(ip("203.0.113.9")));
+
+    // No proxies configured at all
+    assert_eq!(client_ip(Some(ip("203.0.113.9")), &spoofed, &[]), Some(ip("203.0.113.9")));
+}
+
+#[test]
+fn test_trusted_peer_honors_forwarded_for() {
+    let proxy = ip("10.0.0.1");
+    let got = client_ip(Some(proxy), &headers(&[("x-forwarded-for", "198.51.100.7")]), &[proxy]);
+    assert_eq!(got, Some(ip("198.51.100.7")));
+}
+
+#[test]
+fn test_forwarded_for_skips_trusted_hops_but_not_client_supplied_ones() {
+    let (edge, inner) = (ip("10.0.0.1"), ip("10.0.0.2"));
+    // The client sent "6.6.6.6" itself; edge appended the real address, inner appended edge
+    let hops = headers(&[("x-forwarded-for", "6.6.6.6, 198.51.100.7, 10.0.0.1")]);
+    assert_eq!(client_ip(Some(inner), &hops, &[edge, inner]), Some(ip("198.51.100.7")));
+
+    // Repeated headers are one list
+    let split = headers(&[("x-forwarded-for", "198.51.100.7"), ("x-forwarded-for", "10.0.0.1")]);
+    assert_eq!(client_ip(Some(inner), &split, &[edge, inner]), Some(ip("198.51.100.7")));
+}
+
+#[test]
+fn test_trusted_peer_falls_back_to_real_ip_then_socket() {
+    let proxy = ip("10.0.0.1");
+    assert_eq!(client_ip(Some(proxy), &headers(&[("x-real-ip", "198.51.100.8")]), &[proxy]), Some(ip("198.51.100.8")));
+    assert_eq!(client_ip(Some(proxy), &headers(&[("x-forwarded-for", "not-an-ip")]), &[proxy]), Some(proxy));
+    assert_eq!(client_ip(Some(proxy), &HeaderMap::new(), &[proxy]), Some(proxy));
+    assert_eq!(client_ip(None, &headers(&[("x-real-ip", "198.51.100.8")]), &[proxy]), None);
+}
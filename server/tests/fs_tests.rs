@@ -0,0 +1,187 @@
+/// Directory walk helper tests
+///
+/// These tests run the `utils::fs` helpers against small fixture trees on disk.
+
+use obsidian_publisher_server::utils::fs::{copy_dir_recursive, dir_size_and_count, list_files, walk_dir, TempGuard};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use tempfile::TempDir;
+
+/// index.html (5 bytes), notes/a.html (3), notes/deep/b.css (4), empty/
+fn fixture_tree(root: &Path) {
+    std::fs::create_dir_all(root.join("notes/deep")).unwrap();
+    std::fs::create_dir_all(root.join("empty")).unwrap();
+    std::fs::write(root.join("index.html"), b"hello").unwrap();
+    std::fs::write(root.join("notes/a.html"), b"abc").unwrap();
+    std::fs::write(root.join("notes/deep/b.css"), b"body").unwrap();
+}
+
+// ===== walk_dir Tests =====
+
+#[test]
+fn test_walk_dir_visits_directories_before_contents() {
+    let temp = TempDir::new().expect("Failed to create temp dir");
+    fixture_tree(temp.path());
+
+    let mut seen = Vec::new();
+    walk_dir(temp.path(), |entry| {
+        seen.push(entry.path.strip_prefix(temp.path()).unwrap().to_path_buf());
+        Ok(())
+    })
+    .unwrap();
+
+    assert_eq!(seen.len(), 6);
+    let pos = |p: &str| seen.iter().position(|s| s == Path::new(p)).unwrap_or_else(|| panic!("{} not visited", p));
+    assert!(pos("notes") < pos("notes/a.html"));
+    assert!(pos("notes/deep") < pos("notes/deep/b.css"));
+    assert!(!seen.contains(&PathBuf::new()), "root itself is not reported");
+}
+
+#[test]
+fn test_walk_dir_missing_root_visits_nothing() {
+    let temp = TempDir::new().expect("Failed to create temp dir");
+    let mut visits = 0;
+    walk_dir(&temp.path().join("gone"), |_| {
+        visits += 1;
+        Ok(())
+    })
+    .unwrap();
+    assert_eq!(visits, 0);
+}
+
+// ===== dir_size_and_count Tests =====
+
+#[test]
+fn test_dir_size_and_count_fixture_tree() {
+    let temp = TempDir::new().expect("Failed to create temp dir");
+    fixture_tree(temp.path());
+    assert_eq!(dir_size_and_count(temp.path()).unwrap(), (12, 3));
+}
+
+#[test]
+fn test_dir_size_and_count_missing_root_is_empty() {
+    let temp = TempDir::new().expect("Failed to create temp dir");
+    let gone = temp.path().join("deleted-site");
+
+    let (bytes, files) = dir_size_and_count(&gone).expect("walk of a vanished dir should not fail");
+    assert_eq!((bytes, files), (0, 0));
+}
+
+#[test]
+fn test_dir_size_and_count_survives_concurrent_deletion() {
+    let temp = TempDir::new().expect("Failed to create temp dir");
+    let root = temp.path().to_path_buf();
+
+    let make_tree = |root: &PathBuf| {
+        for d in 0..20 {
+            let dir = root.join(format!("site-{}", d)).join("notes");
+            std::fs::create_dir_all(&dir).unwrap();
+            for f in 0..10 {
+                std::fs::write(dir.join(format!("{}.html", f)), b"<p>churn</p>").unwrap();
+            }
+        }
+    };
+    make_tree(&root);
+
+    // Keep deleting and recreating site dirs while walking
+    let stop = Arc::new(AtomicBool::new(false));
+    let churn = {
+        let root = root.clone();
+        let stop = stop.clone();
+        std::thread::spawn(move || {
+            while !stop.load(Ordering::Relaxed) {
+                for d in 0..20 {
+                    std::fs::remove_dir_all(root.join(format!("site-{}", d))).ok();
+                }
+                make_tree(&root);
+            }
+        })
+    };
+
+    for _ in 0..50 {
+        let (bytes, files) = dir_size_and_count(&root).expect("walk should tolerate deletions");
+        assert!(files <= 200);
+        assert!(bytes <= 200 * b"<p>churn</p>".len() as u64);
+    }
+
+    stop.store(true, Ordering::Relaxed);
+    churn.join().unwrap();
+}
+
+// ===== list_files Tests =====
+
+#[test]
+fn test_list_files_is_relative_and_sorted() {
+    let temp = TempDir::new().expect("Failed to create temp dir");
+    fixture_tree(temp.path());
+
+    let files = list_files(temp.path()).unwrap();
+    assert_eq!(
+        files,
+        vec![PathBuf::from("index.html"), PathBuf::from("notes/a.html"), PathBuf::from("notes/deep/b.css")]
+    );
+    assert!(list_files(&temp.path().join("gone")).unwrap().is_empty());
+}
+
+// ===== copy_dir_recursive Tests =====
+
+#[test]
+fn test_copy_dir_recursive_copies_tree() {
+    let temp = TempDir::new().expect("Failed to create temp dir");
+    let src = temp.path().join("src");
+    let dst = temp.path().join("out/dst");
+    fixture_tree(&src);
+
+    copy_dir_recursive(&src, &dst).unwrap();
+
+    assert_eq!(list_files(&dst).unwrap(), list_files(&src).unwrap());
+    assert_eq!(std::fs::read(dst.join("notes/deep/b.css")).unwrap(), b"body");
+    assert!(dst.join("empty").is_dir());
+    assert_eq!(dir_size_and_count(&dst).unwrap(), (12, 3));
+}
+
+#[test]
+fn test_copy_dir_recursive_missing_source_creates_empty_dest() {
+    let temp = TempDir::new().expect("Failed to create temp dir");
+    let dst = temp.path().join("dst");
+
+    copy_dir_recursive(&temp.path().join("gone"), &dst).unwrap();
+    assert!(dst.is_dir());
+    assert!(list_files(&dst).unwrap().is_empty());
+}
+
+// ===== TempGuard Tests =====
+
+#[test]
+fn test_temp_guard_removes_tracked_paths_on_drop() {
+    let temp = TempDir::new().expect("Failed to create temp dir");
+    let dir = temp.path().join(".extract_temp_x");
+    fixture_tree(&dir);
+    let file = temp.path().join("archive.tar.gz");
+    std::fs::write(&file, b"partial").unwrap();
+    let untouched = temp.path().join("keep.txt");
+    std::fs::write(&untouched, b"mine").unwrap();
+
+    let mut guard = TempGuard::new();
+    guard.track(&dir);
+    guard.track(&file);
+    guard.track(temp.path().join("never-created"));
+    drop(guard);
+
+    assert!(!dir.exists());
+    assert!(!file.exists());
+    assert!(untouched.exists());
+}
+
+#[test]
+fn test_temp_guard_keep_disarms() {
+    let temp = TempDir::new().expect("Failed to create temp dir");
+    let dir = temp.path().join("kept");
+    fixture_tree(&dir);
+
+    let mut guard = TempGuard::new();
+    guard.track(&dir);
+    assert_eq!(guard.keep(), vec![dir.clone()]);
+    assert_eq!(list_files(&dir).unwrap().len(), 3);
+}
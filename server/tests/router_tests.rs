@@ -0,0 +1,1260 @@
+/// Router-level tests
+///
+/// These tests drive a small axum router through `tower::Service` to check
+/// response shaping done by layers, without binding a socket.
+
+mod utils;
+
+use axum::{
+    body::{to_bytes, Body},
+    http::{header::{ALLOW, SET_COOKIE}, Request, StatusCode},
+    middleware,
+    routing::{get, post},
+    Router,
+};
+use flate2::{write::GzEncoder, Compression};
+use obsidian_publisher_server::{
+    auth::{AuthService, TokenService, ADMIN_ROLE},
+    config::Config,
+    error::method_not_allowed_json,
+    handlers::auth as auth_handlers,
+    models::{Site, User},
+    routes,
+};
+use std::io::Write;
+use std::sync::Arc;
+use tower::Service;
+use tower_http::decompression::RequestDecompressionLayer;
+use uuid::Uuid;
+use utils::storage::create_test_storage;
+
+#[tokio::test]
+async fn test_method_not_allowed_has_allow_header_and_json_body() {
+    // axum adds `Allow` around each route, so the rewrite has to sit outside the whole router
+    let inner = Router::new().route("/auth/me", get(|| async { "me" }));
+    let standalone = Router::new()
+        .fallback_service(inner)
+        .layer(middleware::map_response(method_not_allowed_json));
+    let (storage, _temp) = create_test_storage().await;
+    let full = routes::build(Arc::new(storage), Arc::new(Config::default()));
+
+    for (mut app, uri) in [(standalone.into_service(), "/auth/me"), (full.into_service(), "/api/sites/names")] {
+        let req = Request::builder()
+            .method("POST")
+            .uri(uri)
+            .body(Body::empty())
+            .unwrap();
+        let res = app.call(req).await.unwrap();
+
+        assert_eq!(res.status(), StatusCode::METHOD_NOT_ALLOWED, "{}", uri);
+        let allow = res.headers().get(ALLOW).expect("Allow header missing").to_str().unwrap().to_string();
+        assert!(allow.contains("GET"), "Allow should list GET, got {}", allow);
+
+        let body = to_bytes(res.into_body(), usize::MAX).await.unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&body).expect("405 body should be JSON");
+        assert_eq!(json["error"], "Method not allowed");
+        assert_eq!(json["code"], "METHOD_NOT_ALLOWED");
+        assert!(json["details"].as_str().unwrap().contains("GET"), "{}: {}", uri, json);
+    }
+}
+
+#[tokio::test]
+async fn test_listing_routes_check_tokens_with_the_shared_service() {
+    let (storage, _temp) = create_test_storage().await;
+    let storage = Arc::new(storage);
+    let config = Config::default();
+    let owner_id = Uuid::new_v4();
+    for (owner, name) in [(owner_id, "mine"), (Uuid::new_v4(), "theirs")] {
+        storage.sites.create(Site::new(Uuid::new_v4(), owner, name.to_string(), "d".to_string())).await.unwrap();
+    }
+    let mut app = routes::build(storage, Arc::new(config.clone())).into_service();
+    let token = TokenService::new(config.server.jwt_secret.clone(), 1)
+        .generate_token(owner_id, "lister".to_string())
+        .unwrap();
+
+    let get = |uri: &str, token: Option<&str>| {
+        let mut req = Request::builder().uri(uri);
+        if let Some(token) = token {
+            req = req.header("authorization", format!("Bearer {}", token));
+        }
+        req.body(Body::empty()).unwrap()
+    };
+
+    let res = app.call(get("/api/sites/names?mine=true", None)).await.unwrap();
+    assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+    let res = app.call(get("/api/sites/names?mine=true", Some(&token))).await.unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+    let body = to_bytes(res.into_body(), usize::MAX).await.unwrap();
+    assert_eq!(serde_json::from_slice::<Vec<String>>(&body).unwrap(), vec!["mine"]);
+
+    let res = app.call(get("/api/sites?include_owner=true", Some(&token))).await.unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+    let res = app.call(get("/api/sites?include_owner=true", Some("not-a-token"))).await.unwrap();
+    assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+}
+
+#[tokio::test]
+async fn test_custom_error_message_for_auth_failure() {
+    let (storage, _temp) = create_test_storage().await;
+    let mut config = Config::default();
+    let message = "Please sign in. Need help? https://support.example.com";
+    config.server.error_messages.insert("AUTH_FAILED".to_string(), message.to_string());
+
+    let mut app = routes::build(Arc::new(storage), Arc::new(config)).into_service();
+
+    // No token: the operator's message, with the usual status and code
+    let req = Request::builder().uri("/auth/me").body(Body::empty()).unwrap();
+    let res = app.call(req).await.unwrap();
+    assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+    let body = to_bytes(res.into_body(), usize::MAX).await.unwrap();
+    let json: serde_json::Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(json["error"], message);
+    assert_eq!(json["code"], "AUTH_FAILED");
+    assert_eq!(json["details"], "Authentication failed");
+
+    // Codes without an override keep the default message
+    let req = Request::builder()
+        .method("POST")
+        .uri("/auth/register")
+        .header("content-type", "application/json")
+        .body(Body::from(r#"{"username":"","password":"pw"}"#))
+        .unwrap();
+    let res = app.call(req).await.unwrap();
+    assert_eq!(res.status(), StatusCode::UNPROCESSABLE_ENTITY);
+    let body = to_bytes(res.into_body(), usize::MAX).await.unwrap();
+    let json: serde_json::Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(json["code"], "VALIDATION_FAILED");
+    assert_eq!(json["error"], "Validation failed");
+}
+
+#[tokio::test]
+async fn test_malformed_json_body_gets_standard_error() {
+    let (storage, _temp) = create_test_storage().await;
+    let mut app = routes::build(Arc::new(storage), Arc::new(Config::default())).into_service();
+
+    let login = |content_type: &str, body: &'static str| {
+        Request::builder()
+            .method("POST")
+            .uri("/auth/login")
+            .header("content-type", content_type)
+            .body(Body::from(body))
+            .unwrap()
+    };
+
+    for req in [
+        login("application/json", "{bad json"),
+        login("application/json", r#"{"username":"only"}"#),
+        login("text/plain", r#"{"username":"u","password":"p"}"#),
+    ] {
+        let res = app.call(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+        let body = to_bytes(res.into_body(), usize::MAX).await.unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&body).expect("rejection body should be JSON");
+        assert_eq!(json["error"], "Invalid input");
+        assert_eq!(json["code"], "INVALID_INPUT");
+        assert!(json["details"].as_str().unwrap().starts_with("Invalid input: "), "got {}", json);
+    }
+}
+
+#[tokio::test]
+async fn test_gzip_json_login_body_is_decompressed() {
+    let (storage, _temp) = create_test_storage().await;
+    storage.users.create(User::new("gzipuser".to_string(), "secret".to_string())).await.expect("Failed to create user");
+
+    let auth_service = Arc::new(AuthService::new(
+        storage.users.clone(),
+        storage.audit.clone(),
+        TokenService::new("test-secret".to_string(), 1),
+        true,
+    ));
+    let mut app = Router::new()
+        .route("/auth/login", post(auth_handlers::login))
+        .with_state(auth_service)
+        .layer(RequestDecompressionLayer::new())
+        .into_service();
+
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(br#"{"username":"gzipuser","password":"secret"}"#).unwrap();
+    let compressed = encoder.finish().unwrap();
+
+    let req = Request::builder()
+        .method("POST")
+        .uri("/auth/login")
+        .header("content-type", "application/json")
+        .header("content-encoding", "gzip")
+        .body(Body::from(compressed))
+        .unwrap();
+    let res = app.call(req).await.unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+
+    let body = to_bytes(res.into_body(), usize::MAX).await.unwrap();
+    let json: serde_json::Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(json["user"]["username"], "gzipuser");
+    assert!(json["token"].as_str().is_some());
+}
+
+#[tokio::test]
+async fn test_require_auth_for_listing_blocks_anonymous_listing() {
+    let (storage, _temp) = create_test_storage().await;
+    let storage = Arc::new(storage);
+    let mut config = Config::default();
+    config.server.require_auth_for_listing = true;
+    let config = Arc::new(config);
+
+    let owner = User::new("private".to_string(), "pw".to_string());
+    let owner_id = owner.id;
+    storage.users.create(owner).await.expect("Failed to create user");
+    storage.sites.create(Site::new(Uuid::new_v4(), owner_id, "mine".to_string(), "d".to_string())).await.unwrap();
+    storage.sites.create(Site::new(Uuid::new_v4(), Uuid::new_v4(), "theirs".to_string(), "d".to_string())).await.unwrap();
+
+    let mut app = routes::build(storage.clone(), config.clone()).into_service();
+
+    let req = Request::builder().uri("/api/sites").body(Body::empty()).unwrap();
+    let res = app.call(req).await.unwrap();
+    assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+
+    // Admin endpoints ignore ?key= and need a signed-in admin
+    let req = Request::builder()
+        .uri(format!("/api/admin/all?key={}", config.server.jwt_secret))
+        .body(Body::empty())
+        .unwrap();
+    let res = app.call(req).await.unwrap();
+    assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+
+    let token = TokenService::new(config.server.jwt_secret.clone(), 1)
+        .generate_token(owner_id, "private".to_string())
+        .unwrap();
+    let req = Request::builder()
+        .uri(format!("/api/admin/all?key={}", config.server.jwt_secret))
+        .header("authorization", format!("Bearer {}", token))
+        .body(Body::empty())
+        .unwrap();
+    let res = app.call(req).await.unwrap();
+    assert_eq!(res.status(), StatusCode::FORBIDDEN);
+
+    let mut admin = User::new("private-admin".to_string(), "pw".to_string());
+    admin.roles = vec![ADMIN_ROLE.to_string()];
+    let admin_token = TokenService::new(config.server.jwt_secret.clone(), 1)
+        .generate_token(admin.id, admin.username.clone())
+        .unwrap();
+    storage.users.create(admin).await.expect("Failed to create admin");
+    for uri in ["/api/admin/all", "/api/admin/storage"] {
+        let req = Request::builder()
+            .uri(uri)
+            .header("authorization", format!("Bearer {}", admin_token))
+            .body(Body::empty())
+            .unwrap();
+        let res = app.call(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK, "{}", uri);
+    }
+
+    // Authenticated callers only see their own sites
+    let req = Request::builder()
+        .uri("/api/sites")
+        .header("authorization", format!("Bearer {}", token))
+        .body(Body::empty())
+        .unwrap();
+    let res = app.call(req).await.unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+    let body = to_bytes(res.into_body(), usize::MAX).await.unwrap();
+    let json: serde_json::Value = serde_json::from_slice(&body).unwrap();
+    let names: Vec<&str> = json.as_array().unwrap().iter().map(|s| s["name"].as_str().unwrap()).collect();
+    assert_eq!(names, vec!["mine"]);
+}
+
+#[tokio::test]
+async fn test_large_site_list_is_gzip_compressed() {
+    use flate2::read::GzDecoder;
+    use std::io::Read;
+
+    let (storage, _temp) = create_test_storage().await;
+    let storage = Arc::new(storage);
+    let owner_id = Uuid::new_v4();
+    for i in 0..200 {
+        storage.sites.create(Site::new(Uuid::new_v4(), owner_id, format!("site-{}", i), "d".to_string())).await.unwrap();
+    }
+    let mut app = routes::build(storage.clone(), Arc::new(Config::default())).into_service();
+
+    let req = Request::builder()
+        .uri("/api/sites")
+        .header("accept-encoding", "gzip")
+        .body(Body::empty())
+        .unwrap();
+    let res = app.call(req).await.unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+    assert_eq!(res.headers()["content-encoding"], "gzip");
+    let compressed = to_bytes(res.into_body(), usize::MAX).await.unwrap();
+    let mut json = String::new();
+    GzDecoder::new(&compressed[..]).read_to_string(&mut json).unwrap();
+    assert!(compressed.len() < json.len());
+    let sites: serde_json::Value = serde_json::from_str(&json).unwrap();
+    assert_eq!(sites.as_array().unwrap().len(), 200);
+
+    // Without Accept-Encoding the body is plain JSON
+    let req = Request::builder().uri("/api/sites").body(Body::empty()).unwrap();
+    let res = app.call(req).await.unwrap();
+    assert!(res.headers().get("content-encoding").is_none());
+
+    // Small bodies and errors are left alone
+    let req = Request::builder()
+        .uri("/api/sites?limit=1")
+        .header("accept-encoding", "gzip")
+        .body(Body::empty())
+        .unwrap();
+    let res = app.call(req).await.unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+    assert!(res.headers().get("content-encoding").is_none());
+
+    let req = Request::builder()
+        .uri("/auth/me")
+        .header("accept-encoding", "gzip")
+        .body(Body::empty())
+        .unwrap();
+    let res = app.call(req).await.unwrap();
+    assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+    assert!(res.headers().get("content-encoding").is_none());
+}
+
+#[tokio::test]
+async fn test_auth_cookie_login_and_cookie_authenticated_access() {
+    let (storage, _temp) = create_test_storage().await;
+    let storage = Arc::new(storage);
+    storage.users.create(User::new("cookie".to_string(), "secret".to_string())).await.expect("Failed to create user");
+    let mut config = Config::default();
+    config.server.auth_cookie = true;
+    config.server.url = "https://publisher.example.com".to_string();
+
+    let mut app = routes::build(storage, Arc::new(config)).into_service();
+
+    let req = Request::builder()
+        .method("POST")
+        .uri("/auth/login")
+        .header("content-type", "application/json")
+        .body(Body::from(r#"{"username":"cookie","password":"secret"}"#))
+        .unwrap();
+    let res = app.call(req).await.unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+
+    let set_cookie = res.headers().get(SET_COOKIE).expect("Set-Cookie missing").to_str().unwrap().to_string();
+    assert!(set_cookie.starts_with("op_token="), "got {}", set_cookie);
+    for attr in ["HttpOnly", "Secure", "SameSite=Strict"] {
+        assert!(set_cookie.contains(attr), "cookie should be {}: {}", attr, set_cookie);
+    }
+
+    let body = to_bytes(res.into_body(), usize::MAX).await.unwrap();
+    let json: serde_json::Value = serde_json::from_slice(&body).unwrap();
+    assert!(json.get("token").is_none(), "token must not be in the body: {}", json);
+    assert_eq!(json["user"]["username"], "cookie");
+
+    // The cookie alone authenticates
+    let cookie = set_cookie.split(';').next().unwrap().to_string();
+    let req = Request::builder()
+        .uri("/auth/me")
+        .header("cookie", format!("theme=dark; {}", cookie))
+        .body(Body::empty())
+        .unwrap();
+    let res = app.call(req).await.unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+    let body = to_bytes(res.into_body(), usize::MAX).await.unwrap();
+    let json: serde_json::Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(json["username"], "cookie");
+
+    let req = Request::builder().uri("/auth/me").body(Body::empty()).unwrap();
+    let res = app.call(req).await.unwrap();
+    assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+}
+
+#[tokio::test]
+async fn test_password_reset_request_and_confirm() {
+    let (storage, _temp) = create_test_storage().await;
+    let storage = Arc::new(storage);
+    let mut user = User::new("forgetful".to_string(), "old-password".to_string());
+    user.email = Some("forgetful@example.com".to_string());
+    storage.users.create(user).await.expect("Failed to create user");
+    let mut config = Config::default();
+    config.auth.expose_reset_token = true;
+
+    let mut app = routes::build(storage, Arc::new(config)).into_service();
+    let post_json = |uri: &str, body: &str| {
+        Request::builder()
+            .method("POST")
+            .uri(uri)
+            .header("content-type", "application/json")
+            .body(Body::from(body.to_string()))
+            .unwrap()
+    };
+
+    // Unknown accounts get the same 200 answer, just without a token
+    let res = app.call(post_json("/auth/password-reset/request", r#"{"email":"nobody@example.com"}"#)).await.unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+    let body = to_bytes(res.into_body(), usize::MAX).await.unwrap();
+    let unknown: serde_json::Value = serde_json::from_slice(&body).unwrap();
+    assert!(unknown.get("token").is_none(), "got {}", unknown);
+
+    let res = app.call(post_json("/auth/password-reset/request", r#"{"email":"Forgetful@Example.com"}"#)).await.unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+    let body = to_bytes(res.into_body(), usize::MAX).await.unwrap();
+    let json: serde_json::Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(json["message"], unknown["message"]);
+    let token = json["token"].as_str().expect("token should be exposed").to_string();
+
+    let confirm = serde_json::json!({ "token": token, "new_password": "brand-new-password" }).to_string();
+    let res = app.call(post_json("/auth/password-reset/confirm", &confirm)).await.unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+
+    let res = app.call(post_json("/auth/login", r#"{"username":"forgetful","password":"old-password"}"#)).await.unwrap();
+    assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+    let res = app.call(post_json("/auth/login", r#"{"username":"forgetful","password":"brand-new-password"}"#)).await.unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+
+    // Single use
+    let again = serde_json::json!({ "token": token, "new_password": "third-password" }).to_string();
+    let res = app.call(post_json("/auth/password-reset/confirm", &again)).await.unwrap();
+    assert_eq!(res.status(), StatusCode::UNPROCESSABLE_ENTITY);
+}
+
+/// `Set-Cookie` from logging in as cookie/secret with the given server URL and override
+async fn login_cookie(url: &str, secure_cookie: Option<bool>) -> String {
+    let (storage, _temp) = create_test_storage().await;
+    storage.users.create(User::new("cookie".to_string(), "secret".to_string())).await.expect("Failed to create user");
+    let mut config = Config::default();
+    config.server.auth_cookie = true;
+    config.server.url = url.to_string();
+    config.server.secure_cookie = secure_cookie;
+
+    let mut app = routes::build(Arc::new(storage), Arc::new(config)).into_service();
+    let req = Request::builder()
+        .method("POST")
+        .uri("/auth/login")
+        .header("content-type", "application/json")
+        .body(Body::from(r#"{"username":"cookie","password":"secret"}"#))
+        .unwrap();
+    let res = app.call(req).await.unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+    res.headers().get(SET_COOKIE).expect("Set-Cookie missing").to_str().unwrap().to_string()
+}
+
+#[tokio::test]
+async fn test_auth_cookie_secure_flag_follows_url_scheme() {
+    let cookie = login_cookie("https://publisher.example.com", None).await;
+    assert!(cookie.contains("; Secure"), "https cookie should be Secure: {}", cookie);
+    assert!(cookie.contains("SameSite=Strict"), "got {}", cookie);
+
+    let cookie = login_cookie("http://localhost:8080", None).await;
+    assert!(!cookie.contains("Secure"), "http cookie must not be Secure: {}", cookie);
+    assert!(cookie.contains("SameSite=Lax"), "got {}", cookie);
+    assert!(cookie.contains("HttpOnly"), "got {}", cookie);
+
+    // The override wins in both directions, e.g. behind a TLS-terminating proxy
+    let cookie = login_cookie("http://localhost:8080", Some(true)).await;
+    assert!(cookie.contains("; Secure"), "got {}", cookie);
+    let cookie = login_cookie("https://publisher.example.com", Some(false)).await;
+    assert!(!cookie.contains("Secure"), "got {}", cookie);
+}
+
+#[tokio::test]
+async fn test_site_redirects_file_is_honored() {
+    use obsidian_publisher_server::{
+        auth::{AuthUser, AuthenticatedUser},
+        handlers::sites::{upload_site, UploadOrigin},
+    };
+    use axum::extract::State;
+    use axum::http::header::LOCATION;
+    use utils::multipart::build_multipart;
+
+    let (storage, _temp) = create_test_storage().await;
+    let storage = Arc::new(storage);
+    let config = Arc::new(Config::default());
+
+    let mut builder = tar::Builder::new(GzEncoder::new(Vec::new(), Compression::default()));
+    let files: [(&str, &[u8]); 2] = [
+        ("index.html", b"<p>home</p>"),
+        ("_redirects", b"# moved pages\n/old-path /new-path\n/temp /elsewhere 302\n/docs https://example.com/docs\nnot-a-rule\n"),
+    ];
+    for (path, data) in files {
+        let mut header = tar::Header::new_gnu();
+        header.set_size(data.len() as u64);
+        header.set_mode(0o644);
+        builder.append_data(&mut header, path, data).unwrap();
+    }
+    let archive = builder.into_inner().unwrap().finish().unwrap();
+
+    let site_id = Uuid::new_v4();
+    let multipart = build_multipart(&[
+        ("uuid", None, site_id.to_string().into_bytes()),
+        ("siteName", None, b"redir-site".to_vec()),
+        ("site", Some("site.tar.gz"), archive),
+    ]).await;
+    let auth = AuthenticatedUser(AuthUser { id: Uuid::new_v4(), username: "redir".to_string(), exp: usize::MAX });
+    let uploaded = upload_site(State((storage.clone(), config.clone())), auth, UploadOrigin::default(), multipart)
+        .await
+        .expect("upload failed")
+        .0;
+    assert_eq!(uploaded.warnings.len(), 1, "malformed line should be reported: {:?}", uploaded.warnings);
+    assert!(uploaded.warnings[0].contains("line 5"), "got {:?}", uploaded.warnings);
+
+    let mut app = routes::build(storage.clone(), config).into_service();
+    let mut get = |uri: String| {
+        let req = Request::builder().uri(uri).body(Body::empty()).unwrap();
+        app.call(req)
+    };
+
+    let res = get("/sites/redir-site/old-path".to_string()).await.unwrap();
+    assert_eq!(res.status(), StatusCode::MOVED_PERMANENTLY);
+    assert_eq!(res.headers()[LOCATION], "/sites/redir-site/new-path");
+
+    let res = get("/sites/redir-site/temp/".to_string()).await.unwrap();
+    assert_eq!(res.status(), StatusCode::FOUND);
+    assert_eq!(res.headers()[LOCATION], "/sites/redir-site/elsewhere");
+
+    // Rules also apply under the version's UUID, keeping that prefix
+    let res = get(format!("/sites/{}/old-path", site_id)).await.unwrap();
+    assert_eq!(res.status(), StatusCode::MOVED_PERMANENTLY);
+    assert_eq!(res.headers()[LOCATION], format!("/sites/{}/new-path", site_id));
+
+    let res = get("/sites/redir-site/docs".to_string()).await.unwrap();
+    assert_eq!(res.headers()[LOCATION], "https://example.com/docs");
+
+    // Unmatched paths fall through to the files
+    let res = get("/sites/redir-site/index.html".to_string()).await.unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+    let body = to_bytes(res.into_body(), usize::MAX).await.unwrap();
+    assert_eq!(&body[..], b"<p>home</p>");
+}
+
+#[tokio::test]
+async fn test_site_password_protection() {
+    use obsidian_publisher_server::{
+        auth::{AuthUser, AuthenticatedUser},
+        handlers::json::JsonBody,
+        handlers::sites::set_site_password,
+        models::SetSitePasswordRequest,
+    };
+    use axum::extract::{Path, State};
+    use axum::http::header::WWW_AUTHENTICATE;
+
+    let (storage, _temp) = create_test_storage().await;
+    let storage = Arc::new(storage);
+    let config = Arc::new(Config::default());
+
+    let owner_id = Uuid::new_v4();
+    let site = Site::new(Uuid::new_v4(), owner_id, "shared".to_string(), "d".to_string());
+    storage.sites.create(site.clone()).await.unwrap();
+    let name_dir = storage.sites.get_site_files_path_str("shared");
+    std::fs::create_dir_all(&name_dir).unwrap();
+    std::fs::write(name_dir.join("index.html"), "<p>for friends</p>").unwrap();
+
+    let set_password = |password: Option<&str>| {
+        let auth = AuthenticatedUser(AuthUser { id: owner_id, username: "owner".to_string(), exp: usize::MAX });
+        let req = SetSitePasswordRequest { password: password.map(str::to_string) };
+        set_site_password(State((storage.clone(), config.clone())), Path(site.id), auth, JsonBody(req))
+    };
+    let res = set_password(Some("secret")).await.expect("set_site_password failed").0;
+    assert!(res.password_protected);
+
+    let mut app = routes::build(storage.clone(), config.clone()).into_service();
+    let mut get = |basic: Option<&str>| {
+        let mut req = Request::builder().uri("/sites/shared/index.html");
+        if let Some(credentials) = basic {
+            req = req.header("authorization", format!("Basic {}", credentials));
+        }
+        app.call(req.body(Body::empty()).unwrap())
+    };
+
+    let res = get(None).await.unwrap();
+    assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+    let challenge = res.headers()[WWW_AUTHENTICATE].to_str().unwrap();
+    assert!(challenge.starts_with("Basic realm=\"shared\""), "got {}", challenge);
+
+    // visitor:secret
+    let res = get(Some("dmlzaXRvcjpzZWNyZXQ=")).await.unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+    let body = to_bytes(res.into_body(), usize::MAX).await.unwrap();
+    assert_eq!(&body[..], b"<p>for friends</p>");
+
+    // visitor:wrong
+    let res = get(Some("dmlzaXRvcjp3cm9uZw==")).await.unwrap();
+    assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+
+    // Clearing the password opens the site again
+    let res = set_password(None).await.unwrap().0;
+    assert!(!res.password_protected);
+    let res = get(None).await.unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+}
+
+#[tokio::test]
+async fn test_upload_root_dir_serves_subfolder() {
+    use obsidian_publisher_server::{
+        auth::{AuthUser, AuthenticatedUser},
+        error::{AppError, ArchiveError},
+        handlers::sites::{upload_site, UploadOrigin},
+    };
+    use axum::{extract::State, response::IntoResponse};
+    use utils::multipart::build_multipart;
+
+    let (storage, _temp) = create_test_storage().await;
+    let storage = Arc::new(storage);
+    let config = Arc::new(Config::default());
+
+    let mut builder = tar::Builder::new(GzEncoder::new(Vec::new(), Compression::default()));
+    let files: [(&str, &[u8]); 3] = [
+        ("index.html", b"<p>source readme</p>"),
+        ("src/main.ts", b"console.log('not served')"),
+        ("dist/index.html", b"<p>built</p>"),
+    ];
+    for (path, data) in files {
+        let mut header = tar::Header::new_gnu();
+        header.set_size(data.len() as u64);
+        header.set_mode(0o644);
+        builder.append_data(&mut header, path, data).unwrap();
+    }
+    let archive = builder.into_inner().unwrap().finish().unwrap();
+
+    let upload = |site_id: Uuid, root_dir: &'static str| {
+        let storage = storage.clone();
+        let config = config.clone();
+        let archive = archive.clone();
+        async move {
+            let multipart = build_multipart(&[
+                ("uuid", None, site_id.to_string().into_bytes()),
+                ("siteName", None, b"built-site".to_vec()),
+                ("rootDir", None, root_dir.as_bytes().to_vec()),
+                ("site", Some("site.tar.gz"), archive),
+            ]).await;
+            let auth = AuthenticatedUser(AuthUser { id: Uuid::nil(), username: "builder".to_string(), exp: usize::MAX });
+            upload_site(State((storage, config)), auth, UploadOrigin::default(), multipart).await
+        }
+    };
+
+    // A rootDir missing from the archive is refused
+    let err = upload(Uuid::new_v4(), "public").await.unwrap_err();
+    assert!(matches!(&err, AppError::Archive(ArchiveError::RootDirNotFound(dir)) if dir == "public"), "got {:?}", err);
+    assert_eq!(err.into_response().status(), StatusCode::UNPROCESSABLE_ENTITY);
+
+    let res = upload(Uuid::new_v4(), "dist").await.expect("upload failed").0;
+    assert_eq!(res.name, "built-site");
+
+    let mut app = routes::build(storage.clone(), config.clone()).into_service();
+    let req = Request::builder().uri("/sites/built-site/index.html").body(Body::empty()).unwrap();
+    let res = app.call(req).await.unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+    let body = to_bytes(res.into_body(), usize::MAX).await.unwrap();
+    assert_eq!(&body[..], b"<p>built</p>");
+
+    // Files outside rootDir are not published
+    let req = Request::builder().uri("/sites/built-site/src/main.ts").body(Body::empty()).unwrap();
+    let res = app.call(req).await.unwrap();
+    assert_eq!(res.status(), StatusCode::NOT_FOUND);
+
+    // The same archive is only a duplicate when it publishes the same rootDir
+    let res = upload(Uuid::new_v4(), "dist").await.expect("upload failed").0;
+    assert_eq!(res.deduplicated, Some(true));
+    let res = upload(Uuid::new_v4(), "").await.expect("upload failed").0;
+    assert_eq!(res.deduplicated, None);
+    let req = Request::builder().uri("/sites/built-site/index.html").body(Body::empty()).unwrap();
+    let res = app.call(req).await.unwrap();
+    let body = to_bytes(res.into_body(), usize::MAX).await.unwrap();
+    assert_eq!(&body[..], b"<p>source readme</p>");
+}
+
+#[tokio::test]
+async fn test_site_root_is_checked_after_root_dir() {
+    use obsidian_publisher_server::{
+        auth::{AuthUser, AuthenticatedUser},
+        error::AppError,
+        handlers::sites::{upload_site, UploadOrigin},
+    };
+    use axum::extract::State;
+    use utils::multipart::build_multipart;
+
+    let (storage, _temp) = create_test_storage().await;
+    let storage = Arc::new(storage);
+    let config = Arc::new(Config::default());
+
+    let archive = |files: &[(&str, &[u8])]| {
+        let mut builder = tar::Builder::new(GzEncoder::new(Vec::new(), Compression::default()));
+        for (path, data) in files {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(data.len() as u64);
+            header.set_mode(0o644);
+            builder.append_data(&mut header, path, *data).unwrap();
+        }
+        builder.into_inner().unwrap().finish().unwrap()
+    };
+    let upload = |site_id: Uuid, archive: Vec<u8>| {
+        let storage = storage.clone();
+        let config = config.clone();
+        async move {
+            let multipart = build_multipart(&[
+                ("uuid", None, site_id.to_string().into_bytes()),
+                ("siteName", None, b"checked-site".to_vec()),
+                ("rootDir", None, b"dist".to_vec()),
+                ("site", Some("site.tar.gz"), archive),
+            ]).await;
+            let auth = AuthenticatedUser(AuthUser { id: Uuid::nil(), username: "builder".to_string(), exp: usize::MAX });
+            upload_site(State((storage, config)), auth, UploadOrigin::default(), multipart).await
+        }
+    };
+
+    // macOS metadata outside rootDir is dropped with the rest, so the result is clean
+    let res = upload(Uuid::new_v4(), archive(&[
+        ("index.html", b"<p>readme</p>"),
+        ("__MACOSX/dist/._index.html", b"resource fork"),
+        ("dist/index.html", b"<p>v1</p>"),
+    ])).await.expect("upload failed").0;
+    assert_eq!(res.name, "checked-site");
+
+    // Metadata inside rootDir would be published, so the upload is refused and rolled back
+    let bad_id = Uuid::new_v4();
+    let err = upload(bad_id, archive(&[
+        ("dist/index.html", b"<p>v2</p>"),
+        ("dist/.DS_Store", b"finder"),
+        ("dist/img/Thumbs.db", b"thumbs"),
+    ])).await.unwrap_err();
+    match &err {
+        AppError::InvalidInput(msg) => {
+            assert!(msg.contains(".DS_Store") && msg.contains("img/Thumbs.db"), "got {}", msg);
+        }
+        other => panic!("expected InvalidInput, got {:?}", other),
+    }
+    assert!(!storage.sites.get_site_files_path(bad_id).exists(), "the refused version's files should be gone");
+    assert!(storage.sites.get(bad_id).await.unwrap().is_none());
+
+    let mut app = routes::build(storage.clone(), config.clone()).into_service();
+    let req = Request::builder().uri("/sites/checked-site/index.html").body(Body::empty()).unwrap();
+    let res = app.call(req).await.unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+    let body = to_bytes(res.into_body(), usize::MAX).await.unwrap();
+    assert_eq!(&body[..], b"<p>v1</p>", "the live version should be untouched");
+}
+
+#[test]
+fn test_site_root_without_index_names_what_it_found() {
+    use obsidian_publisher_server::{error::AppError, handlers::sites::check_site_root};
+
+    let temp = tempfile::tempdir().unwrap();
+    for dir in ["blog", "docs"] {
+        std::fs::create_dir_all(temp.path().join(dir)).unwrap();
+        std::fs::write(temp.path().join(dir).join("index.html"), b"<p></p>").unwrap();
+    }
+    std::fs::write(temp.path().join("README.md"), b"hi").unwrap();
+
+    match check_site_root(temp.path()) {
+        Err(AppError::InvalidInput(msg)) => {
+            assert!(msg.contains("no index.html"), "got {}", msg);
+            assert!(msg.contains("README.md, blog/, docs/"), "got {}", msg);
+        }
+        other => panic!("expected InvalidInput, got {:?}", other),
+    }
+
+    std::fs::write(temp.path().join("index.html"), b"<p></p>").unwrap();
+    check_site_root(temp.path()).expect("a top-level index.html is enough");
+}
+
+#[tokio::test]
+async fn test_spa_mode_falls_back_to_index_html() {
+    use obsidian_publisher_server::{
+        auth::{AuthUser, AuthenticatedUser},
+        handlers::sites::{upload_site, UploadOrigin},
+    };
+    use axum::extract::State;
+    use utils::multipart::build_multipart;
+
+    let (storage, _temp) = create_test_storage().await;
+    let storage = Arc::new(storage);
+    let config = Arc::new(Config::default());
+
+    let mut builder = tar::Builder::new(GzEncoder::new(Vec::new(), Compression::default()));
+    let files: [(&str, &[u8]); 2] = [
+        ("index.html", b"<div id=\"app\"></div>"),
+        ("assets/app.js", b"route()"),
+    ];
+    for (path, data) in files {
+        let mut header = tar::Header::new_gnu();
+        header.set_size(data.len() as u64);
+        header.set_mode(0o644);
+        builder.append_data(&mut header, path, data).unwrap();
+    }
+    let archive = builder.into_inner().unwrap().finish().unwrap();
+
+    let multipart = build_multipart(&[
+        ("uuid", None, Uuid::new_v4().to_string().into_bytes()),
+        ("siteName", None, b"spa-site".to_vec()),
+        ("spaMode", None, b"true".to_vec()),
+        ("site", Some("site.tar.gz"), archive),
+    ]).await;
+    let auth = AuthenticatedUser(AuthUser { id: Uuid::new_v4(), username: "spa".to_string(), exp: usize::MAX });
+    let uploaded = upload_site(State((storage.clone(), config.clone())), auth, UploadOrigin::default(), multipart)
+        .await
+        .expect("upload failed")
+        .0;
+    assert!(uploaded.spa_mode);
+
+    let mut app = routes::build(storage.clone(), config).into_service();
+    let mut get = |uri: &str| {
+        let req = Request::builder().uri(uri).body(Body::empty()).unwrap();
+        app.call(req)
+    };
+
+    // Deep link handled by the client-side router
+    let res = get("/sites/spa-site/notes/foo").await.unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+    let body = to_bytes(res.into_body(), usize::MAX).await.unwrap();
+    assert_eq!(&body[..], b"<div id=\"app\"></div>");
+
+    // Real files are still served as themselves
+    let res = get("/sites/spa-site/assets/app.js").await.unwrap();
+    let body = to_bytes(res.into_body(), usize::MAX).await.unwrap();
+    assert_eq!(&body[..], b"route()");
+
+    // Missing assets are not masked by the fallback
+    let res = get("/sites/spa-site/assets/missing.js").await.unwrap();
+    assert_eq!(res.status(), StatusCode::NOT_FOUND);
+    let res = get("/sites/spa-site/theme.css").await.unwrap();
+    assert_eq!(res.status(), StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn test_auto_sitemap_lists_site_pages_under_site_base() {
+    use obsidian_publisher_server::{
+        auth::{AuthUser, AuthenticatedUser},
+        handlers::sites::{upload_site, UploadOrigin},
+    };
+    use axum::extract::State;
+    use utils::multipart::build_multipart;
+
+    let (storage, _temp) = create_test_storage().await;
+    let storage = Arc::new(storage);
+    let mut config = Config::default();
+    config.server.url = "https://pub.example.com".to_string();
+    let config = Arc::new(config);
+
+    let mut builder = tar::Builder::new(GzEncoder::new(Vec::new(), Compression::default()));
+    let files: [(&str, &[u8]); 6] = [
+        ("index.html", b"<p>home</p>"),
+        ("about.html", b"<p>about</p>"),
+        ("docs/index.html", b"<p>docs</p>"),
+        ("docs/getting started.html", b"<p>guide</p>"),
+        ("assets/app.js", b"start()"),
+        ("robots.txt", b"User-agent: *\nAllow: /\n"),
+    ];
+    for (path, data) in files {
+        let mut header = tar::Header::new_gnu();
+        header.set_size(data.len() as u64);
+        header.set_mode(0o644);
+        builder.append_data(&mut header, path, data).unwrap();
+    }
+    let archive = builder.into_inner().unwrap().finish().unwrap();
+
+    let multipart = build_multipart(&[
+        ("uuid", None, Uuid::new_v4().to_string().into_bytes()),
+        ("siteName", None, b"seo-site".to_vec()),
+        ("autoSitemap", None, b"true".to_vec()),
+        ("site", Some("site.tar.gz"), archive),
+    ]).await;
+    let auth = AuthenticatedUser(AuthUser { id: Uuid::new_v4(), username: "seo".to_string(), exp: usize::MAX });
+    let uploaded = upload_site(State((storage.clone(), config.clone())), auth, UploadOrigin::default(), multipart)
+        .await
+        .expect("upload failed")
+        .0;
+    assert!(uploaded.auto_sitemap);
+
+    let mut app = routes::build(storage.clone(), config).into_service();
+    let mut get = |uri: &str| {
+        let req = Request::builder().uri(uri).body(Body::empty()).unwrap();
+        app.call(req)
+    };
+
+    // A robots.txt from the archive is served as is
+    let res = get("/sites/seo-site/robots.txt").await.unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+    let body = to_bytes(res.into_body(), usize::MAX).await.unwrap();
+    assert_eq!(&body[..], b"User-agent: *\nAllow: /\n");
+
+    let res = get("/sites/seo-site/sitemap.xml").await.unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+    assert_eq!(res.headers()["content-type"], "application/xml; charset=utf-8");
+    let body = String::from_utf8(to_bytes(res.into_body(), usize::MAX).await.unwrap().to_vec()).unwrap();
+    let locs: Vec<&str> = body
+        .lines()
+        .filter_map(|l| l.trim().strip_prefix("<url><loc>")?.strip_suffix("</loc></url>"))
+        .collect();
+    assert_eq!(locs, vec![
+        "https://pub.example.com/sites/seo-site/about.html",
+        "https://pub.example.com/sites/seo-site/docs/getting%20started.html",
+        "https://pub.example.com/sites/seo-site/docs/",
+        "https://pub.example.com/sites/seo-site/",
+    ]);
+
+    // Addressed by UUID, the URLs stay under the UUID base
+    let res = get(&format!("/sites/{}/sitemap.xml", uploaded.id)).await.unwrap();
+    let body = String::from_utf8(to_bytes(res.into_body(), usize::MAX).await.unwrap().to_vec()).unwrap();
+    assert!(body.contains(&format!("<loc>https://pub.example.com/sites/{}/about.html</loc>", uploaded.id)), "{}", body);
+
+    // Without the flag there is nothing to serve
+    let mut site = storage.sites.get(uploaded.id).await.unwrap().unwrap();
+    site.auto_sitemap = false;
+    storage.sites.update(site).await.unwrap();
+    let res = get("/sites/seo-site/sitemap.xml").await.unwrap();
+    assert_eq!(res.status(), StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn test_custom_headers_are_sent_with_site_files() {
+    use obsidian_publisher_server::{
+        auth::{AuthUser, AuthenticatedUser},
+        error::AppError,
+        handlers::{json::JsonBody, sites::patch_site},
+        models::PatchSiteRequest,
+    };
+    use axum::extract::{Path, State};
+
+    let (storage, _temp) = create_test_storage().await;
+    let storage = Arc::new(storage);
+    let config = Arc::new(Config::default());
+    let owner = Uuid::new_v4();
+    let site = Site::new(Uuid::new_v4(), owner, "draft".to_string(), "d".to_string());
+    for dir in [storage.sites.get_site_files_path(site.id), storage.sites.get_site_files_path_str("draft")] {
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("index.html"), b"<p>draft</p>").unwrap();
+    }
+    let site_id = site.id;
+    storage.sites.create(site).await.unwrap();
+
+    let patch = |body: &str| {
+        let req: PatchSiteRequest = serde_json::from_str(body).unwrap();
+        let auth = AuthenticatedUser(AuthUser { id: owner, username: "owner".to_string(), exp: usize::MAX });
+        patch_site(State((storage.clone(), config.clone())), Path(site_id), auth, JsonBody(req))
+    };
+
+    // Denied and malformed headers are refused, leaving the site as it was
+    for body in [
+        r#"{ "customHeaders": [["Set-Cookie", "op_token=x"]] }"#,
+        r#"{ "customHeaders": [["Bad Name", "x"]] }"#,
+        r#"{ "customHeaders": [["X-Robots-Tag", "noindex"], ["x-robots-tag", "none"]] }"#,
+    ] {
+        let res = patch(body).await;
+        assert!(matches!(res, Err(AppError::Validation(_))), "{} -> {:?}", body, res.map(|r| r.0));
+    }
+    assert!(storage.sites.get(site_id).await.unwrap().unwrap().custom_headers.is_empty());
+
+    let res = patch(r#"{ "customHeaders": [["X-Robots-Tag", "noindex"]] }"#).await.expect("patch failed").0;
+    assert_eq!(res.custom_headers, vec![("x-robots-tag".to_string(), "noindex".to_string())]);
+
+    let mut app = routes::build(storage.clone(), config.clone()).into_service();
+    let by_id = format!("/sites/{}/index.html", site_id);
+    for uri in ["/sites/draft/index.html", "/sites/draft/", by_id.as_str(), "/sites/draft/missing.html"] {
+        let req = Request::builder().uri(uri).body(Body::empty()).unwrap();
+        let res = app.call(req).await.unwrap();
+        assert_eq!(res.headers()["x-robots-tag"], "noindex", "{}", uri);
+    }
+
+    // Other sites and API responses don't get them
+    let req = Request::builder().uri("/api/sites").body(Body::empty()).unwrap();
+    let res = app.call(req).await.unwrap();
+    assert!(res.headers().get("x-robots-tag").is_none());
+
+    let cleared = patch(r#"{ "customHeaders": [] }"#).await.expect("clearing failed").0;
+    assert!(cleared.custom_headers.is_empty());
+    let mut app = routes::build(storage.clone(), config).into_service();
+    let req = Request::builder().uri("/sites/draft/index.html").body(Body::empty()).unwrap();
+    let res = app.call(req).await.unwrap();
+    assert!(res.headers().get("x-robots-tag").is_none());
+}
+
+#[tokio::test]
+async fn test_fingerprint_assets_appends_content_hash() {
+    use obsidian_publisher_server::{
+        auth::{AuthUser, AuthenticatedUser},
+        handlers::sites::{upload_site, UploadOrigin},
+    };
+    use axum::extract::State;
+    use sha2::{Digest, Sha256};
+    use utils::multipart::build_multipart;
+
+    let (storage, _temp) = create_test_storage().await;
+    let storage = Arc::new(storage);
+    let config = Arc::new(Config::default());
+
+    let site_id = Uuid::new_v4();
+    let index = format!(
+        concat!(
+            "<link rel=\"stylesheet\" href=\"css/style.css\">\n",
+            "<script src='/sites/{}/app.js'></script>\n",
+            "<img data-src=\"css/style.css\" src=\"https://cdn.example.com/x.png\">\n",
+            "<a href=\"notes.html\">notes</a>"
+        ),
+        site_id
+    );
+    let css: &[u8] = b"body { color: #333; }";
+    let mut builder = tar::Builder::new(GzEncoder::new(Vec::new(), Compression::default()));
+    let files: [(&str, &[u8]); 4] = [
+        ("index.html", index.as_bytes()),
+        ("notes.html", b"<p>notes</p>"),
+        ("css/style.css", css),
+        ("app.js", b"start()"),
+    ];
+    for (path, data) in files {
+        let mut header = tar::Header::new_gnu();
+        header.set_size(data.len() as u64);
+        header.set_mode(0o644);
+        builder.append_data(&mut header, path, data).unwrap();
+    }
+    let archive = builder.into_inner().unwrap().finish().unwrap();
+
+    let multipart = build_multipart(&[
+        ("uuid", None, site_id.to_string().into_bytes()),
+        ("siteName", None, b"fp-site".to_vec()),
+        ("fingerprintAssets", None, b"true".to_vec()),
+        ("site", Some("site.tar.gz"), archive),
+    ]).await;
+    let auth = AuthenticatedUser(AuthUser { id: Uuid::new_v4(), username: "fp".to_string(), exp: usize::MAX });
+    let uploaded = upload_site(State((storage.clone(), config.clone())), auth, UploadOrigin::default(), multipart)
+        .await
+        .expect("upload failed")
+        .0;
+    assert!(uploaded.fingerprint_assets);
+
+    let hash_of = |data: &[u8]| -> String {
+        Sha256::digest(data).iter().map(|b| format!("{:02x}", b)).collect::<String>()[..12].to_string()
+    };
+
+    let mut app = routes::build(storage.clone(), config).into_service();
+    let req = Request::builder().uri("/sites/fp-site/index.html").body(Body::empty()).unwrap();
+    let res = app.call(req).await.unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+    let html = String::from_utf8(to_bytes(res.into_body(), usize::MAX).await.unwrap().to_vec()).unwrap();
+    assert!(html.contains(&format!("href=\"css/style.css?v={}\"", hash_of(css))), "{}", html);
+    assert!(html.contains(&format!("src='/sites/fp-site/app.js?v={}'", hash_of(b"start()"))), "{}", html);
+    // Attributes that aren't loaded as-is, external URLs and page links are untouched
+    assert!(html.contains("data-src=\"css/style.css\""), "{}", html);
+    assert!(html.contains("src=\"https://cdn.example.com/x.png\""), "{}", html);
+    assert!(html.contains("href=\"notes.html\""), "{}", html);
+
+    // The fingerprinted URL still serves the asset
+    let req = Request::builder().uri(format!("/sites/fp-site/css/style.css?v={}", hash_of(css))).body(Body::empty()).unwrap();
+    let res = app.call(req).await.unwrap();
+    assert_eq!(&to_bytes(res.into_body(), usize::MAX).await.unwrap()[..], css);
+
+    // The UUID copy keeps the original markup
+    let req = Request::builder().uri(format!("/sites/{}/index.html", site_id)).body(Body::empty()).unwrap();
+    let res = app.call(req).await.unwrap();
+    let original = to_bytes(res.into_body(), usize::MAX).await.unwrap();
+    assert_eq!(&original[..], index.as_bytes());
+}
+
+#[tokio::test]
+async fn test_upload_origin_is_recorded_for_admins() {
+    use axum::extract::connect_info::MockConnectInfo;
+    use std::net::SocketAddr;
+    use utils::{multipart::multipart_body, storage::create_test_archive_file};
+
+    for record in [true, false] {
+        let (storage, temp) = create_test_storage().await;
+        let storage = Arc::new(storage);
+        let mut config = Config::default();
+        config.server.record_upload_origin = record;
+        let config = Arc::new(config);
+
+        let owner_id = Uuid::new_v4();
+        let token = TokenService::new(config.server.jwt_secret.clone(), 1)
+            .generate_token(owner_id, "origin".to_string())
+            .unwrap();
+        let site_id = Uuid::new_v4();
+        let (content_type, body) = multipart_body(&[
+            ("uuid", None, site_id.to_string().into_bytes()),
+            ("siteName", None, b"origin-site".to_vec()),
+            ("site", Some("site.tar.gz"), std::fs::read(create_test_archive_file(temp.path(), &site_id)).unwrap()),
+        ]);
+
+        let mut app = routes::build(storage.clone(), config.clone())
+            .layer(MockConnectInfo(SocketAddr::from(([203, 0, 113, 7], 40000))))
+            .into_service();
+        let req = Request::builder()
+            .method("POST")
+            .uri("/api/sites")
+            .header("authorization", format!("Bearer {}", token))
+            .header("content-type", content_type)
+            .header("user-agent", "publisher-cli/1.2")
+            .body(Body::from(body))
+            .unwrap();
+        let res = app.call(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        // The uploader's own response doesn't echo it back
+        let uploaded: serde_json::Value = serde_json::from_slice(&to_bytes(res.into_body(), usize::MAX).await.unwrap()).unwrap();
+        assert!(uploaded.get("source_ip").is_none() && uploaded.get("user_agent").is_none());
+
+        let stored = storage.sites.get(site_id).await.unwrap().expect("site record missing");
+        let req = Request::builder()
+            .uri(format!("/api/admin/all?key={}", config.server.jwt_secret))
+            .body(Body::empty())
+            .unwrap();
+        let res = app.call(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        let report: serde_json::Value = serde_json::from_slice(&to_bytes(res.into_body(), usize::MAX).await.unwrap()).unwrap();
+        let site = &report["sites"][0];
+        assert_eq!(site["id"], site_id.to_string());
+
+        if record {
+            assert_eq!(stored.source_ip.as_deref(), Some("203.0.113.7"));
+            assert_eq!(stored.user_agent.as_deref(), Some("publisher-cli/1.2"));
+            assert_eq!(site["source_ip"], "203.0.113.7");
+            assert_eq!(site["user_agent"], "publisher-cli/1.2");
+        } else {
+            assert!(stored.source_ip.is_none() && stored.user_agent.is_none());
+            assert!(site["source_ip"].is_null() && site["user_agent"].is_null());
+        }
+    }
+}
+
+#[tokio::test]
+async fn test_unknown_site_serves_not_found_page() {
+    use obsidian_publisher_server::{
+        auth::{AuthUser, AuthenticatedUser},
+        handlers::sites::{upload_site, UploadOrigin},
+    };
+    use axum::extract::State;
+    use utils::{multipart::build_multipart, storage::create_test_archive_file};
+
+    let (storage, temp) = create_test_storage().await;
+    let storage = Arc::new(storage);
+    let page = temp.path().join("not-found.html");
+    std::fs::write(&page, "<h1>No such site</h1>").unwrap();
+
+    let site_id = Uuid::new_v4();
+    let multipart = build_multipart(&[
+        ("uuid", None, site_id.to_string().into_bytes()),
+        ("siteName", None, b"known-site".to_vec()),
+        ("site", Some("site.tar.gz"), std::fs::read(create_test_archive_file(temp.path(), &site_id)).unwrap()),
+    ]).await;
+    let auth = AuthenticatedUser(AuthUser { id: Uuid::new_v4(), username: "known".to_string(), exp: usize::MAX });
+    let res = upload_site(State((storage.clone(), Arc::new(Config::default()))), auth, UploadOrigin::default(), multipart)
+        .await
+        .expect("upload failed")
+        .0;
+    assert_eq!(res.id, site_id);
+
+    let mut config = Config::default();
+    config.server.sites_not_found_page = Some(page);
+    let mut app = routes::build(storage.clone(), Arc::new(config)).into_service();
+
+    for uri in ["/sites/no-such-site/", "/sites/no-such-site/deep/page.html"] {
+        let res = app.call(Request::builder().uri(uri).body(Body::empty()).unwrap()).await.unwrap();
+        assert_eq!(res.status(), StatusCode::NOT_FOUND, "{}", uri);
+        assert!(res.headers()["content-type"].to_str().unwrap().starts_with("text/html"));
+        let body = to_bytes(res.into_body(), usize::MAX).await.unwrap();
+        assert_eq!(&body[..], b"<h1>No such site</h1>", "{}", uri);
+    }
+
+    // A missing file inside an existing site is not the site-level page
+    let res = app.call(Request::builder().uri("/sites/known-site/missing.html").body(Body::empty()).unwrap()).await.unwrap();
+    assert_eq!(res.status(), StatusCode::NOT_FOUND);
+    let body = to_bytes(res.into_body(), usize::MAX).await.unwrap();
+    assert_ne!(&body[..], b"<h1>No such site</h1>");
+
+    // Without the page, unknown sites get the JSON error
+    let mut app = routes::build(storage, Arc::new(Config::default())).into_service();
+    let res = app.call(Request::builder().uri("/sites/no-such-site/").body(Body::empty()).unwrap()).await.unwrap();
+    assert_eq!(res.status(), StatusCode::NOT_FOUND);
+    let body = to_bytes(res.into_body(), usize::MAX).await.unwrap();
+    let json: serde_json::Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(json["code"], "SITE_NOT_FOUND");
+}
+
+#[tokio::test]
+async fn test_ready_reports_ok() {
+    let (storage, _temp) = create_test_storage().await;
+    let mut app = routes::build(Arc::new(storage), Arc::new(Config::default())).into_service();
+
+    let res = app.call(Request::builder().uri("/ready").body(Body::empty()).unwrap()).await.unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+    let body = to_bytes(res.into_body(), usize::MAX).await.unwrap();
+    let json: serde_json::Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(json, serde_json::json!({ "ready": true, "database": true, "storage_writable": true }));
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_ready_reports_read_only_sites_dir() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let (storage, _temp) = create_test_storage().await;
+    let sites_dir = storage.sites.get_site_files_path_str("");
+    std::fs::set_permissions(&sites_dir, std::fs::Permissions::from_mode(0o555)).unwrap();
+    // root ignores directory permissions; nothing to observe then
+    if std::fs::write(sites_dir.join("probe"), b"x").is_ok() {
+        std::fs::remove_file(sites_dir.join("probe")).unwrap();
+        std::fs::set_permissions(&sites_dir, std::fs::Permissions::from_mode(0o755)).unwrap();
+        return;
+    }
+    let mut app = routes::build(Arc::new(storage), Arc::new(Config::default())).into_service();
+
+    let res = app.call(Request::builder().uri("/ready").body(Body::empty()).unwrap()).await.unwrap();
+    std::fs::set_permissions(&sites_dir, std::fs::Permissions::from_mode(0o755)).unwrap();
+    assert_eq!(res.status(), StatusCode::SERVICE_UNAVAILABLE);
+    let body = to_bytes(res.into_body(), usize::MAX).await.unwrap();
+    let json: serde_json::Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(json["ready"], false);
+    assert_eq!(json["database"], true);
+    assert_eq!(json["storage_writable"], false);
+}
+
+#[tokio::test]
+async fn test_list_sites_as_ndjson() {
+    let (storage, _temp) = create_test_storage().await;
+    let owner_id = Uuid::new_v4();
+    for name in ["one", "two", "three"] {
+        storage.sites.create(Site::new(Uuid::new_v4(), owner_id, name.to_string(), "d".to_string())).await.unwrap();
+    }
+    let mut app = routes::build(Arc::new(storage), Arc::new(Config::default())).into_service();
+
+    let req = Request::builder()
+        .uri("/api/sites?limit=2")
+        .header("accept", "application/x-ndjson")
+        .body(Body::empty())
+        .unwrap();
+    let res = app.call(req).await.unwrap();
+    assert_eq!(res.status(), StatusCode::OK);
+    assert_eq!(res.headers()["content-type"], "application/x-ndjson");
+    // Streamed from storage, so the total isn't known up front
+    assert!(res.headers().get("X-Total-Count").is_none());
+    let body = to_bytes(res.into_body(), usize::MAX).await.unwrap();
+    let text = std::str::from_utf8(&body).unwrap();
+    assert!(text.ends_with('\n'));
+    let lines: Vec<serde_json::Value> = text.lines()
+        .map(|line| serde_json::from_str(line).expect("each line should be one JSON object"))
+        .collect();
+    assert_eq!(lines.len(), 2);
+    assert!(lines.iter().all(|site| site["name"].is_string() && site["id"].is_string()));
+
+    // Plain JSON clients still get an array
+    let req = Request::builder().uri("/api/sites").header("accept", "application/json").body(Body::empty()).unwrap();
+    let res = app.call(req).await.unwrap();
+    let body = to_bytes(res.into_body(), usize::MAX).await.unwrap();
+    let json: serde_json::Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(json.as_array().map(Vec::len), Some(3));
+}
+
+#[tokio::test]
+async fn test_request_id_is_echoed_or_generated() {
+    let (storage, _temp) = create_test_storage().await;
+    let mut app = routes::build(Arc::new(storage), Arc::new(Config::default())).into_service();
+
+    let req = Request::builder().uri("/api/sites").header("x-request-id", "proxy-abc-123").body(Body::empty()).unwrap();
+    let res = app.call(req).await.unwrap();
+    assert_eq!(res.headers()["x-request-id"], "proxy-abc-123");
+
+    // Errors and unknown paths carry it too; a missing or unusable id is replaced by a UUID
+    let too_long = "x".repeat(200);
+    for req in [
+        Request::builder().uri("/auth/me").body(Body::empty()).unwrap(),
+        Request::builder().uri("/no/such/page").header("x-request-id", "has spaces").body(Body::empty()).unwrap(),
+        Request::builder().uri("/sites/none/").header("x-request-id", too_long.as_str()).body(Body::empty()).unwrap(),
+    ] {
+        let uri = req.uri().clone();
+        let res = app.call(req).await.unwrap();
+        let id = res.headers().get("x-request-id").unwrap_or_else(|| panic!("{} has no x-request-id", uri));
+        assert!(Uuid::parse_str(id.to_str().unwrap()).is_ok(), "{}: {:?}", uri, id);
+    }
+}
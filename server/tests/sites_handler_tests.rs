@@ -9,16 +9,50 @@
 mod utils;
 
 use obsidian_publisher_server::{
-    models::{User, Site, SiteResponse},
+    auth::{AuthUser, AuthenticatedUser, ADMIN_ROLE},
+    config::{ArchiveConfig, Config, SiteUrlStyle},
+    error::{AppError, ArchiveError},
+    storage::Storage,
+    models::{BulkSiteStatsRequest, PatchSiteRequest, RenameSiteRequest, User, Site, SiteResponse, UpdateSiteRequest},
+    handlers::json::JsonBody,
     handlers::sites::{
+        accept_site_name,
+        authorize_site_access,
+        bulk_site_stats,
+        delete_site,
+        delete_my_sites,
+        delete_sites_by_name,
+        is_admin_request,
+        MAX_STATS_IDS,
+        LIST_PAGE_SIZE,
+        list_all,
+        list_names,
+        list_own,
+        patch_site,
+        rebuild_site,
+        rename_site,
+        resolve_site_name,
+        upload_site,
         validate_site_name, 
         process_site_archive, 
         save_site_record,
+        slugify_site_name,
+        SiteList,
+        SiteSettings,
         SiteUploadParams,
+        UploadOrigin,
+        update_site,
     },
+    utils::{fs::dir_size_and_count, text::{sanitize_text, MAX_DESCRIPTION_LEN}},
 };
+use axum::extract::{Path, Query, State};
+use axum::http::HeaderMap;
+use std::collections::HashMap;
+use std::sync::Arc;
 use uuid::Uuid;
-use utils::storage::{create_test_storage, create_test_archive_file};
+use utils::logs::capture_logs;
+use utils::multipart::{build_multipart, multipart_body};
+use utils::storage::{create_test_storage, create_test_storage_with, create_test_archive_file};
 
 // ===== validate_site_name Tests =====
 
@@ -46,6 +80,103 @@ fn test_validate_site_name_invalid() {
     assert!(validate_site_name("my.site").is_err()); // dot
     assert!(validate_site_name("my/site").is_err()); // slash
     assert!(validate_site_name("my@site").is_err()); // at
+
+    // UUID-shaped names would share a directory with a site version
+    assert!(validate_site_name(&Uuid::new_v4().to_string()).is_err());
+    assert!(validate_site_name(&Uuid::new_v4().simple().to_string()).is_err());
+}
+
+#[test]
+fn test_slugify_site_name() {
+    assert_eq!(slugify_site_name("My Cool Site!"), "my-cool-site");
+    assert_eq!(slugify_site_name("  Notes -- 2024 edition  "), "notes-2024-edition");
+    assert_eq!(slugify_site_name("keep_under_scores"), "keep_under_scores");
+    assert_eq!(slugify_site_name("!!! ..."), "");
+    assert_eq!(slugify_site_name(&"a".repeat(80)).len(), 64);
+}
+
+#[test]
+fn test_accept_site_name_slugifies_only_when_allowed() {
+    assert_eq!(accept_site_name("Already-Fine".to_string(), true).unwrap(), "Already-Fine");
+    assert!(matches!(accept_site_name("My Cool Site!".to_string(), false), Err(AppError::InvalidInput(_))));
+    assert_eq!(accept_site_name("My Cool Site!".to_string(), true).unwrap(), "my-cool-site");
+    assert!(matches!(accept_site_name("?!.".to_string(), true), Err(AppError::InvalidInput(_))));
+    // A slug that is a UUID is still refused
+    let id = Uuid::new_v4().to_string().to_uppercase() + "!";
+    assert!(accept_site_name(id, true).is_err());
+}
+
+#[tokio::test]
+async fn test_upload_site_uses_slug_when_allowed() {
+    let (storage, temp) = create_test_storage().await;
+    let storage = Arc::new(storage);
+    let mut config = Config::default();
+    config.server.allow_slugify = true;
+    let config = Arc::new(config);
+    let auth = || AuthenticatedUser(AuthUser { id: Uuid::new_v4(), username: "slugger".to_string(), exp: usize::MAX });
+    let upload = |name: &'static str| {
+        let site_id = Uuid::new_v4();
+        let archive_bytes = std::fs::read(create_test_archive_file(temp.path(), &site_id)).unwrap();
+        let (storage, config) = (storage.clone(), config.clone());
+        async move {
+            let multipart = build_multipart(&[
+                ("uuid", None, site_id.to_string().into_bytes()),
+                ("siteName", None, name.as_bytes().to_vec()),
+                ("site", Some("site.tar.gz"), archive_bytes),
+            ]).await;
+            upload_site(State((storage, config)), auth(), UploadOrigin::default(), multipart).await
+        }
+    };
+
+    let res = upload("My Cool Site!").await.expect("upload failed").0;
+    assert_eq!(res.name, "my-cool-site");
+    let html = std::fs::read_to_string(storage.sites.get_site_files_path_str("my-cool-site").join("index.html")).unwrap();
+    assert!(html.contains("/sites/my-cool-site/page.html"), "got {}", html);
+
+    let err = upload("!!!").await.unwrap_err();
+    assert!(matches!(err, AppError::InvalidInput(_)), "got {:?}", err);
+}
+
+// ===== authorize_site_access Tests =====
+
+fn auth_user(id: Uuid) -> AuthUser {
+    AuthUser { id, username: "someone".to_string(), exp: usize::MAX }
+}
+
+#[test]
+fn test_owner_is_allowed() {
+    let owner = Uuid::new_v4();
+    let site = Site::new(Uuid::new_v4(), owner, "mine".to_string(), "d".to_string());
+    assert!(site.ensure_owned_by(owner).is_ok());
+    assert!(authorize_site_access(&site, &auth_user(owner), false).is_ok());
+}
+
+#[test]
+fn test_non_owner_is_denied() {
+    let site = Site::new(Uuid::new_v4(), Uuid::new_v4(), "theirs".to_string(), "d".to_string());
+    let stranger = Uuid::new_v4();
+    assert!(matches!(site.ensure_owned_by(stranger), Err(AppError::AuthorizationFailed)));
+    assert!(matches!(authorize_site_access(&site, &auth_user(stranger), false), Err(AppError::AuthorizationFailed)));
+}
+
+#[test]
+fn test_admin_bypasses_ownership() {
+    let config = Config::default();
+    let site = Site::new(Uuid::new_v4(), Uuid::new_v4(), "theirs".to_string(), "d".to_string());
+
+    let mut params = HashMap::new();
+    assert!(!is_admin_request(&params, &config));
+    params.insert("key".to_string(), "wrong".to_string());
+    assert!(!is_admin_request(&params, &config));
+    params.insert("key".to_string(), config.server.jwt_secret.clone());
+    assert!(is_admin_request(&params, &config));
+
+    assert!(authorize_site_access(&site, &auth_user(Uuid::new_v4()), is_admin_request(&params, &config)).is_ok());
+
+    // A private server does not take the key at all
+    let mut private = config.clone();
+    private.server.require_auth_for_listing = true;
+    assert!(!is_admin_request(&params, &private));
 }
 
 // ===== process_site_archive Tests =====
@@ -67,11 +198,17 @@ async fn test_process_site_archive_creates_both_directories() {
         user_id,
         archive_filename: "site.tar.gz".to_string(),
         archive_path,
+        root_dir: None,
+        spa_mode: None,
+        fingerprint_assets: None,
+        auto_sitemap: None,
+        origin: UploadOrigin::default(),
     };
     
     // Process archive
-    let (uuid_dir, name_dir) = process_site_archive(&storage, &params).await
+    let (uuid_dir, name_dir, warnings, _) = process_site_archive(&storage, &params, &ArchiveConfig::default(), false).await
         .expect("process_site_archive failed");
+    assert!(warnings.is_empty(), "clean archive should extract without warnings: {:?}", warnings);
     
     // Verify both directories exist
     assert!(uuid_dir.exists(), "UUID directory should exist");
@@ -104,6 +241,438 @@ async fn test_process_site_archive_creates_both_directories() {
     );
 }
 
+/// tar.gz with an index page linking to its own UUID path plus `pages` larger HTML pages
+fn build_bulky_archive(site_id: Uuid, pages: usize) -> Vec<u8> {
+    use std::io::Write;
+    let mut builder = tar::Builder::new(Vec::new());
+    let mut append = |path: &str, content: &[u8]| {
+        let mut header = tar::Header::new_gnu();
+        header.set_path(path).unwrap();
+        header.set_size(content.len() as u64);
+        header.set_mode(0o644);
+        header.set_cksum();
+        builder.append(&header, content).unwrap();
+    };
+    append("index.html", format!(r#"<a href="/sites/{}/page-0.html">first</a>"#, site_id).as_bytes());
+    for i in 0..pages {
+        let body = format!(r#"<p><a href="/sites/{}/index.html">home</a> page {}</p>"#, site_id, i).repeat(200);
+        append(&format!("page-{}.html", i), body.as_bytes());
+    }
+    let tar_data = builder.into_inner().unwrap();
+    let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+    encoder.write_all(&tar_data).unwrap();
+    encoder.finish().unwrap()
+}
+
+#[tokio::test]
+async fn test_process_site_archive_reports_extraction_metrics() {
+    let (storage, temp) = create_test_storage().await;
+    let storage = Arc::new(storage);
+    let site_id = Uuid::new_v4();
+    let archive = build_bulky_archive(site_id, 40);
+    let archive_path = temp.path().join("bulky.tar.gz");
+    std::fs::write(&archive_path, &archive).unwrap();
+
+    let params = SiteUploadParams {
+        site_id,
+        site_name: "bulky".to_string(),
+        user_id: Uuid::new_v4(),
+        archive_filename: "bulky.tar.gz".to_string(),
+        archive_path,
+        root_dir: None,
+        spa_mode: None,
+        fingerprint_assets: None,
+        auto_sitemap: None,
+        origin: UploadOrigin::default(),
+    };
+    let (uuid_dir, _, _, metrics) = process_site_archive(&storage, &params, &ArchiveConfig::default(), false).await
+        .expect("process_site_archive failed");
+
+    assert!(metrics.decompress_us > 0, "{:?}", metrics);
+    assert!(metrics.replace_us > 0, "{:?}", metrics);
+    assert_eq!(metrics.archive_bytes, archive.len() as u64);
+    assert_eq!(metrics.extracted_files, 41);
+    assert_eq!((metrics.extracted_bytes, metrics.extracted_files), dir_size_and_count(&uuid_dir).unwrap());
+    assert!(metrics.extracted_bytes > metrics.archive_bytes, "HTML should compress: {:?}", metrics);
+
+    // Upload responses carry them only with `server.upload_metrics`
+    for enabled in [false, true] {
+        let site_id = Uuid::new_v4();
+        let multipart = build_multipart(&[
+            ("uuid", None, site_id.to_string().into_bytes()),
+            ("siteName", None, format!("bulky-{}", enabled).into_bytes()),
+            ("site", Some("bulky.tar.gz"), build_bulky_archive(site_id, 5)),
+        ]).await;
+        let mut config = Config::default();
+        config.server.upload_metrics = enabled;
+        let auth = AuthenticatedUser(AuthUser { id: Uuid::new_v4(), username: "tuner".to_string(), exp: usize::MAX });
+        let res = upload_site(State((storage.clone(), Arc::new(config))), auth, UploadOrigin::default(), multipart)
+            .await
+            .expect("upload failed")
+            .0;
+        assert_eq!(res.metrics.is_some(), enabled);
+        let json = serde_json::to_value(&res).unwrap();
+        assert_eq!(json.get("metrics").is_some(), enabled);
+        if enabled {
+            assert!(json["metrics"]["decompress_us"].as_u64().unwrap() > 0);
+            assert_eq!(json["metrics"]["extracted_files"], 6);
+        }
+    }
+}
+
+#[tokio::test]
+async fn test_process_site_archive_respects_extraction_limit() {
+    let (storage, temp) = create_test_storage_with(|c| c.max_concurrent_extractions = 1).await;
+    let storage = Arc::new(storage);
+
+    // Occupy the only slot so both uploads have to queue
+    let held = storage.extractions.acquire().await.unwrap();
+
+    let mut tasks = Vec::new();
+    for name in ["queued-a", "queued-b"] {
+        let site_id = Uuid::new_v4();
+        let archive_dir = temp.path().join(name);
+        std::fs::create_dir_all(&archive_dir).unwrap();
+        let params = SiteUploadParams {
+            site_id,
+            site_name: name.to_string(),
+            user_id: Uuid::new_v4(),
+            archive_filename: "site.tar.gz".to_string(),
+            archive_path: create_test_archive_file(&archive_dir, &site_id),
+            root_dir: None,
+            spa_mode: None,
+            fingerprint_assets: None,
+            auto_sitemap: None,
+            origin: UploadOrigin::default(),
+        };
+        let storage = storage.clone();
+        tasks.push(tokio::spawn(async move {
+            process_site_archive(&storage, &params, &ArchiveConfig::default(), false).await
+        }));
+    }
+
+    tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+    assert!(tasks.iter().all(|t| !t.is_finished()), "extractions should wait for a free slot");
+
+    drop(held);
+    for task in tasks {
+        task.await.unwrap().expect("queued extraction failed");
+        assert!(storage.extractions.available_permits() <= 1);
+    }
+    assert_eq!(storage.extractions.available_permits(), 1);
+}
+
+#[tokio::test]
+async fn test_upload_site_limits_concurrent_uploads_per_user() {
+    use axum::response::IntoResponse;
+
+    let (storage, temp) = create_test_storage_with(|c| {
+        c.max_concurrent_extractions = 1;
+        c.max_concurrent_uploads_per_user = 1;
+    })
+    .await;
+    let storage = Arc::new(storage);
+    let busy_user = Uuid::new_v4();
+    let other_user = Uuid::new_v4();
+
+    // Runs `upload_site` in the background; the archive file name keeps the temp files apart
+    let spawn_upload = |user_id: Uuid, name: &'static str| {
+        let site_id = Uuid::new_v4();
+        let archive_dir = temp.path().join(name);
+        std::fs::create_dir_all(&archive_dir).unwrap();
+        let archive = std::fs::read(create_test_archive_file(&archive_dir, &site_id)).unwrap();
+        let storage = storage.clone();
+        tokio::spawn(async move {
+            let file_name = format!("{}.tar.gz", name);
+            let multipart = build_multipart(&[
+                ("uuid", None, site_id.to_string().into_bytes()),
+                ("siteName", None, name.as_bytes().to_vec()),
+                ("site", Some(file_name.as_str()), archive),
+            ]).await;
+            let auth = AuthenticatedUser(AuthUser { id: user_id, username: "uploader".to_string(), exp: usize::MAX });
+            upload_site(State((storage, Arc::new(Config::default()))), auth, UploadOrigin::default(), multipart).await.map(|res| res.0)
+        })
+    };
+
+    // Hold the only extraction slot so the first upload stays in flight
+    let held = storage.extractions.acquire().await.unwrap();
+    let first = spawn_upload(busy_user, "busy-first");
+    for _ in 0..100 {
+        if storage.uploads.in_flight(busy_user) == 1 {
+            break;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+    }
+    assert_eq!(storage.uploads.in_flight(busy_user), 1);
+
+    let err = spawn_upload(busy_user, "busy-second").await.unwrap().unwrap_err();
+    assert!(matches!(err, AppError::TooManyUploads(1)), "got {:?}", err);
+    assert_eq!(err.into_response().status(), axum::http::StatusCode::TOO_MANY_REQUESTS);
+
+    // Another user only waits for extraction like everyone else
+    let other = spawn_upload(other_user, "other-user");
+    tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+    assert!(!other.is_finished(), "the other user's upload should be queued, not refused");
+
+    drop(held);
+    first.await.unwrap().expect("first upload failed");
+    other.await.unwrap().expect("other user's upload failed");
+    assert_eq!(storage.uploads.in_flight(busy_user), 0);
+    assert_eq!(storage.uploads.in_flight(other_user), 0);
+
+    // Failed uploads give their slot back too
+    let err = spawn_upload(busy_user, "..bad-name").await.unwrap().unwrap_err();
+    assert!(!matches!(err, AppError::TooManyUploads(_)), "got {:?}", err);
+    assert_eq!(storage.uploads.in_flight(busy_user), 0);
+    spawn_upload(busy_user, "busy-third").await.unwrap().expect("slot should be free again");
+}
+
+#[tokio::test]
+async fn test_process_site_archive_keeps_temp_on_error() {
+    let (storage, temp) = create_test_storage().await;
+    let (logs, _guard) = capture_logs();
+
+    let site_id = Uuid::new_v4();
+    // Not a gzip stream: extraction fails
+    let archive_path = temp.path().join("broken.tar.gz");
+    std::fs::write(&archive_path, b"definitely not an archive").unwrap();
+
+    let params = SiteUploadParams {
+        site_id,
+        site_name: "broken-site".to_string(),
+        user_id: Uuid::new_v4(),
+        archive_filename: "broken.tar.gz".to_string(),
+        archive_path: archive_path.clone(),
+        root_dir: None,
+        spa_mode: None,
+        fingerprint_assets: None,
+        auto_sitemap: None,
+        origin: UploadOrigin::default(),
+    };
+
+    let res = process_site_archive(&storage, &params, &ArchiveConfig::default(), true).await;
+    assert!(res.is_err(), "extraction of a broken archive should fail");
+
+    assert!(archive_path.exists(), "archive should be kept for inspection");
+    let output = logs.contents();
+    assert!(output.contains("keeping temp artifacts"), "log should mention kept artifacts: {}", output);
+    assert!(output.contains("broken.tar.gz"), "log should include the archive path: {}", output);
+
+    // Without the flag the archive is cleaned up
+    let res = process_site_archive(&storage, &params, &ArchiveConfig::default(), false).await;
+    assert!(res.is_err());
+    assert!(!archive_path.exists(), "archive should be removed when the flag is off");
+}
+
+#[tokio::test]
+async fn test_process_site_archive_rejects_empty_archive() {
+    use flate2::{write::GzEncoder, Compression};
+    let (storage, temp) = create_test_storage().await;
+
+    // Archive holding only an empty directory entry
+    let mut builder = tar::Builder::new(GzEncoder::new(Vec::new(), Compression::default()));
+    let mut header = tar::Header::new_gnu();
+    header.set_entry_type(tar::EntryType::Directory);
+    header.set_size(0);
+    header.set_mode(0o755);
+    builder.append_data(&mut header, "empty/", std::io::empty()).unwrap();
+    let archive_data = builder.into_inner().unwrap().finish().unwrap();
+    let archive_path = temp.path().join("empty.tar.gz");
+    std::fs::write(&archive_path, archive_data).unwrap();
+
+    let site_id = Uuid::new_v4();
+    let params = SiteUploadParams {
+        site_id,
+        site_name: "empty-site".to_string(),
+        user_id: Uuid::new_v4(),
+        archive_filename: "empty.tar.gz".to_string(),
+        archive_path,
+        root_dir: None,
+        spa_mode: None,
+        fingerprint_assets: None,
+        auto_sitemap: None,
+        origin: UploadOrigin::default(),
+    };
+
+    let err = process_site_archive(&storage, &params, &ArchiveConfig::default(), false)
+        .await
+        .expect_err("empty archive should be rejected");
+    assert!(matches!(err, AppError::Archive(ArchiveError::Empty)), "got {:?}", err);
+
+    assert!(!storage.sites.get_site_files_path(site_id).exists(), "UUID dir should be rolled back");
+    assert!(!storage.sites.get_site_files_path_str("empty-site").exists(), "siteName dir should not be created");
+}
+
+#[tokio::test]
+async fn test_process_site_archive_invalid_upload_keeps_live_site() {
+    use flate2::{write::GzEncoder, Compression};
+    let (storage, temp) = create_test_storage().await;
+
+    // Publish a working site under the name
+    let live_id = Uuid::new_v4();
+    let params = SiteUploadParams {
+        site_id: live_id,
+        site_name: "live-site".to_string(),
+        user_id: Uuid::new_v4(),
+        archive_filename: "site.tar.gz".to_string(),
+        archive_path: create_test_archive_file(temp.path(), &live_id),
+        root_dir: None,
+        spa_mode: None,
+        fingerprint_assets: None,
+        auto_sitemap: None,
+        origin: UploadOrigin::default(),
+    };
+    let (_, name_dir, _, _) = process_site_archive(&storage, &params, &ArchiveConfig::default(), false)
+        .await
+        .expect("initial publish failed");
+    let live_html = std::fs::read_to_string(name_dir.join("index.html")).unwrap();
+
+    // A well-formed archive that lacks index.html
+    let mut builder = tar::Builder::new(GzEncoder::new(Vec::new(), Compression::default()));
+    let body = b"<p>orphan</p>";
+    let mut header = tar::Header::new_gnu();
+    header.set_size(body.len() as u64);
+    header.set_mode(0o644);
+    builder.append_data(&mut header, "notes/page.html", &body[..]).unwrap();
+    let archive_path = temp.path().join("no-index.tar.gz");
+    std::fs::write(&archive_path, builder.into_inner().unwrap().finish().unwrap()).unwrap();
+
+    let next_id = Uuid::new_v4();
+    let params = SiteUploadParams {
+        site_id: next_id,
+        site_name: "live-site".to_string(),
+        user_id: Uuid::new_v4(),
+        archive_filename: "no-index.tar.gz".to_string(),
+        archive_path,
+        root_dir: None,
+        spa_mode: None,
+        fingerprint_assets: None,
+        auto_sitemap: None,
+        origin: UploadOrigin::default(),
+    };
+    let err = process_site_archive(&storage, &params, &ArchiveConfig::default(), false)
+        .await
+        .expect_err("archive without index.html should be rejected");
+    assert!(matches!(err, AppError::Archive(ArchiveError::NoIndexHtml(ref msg)) if msg.contains("index.html")), "got {:?}", err);
+
+    // Rejected before extraction: nothing written for the new id, live copy untouched
+    assert!(!storage.sites.get_site_files_path(next_id).exists(), "new UUID dir should not be created");
+    assert_eq!(std::fs::read_to_string(name_dir.join("index.html")).unwrap(), live_html);
+    assert!(!name_dir.join("notes").exists());
+}
+
+#[tokio::test]
+async fn test_process_site_archive_rejects_name_matching_existing_uuid() {
+    let (storage, temp) = create_test_storage().await;
+
+    // An existing site version and its UUID directory
+    let victim_id = Uuid::new_v4();
+    let params = SiteUploadParams {
+        site_id: victim_id,
+        site_name: "victim".to_string(),
+        user_id: Uuid::new_v4(),
+        archive_filename: "site.tar.gz".to_string(),
+        archive_path: create_test_archive_file(temp.path(), &victim_id),
+        root_dir: None,
+        spa_mode: None,
+        fingerprint_assets: None,
+        auto_sitemap: None,
+        origin: UploadOrigin::default(),
+    };
+    let (victim_dir, _, _, _) = process_site_archive(&storage, &params, &ArchiveConfig::default(), false)
+        .await
+        .expect("initial publish failed");
+    save_site_record(&storage, victim_id, "victim", params.user_id, None, Vec::new(), SiteSettings::default()).await.unwrap();
+    let victim_html = std::fs::read_to_string(victim_dir.join("index.html")).unwrap();
+
+    // Another user names their site after that UUID
+    let attacker_id = Uuid::new_v4();
+    let params = SiteUploadParams {
+        site_id: attacker_id,
+        site_name: victim_id.to_string(),
+        user_id: Uuid::new_v4(),
+        archive_filename: "site.tar.gz".to_string(),
+        archive_path: create_test_archive_file(temp.path(), &attacker_id),
+        root_dir: None,
+        spa_mode: None,
+        fingerprint_assets: None,
+        auto_sitemap: None,
+        origin: UploadOrigin::default(),
+    };
+    let err = process_site_archive(&storage, &params, &ArchiveConfig::default(), false)
+        .await
+        .expect_err("name colliding with a UUID directory should be rejected");
+    assert!(matches!(err, AppError::SiteNameConflict(ref name) if *name == victim_id.to_string()), "got {:?}", err);
+
+    assert_eq!(std::fs::read_to_string(victim_dir.join("index.html")).unwrap(), victim_html);
+    assert!(!storage.sites.get_site_files_path(attacker_id).exists());
+}
+
+#[tokio::test]
+async fn test_size_cache_tracks_uploads() {
+    use flate2::{write::GzEncoder, Compression};
+    let (storage, temp) = create_test_storage().await;
+
+    let first_id = Uuid::new_v4();
+    let params = SiteUploadParams {
+        site_id: first_id,
+        site_name: "sized".to_string(),
+        user_id: Uuid::new_v4(),
+        archive_filename: "site.tar.gz".to_string(),
+        archive_path: create_test_archive_file(temp.path(), &first_id),
+        root_dir: None,
+        spa_mode: None,
+        fingerprint_assets: None,
+        auto_sitemap: None,
+        origin: UploadOrigin::default(),
+    };
+    let (uuid_dir, name_dir, _, _) = process_site_archive(&storage, &params, &ArchiveConfig::default(), false)
+        .await
+        .expect("upload failed");
+
+    // Recorded during extraction and equal to a fresh walk
+    let cached = storage.sizes.cached(&uuid_dir).expect("UUID dir should be cached after upload");
+    assert_eq!(cached, dir_size_and_count(&uuid_dir).unwrap());
+    let before = storage.sizes.cached(&name_dir).expect("siteName dir should be cached after upload");
+    assert_eq!(before, dir_size_and_count(&name_dir).unwrap());
+
+    // Re-upload under the same name with an extra page
+    let mut builder = tar::Builder::new(GzEncoder::new(Vec::new(), Compression::default()));
+    for (path, data) in [("index.html", &b"<p>v2</p>"[..]), ("extra.html", &b"<p>more content</p>"[..])] {
+        let mut header = tar::Header::new_gnu();
+        header.set_size(data.len() as u64);
+        header.set_mode(0o644);
+        builder.append_data(&mut header, path, data).unwrap();
+    }
+    let archive_path = temp.path().join("v2.tar.gz");
+    std::fs::write(&archive_path, builder.into_inner().unwrap().finish().unwrap()).unwrap();
+    let params = SiteUploadParams {
+        site_id: Uuid::new_v4(),
+        site_name: "sized".to_string(),
+        user_id: params.user_id,
+        archive_filename: "v2.tar.gz".to_string(),
+        archive_path,
+        root_dir: None,
+        spa_mode: None,
+        fingerprint_assets: None,
+        auto_sitemap: None,
+        origin: UploadOrigin::default(),
+    };
+    process_site_archive(&storage, &params, &ArchiveConfig::default(), false)
+        .await
+        .expect("re-upload failed");
+
+    let after = storage.sizes.cached(&name_dir).expect("siteName dir should stay cached");
+    assert_ne!(after, before);
+    assert_eq!(after, dir_size_and_count(&name_dir).unwrap());
+    assert_eq!(after.1, 2);
+
+    // Invalidated entries fall back to a walk
+    storage.sizes.invalidate(&uuid_dir);
+    assert!(storage.sizes.cached(&uuid_dir).is_none());
+    assert_eq!(storage.sizes.get_or_walk(&uuid_dir).unwrap(), cached);
+}
+
 // ===== save_site_record Tests =====
 
 #[tokio::test]
@@ -119,7 +688,7 @@ async fn test_save_site_record_create_new() {
     let site_name = "new-site".to_string();
     
     // Save record using the actual function signature
-    let site = save_site_record(&storage, site_id, &site_name, user_id).await
+    let site = save_site_record(&storage, site_id, &site_name, user_id, None, Vec::new(), SiteSettings::default()).await
         .expect("save_site_record failed");
     
     assert_eq!(site.id, site_id);
@@ -150,7 +719,7 @@ async fn test_save_site_record_creates_new_version() {
     // Create new version via save_site_record (simulating re-upload)
     let site2_id = Uuid::new_v4();
     
-    let new_site = save_site_record(&storage, site2_id, &site_name, user_id).await
+    let new_site = save_site_record(&storage, site2_id, &site_name, user_id, None, Vec::new(), SiteSettings::default()).await
         .expect("save_site_record failed");
     
     // Should have the NEW site_id (new version)
@@ -177,9 +746,1185 @@ fn test_site_response_contains_both_urls() {
     let owner_id = Uuid::new_v4();
     
     let site = Site::new(site_id, owner_id, site_name.clone(), "Test".to_string());
-    let response = SiteResponse::from_site(site, "https://example.com");
+    let response = SiteResponse::from_site(site, "https://example.com", SiteUrlStyle::default());
     
     // Verify both URLs are present
     assert_eq!(response.url, format!("https://example.com/sites/{}/", site_name));
     assert_eq!(response.url_by_id, format!("https://example.com/sites/{}/", site_id));
 }
+
+#[test]
+fn test_site_response_url_styles() {
+    let site_id = Uuid::new_v4();
+    let owner_id = Uuid::new_v4();
+    let cases = [
+        (SiteUrlStyle::TrailingSlash, "https://example.com/sites/my-blog/", format!("https://example.com/sites/{}/", site_id)),
+        (SiteUrlStyle::NoSlash, "https://example.com/sites/my-blog", format!("https://example.com/sites/{}", site_id)),
+        (SiteUrlStyle::ExplicitIndex, "https://example.com/sites/my-blog/index.html", format!("https://example.com/sites/{}/index.html", site_id)),
+    ];
+
+    for (style, url, url_by_id) in cases {
+        let site = Site::new(site_id, owner_id, "my-blog".to_string(), "Test".to_string());
+        let response = SiteResponse::from_site(site, "https://example.com", style);
+        assert_eq!(response.url, url, "{:?}", style);
+        assert_eq!(response.url_by_id, url_by_id, "{:?}", style);
+    }
+}
+
+#[test]
+fn test_site_url_style_parses_from_config() {
+    let parse = |raw: &str| serde_json::from_value::<SiteUrlStyle>(serde_json::Value::String(raw.to_string())).unwrap();
+    assert_eq!(parse("trailing_slash"), SiteUrlStyle::TrailingSlash);
+    assert_eq!(parse("no_slash"), SiteUrlStyle::NoSlash);
+    assert_eq!(parse("explicit_index"), SiteUrlStyle::ExplicitIndex);
+}
+
+// ===== list_all pagination Tests =====
+
+fn array(list: &SiteList) -> &[SiteResponse] {
+    match list {
+        SiteList::Array(sites) => sites,
+        SiteList::Lines(_) => panic!("expected a JSON array"),
+    }
+}
+
+#[tokio::test]
+async fn test_list_all_pagination_headers() {
+    let (storage, _temp) = create_test_storage().await;
+
+    let user = User::new("pager".to_string(), "pass".to_string());
+    let user_id = user.id;
+    storage.users.create(user).await.expect("Failed to create user");
+
+    for i in 0..5 {
+        let mut site = Site::new(Uuid::new_v4(), user_id, format!("site-{}", i), "Test".to_string());
+        site.created_at = chrono::Utc::now() - chrono::Duration::minutes(i);
+        storage.sites.create(site).await.expect("Failed to create site");
+    }
+    let stored = storage.sites.list_all().await.expect("list_all failed").len();
+
+    let mut config = Config::default();
+    config.server.url = "https://example.com".to_string();
+
+    let params: HashMap<String, String> = [("offset", "2"), ("limit", "2")]
+        .into_iter()
+        .map(|(k, v)| (k.to_string(), v.to_string()))
+        .collect();
+
+    let (headers, body) = list_all(State((Arc::new(storage), Arc::new(config))), None, HeaderMap::new(), Query(params))
+        .await
+        .expect("list_all handler failed");
+
+    assert_eq!(array(&body).len(), 2);
+    assert_eq!(headers["X-Total-Count"], stored.to_string().as_str());
+    assert_eq!(headers["X-Offset"], "2");
+
+    let link = headers["link"].to_str().unwrap();
+    assert!(link.contains("<https://example.com/api/sites?offset=4&limit=2>; rel=\"next\""));
+    assert!(link.contains("<https://example.com/api/sites?offset=0&limit=2>; rel=\"prev\""));
+}
+
+#[tokio::test]
+async fn test_list_all_latest_only() {
+    let (storage, _temp) = create_test_storage().await;
+
+    let user = User::new("versions".to_string(), "pass".to_string());
+    let user_id = user.id;
+    storage.users.create(user).await.expect("Failed to create user");
+
+    let mut newest = HashMap::new();
+    for name in ["alpha", "beta"] {
+        for hours_ago in [3, 2, 1] {
+            let mut site = Site::new(Uuid::new_v4(), user_id, name.to_string(), format!("{}h", hours_ago));
+            site.created_at = chrono::Utc::now() - chrono::Duration::hours(hours_ago);
+            if hours_ago == 1 {
+                newest.insert(name.to_string(), site.id);
+            }
+            storage.sites.create(site).await.expect("Failed to create site");
+        }
+    }
+
+    let params: HashMap<String, String> = [("latest_only".to_string(), "true".to_string())].into_iter().collect();
+    let (headers, body) = list_all(State((Arc::new(storage), Arc::new(Config::default()))), None, HeaderMap::new(), Query(params))
+        .await
+        .expect("list_all handler failed");
+
+    assert_eq!(array(&body).len(), 2);
+    assert_eq!(headers["X-Total-Count"], "2");
+    for site in array(&body).iter() {
+        assert_eq!(Some(&site.id), newest.get(&site.name), "should return newest version of {}", site.name);
+    }
+}
+
+#[tokio::test]
+async fn test_list_all_with_stats() {
+    let (storage, _temp) = create_test_storage().await;
+
+    let user = User::new("stats".to_string(), "pass".to_string());
+    let user_id = user.id;
+    storage.users.create(user).await.expect("Failed to create user");
+
+    for hours_ago in [2, 1] {
+        let mut site = Site::new(Uuid::new_v4(), user_id, "stats-site".to_string(), "Test".to_string());
+        site.created_at = chrono::Utc::now() - chrono::Duration::hours(hours_ago);
+        let dir = storage.sites.get_site_files_path(site.id);
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("index.html"), "<html>stats</html>").unwrap();
+        storage.sites.create(site).await.expect("Failed to create site");
+    }
+
+    let params: HashMap<String, String> = [("with_stats".to_string(), "true".to_string())].into_iter().collect();
+    let (_headers, body) = list_all(State((Arc::new(storage), Arc::new(Config::default()))), None, HeaderMap::new(), Query(params))
+        .await
+        .expect("list_all handler failed");
+
+    assert_eq!(array(&body).len(), 1, "with_stats collapses to the latest version per name");
+    assert_eq!(array(&body)[0].version_count, Some(2));
+    assert!(array(&body)[0].total_bytes.unwrap() > 0);
+}
+
+#[tokio::test]
+async fn test_list_all_include_owner_resolves_usernames() {
+    let (storage, _temp) = create_test_storage().await;
+    let config = Config::default();
+
+    let alice = User::new("alice".to_string(), "pw".to_string());
+    let bob = User::new("bob".to_string(), "pw".to_string());
+    let (alice_id, bob_id) = (alice.id, bob.id);
+    storage.users.create(alice).await.unwrap();
+    storage.users.create(bob).await.unwrap();
+    for (owner_id, name) in [(alice_id, "alice-notes"), (alice_id, "alice-blog"), (bob_id, "bob-wiki")] {
+        storage.sites.create(Site::new(Uuid::new_v4(), owner_id, name.to_string(), "d".to_string())).await.unwrap();
+    }
+    let state = (Arc::new(storage), Arc::new(config.clone()));
+
+    let mut params = HashMap::new();
+    params.insert("include_owner".to_string(), "true".to_string());
+
+    // Anonymous callers can't ask for owner names
+    let err = list_all(State(state.clone()), None, HeaderMap::new(), Query(params.clone())).await.unwrap_err();
+    assert!(matches!(err, AppError::AuthenticationFailed), "got {:?}", err);
+
+    let bob = AuthenticatedUser(AuthUser { id: bob_id, username: "bob".to_string(), exp: usize::MAX });
+    let (_headers, body) = list_all(State(state.clone()), Some(bob), HeaderMap::new(), Query(params)).await.expect("list_all failed");
+
+    let owners: HashMap<String, Option<String>> = array(&body).iter()
+        .map(|s| (s.name.clone(), s.owner_username.clone()))
+        .collect();
+    assert_eq!(owners.len(), 3);
+    assert_eq!(owners["alice-notes"].as_deref(), Some("alice"));
+    assert_eq!(owners["alice-blog"].as_deref(), Some("alice"));
+    assert_eq!(owners["bob-wiki"].as_deref(), Some("bob"));
+
+    // Not requested: field stays out of the response
+    let (_headers, body) = list_all(State(state), None, HeaderMap::new(), Query(HashMap::new())).await.unwrap();
+    assert!(array(&body).iter().all(|s| s.owner_username.is_none()));
+}
+
+#[tokio::test]
+async fn test_list_all_streams_ndjson_page_by_page() {
+    let (storage, _temp) = create_test_storage().await;
+    let owner_id = Uuid::new_v4();
+    let mut ids = Vec::new();
+    for i in 0..LIST_PAGE_SIZE + 5 {
+        let site = Site::new(Uuid::new_v4(), owner_id, format!("paged-{}", i), "d".to_string());
+        ids.push(site.id);
+        storage.sites.create(site).await.unwrap();
+    }
+    ids.sort();
+    let state = (Arc::new(storage), Arc::new(Config::default()));
+    let mut accept = HeaderMap::new();
+    accept.insert("accept", "application/x-ndjson".parse().unwrap());
+
+    let listed = |query: &[(&str, String)]| {
+        let params: HashMap<String, String> = query.iter().map(|(k, v)| (k.to_string(), v.clone())).collect();
+        let (state, accept) = (state.clone(), accept.clone());
+        async move {
+            let (headers, body) = list_all(State(state), None, accept, Query(params)).await.expect("list_all failed");
+            assert!(headers.get("X-Total-Count").is_none(), "a streamed listing has no total");
+            let SiteList::Lines(body) = body else { panic!("expected NDJSON") };
+            let body = axum::body::to_bytes(body, usize::MAX).await.unwrap();
+            std::str::from_utf8(&body).unwrap()
+                .lines()
+                .map(|line| serde_json::from_str::<serde_json::Value>(line).unwrap()["id"].as_str().unwrap().parse().unwrap())
+                .collect::<Vec<Uuid>>()
+        }
+    };
+
+    // Every version, in id order, across storage pages
+    assert_eq!(listed(&[]).await, ids);
+    // offset/limit apply to the stream and may straddle a page boundary
+    let window = listed(&[("offset", (LIST_PAGE_SIZE - 2).to_string()), ("limit", "4".to_string())]).await;
+    assert_eq!(window, ids[LIST_PAGE_SIZE - 2..LIST_PAGE_SIZE + 2]);
+}
+
+#[tokio::test]
+async fn test_list_own_only_lists_the_callers_sites() {
+    let (storage, _temp) = create_test_storage().await;
+    let (owner_id, other_id) = (Uuid::new_v4(), Uuid::new_v4());
+    let mut newest = HashMap::new();
+    for (owner, name, hours_ago) in [(owner_id, "mine", 2), (owner_id, "mine", 1), (owner_id, "also-mine", 1), (other_id, "theirs", 1)] {
+        let mut site = Site::new(Uuid::new_v4(), owner, name.to_string(), "d".to_string());
+        site.created_at = chrono::Utc::now() - chrono::Duration::hours(hours_ago);
+        newest.insert(name.to_string(), site.id);
+        storage.sites.create(site).await.unwrap();
+    }
+    let state = (Arc::new(storage), Arc::new(Config::default()));
+    let auth = || AuthenticatedUser(AuthUser { id: owner_id, username: "lister".to_string(), exp: usize::MAX });
+
+    let (headers, body) = list_own(State(state.clone()), auth(), HeaderMap::new(), Query(HashMap::new())).await.unwrap();
+    assert_eq!(array(&body).len(), 3);
+    assert_eq!(headers["X-Total-Count"], "3");
+    assert!(array(&body).iter().all(|s| s.name != "theirs"));
+
+    let params: HashMap<String, String> = [("latest_only".to_string(), "true".to_string())].into_iter().collect();
+    let (_headers, body) = list_own(State(state), auth(), HeaderMap::new(), Query(params)).await.unwrap();
+    assert_eq!(array(&body).len(), 2);
+    for site in array(&body).iter() {
+        assert_eq!(Some(&site.id), newest.get(&site.name), "should return newest version of {}", site.name);
+    }
+}
+
+// ===== list_names Tests =====
+
+#[tokio::test]
+async fn test_list_names_is_distinct_and_sorted() {
+    let (storage, _temp) = create_test_storage().await;
+    let config = Config::default();
+
+    let owner = Uuid::new_v4();
+    let other = Uuid::new_v4();
+    for (owner_id, name) in [
+        (owner, "zeta"), (owner, "alpha"), (owner, "zeta"),
+        (other, "mid"), (other, "alpha"), (owner, "zeta"),
+    ] {
+        storage.sites.create(Site::new(Uuid::new_v4(), owner_id, name.to_string(), "v".to_string())).await.unwrap();
+    }
+    let state = (Arc::new(storage), Arc::new(config.clone()));
+
+    let (headers, body) = list_names(State(state.clone()), None, Query(HashMap::new()))
+        .await
+        .expect("list_names failed");
+    assert_eq!(body.0, vec!["alpha", "mid", "zeta"]);
+    assert_eq!(headers["X-Total-Count"], "3");
+
+    let mut params = HashMap::new();
+    params.insert("limit".to_string(), "2".to_string());
+    params.insert("offset".to_string(), "1".to_string());
+    let (_headers, body) = list_names(State(state.clone()), None, Query(params)).await.unwrap();
+    assert_eq!(body.0, vec!["mid", "zeta"]);
+
+    // ?mine=true scopes to the caller
+    let mut params = HashMap::new();
+    params.insert("mine".to_string(), "true".to_string());
+    let err = list_names(State(state.clone()), None, Query(params.clone())).await.unwrap_err();
+    assert!(matches!(err, AppError::AuthenticationFailed), "got {:?}", err);
+    let caller = AuthenticatedUser(AuthUser { id: other, username: "other".to_string(), exp: usize::MAX });
+    let (_headers, body) = list_names(State(state), Some(caller), Query(params)).await.unwrap();
+    assert_eq!(body.0, vec!["alpha", "mid"]);
+}
+
+// ===== upload_site Tests =====
+
+#[tokio::test]
+async fn test_upload_site_verifies_replacement_and_audits() {
+    let (storage, temp) = create_test_storage().await;
+    let storage = Arc::new(storage);
+
+    let user = User::new("uploader".to_string(), "pass".to_string());
+    let user_id = user.id;
+    storage.users.create(user).await.expect("Failed to create user");
+
+    let site_id = Uuid::new_v4();
+    let archive_bytes = std::fs::read(create_test_archive_file(temp.path(), &site_id)).unwrap();
+    let multipart = build_multipart(&[
+        ("uuid", None, site_id.to_string().into_bytes()),
+        ("siteName", None, b"audited-site".to_vec()),
+        ("site", Some("site.tar.gz"), archive_bytes),
+    ]).await;
+
+    let auth = AuthenticatedUser(AuthUser { id: user_id, username: "uploader".to_string(), exp: usize::MAX });
+    let res = upload_site(State((storage.clone(), Arc::new(Config::default()))), auth, UploadOrigin::default(), multipart)
+        .await
+        .expect("upload_site failed");
+    // test archive's index.html links to /sites/{uuid}/ which must be rewritten
+    assert_eq!(res.0.replacement_verified, Some(true));
+
+    let events = storage.audit.recent(10).await.expect("recent failed");
+    let upload = events.iter().find(|e| e.action == "site_upload").expect("site_upload event missing");
+    assert_eq!(upload.actor_id, Some(user_id));
+    assert_eq!(upload.target, Some(format!("audited-site:{}", site_id)));
+}
+
+#[tokio::test]
+async fn test_upload_site_reports_skipped_entries() {
+    use std::io::Write;
+
+    let (storage, _temp) = create_test_storage().await;
+    let storage = Arc::new(storage);
+
+    let user = User::new("warned".to_string(), "pass".to_string());
+    let user_id = user.id;
+    storage.users.create(user).await.expect("Failed to create user");
+
+    let mut zip = zip::ZipWriter::new(std::io::Cursor::new(Vec::new()));
+    let options: zip::write::FileOptions<'_, ()> = zip::write::FileOptions::default();
+    zip.start_file("index.html", options).expect("start index");
+    zip.write_all(b"<html>ok</html>").expect("write index");
+    zip.add_symlink("link.html", "/etc/passwd", options).expect("add symlink");
+    zip.start_file("../escape.html", options).expect("start escape");
+    zip.write_all(b"outside").expect("write escape");
+    let archive_bytes = zip.finish().expect("finish zip").into_inner();
+
+    let site_id = Uuid::new_v4();
+    let multipart = build_multipart(&[
+        ("uuid", None, site_id.to_string().into_bytes()),
+        ("siteName", None, b"warned-site".to_vec()),
+        ("site", Some("site.zip"), archive_bytes),
+    ]).await;
+
+    let auth = AuthenticatedUser(AuthUser { id: user_id, username: "warned".to_string(), exp: usize::MAX });
+    let res = upload_site(State((storage.clone(), Arc::new(Config::default()))), auth, UploadOrigin::default(), multipart)
+        .await
+        .expect("upload_site failed");
+
+    let warnings = &res.0.warnings;
+    assert_eq!(warnings.len(), 2, "got {:?}", warnings);
+    assert!(warnings.iter().any(|w| w.contains("link.html") && w.contains("links are not extracted")));
+    assert!(warnings.iter().any(|w| w.contains("../escape.html") && w.contains("outside the site directory")));
+
+    let site_dir = storage.sites.get_site_files_path(site_id);
+    assert!(site_dir.join("index.html").exists());
+    assert!(!site_dir.join("link.html").exists());
+    assert!(!site_dir.parent().unwrap().join("escape.html").exists());
+
+    let json = serde_json::to_value(&res.0).unwrap();
+    assert_eq!(json["warnings"].as_array().unwrap().len(), 2);
+}
+
+#[tokio::test]
+async fn test_upload_rejects_oversized_text_field() {
+    let (storage, temp) = create_test_storage().await;
+    let storage = Arc::new(storage);
+    let user_id = Uuid::new_v4();
+    let site_id = Uuid::new_v4();
+    let archive_bytes = std::fs::read(create_test_archive_file(temp.path(), &site_id)).unwrap();
+
+    let multipart = build_multipart(&[
+        ("uuid", None, vec![b'a'; 10 * 1024]),
+        ("siteName", None, b"huge-uuid".to_vec()),
+        ("site", Some("huge-uuid.tar.gz"), archive_bytes),
+    ]).await;
+
+    let auth = AuthenticatedUser(AuthUser { id: user_id, username: "huge".to_string(), exp: usize::MAX });
+    let err = upload_site(State((storage.clone(), Arc::new(Config::default()))), auth, UploadOrigin::default(), multipart)
+        .await
+        .unwrap_err();
+    assert!(
+        matches!(&err, AppError::InvalidInput(msg) if msg.contains("'uuid'") && msg.contains("1024 bytes")),
+        "got {:?}", err
+    );
+
+    // The upload stopped at the uuid field: the archive after it was never streamed to disk
+    let temp_dir = storage.sites.get_site_files_path_str(".upload_temp");
+    assert!(!temp_dir.join("huge-uuid.tar.gz").exists());
+    assert!(storage.sites.get(site_id).await.unwrap().is_none());
+}
+
+#[tokio::test]
+async fn test_upload_reports_all_missing_parts() {
+    use axum::{http::StatusCode, response::IntoResponse};
+
+    let (storage, temp) = create_test_storage().await;
+    let storage = Arc::new(storage);
+    let archive_bytes = std::fs::read(create_test_archive_file(temp.path(), &Uuid::new_v4())).unwrap();
+    let multipart = build_multipart(&[("site", Some("only-archive.tar.gz"), archive_bytes)]).await;
+
+    let auth = AuthenticatedUser(AuthUser { id: Uuid::new_v4(), username: "partial".to_string(), exp: usize::MAX });
+    let err = upload_site(State((storage.clone(), Arc::new(Config::default()))), auth, UploadOrigin::default(), multipart)
+        .await
+        .unwrap_err();
+    match &err {
+        AppError::Validation(fields) => {
+            let names: Vec<&str> = fields.iter().map(|f| f.field.as_str()).collect();
+            assert_eq!(names, vec!["uuid", "siteName"]);
+        }
+        other => panic!("expected Validation, got {:?}", other),
+    }
+    // The streamed archive doesn't linger in the temp dir
+    assert!(!storage.sites.get_site_files_path_str(".upload_temp").join("only-archive.tar.gz").exists());
+
+    let res = err.into_response();
+    assert_eq!(res.status(), StatusCode::UNPROCESSABLE_ENTITY);
+    let body = axum::body::to_bytes(res.into_body(), usize::MAX).await.unwrap();
+    let json: serde_json::Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(json["fields"].as_array().unwrap().len(), 2);
+}
+
+#[tokio::test]
+async fn test_upload_identical_archive_is_deduplicated() {
+    let (storage, temp) = create_test_storage().await;
+    let storage = Arc::new(storage);
+    let config = Arc::new(Config::default());
+
+    let user = User::new("dedup".to_string(), "pass".to_string());
+    let user_id = user.id;
+    storage.users.create(user).await.expect("Failed to create user");
+
+    let first_id = Uuid::new_v4();
+    let archive_bytes = std::fs::read(create_test_archive_file(temp.path(), &first_id)).unwrap();
+
+    let upload = |site_id: Uuid| {
+        let storage = storage.clone();
+        let config = config.clone();
+        let archive_bytes = archive_bytes.clone();
+        async move {
+            let multipart = build_multipart(&[
+                ("uuid", None, site_id.to_string().into_bytes()),
+                ("siteName", None, b"dedup-site".to_vec()),
+                ("site", Some("site.tar.gz"), archive_bytes),
+            ]).await;
+            let auth = AuthenticatedUser(AuthUser { id: user_id, username: "dedup".to_string(), exp: usize::MAX });
+            upload_site(State((storage, config)), auth, UploadOrigin::default(), multipart).await.expect("upload_site failed").0
+        }
+    };
+
+    let first = upload(first_id).await;
+    assert_eq!(first.deduplicated, None);
+
+    let second_id = Uuid::new_v4();
+    let second = upload(second_id).await;
+    assert_eq!(second.deduplicated, Some(true));
+    assert_eq!(second.id, first_id, "dedup should return the existing version");
+
+    let versions = storage.sites.get_all_by_name("dedup-site").await.unwrap();
+    assert_eq!(versions.len(), 1);
+    assert!(versions[0].content_hash.is_some());
+    assert!(!storage.sites.get_site_files_path(second_id).exists());
+}
+
+async fn upload_version(storage: &Arc<Storage>, temp: &std::path::Path, user_id: Uuid, mode: Option<&str>) -> Result<SiteResponse, AppError> {
+    let site_id = Uuid::new_v4();
+    let archive_bytes = std::fs::read(create_test_archive_file(temp, &site_id)).unwrap();
+    let mut parts = vec![
+        ("uuid", None, site_id.to_string().into_bytes()),
+        ("siteName", None, b"shared-name".to_vec()),
+        ("site", Some("site.tar.gz"), archive_bytes),
+    ];
+    if let Some(mode) = mode {
+        parts.push(("mode", None, mode.as_bytes().to_vec()));
+    }
+    let multipart = build_multipart(&parts).await;
+    let auth = AuthenticatedUser(AuthUser { id: user_id, username: "creator".to_string(), exp: usize::MAX });
+    upload_site(State((storage.clone(), Arc::new(Config::default()))), auth, UploadOrigin::default(), multipart).await.map(|res| res.0)
+}
+
+#[tokio::test]
+async fn test_upload_site_create_only_rejects_existing_name() {
+    let (storage, temp) = create_test_storage().await;
+    let storage = Arc::new(storage);
+    let user_id = Uuid::new_v4();
+
+    // create-only succeeds while the name is free
+    upload_version(&storage, temp.path(), user_id, Some("create")).await.expect("first create failed");
+
+    // ...and refuses afterwards, even for the same owner
+    let err = upload_version(&storage, temp.path(), user_id, Some("create")).await.unwrap_err();
+    assert!(matches!(err, AppError::SiteNameConflict(ref name) if name == "shared-name"), "got {:?}", err);
+    assert_eq!(storage.sites.get_all_by_name("shared-name").await.unwrap().len(), 1);
+
+    let err = upload_version(&storage, temp.path(), user_id, Some("replace")).await.unwrap_err();
+    assert!(matches!(err, AppError::InvalidInput(_)), "got {:?}", err);
+}
+
+#[tokio::test]
+async fn test_upload_site_default_mode_adds_version() {
+    let (storage, temp) = create_test_storage().await;
+    let storage = Arc::new(storage);
+    let user_id = Uuid::new_v4();
+
+    upload_version(&storage, temp.path(), user_id, None).await.expect("first upload failed");
+    upload_version(&storage, temp.path(), user_id, None).await.expect("re-upload failed");
+    upload_version(&storage, temp.path(), user_id, Some("version")).await.expect("explicit version upload failed");
+
+    assert_eq!(storage.sites.get_all_by_name("shared-name").await.unwrap().len(), 3);
+}
+
+#[tokio::test]
+async fn test_upload_site_rejects_uuid_already_in_use() {
+    let (storage, temp) = create_test_storage().await;
+    let storage = Arc::new(storage);
+    let config = Arc::new(Config::default());
+    let owner = AuthenticatedUser(AuthUser { id: Uuid::new_v4(), username: "first".to_string(), exp: usize::MAX });
+    let site_id = Uuid::new_v4();
+    let archive_bytes = std::fs::read(create_test_archive_file(temp.path(), &site_id)).unwrap();
+
+    let parts = |name: &str| vec![
+        ("uuid", None, site_id.to_string().into_bytes()),
+        ("siteName", None, name.as_bytes().to_vec()),
+        ("site", Some("site.tar.gz"), archive_bytes.clone()),
+    ];
+    let multipart = build_multipart(&parts("first-owner")).await;
+    let first = upload_site(State((storage.clone(), config.clone())), owner, UploadOrigin::default(), multipart)
+        .await
+        .expect("first upload failed");
+    assert_eq!(first.0.id, site_id);
+
+    // Someone else reusing the uuid is refused before anything is extracted
+    let other = AuthenticatedUser(AuthUser { id: Uuid::new_v4(), username: "second".to_string(), exp: usize::MAX });
+    let multipart = build_multipart(&parts("orphan-check")).await;
+    let err = upload_site(State((storage.clone(), config)), other, UploadOrigin::default(), multipart)
+        .await
+        .unwrap_err();
+    assert!(matches!(err, AppError::InvalidInput(_)), "got {:?}", err);
+
+    assert_eq!(storage.sites.get(site_id).await.unwrap().unwrap().name, "first-owner");
+    assert!(storage.sites.get_site_files_path(site_id).join("index.html").exists(), "existing UUID dir should stay");
+    assert!(storage.sites.get_site_files_path_str("first-owner").join("index.html").exists());
+    assert!(!storage.sites.get_site_files_path_str("orphan-check").exists());
+}
+
+#[tokio::test]
+async fn test_upload_site_restores_previous_version_when_record_write_fails() {
+    let (storage, temp) = create_test_storage_with(|c| c.max_concurrent_extractions = 1).await;
+    let storage = Arc::new(storage);
+    let config = Arc::new(Config::default());
+    let user_id = Uuid::new_v4();
+
+    let upload = |site_id: Uuid, dir: &str| {
+        let archive_dir = temp.path().join(dir);
+        std::fs::create_dir_all(&archive_dir).unwrap();
+        let archive = std::fs::read(create_test_archive_file(&archive_dir, &site_id)).unwrap();
+        let (storage, config) = (storage.clone(), config.clone());
+        tokio::spawn(async move {
+            let multipart = build_multipart(&[
+                ("uuid", None, site_id.to_string().into_bytes()),
+                ("siteName", None, b"orphan-check".to_vec()),
+                ("site", Some("site.tar.gz"), archive),
+            ]).await;
+            let auth = AuthenticatedUser(AuthUser { id: user_id, username: "orphan".to_string(), exp: usize::MAX });
+            upload_site(State((storage, config)), auth, UploadOrigin::default(), multipart).await.map(|res| res.0)
+        })
+    };
+    let previous = upload(Uuid::new_v4(), "v1").await.unwrap().expect("first version failed");
+
+    // The second version passes the uuid check, then waits for an extraction slot while
+    // another request takes its uuid, so the record write fails once the files are in place
+    let held = storage.extractions.acquire().await.unwrap();
+    let site_id = Uuid::new_v4();
+    let second = upload(site_id, "v2");
+    for _ in 0..100 {
+        if storage.uploads.in_flight(user_id) == 1 {
+            break;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+    }
+    tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+    storage.sites.create(Site::new(site_id, Uuid::new_v4(), "first-owner".to_string(), "d".to_string())).await.unwrap();
+    drop(held);
+    assert!(second.await.unwrap().is_err(), "duplicate id insert should fail");
+
+    assert_eq!(storage.sites.get(site_id).await.unwrap().unwrap().name, "first-owner");
+    assert!(!storage.sites.get_site_files_path(site_id).exists(), "UUID dir should be removed");
+    let index = std::fs::read_to_string(storage.sites.get_site_files_path_str("orphan-check").join("index.html"))
+        .expect("siteName dir should still serve the previous version");
+    assert!(index.contains("/sites/orphan-check/"));
+    assert_eq!(storage.sites.get_latest_by_name("orphan-check").await.unwrap().unwrap().id, previous.id);
+}
+
+#[tokio::test]
+async fn test_upload_site_keeps_live_name_dir_when_swap_fails() {
+    let (storage, temp) = create_test_storage().await;
+    let storage = Arc::new(storage);
+    let config = Arc::new(Config::default());
+    let user_id = Uuid::new_v4();
+
+    let upload = |site_id: Uuid, dir: &str| {
+        let archive_dir = temp.path().join(dir);
+        std::fs::create_dir_all(&archive_dir).unwrap();
+        let archive = std::fs::read(create_test_archive_file(&archive_dir, &site_id)).unwrap();
+        let (storage, config) = (storage.clone(), config.clone());
+        async move {
+            let multipart = build_multipart(&[
+                ("uuid", None, site_id.to_string().into_bytes()),
+                ("siteName", None, b"swap-check".to_vec()),
+                ("site", Some("site.tar.gz"), archive),
+            ]).await;
+            let auth = AuthenticatedUser(AuthUser { id: user_id, username: "swap".to_string(), exp: usize::MAX });
+            upload_site(State((storage, config)), auth, UploadOrigin::default(), multipart).await.map(|res| res.0)
+        }
+    };
+    let previous = upload(Uuid::new_v4(), "v1").await.expect("first version failed");
+
+    // A non-empty directory where the live copy would be moved aside makes the swap fail
+    // after both copies are built
+    let site_id = Uuid::new_v4();
+    let blocker = storage.sites.get_site_files_path_str(&format!(".extract_temp_{}", site_id)).join("previous");
+    std::fs::create_dir_all(&blocker).unwrap();
+    std::fs::write(blocker.join("keep"), b"x").unwrap();
+    assert!(upload(site_id, "v2").await.is_err(), "swap should fail");
+
+    assert!(storage.sites.get(site_id).await.unwrap().is_none());
+    assert!(!storage.sites.get_site_files_path(site_id).exists(), "UUID dir should be removed");
+    let index = std::fs::read_to_string(storage.sites.get_site_files_path_str("swap-check").join("index.html"))
+        .expect("siteName dir should still serve the previous version");
+    assert!(index.contains("/sites/swap-check/"));
+    assert_eq!(storage.sites.get_latest_by_name("swap-check").await.unwrap().unwrap().id, previous.id);
+}
+
+#[tokio::test]
+async fn test_concurrent_uploads_with_the_same_filename_do_not_collide() {
+    let (storage, temp) = create_test_storage().await;
+    let storage = Arc::new(storage);
+    let config = Arc::new(Config::default());
+    let user_id = Uuid::new_v4();
+
+    let upload = |site_id: Uuid, name: &'static str| {
+        let archive_dir = temp.path().join(name);
+        std::fs::create_dir_all(&archive_dir).unwrap();
+        let archive = std::fs::read(create_test_archive_file(&archive_dir, &site_id)).unwrap();
+        let (storage, config) = (storage.clone(), config.clone());
+        tokio::spawn(async move {
+            let multipart = build_multipart(&[
+                ("uuid", None, site_id.to_string().into_bytes()),
+                ("siteName", None, name.as_bytes().to_vec()),
+                ("site", Some("site.tar.gz"), archive),
+            ]).await;
+            let auth = AuthenticatedUser(AuthUser { id: user_id, username: "twins".to_string(), exp: usize::MAX });
+            upload_site(State((storage, config)), auth, UploadOrigin::default(), multipart).await.map(|res| res.0)
+        })
+    };
+
+    // Both archives are staged before either is extracted
+    let held = storage.extractions.acquire().await.unwrap();
+    let (first_id, second_id) = (Uuid::new_v4(), Uuid::new_v4());
+    let first = upload(first_id, "twin-a");
+    let second = upload(second_id, "twin-b");
+    for _ in 0..100 {
+        if storage.uploads.in_flight(user_id) == 2 {
+            break;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+    }
+    tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+    drop(held);
+
+    assert_eq!(first.await.unwrap().expect("first upload failed").id, first_id);
+    assert_eq!(second.await.unwrap().expect("second upload failed").id, second_id);
+    for (id, name) in [(first_id, "twin-a"), (second_id, "twin-b")] {
+        let original = std::fs::read_to_string(storage.sites.get_site_files_path(id).join("index.html")).unwrap();
+        assert!(original.contains(&format!("/sites/{}/", id)), "{} got the other archive", name);
+    }
+}
+
+// ===== description sanitizing Tests =====
+
+#[test]
+fn test_sanitize_text_strips_controls_and_bounds_length() {
+    assert_eq!(sanitize_text("  my\u{0}\u{7} notes\r\n\tfor   the\u{1b}[31m team  ", 100), "my notes for the[31m team");
+    assert_eq!(sanitize_text("\n\t  ", 100), "");
+
+    let long = "word ".repeat(300);
+    let bounded = sanitize_text(&long, MAX_DESCRIPTION_LEN);
+    assert!(bounded.chars().count() <= MAX_DESCRIPTION_LEN);
+    assert!(!bounded.ends_with(' '));
+
+    // Bounded by chars, never splitting a multi-byte character
+    assert_eq!(sanitize_text("笔记笔记笔记", 4), "笔记笔记");
+}
+
+#[tokio::test]
+async fn test_update_site_persists_sanitized_description() {
+    let (storage, _temp) = create_test_storage().await;
+    let storage = Arc::new(storage);
+    let user_id = Uuid::new_v4();
+    let site = Site::new(Uuid::new_v4(), user_id, "clean-me".to_string(), "old".to_string());
+    let site_id = site.id;
+    storage.sites.create(site).await.unwrap();
+
+    let auth = AuthenticatedUser(AuthUser { id: user_id, username: "cleaner".to_string(), exp: usize::MAX });
+    let req = UpdateSiteRequest { description: format!("<b>new</b>\u{0}\n\n  text{}", "x".repeat(1000)), spa_mode: None };
+    let res = update_site(State((storage.clone(), Arc::new(Config::default()))), Path(site_id), auth, JsonBody(req))
+        .await
+        .expect("update_site failed");
+
+    let stored = storage.sites.get(site_id).await.unwrap().unwrap().description;
+    assert_eq!(stored, res.0.description);
+    assert!(stored.starts_with("<b>new</b> text"));
+    assert!(!stored.contains('\u{0}') && !stored.contains('\n'));
+    assert_eq!(stored.chars().count(), MAX_DESCRIPTION_LEN);
+}
+
+// ===== patch_site Tests =====
+
+#[tokio::test]
+async fn test_patch_site_only_changes_present_fields() {
+    let (storage, _temp) = create_test_storage().await;
+    let storage = Arc::new(storage);
+    let user_id = Uuid::new_v4();
+    let site = Site::new(Uuid::new_v4(), user_id, "patch-me".to_string(), "keep this".to_string());
+    let site_id = site.id;
+    storage.sites.create(site).await.unwrap();
+    let config = Arc::new(Config::default());
+
+    let req: PatchSiteRequest = serde_json::from_str(r#"{ "spaMode": true }"#).unwrap();
+    let auth = AuthenticatedUser(AuthUser { id: user_id, username: "patcher".to_string(), exp: usize::MAX });
+    let res = patch_site(State((storage.clone(), config.clone())), Path(site_id), auth, JsonBody(req))
+        .await
+        .expect("patch_site failed");
+    assert!(res.0.spa_mode);
+
+    let stored = storage.sites.get(site_id).await.unwrap().unwrap();
+    assert!(stored.spa_mode);
+    assert_eq!(stored.description, "keep this");
+
+    // An empty patch changes nothing, and other users still can't patch
+    let auth = AuthenticatedUser(AuthUser { id: user_id, username: "patcher".to_string(), exp: usize::MAX });
+    let unchanged = patch_site(State((storage.clone(), config.clone())), Path(site_id), auth, JsonBody(PatchSiteRequest::default()))
+        .await
+        .expect("empty patch failed")
+        .0;
+    assert_eq!(unchanged.id, site_id);
+    let stored = storage.sites.get(site_id).await.unwrap().unwrap();
+    assert!(stored.spa_mode);
+    assert_eq!(stored.description, "keep this");
+
+    let stranger = AuthenticatedUser(AuthUser { id: Uuid::new_v4(), username: "x".to_string(), exp: usize::MAX });
+    let req = PatchSiteRequest { description: Some("mine now".to_string()), spa_mode: None, auto_sitemap: None, custom_headers: None };
+    let res = patch_site(State((storage.clone(), config)), Path(site_id), stranger, JsonBody(req)).await;
+    assert!(matches!(res, Err(AppError::AuthorizationFailed)));
+}
+
+// ===== delete_sites_by_name Tests =====
+
+#[tokio::test]
+async fn test_delete_sites_by_name_only_touches_own_versions() {
+    let (storage, _temp) = create_test_storage().await;
+    let storage = Arc::new(storage);
+
+    let owner = User::new("owner".to_string(), "pass".to_string());
+    let owner_id = owner.id;
+    storage.users.create(owner).await.expect("Failed to create owner");
+    let other = User::new("other".to_string(), "pass".to_string());
+    let other_id = other.id;
+    storage.users.create(other).await.expect("Failed to create other");
+
+    let site_name = "shared-name".to_string();
+    let mut own_ids = Vec::new();
+    for i in 0..3 {
+        let mut site = Site::new(Uuid::new_v4(), owner_id, site_name.clone(), format!("v{}", i));
+        site.created_at = chrono::Utc::now() - chrono::Duration::hours(3 - i);
+        own_ids.push(site.id);
+        std::fs::create_dir_all(storage.sites.get_site_files_path(site.id)).unwrap();
+        storage.sites.create(site).await.expect("Failed to create own site");
+    }
+    let foreign = Site::new(Uuid::new_v4(), other_id, site_name.clone(), "foreign".to_string());
+    let foreign_id = foreign.id;
+    storage.sites.create(foreign).await.expect("Failed to create foreign site");
+
+    let auth = AuthenticatedUser(AuthUser { id: owner_id, username: "owner".to_string(), exp: usize::MAX });
+    let res = delete_sites_by_name(
+        State((storage.clone(), Arc::new(Config::default()))),
+        Path(site_name.clone()),
+        auth,
+    )
+    .await
+    .expect("delete_sites_by_name failed");
+    assert_eq!(res.0["deleted"], 3);
+
+    for id in own_ids {
+        assert!(storage.sites.get(id).await.unwrap().is_none(), "own version should be deleted");
+        assert!(!storage.sites.get_site_files_path(id).exists(), "own UUID dir should be removed");
+    }
+    let remaining = storage.sites.get_all_by_name(&site_name).await.unwrap();
+    assert_eq!(remaining.len(), 1);
+    assert_eq!(remaining[0].id, foreign_id);
+}
+
+#[tokio::test]
+async fn test_delete_my_sites_only_removes_callers_sites() {
+    let (storage, _temp) = create_test_storage().await;
+    let storage = Arc::new(storage);
+    let (me, other) = (Uuid::new_v4(), Uuid::new_v4());
+
+    let mut mine = Vec::new();
+    let mut theirs = Vec::new();
+    for (owner, name) in [(me, "blog"), (me, "blog"), (me, "notes"), (other, "blog"), (other, "other-site")] {
+        let site = Site::new(Uuid::new_v4(), owner, name.to_string(), "d".to_string());
+        for dir in [storage.sites.get_site_files_path(site.id), storage.sites.get_site_files_path_str(name)] {
+            std::fs::create_dir_all(&dir).unwrap();
+            std::fs::write(dir.join("index.html"), b"<p></p>").unwrap();
+        }
+        if owner == me { mine.push(site.id) } else { theirs.push(site.id) }
+        storage.sites.create(site).await.unwrap();
+    }
+
+    let auth = || AuthenticatedUser(AuthUser { id: me, username: "me".to_string(), exp: usize::MAX });
+    let res = delete_my_sites(State((storage.clone(), Arc::new(Config::default()))), auth())
+        .await
+        .expect("delete_my_sites failed");
+    assert_eq!(res.0["deleted"], 3);
+
+    for id in mine {
+        assert!(storage.sites.get(id).await.unwrap().is_none());
+        assert!(!storage.sites.get_site_files_path(id).exists());
+    }
+    assert!(!storage.sites.get_site_files_path_str("notes").exists());
+    for id in &theirs {
+        assert!(storage.sites.get(*id).await.unwrap().is_some(), "other user's site was deleted");
+        assert!(storage.sites.get_site_files_path(*id).is_dir());
+    }
+    // Still serving the other user's version of the shared name
+    assert!(storage.sites.get_site_files_path_str("blog").is_dir());
+    assert_eq!(storage.sites.list_by_owner(other).await.unwrap().len(), 2);
+
+    let res = delete_my_sites(State((storage.clone(), Arc::new(Config::default()))), auth()).await.unwrap();
+    assert_eq!(res.0["deleted"], 0);
+}
+
+#[tokio::test]
+async fn test_delete_site_removes_name_dir_with_last_version() {
+    let (storage, temp) = create_test_storage().await;
+    let storage = Arc::new(storage);
+    let user_id = Uuid::new_v4();
+    let auth = || AuthenticatedUser(AuthUser { id: user_id, username: "creator".to_string(), exp: usize::MAX });
+    let state = || State((storage.clone(), Arc::new(Config::default())));
+
+    let first = upload_version(&storage, temp.path(), user_id, None).await.expect("first upload failed");
+    let second = upload_version(&storage, temp.path(), user_id, None).await.expect("second upload failed");
+    let name_dir = storage.sites.get_site_files_path_str("shared-name");
+    assert!(name_dir.is_dir());
+
+    // Another version still uses the name directory
+    let res = delete_site(state(), Path(first.id), auth()).await.expect("deleting the first version failed").0;
+    assert_eq!(res["message"], "Site deleted successfully");
+    assert!(!storage.sites.get_site_files_path(first.id).exists());
+    assert!(name_dir.is_dir(), "name dir is still shared with the second version");
+
+    let res = delete_site(state(), Path(second.id), auth()).await.expect("deleting the last version failed").0;
+    assert_eq!(res["message"], "Site deleted successfully");
+    assert!(!storage.sites.get_site_files_path(second.id).exists());
+    assert!(!name_dir.exists(), "unreferenced name dir should be removed");
+    assert!(storage.sites.get_all_by_name("shared-name").await.unwrap().is_empty());
+}
+
+// ===== resolve_site_name Tests =====
+
+#[tokio::test]
+async fn test_resolve_site_name_scoped_to_owner() {
+    let (storage, _temp) = create_test_storage().await;
+    let storage = Arc::new(storage);
+    let config = Arc::new(Config::default());
+
+    let owner = User::new("resolver".to_string(), "pass".to_string());
+    let owner_id = owner.id;
+    storage.users.create(owner).await.expect("Failed to create owner");
+    let other = User::new("stranger".to_string(), "pass".to_string());
+    let other_id = other.id;
+    storage.users.create(other).await.expect("Failed to create other");
+
+    let mut old = Site::new(Uuid::new_v4(), owner_id, "mine".to_string(), "v1".to_string());
+    old.created_at = chrono::Utc::now() - chrono::Duration::hours(1);
+    let old_id = old.id;
+    storage.sites.create(old).await.unwrap();
+    let new = Site::new(Uuid::new_v4(), owner_id, "mine".to_string(), "v2".to_string());
+    let new_id = new.id;
+    storage.sites.create(new).await.unwrap();
+    storage.sites.create(Site::new(Uuid::new_v4(), other_id, "theirs".to_string(), "x".to_string())).await.unwrap();
+
+    let query = |name: &str| -> Query<HashMap<String, String>> {
+        Query([("name".to_string(), name.to_string())].into_iter().collect())
+    };
+    let auth = || AuthenticatedUser(AuthUser { id: owner_id, username: "resolver".to_string(), exp: usize::MAX });
+
+    let res = resolve_site_name(State((storage.clone(), config.clone())), query("mine"), auth())
+        .await
+        .expect("resolve own name failed");
+    assert_eq!(res.0.latest, new_id);
+    assert_eq!(res.0.versions, vec![new_id, old_id]);
+
+    let foreign = resolve_site_name(State((storage.clone(), config.clone())), query("theirs"), auth()).await;
+    assert!(foreign.is_err(), "foreign name should not be resolvable by a non-admin");
+
+    // Admins by role resolve any name
+    let mut admin = User::new("resolve-admin".to_string(), "pass".to_string());
+    admin.roles = vec![ADMIN_ROLE.to_string()];
+    let admin_auth = AuthenticatedUser(AuthUser { id: admin.id, username: admin.username.clone(), exp: usize::MAX });
+    storage.users.create(admin).await.expect("Failed to create admin");
+    let res = resolve_site_name(State((storage.clone(), config.clone())), query("theirs"), admin_auth)
+        .await
+        .expect("admin resolve failed");
+    assert_eq!(res.0.versions.len(), 1);
+}
+
+#[tokio::test]
+async fn test_rebuild_regenerates_site_name_dir() {
+    let (storage, temp) = create_test_storage().await;
+    let storage = Arc::new(storage);
+    let config = Arc::new(Config::default());
+    let owner_id = Uuid::new_v4();
+    let auth = |id: Uuid| AuthenticatedUser(AuthUser { id, username: "rebuilder".to_string(), exp: usize::MAX });
+
+    let mut versions = Vec::new();
+    for _ in 0..2 {
+        let site_id = Uuid::new_v4();
+        let archive_bytes = std::fs::read(create_test_archive_file(temp.path(), &site_id)).unwrap();
+        let multipart = build_multipart(&[
+            ("uuid", None, site_id.to_string().into_bytes()),
+            ("siteName", None, b"rebuilt".to_vec()),
+            ("site", Some("site.tar.gz"), archive_bytes),
+        ]).await;
+        let res = upload_site(State((storage.clone(), config.clone())), auth(owner_id), UploadOrigin::default(), multipart)
+            .await
+            .expect("upload_site failed")
+            .0;
+        assert_eq!(res.id, site_id);
+        versions.push(site_id);
+        tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+    }
+    let (old, latest) = (versions[0], versions[1]);
+    let rebuild = |id: Uuid, user: Uuid, key: Option<&str>| {
+        let params: HashMap<String, String> = key.map(|k| ("key".to_string(), k.to_string())).into_iter().collect();
+        rebuild_site(State((storage.clone(), config.clone())), Path(id), Query(params), auth(user))
+    };
+
+    let name_dir = storage.sites.get_site_files_path_str("rebuilt");
+    std::fs::remove_dir_all(&name_dir).unwrap();
+
+    // Only the owner (or an admin) may rebuild, and only the version the name points at
+    let stranger = Uuid::new_v4();
+    assert!(matches!(rebuild(latest, stranger, None).await, Err(AppError::AuthorizationFailed)));
+    assert!(matches!(rebuild(old, owner_id, None).await, Err(AppError::InvalidInput(_))));
+    assert!(!name_dir.exists());
+
+    let res = rebuild(latest, owner_id, None).await.expect("rebuild failed").0;
+    assert_eq!(res.id, latest);
+    let html = std::fs::read_to_string(name_dir.join("index.html")).expect("index.html not rebuilt");
+    assert!(html.contains("/sites/rebuilt/page.html"), "got {}", html);
+    assert!(!html.contains(&latest.to_string()));
+
+    // The UUID directory keeps the original links
+    let original = std::fs::read_to_string(storage.sites.get_site_files_path(latest).join("index.html")).unwrap();
+    assert!(original.contains(&format!("/sites/{}/page.html", latest)));
+
+    let res = rebuild(latest, stranger, Some(&config.server.jwt_secret)).await.expect("admin rebuild failed").0;
+    assert_eq!(res.id, latest);
+    assert!(name_dir.join("index.html").exists());
+    assert!(!storage.sites.get_site_files_path_str(&format!(".rebuild_temp_{}", latest)).exists());
+
+    // Users holding the admin role count as admins too
+    let mut admin = User::new("rebuild-admin".to_string(), "pass".to_string());
+    admin.roles = vec![ADMIN_ROLE.to_string()];
+    let admin_id = admin.id;
+    storage.users.create(admin).await.expect("Failed to create admin");
+    rebuild(latest, admin_id, None).await.expect("role admin rebuild failed");
+
+    // On a private server the key is no admin pass
+    let mut private = (*config).clone();
+    private.server.require_auth_for_listing = true;
+    let params: HashMap<String, String> = [("key".to_string(), config.server.jwt_secret.clone())].into_iter().collect();
+    let res = rebuild_site(State((storage.clone(), Arc::new(private))), Path(latest), Query(params), auth(stranger)).await;
+    assert!(matches!(res, Err(AppError::AuthorizationFailed)));
+}
+
+async fn rename(storage: &Arc<Storage>, site_id: Uuid, user_id: Uuid, new_name: &str) -> Result<SiteResponse, AppError> {
+    let auth = AuthenticatedUser(AuthUser { id: user_id, username: "creator".to_string(), exp: usize::MAX });
+    let req = JsonBody(RenameSiteRequest { new_name: new_name.to_string() });
+    rename_site(State((storage.clone(), Arc::new(Config::default()))), Path(site_id), Query(HashMap::new()), auth, req).await.map(|res| res.0)
+}
+
+#[tokio::test]
+async fn test_rename_site_moves_name_dir() {
+    let (storage, temp) = create_test_storage().await;
+    let storage = Arc::new(storage);
+    let owner_id = Uuid::new_v4();
+    let site = upload_version(&storage, temp.path(), owner_id, None).await.expect("upload failed");
+
+    // Only the owner may rename
+    assert!(matches!(rename(&storage, site.id, Uuid::new_v4(), "renamed").await, Err(AppError::AuthorizationFailed)));
+    assert!(rename(&storage, site.id, owner_id, "bad name!").await.is_err());
+
+    let res = rename(&storage, site.id, owner_id, "renamed").await.expect("rename failed");
+    assert_eq!(res.id, site.id);
+    assert_eq!(res.name, "renamed");
+    assert_eq!(storage.sites.get(site.id).await.unwrap().unwrap().name, "renamed");
+    assert!(storage.sites.get_all_by_name("shared-name").await.unwrap().is_empty());
+
+    let html = std::fs::read_to_string(storage.sites.get_site_files_path_str("renamed").join("index.html")).unwrap();
+    assert!(html.contains("/sites/renamed/page.html"), "got {}", html);
+    assert!(!storage.sites.get_site_files_path_str("shared-name").exists());
+    assert!(storage.sites.get_site_files_path(site.id).is_dir());
+}
+
+#[tokio::test]
+async fn test_rename_site_by_admin() {
+    let (storage, temp) = create_test_storage().await;
+    let storage = Arc::new(storage);
+    let site = upload_version(&storage, temp.path(), Uuid::new_v4(), None).await.expect("upload failed");
+
+    let mut admin = User::new("rename-admin".to_string(), "pass".to_string());
+    admin.roles = vec![ADMIN_ROLE.to_string()];
+    let admin_id = admin.id;
+    storage.users.create(admin).await.expect("Failed to create admin");
+    let res = rename(&storage, site.id, admin_id, "admin-renamed").await.expect("role admin rename failed");
+    assert_eq!(res.name, "admin-renamed");
+
+    // The key is refused once the server is private
+    let mut private = Config::default();
+    private.server.require_auth_for_listing = true;
+    let params: HashMap<String, String> = [("key".to_string(), private.server.jwt_secret.clone())].into_iter().collect();
+    let auth = AuthenticatedUser(AuthUser { id: Uuid::new_v4(), username: "keyholder".to_string(), exp: usize::MAX });
+    let req = JsonBody(RenameSiteRequest { new_name: "key-renamed".to_string() });
+    let res = rename_site(State((storage.clone(), Arc::new(private))), Path(site.id), Query(params), auth, req).await;
+    assert!(matches!(res, Err(AppError::AuthorizationFailed)));
+}
+
+#[tokio::test]
+async fn test_rename_site_rejects_existing_name() {
+    let (storage, temp) = create_test_storage().await;
+    let storage = Arc::new(storage);
+    let owner_id = Uuid::new_v4();
+    let site = upload_version(&storage, temp.path(), owner_id, None).await.expect("upload failed");
+
+    let other_id = Uuid::new_v4();
+    let archive_bytes = std::fs::read(create_test_archive_file(temp.path(), &other_id)).unwrap();
+    let multipart = build_multipart(&[
+        ("uuid", None, other_id.to_string().into_bytes()),
+        ("siteName", None, b"taken".to_vec()),
+        ("site", Some("other.tar.gz"), archive_bytes),
+    ]).await;
+    let auth = AuthenticatedUser(AuthUser { id: owner_id, username: "creator".to_string(), exp: usize::MAX });
+    let other = upload_site(State((storage.clone(), Arc::new(Config::default()))), auth, UploadOrigin::default(), multipart)
+        .await
+        .expect("upload failed")
+        .0;
+    assert_eq!(other.id, other_id);
+
+    let err = rename(&storage, site.id, owner_id, "taken").await.unwrap_err();
+    assert!(matches!(err, AppError::SiteNameConflict(ref name) if name == "taken"), "got {:?}", err);
+    assert_eq!(storage.sites.get(site.id).await.unwrap().unwrap().name, "shared-name");
+    assert_eq!(storage.sites.get_all_by_name("taken").await.unwrap().len(), 1);
+    let html = std::fs::read_to_string(storage.sites.get_site_files_path_str("taken").join("index.html")).unwrap();
+    assert!(html.contains("/sites/taken/page.html"), "got {}", html);
+}
+
+#[tokio::test]
+async fn test_rename_site_keeps_other_versions() {
+    let (storage, temp) = create_test_storage().await;
+    let storage = Arc::new(storage);
+    let owner_id = Uuid::new_v4();
+    let older = upload_version(&storage, temp.path(), owner_id, None).await.expect("upload failed");
+    tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+    let latest = upload_version(&storage, temp.path(), owner_id, None).await.expect("upload failed");
+
+    // Renaming the latest version hands the old name back to the remaining one
+    rename(&storage, latest.id, owner_id, "split-off").await.expect("rename failed");
+
+    let remaining = storage.sites.get_all_by_name("shared-name").await.unwrap();
+    assert_eq!(remaining.iter().map(|s| s.id).collect::<Vec<_>>(), vec![older.id]);
+    let old_dir = storage.sites.get_site_files_path_str("shared-name");
+    let html = std::fs::read_to_string(old_dir.join("index.html")).expect("old name dir removed");
+    assert!(html.contains("/sites/shared-name/page.html"), "got {}", html);
+    assert!(storage.sites.get_site_files_path(older.id).is_dir());
+
+    let html = std::fs::read_to_string(storage.sites.get_site_files_path_str("split-off").join("index.html")).unwrap();
+    assert!(html.contains("/sites/split-off/page.html"), "got {}", html);
+}
+
+#[tokio::test]
+async fn test_bulk_site_stats_only_covers_own_sites() {
+    let (storage, _temp) = create_test_storage().await;
+    let storage = Arc::new(storage);
+    let config = Arc::new(Config::default());
+    let (owner_id, other_id) = (Uuid::new_v4(), Uuid::new_v4());
+
+    let mut sites = Vec::new();
+    for (owner, name, hours_ago) in [(owner_id, "docs", 2), (owner_id, "docs", 1), (owner_id, "blog", 1), (other_id, "theirs", 1)] {
+        let mut site = Site::new(Uuid::new_v4(), owner, name.to_string(), "d".to_string());
+        site.created_at = chrono::Utc::now() - chrono::Duration::hours(hours_ago);
+        let dir = storage.sites.get_site_files_path(site.id);
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("index.html"), "<p>hello</p>").unwrap();
+        std::fs::write(dir.join("page.html"), "<p>page</p>").unwrap();
+        storage.sites.create(site.clone()).await.unwrap();
+        sites.push(site);
+    }
+    let ids: Vec<Uuid> = sites.iter().map(|s| s.id).collect();
+    let stats = |user: Uuid, key: Option<&str>, ids: Vec<Uuid>| {
+        let params: HashMap<String, String> = key.map(|k| ("key".to_string(), k.to_string())).into_iter().collect();
+        let auth = AuthenticatedUser(AuthUser { id: user, username: "dash".to_string(), exp: usize::MAX });
+        bulk_site_stats(State((storage.clone(), config.clone())), Query(params), auth, JsonBody(BulkSiteStatsRequest { ids }))
+    };
+
+    let res = stats(owner_id, None, ids.clone()).await.expect("bulk stats failed").0;
+    assert_eq!(res.len(), 3, "foreign site should be omitted");
+    assert!(!res.contains_key(&sites[3].id));
+    let older = &res[&sites[0].id];
+    assert_eq!((older.size_bytes, older.file_count), (23, 2));
+    assert_eq!(older.version_count, 2);
+    assert_eq!(older.last_modified, sites[1].created_at);
+    assert_eq!(res[&sites[2].id].version_count, 1);
+
+    // Admins see everything, whether by key or by role
+    let res = stats(owner_id, Some(&config.server.jwt_secret), ids.clone()).await.unwrap().0;
+    assert_eq!(res.len(), 4);
+    let mut admin = User::new("stats-admin".to_string(), "pass".to_string());
+    admin.roles = vec![ADMIN_ROLE.to_string()];
+    let admin_id = admin.id;
+    storage.users.create(admin).await.expect("Failed to create admin");
+    assert_eq!(stats(admin_id, None, ids).await.unwrap().0.len(), 4);
+
+    let too_many = vec![Uuid::new_v4(); MAX_STATS_IDS + 1];
+    assert!(matches!(stats(owner_id, None, too_many).await, Err(AppError::InvalidInput(_))));
+}
+
+#[tokio::test]
+async fn test_upload_site_cancelled_mid_body_removes_partial_archive() {
+    use axum::extract::{FromRequest, Multipart};
+    use futures_util::StreamExt;
+
+    let (storage, _temp) = create_test_storage().await;
+    let storage = Arc::new(storage);
+    let site_id = Uuid::new_v4();
+    let archive: Vec<u8> = (0..256 * 1024).map(|i| (i % 251) as u8).collect();
+    let (content_type, body) = multipart_body(&[
+        ("uuid", None, site_id.to_string().into_bytes()),
+        ("siteName", None, b"cancelled".to_vec()),
+        ("site", Some("cancelled.tar.gz"), archive),
+    ]);
+    // Half the body arrives, then the client goes quiet
+    let first_half = axum::body::Bytes::from(body[..body.len() / 2].to_vec());
+    let stalled = futures_util::stream::iter([Ok::<_, std::io::Error>(first_half)]).chain(futures_util::stream::pending());
+    let req = axum::http::Request::builder()
+        .method("POST")
+        .uri("/api/sites")
+        .header("content-type", content_type)
+        .body(axum::body::Body::from_stream(stalled))
+        .unwrap();
+    let multipart = Multipart::from_request(req, &()).await.unwrap();
+
+    let user_id = Uuid::new_v4();
+    let auth = AuthenticatedUser(AuthUser { id: user_id, username: "quitter".to_string(), exp: usize::MAX });
+    let mut upload = Box::pin(upload_site(State((storage.clone(), Arc::new(Config::default()))), auth, UploadOrigin::default(), multipart));
+    // The archive is staged under a generated name, so look for any file in the temp dir
+    let temp_dir = storage.sites.get_site_files_path_str(".upload_temp");
+    let staged = || std::fs::read_dir(&temp_dir).map(|entries| entries.count()).unwrap_or(0);
+
+    let wait_for_partial = async {
+        while staged() == 0 {
+            tokio::time::sleep(std::time::Duration::from_millis(5)).await;
+        }
+    };
+    tokio::time::timeout(std::time::Duration::from_secs(10), async {
+        tokio::select! {
+            res = &mut upload => panic!("upload should be waiting for the rest of the body, got {:?}", res.map(|r| r.0.id)),
+            _ = wait_for_partial => {}
+        }
+    }).await.expect("partial archive never appeared");
+
+    // Dropping the handler future is what a disconnect or timeout does
+    drop(upload);
+    assert_eq!(staged(), 0, "partial archive should be removed on cancellation");
+    assert_eq!(storage.uploads.in_flight(user_id), 0);
+    assert!(storage.sites.get(site_id).await.unwrap().is_none());
+}
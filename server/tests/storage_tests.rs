@@ -9,9 +9,16 @@
 
 mod utils;
 
-use obsidian_publisher_server::models::{User, Site};
+use obsidian_publisher_server::{
+    config::{default_db_retries, default_db_retry_backoff_ms, ArchiveConfig, StaticStorageConfig, StorageConfig, StorageEntry, StorageRole},
+    error::AppError,
+    models::{AuditAction, AuditEvent, User, Site},
+    storage::{memory, SiteStorage, Storage, UserStorage, AUDIT_PRUNE_BATCH},
+};
+use tempfile::TempDir;
 use uuid::Uuid;
-use utils::storage::create_test_storage;
+use utils::logs::capture_logs;
+use utils::storage::{create_memory_storage, create_test_storage};
 
 #[tokio::test]
 async fn test_user_crud_lifecycle() {
@@ -361,4 +368,724 @@ async fn test_site_multiple_versions() {
     assert_eq!(all_versions[0].id, site3_id, "First should be newest (v3)");
     assert_eq!(all_versions[1].id, site2_id, "Second should be v2");
     assert_eq!(all_versions[2].id, site1_id, "Third should be oldest (v1)");
-}
\ No newline at end of file
+}
+
+#[cfg(all(feature = "sled", feature = "orm"))]
+#[tokio::test]
+async fn test_role_routing_users_sqlite_sites_sled() {
+    let temp = TempDir::new().expect("Failed to create temp dir");
+    let sqlite_dir = temp.path().join("users-sqlite");
+    let sled_dir = temp.path().join("sites-sled");
+
+    let config = StorageConfig {
+        sites: StaticStorageConfig { path: temp.path().join("sites") },
+        db: vec![
+            StorageEntry { name: Some("users".to_string()), backend: "sqlite".to_string(), path: Some(sqlite_dir.clone()), role: Some(StorageRole::Users) },
+            StorageEntry { name: Some("sites".to_string()), backend: "sled".to_string(), path: Some(sled_dir.clone()), role: Some(StorageRole::Sites) },
+        ],
+        archive: ArchiveConfig::default(),
+        keep_temp_on_error: false,
+        max_concurrent_extractions: 4,
+        max_concurrent_uploads_per_user: 2,
+        audit_retention_days: 0,
+        debug_concurrent_writes: false,
+        db_retries: default_db_retries(),
+        db_retry_backoff_ms: default_db_retry_backoff_ms(),
+    };
+    let storage = Storage::new(&config).await.expect("Failed to create storage");
+
+    assert_eq!(storage.users.backend(), "orm");
+    assert_eq!(storage.audit.backend(), "orm");
+    assert_eq!(storage.sites.backend(), "sled");
+
+    let user = User::new("routed".to_string(), "pw".to_string());
+    let user_id = user.id;
+    storage.users.create(user).await.expect("Failed to create user");
+    let site = Site::new(Uuid::new_v4(), user_id, "routed-site".to_string(), "desc".to_string());
+    storage.sites.create(site.clone()).await.expect("Failed to create site");
+
+    assert_eq!(storage.users.get(user_id).await.unwrap().unwrap().username, "routed");
+    assert_eq!(storage.sites.get(site.id).await.unwrap().unwrap().name, "routed-site");
+
+    // Users went to sqlite only; sled holds sites only
+    assert!(sqlite_dir.join("db.sqlite").exists());
+    assert!(sled_dir.exists());
+    assert!(!sled_dir.join("users.db").exists());
+}
+
+#[tokio::test]
+async fn test_unusable_storage_config_is_rejected() {
+    let temp = TempDir::new().expect("Failed to create temp dir");
+    let config = StorageConfig {
+        sites: StaticStorageConfig { path: temp.path().join("sites") },
+        db: vec![
+            StorageEntry { name: None, backend: "sled".to_string(), path: Some(temp.path().join("a")), role: None },
+            StorageEntry { name: None, backend: "sled".to_string(), path: Some(temp.path().join("b")), role: None },
+        ],
+        archive: ArchiveConfig::default(),
+        keep_temp_on_error: false,
+        max_concurrent_extractions: 4,
+        max_concurrent_uploads_per_user: 2,
+        audit_retention_days: 0,
+        debug_concurrent_writes: false,
+        db_retries: default_db_retries(),
+        db_retry_backoff_ms: default_db_retry_backoff_ms(),
+    };
+
+    let err = Storage::new(&config).await.err().expect("duplicate sled backends should be rejected");
+    match err.downcast_ref::<AppError>() {
+        Some(AppError::Config(msg)) => assert!(msg.contains("duplicate 'sled' backends"), "got {}", msg),
+        other => panic!("expected AppError::Config, got {:?}", other),
+    }
+    // Nothing should have been opened or created
+    assert!(!temp.path().join("a").exists());
+}
+
+#[tokio::test]
+async fn test_db_nested_in_sites_path_is_rejected() {
+    let temp = TempDir::new().expect("Failed to create temp dir");
+    let sites = temp.path().join("sites");
+    let config = StorageConfig {
+        sites: StaticStorageConfig { path: sites.clone() },
+        db: vec![StorageEntry { name: None, backend: "sled".to_string(), path: Some(sites.join("db")), role: None }],
+        archive: ArchiveConfig::default(),
+        keep_temp_on_error: false,
+        max_concurrent_extractions: 4,
+        max_concurrent_uploads_per_user: 2,
+        audit_retention_days: 0,
+        debug_concurrent_writes: false,
+        db_retries: default_db_retries(),
+        db_retry_backoff_ms: default_db_retry_backoff_ms(),
+    };
+
+    let err = Storage::new(&config).await.err().expect("a database inside the sites directory should be rejected");
+    match err.downcast_ref::<AppError>() {
+        Some(AppError::Config(msg)) => assert!(msg.contains("are nested"), "got {}", msg),
+        other => panic!("expected AppError::Config, got {:?}", other),
+    }
+    assert!(!sites.exists(), "nothing should have been created");
+}
+
+/// Backends compiled into this build, by their `storage.db` name
+const BACKENDS: &[&str] = &[
+    #[cfg(feature = "sled")]
+    "sled",
+    #[cfg(feature = "orm")]
+    "sqlite",
+];
+
+/// Storage whose users live only in the given backend ("sled" or "sqlite"); sites go to
+/// the other one when both are compiled in, else share it
+async fn storage_with_users_on(backend: &str, temp: &TempDir) -> Storage {
+    let users = StorageEntry { name: None, backend: backend.to_string(), path: Some(temp.path().join("users-db")), role: None };
+    let db = match BACKENDS.iter().find(|b| **b != backend) {
+        Some(other) => vec![
+            StorageEntry { role: Some(StorageRole::Users), ..users },
+            StorageEntry { name: None, backend: other.to_string(), path: Some(temp.path().join("sites-db")), role: Some(StorageRole::Sites) },
+        ],
+        None => vec![users],
+    };
+    let config = StorageConfig {
+        sites: StaticStorageConfig { path: temp.path().join("sites") },
+        db,
+        archive: ArchiveConfig::default(),
+        keep_temp_on_error: false,
+        max_concurrent_extractions: 4,
+        max_concurrent_uploads_per_user: 2,
+        audit_retention_days: 0,
+        debug_concurrent_writes: false,
+        db_retries: default_db_retries(),
+        db_retry_backoff_ms: default_db_retry_backoff_ms(),
+    };
+    Storage::new(&config).await.expect("Failed to create storage")
+}
+
+#[tokio::test]
+async fn test_duplicate_user_create_is_typed_conflict() {
+    for &backend in BACKENDS {
+        let temp = TempDir::new().expect("Failed to create temp dir");
+        let storage = storage_with_users_on(backend, &temp).await;
+
+        let created = storage.users.create(User::new("taken".to_string(), "pw".to_string())).await
+            .unwrap_or_else(|e| panic!("{}: first create failed: {:?}", backend, e));
+        assert_eq!(created.username, "taken");
+
+        let err = storage.users.create(User::new("taken".to_string(), "other".to_string())).await.unwrap_err();
+        assert!(matches!(err, AppError::UserAlreadyExists), "{}: got {:?}", backend, err);
+
+        // The original account is untouched
+        let stored = storage.users.get_by_username("taken").await.unwrap().unwrap();
+        assert_eq!(stored.id, created.id);
+        assert_eq!(storage.users.count().await.unwrap(), 1, "{}", backend);
+    }
+
+    // Default storage (both backends compared, when compiled in) reports the same conflict
+    let (storage, _temp) = create_test_storage().await;
+    storage.users.create(User::new("taken".to_string(), "pw".to_string())).await.unwrap();
+    let err = storage.users.create(User::new("taken".to_string(), "pw".to_string())).await.unwrap_err();
+    assert!(matches!(err, AppError::UserAlreadyExists), "got {:?}", err);
+}
+
+#[tokio::test]
+async fn test_email_is_unique_across_users_on_every_backend() {
+    for &backend in BACKENDS {
+        let temp = TempDir::new().expect("Failed to create temp dir");
+        let storage = storage_with_users_on(backend, &temp).await;
+
+        let mut first = User::new("first".to_string(), "pw".to_string());
+        first.email = Some("shared@example.com".to_string());
+        storage.users.create(first.clone()).await
+            .unwrap_or_else(|e| panic!("{}: first create failed: {:?}", backend, e));
+
+        // Create with a taken email fails and leaves the username free
+        let mut second = User::new("second".to_string(), "pw".to_string());
+        second.email = Some("shared@example.com".to_string());
+        let err = storage.users.create(second.clone()).await.unwrap_err();
+        assert!(matches!(err, AppError::EmailAlreadyExists), "{}: got {:?}", backend, err);
+        assert!(storage.users.get_by_username("second").await.unwrap().is_none(), "{}", backend);
+
+        // Users without an email never collide
+        second.email = None;
+        storage.users.create(second.clone()).await
+            .unwrap_or_else(|e| panic!("{}: create without email failed: {:?}", backend, e));
+        storage.users.create(User::new("third".to_string(), "pw".to_string())).await
+            .unwrap_or_else(|e| panic!("{}: second email-less create failed: {:?}", backend, e));
+
+        // Update onto a taken email fails too
+        second.email = Some("shared@example.com".to_string());
+        let err = storage.users.update(second.clone()).await.unwrap_err();
+        assert!(matches!(err, AppError::EmailAlreadyExists), "{}: got {:?}", backend, err);
+        assert!(storage.users.get(second.id).await.unwrap().unwrap().email.is_none(), "{}", backend);
+
+        // Changing the first user's email releases the old address
+        first.email = Some("moved@example.com".to_string());
+        storage.users.update(first.clone()).await.unwrap();
+        storage.users.update(second.clone()).await
+            .unwrap_or_else(|e| panic!("{}: claiming a released email failed: {:?}", backend, e));
+        assert_eq!(storage.users.get(second.id).await.unwrap().unwrap().email.as_deref(), Some("shared@example.com"));
+
+        // Deleting a user releases its address
+        storage.users.delete(first.id).await.unwrap();
+        let mut fourth = User::new("fourth".to_string(), "pw".to_string());
+        fourth.email = Some("moved@example.com".to_string());
+        storage.users.create(fourth).await
+            .unwrap_or_else(|e| panic!("{}: reusing a deleted user's email failed: {:?}", backend, e));
+    }
+}
+
+#[tokio::test]
+async fn test_list_by_owner_is_newest_first_on_every_backend() {
+    // Inserted out of order; the number is how many hours old each site is
+    let ages = [3, 0, 5, 1, 4, 2];
+    let owner_id = Uuid::new_v4();
+    let now = chrono::Utc::now();
+    let sites: Vec<Site> = ages
+        .iter()
+        .map(|&age| {
+            let mut site = Site::new(Uuid::new_v4(), owner_id, format!("aged-{}", age), "d".to_string());
+            site.created_at = now - chrono::Duration::hours(age);
+            site
+        })
+        .collect();
+    let mut expected: Vec<&Site> = sites.iter().collect();
+    expected.sort_by_key(|s| std::cmp::Reverse(s.created_at));
+    let expected: Vec<Uuid> = expected.iter().map(|s| s.id).collect();
+
+    // Sites on each backend in turn (users go to the other one, if compiled in)
+    for &users_backend in BACKENDS.iter().rev() {
+        let temp = TempDir::new().expect("Failed to create temp dir");
+        let storage = storage_with_users_on(users_backend, &temp).await;
+        for site in &sites {
+            storage.sites.create(site.clone()).await.unwrap();
+        }
+        storage.sites.create(Site::new(Uuid::new_v4(), Uuid::new_v4(), "other".to_string(), "d".to_string())).await.unwrap();
+
+        let listed: Vec<Uuid> = storage.sites.list_by_owner(owner_id).await.unwrap().iter().map(|s| s.id).collect();
+        assert_eq!(listed, expected, "users on {}", users_backend);
+    }
+
+    // Default storage compares both backends' answers and warns when they differ
+    let (storage, _temp) = create_test_storage().await;
+    for site in &sites {
+        storage.sites.create(site.clone()).await.unwrap();
+    }
+    let (logs, _guard) = capture_logs();
+    let listed: Vec<Uuid> = storage.sites.list_by_owner(owner_id).await.unwrap().iter().map(|s| s.id).collect();
+    assert_eq!(listed, expected);
+    assert!(!logs.contents().contains("list_by_owner mismatch"), "{}", logs.contents());
+}
+
+/// sled's background I/O can keep the file lock for a moment after the last handle is dropped
+#[cfg(feature = "sled")]
+fn open_sled_when_released(path: &std::path::Path) -> sled::Db {
+    for _ in 0..50 {
+        if let Ok(db) = sled::open(path) {
+            return db;
+        }
+        std::thread::sleep(std::time::Duration::from_millis(20));
+    }
+    sled::open(path).expect("open sled")
+}
+
+#[cfg(feature = "sled")]
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn test_sled_user_count_under_concurrent_writes() {
+    let temp = TempDir::new().expect("Failed to create temp dir");
+    let storage = std::sync::Arc::new(storage_with_users_on("sled", &temp).await);
+
+    // 40 creates racing with deletes of every other user
+    let mut tasks = Vec::new();
+    for i in 0..40 {
+        let storage = storage.clone();
+        tasks.push(tokio::spawn(async move {
+            let user = storage.users.create(User::new(format!("user-{}", i), "pw".to_string())).await.unwrap();
+            if i % 2 == 0 {
+                storage.users.delete(user.id).await.unwrap();
+                // A repeated delete must not decrement again
+                storage.users.delete(user.id).await.unwrap();
+            }
+        }));
+    }
+    for task in tasks {
+        task.await.unwrap();
+    }
+
+    assert_eq!(storage.users.count().await.unwrap(), 20);
+    assert_eq!(storage.users.list_all().await.unwrap().len(), 20);
+
+    // A missing counter is rebuilt from a scan
+    drop(storage);
+    {
+        let db = open_sled_when_released(&temp.path().join("users-db").join("users.db"));
+        db.remove("meta:user_count").expect("remove counter");
+        db.flush().expect("flush");
+    }
+    let storage = storage_with_users_on("sled", &temp).await;
+    assert_eq!(storage.users.count().await.unwrap(), 20);
+    storage.users.create(User::new("late".to_string(), "pw".to_string())).await.unwrap();
+    assert_eq!(storage.users.count().await.unwrap(), 21);
+}
+
+#[tokio::test]
+async fn test_prune_audit_keeps_recent_events_on_every_backend() {
+    for &backend in BACKENDS {
+        let temp = TempDir::new().expect("Failed to create temp dir");
+        let storage = storage_with_users_on(backend, &temp).await;
+
+        // More old events than one batch, so pruning has to loop
+        let old_count = AUDIT_PRUNE_BATCH + 3;
+        for i in 0..old_count {
+            let mut event = AuditEvent::new(AuditAction::LoginSuccess, None, Some(format!("old-{}", i)));
+            event.timestamp = chrono::Utc::now() - chrono::Duration::days(40);
+            storage.audit.append(event).await.unwrap();
+        }
+        let mut edge = AuditEvent::new(AuditAction::LoginSuccess, None, Some("edge".to_string()));
+        edge.timestamp = chrono::Utc::now() - chrono::Duration::days(29);
+        storage.audit.append(edge).await.unwrap();
+        storage.audit.append(AuditEvent::new(AuditAction::LoginSuccess, None, Some("recent".to_string()))).await.unwrap();
+
+        let removed = storage.prune_audit(30).await
+            .unwrap_or_else(|e| panic!("{}: prune failed: {:?}", backend, e));
+        assert_eq!(removed, old_count, "{}", backend);
+
+        let left = storage.audit.recent(usize::MAX).await.unwrap();
+        let targets: Vec<_> = left.iter().filter_map(|e| e.target.as_deref()).collect();
+        assert_eq!(targets, vec!["recent", "edge"], "{}", backend);
+
+        assert_eq!(storage.prune_audit(30).await.unwrap(), 0, "{}", backend);
+    }
+}
+
+#[cfg(feature = "debug_sled_and_orm")]
+#[tokio::test]
+async fn test_debug_create_reads_back_identically_from_both_backends() {
+    use obsidian_publisher_server::storage::{debug, orm, sled};
+
+    let temp = TempDir::new().expect("Failed to create temp dir");
+    let sled_path = temp.path().join("sled");
+    let sites_path = temp.path().join("sites");
+    let url = obsidian_publisher_server::storage::get_database_url(&StorageEntry {
+        name: None, backend: "sqlite".to_string(), path: Some(temp.path().to_path_buf()), role: None,
+    });
+    let (sled_users, orm_users) = (sled::UserStorage::new(&sled_path).await.unwrap(), orm::UserStorage::new(&url).await.unwrap());
+    let (sled_sites, orm_sites) = (
+        sled::SiteStorage::new(&sled_path, sites_path.clone()).await.unwrap(),
+        orm::SiteStorage::new(&url, sites_path).await.unwrap(),
+    );
+    let users = debug::UserStorage::new(sled_users.clone(), orm_users.clone()).await.unwrap();
+    let sites = debug::SiteStorage::new(sled_sites.clone(), orm_sites.clone()).await.unwrap();
+
+    let (logs, _guard) = capture_logs();
+    let mut user = User::new("twin".to_string(), "pw".to_string());
+    user.email = Some("twin@example.com".to_string());
+    user.roles = vec!["editor".to_string()];
+    users.create(user.clone()).await.expect("debug user create failed");
+    let mut site = Site::new(Uuid::new_v4(), user.id, "twin-site".to_string(), "desc".to_string());
+    site.domain = Some("twin.example.com".to_string());
+    site.content_hash = Some("abc123".to_string());
+    sites.create(site.clone()).await.expect("debug site create failed");
+
+    let a = serde_json::to_string(&sled_users.get(user.id).await.unwrap()).unwrap();
+    let b = serde_json::to_string(&orm_users.get(user.id).await.unwrap()).unwrap();
+    assert_eq!(a, b);
+    assert_eq!(a, serde_json::to_string(&Some(&user)).unwrap());
+    let a = serde_json::to_string(&sled_sites.get(site.id).await.unwrap()).unwrap();
+    let b = serde_json::to_string(&orm_sites.get(site.id).await.unwrap()).unwrap();
+    assert_eq!(a, b);
+    assert_eq!(a, serde_json::to_string(&Some(&site)).unwrap());
+    assert!(!logs.contents().contains("after create"), "{}", logs.contents());
+}
+
+#[cfg(feature = "debug_sled_and_orm")]
+#[tokio::test]
+async fn test_debug_concurrent_writes_stay_consistent_and_report_divergence() {
+    use obsidian_publisher_server::storage::{debug, orm, sled};
+
+    let temp = TempDir::new().expect("Failed to create temp dir");
+    let url = obsidian_publisher_server::storage::get_database_url(&StorageEntry {
+        name: None, backend: "sqlite".to_string(), path: Some(temp.path().to_path_buf()), role: None,
+    });
+    let sled_users = sled::UserStorage::new(&temp.path().join("sled")).await.unwrap();
+    let orm_users = orm::UserStorage::new(&url).await.unwrap();
+    let users = debug::UserStorage::new(sled_users.clone(), orm_users.clone()).await.unwrap()
+        .with_concurrent_writes(true);
+
+    let (logs, _guard) = capture_logs();
+    let mut kept = User::new("kept".to_string(), "pw".to_string());
+    users.create(kept.clone()).await.expect("concurrent create failed");
+    kept.email = Some("kept@example.com".to_string());
+    users.update(kept.clone()).await.expect("concurrent update failed");
+    let gone = users.create(User::new("gone".to_string(), "pw".to_string())).await.unwrap();
+    users.delete(gone.id).await.expect("concurrent delete failed");
+
+    let a = serde_json::to_string(&sled_users.list_all().await.unwrap()).unwrap();
+    let b = serde_json::to_string(&orm_users.list_all().await.unwrap()).unwrap();
+    assert_eq!(a, b);
+    assert_eq!(orm_users.get_by_email("kept@example.com").await.unwrap().map(|u| u.id), Some(kept.id));
+    assert!(!logs.contents().contains("mismatch"), "{}", logs.contents());
+
+    // Drop the row behind debug's back: the orm half of the next update fails while sled's succeeds
+    orm_users.delete(kept.id).await.unwrap();
+    kept.roles = vec!["editor".to_string()];
+    let err = users.update(kept).await.unwrap_err();
+    assert!(matches!(err, AppError::UserNotFound), "got {:?}", err);
+    assert!(logs.contents().contains("update mismatch"), "{}", logs.contents());
+}
+
+/// Behavior every `UserStore` shares, checked through the runtime-dispatched handle
+async fn check_user_store(users: &UserStorage) {
+    let backend = users.backend();
+    let mut alice = User::new("alice".to_string(), "pw".to_string());
+    alice.email = Some("alice@example.com".to_string());
+    alice.created_at = chrono::Utc::now() - chrono::Duration::hours(1);
+    users.create(alice.clone()).await.unwrap_or_else(|e| panic!("{}: create failed: {:?}", backend, e));
+    let bob = users.create(User::new("bob".to_string(), "pw".to_string())).await.unwrap();
+
+    assert_eq!(users.get_by_username("alice").await.unwrap().map(|u| u.id), Some(alice.id), "{}", backend);
+    assert_eq!(users.get_by_email("alice@example.com").await.unwrap().map(|u| u.id), Some(alice.id), "{}", backend);
+    let err = users.create(User::new("alice".to_string(), "pw".to_string())).await.unwrap_err();
+    assert!(matches!(err, AppError::UserAlreadyExists), "{}: got {:?}", backend, err);
+
+    let listed: Vec<Uuid> = users.list_all().await.unwrap().iter().map(|u| u.id).collect();
+    assert_eq!(listed, vec![bob.id, alice.id], "{}: list_all should be newest first", backend);
+    assert_eq!(users.count().await.unwrap(), 2, "{}", backend);
+
+    let mut by_id = vec![alice.id, bob.id];
+    by_id.sort();
+    let first = users.list_page(None, 1).await.unwrap();
+    assert_eq!(first.iter().map(|u| u.id).collect::<Vec<_>>(), &by_id[..1], "{}", backend);
+    let rest = users.list_page(Some(first[0].id), 10).await.unwrap();
+    assert_eq!(rest.iter().map(|u| u.id).collect::<Vec<_>>(), &by_id[1..], "{}: pages follow id order", backend);
+    assert!(users.list_page(Some(by_id[1]), 10).await.unwrap().is_empty(), "{}", backend);
+
+    users.delete(alice.id).await.unwrap();
+    users.delete(alice.id).await.unwrap();
+    assert!(users.get_by_username("alice").await.unwrap().is_none(), "{}", backend);
+    assert!(users.get_by_email("alice@example.com").await.unwrap().is_none(), "{}", backend);
+    assert_eq!(users.count().await.unwrap(), 1, "{}", backend);
+}
+
+/// Behavior every `SiteStore` shares, checked through the runtime-dispatched handle
+async fn check_site_store(sites: &SiteStorage) {
+    let backend = sites.backend();
+    let owner = Uuid::new_v4();
+    let now = chrono::Utc::now();
+    let version = |name: &str, hours_old: i64| {
+        let mut site = Site::new(Uuid::new_v4(), owner, name.to_string(), "d".to_string());
+        site.created_at = now - chrono::Duration::hours(hours_old);
+        site
+    };
+    let (old, new, other) = (version("blog", 2), version("blog", 1), version("docs", 3));
+    for site in [&old, &new, &other] {
+        sites.create(site.clone()).await.unwrap_or_else(|e| panic!("{}: create failed: {:?}", backend, e));
+    }
+    let elsewhere = Site::new(Uuid::new_v4(), Uuid::new_v4(), "elsewhere".to_string(), "d".to_string());
+    sites.create(elsewhere.clone()).await.unwrap();
+    // An id is never reused: the second create is refused instead of overwriting
+    let mut clash = elsewhere.clone();
+    clash.name = "clash".to_string();
+    assert!(sites.create(clash).await.is_err(), "{}: duplicate id should be refused", backend);
+    assert_eq!(sites.get(elsewhere.id).await.unwrap().map(|s| s.name).as_deref(), Some("elsewhere"), "{}", backend);
+
+    assert_eq!(sites.get_latest_by_name("blog").await.unwrap().map(|s| s.id), Some(new.id), "{}", backend);
+    let all: Vec<Uuid> = sites.get_all_by_name("blog").await.unwrap().iter().map(|s| s.id).collect();
+    assert_eq!(all, vec![new.id, old.id], "{}", backend);
+    let owned: Vec<Uuid> = sites.list_by_owner(owner).await.unwrap().iter().map(|s| s.id).collect();
+    assert_eq!(owned, vec![new.id, old.id, other.id], "{}", backend);
+    assert_eq!(sites.list_all().await.unwrap().len(), 4, "{}", backend);
+
+    let mut by_id = vec![old.id, new.id, other.id, elsewhere.id];
+    by_id.sort();
+    let mut paged = Vec::new();
+    let mut after = None;
+    loop {
+        let page = sites.list_page(after, 3).await.unwrap();
+        paged.extend(page.iter().map(|s| s.id));
+        if page.len() < 3 {
+            break;
+        }
+        after = page.last().map(|s| s.id);
+    }
+    assert_eq!(paged, by_id, "{}: pages should cover every version once, by id", backend);
+    assert_eq!(sites.list_distinct_names().await.unwrap(), vec!["blog", "docs", "elsewhere"], "{}", backend);
+    let latest: Vec<String> = sites.list_latest_per_name().await.unwrap().into_iter().map(|s| s.name).collect();
+    assert_eq!(latest, vec!["elsewhere", "blog", "docs"], "{}", backend);
+
+    let mut renamed = other.clone();
+    renamed.name = "handbook".to_string();
+    sites.update(renamed).await.unwrap();
+    assert!(sites.get_latest_by_name("docs").await.unwrap().is_none(), "{}", backend);
+    assert_eq!(sites.get(other.id).await.unwrap().map(|s| s.name).as_deref(), Some("handbook"), "{}", backend);
+
+    // The name directory goes with the last version
+    let name_dir = sites.get_site_files_path_str("blog");
+    std::fs::create_dir_all(&name_dir).unwrap();
+    std::fs::create_dir_all(sites.get_site_files_path(new.id)).unwrap();
+    sites.delete(new.id).await.unwrap();
+    assert!(!sites.get_site_files_path(new.id).exists(), "{}", backend);
+    assert!(name_dir.exists(), "{}: blog still has a version", backend);
+    sites.delete(old.id).await.unwrap();
+    assert!(!name_dir.exists(), "{}", backend);
+    assert!(sites.get_latest_by_name("blog").await.unwrap().is_none(), "{}", backend);
+}
+
+#[tokio::test]
+async fn test_user_store_semantics_on_every_backend() {
+    for &backend in BACKENDS {
+        let temp = TempDir::new().expect("Failed to create temp dir");
+        check_user_store(&storage_with_users_on(backend, &temp).await.users).await;
+    }
+    let (storage, _temp) = create_test_storage().await;
+    check_user_store(&storage.users).await;
+    check_user_store(&UserStorage::new(memory::UserStorage::new())).await;
+}
+
+#[tokio::test]
+async fn test_site_store_semantics_on_every_backend() {
+    // Sites go to the backend users are not on, so run from each side
+    for &users_backend in BACKENDS.iter().rev() {
+        let temp = TempDir::new().expect("Failed to create temp dir");
+        check_site_store(&storage_with_users_on(users_backend, &temp).await.sites).await;
+    }
+    let (storage, _temp) = create_test_storage().await;
+    check_site_store(&storage.sites).await;
+
+    let temp = TempDir::new().expect("Failed to create temp dir");
+    let sites = SiteStorage::new(memory::SiteStorage::new(temp.path().join("sites")).unwrap());
+    assert_eq!(sites.backend(), "memory");
+    check_site_store(&sites).await;
+}
+
+#[tokio::test]
+async fn test_memory_backend_selected_from_config() {
+    let (storage, _temp) = create_memory_storage().await;
+    assert_eq!(storage.users.backend(), "memory");
+    assert_eq!(storage.sites.backend(), "memory");
+    assert_eq!(storage.audit.backend(), "memory");
+    assert_eq!(storage.invites.backend(), "memory");
+    assert_eq!(storage.password_resets.backend(), "memory");
+
+    check_user_store(&storage.users).await;
+    check_site_store(&storage.sites).await;
+
+    let mut old = AuditEvent::new(AuditAction::LoginSuccess, None, Some("old".to_string()));
+    old.timestamp = chrono::Utc::now() - chrono::Duration::days(40);
+    storage.audit.append(old).await.unwrap();
+    storage.audit.append(AuditEvent::new(AuditAction::LoginSuccess, None, Some("recent".to_string()))).await.unwrap();
+    assert_eq!(storage.prune_audit(30).await.unwrap(), 1);
+    let left: Vec<_> = storage.audit.recent(10).await.unwrap().into_iter().filter_map(|e| e.target).collect();
+    assert_eq!(left, vec!["recent"]);
+}
+
+/// The same writes, in the same order, against any storage
+#[cfg(feature = "sled")]
+async fn apply_parity_script(storage: &Storage, owner: &User, bystander: &User, sites: &[Site]) {
+    storage.users.create(owner.clone()).await.unwrap();
+    storage.users.create(bystander.clone()).await.unwrap();
+    for site in sites {
+        storage.sites.create(site.clone()).await.unwrap();
+    }
+    let mut renamed = sites[1].clone();
+    renamed.name = "renamed".to_string();
+    storage.sites.update(renamed).await.unwrap();
+    storage.sites.delete(sites[3].id).await.unwrap();
+}
+
+/// Everything the read methods return, as JSON, with `list_all` sorted by id since its
+/// order is backend-specific
+#[cfg(feature = "sled")]
+async fn parity_snapshot(storage: &Storage, owner_id: Uuid) -> Vec<String> {
+    let mut all_sites = storage.sites.list_all().await.unwrap();
+    all_sites.sort_by_key(|s| s.id);
+    vec![
+        serde_json::to_string(&storage.users.list_all().await.unwrap()).unwrap(),
+        serde_json::to_string(&storage.users.get_by_username("owner").await.unwrap()).unwrap(),
+        serde_json::to_string(&storage.users.count().await.unwrap()).unwrap(),
+        serde_json::to_string(&all_sites).unwrap(),
+        serde_json::to_string(&storage.sites.list_by_owner(owner_id).await.unwrap()).unwrap(),
+        serde_json::to_string(&storage.sites.get_latest_by_name("blog").await.unwrap()).unwrap(),
+        serde_json::to_string(&storage.sites.get_all_by_name("blog").await.unwrap()).unwrap(),
+        serde_json::to_string(&storage.sites.list_latest_per_name().await.unwrap()).unwrap(),
+        serde_json::to_string(&storage.sites.list_distinct_names().await.unwrap()).unwrap(),
+    ]
+}
+
+#[cfg(feature = "sled")]
+#[tokio::test]
+async fn test_memory_backend_matches_sled() {
+    let mut owner = User::new("owner".to_string(), "pw".to_string());
+    owner.email = Some("owner@example.com".to_string());
+    owner.created_at = chrono::Utc::now() - chrono::Duration::minutes(5);
+    let bystander = User::new("bystander".to_string(), "pw".to_string());
+    let now = chrono::Utc::now();
+    let sites: Vec<Site> = [("blog", 4), ("blog", 3), ("docs", 2), ("blog", 1), ("notes", 0)]
+        .iter()
+        .map(|&(name, hours_old)| {
+            let mut site = Site::new(Uuid::new_v4(), owner.id, name.to_string(), "d".to_string());
+            site.created_at = now - chrono::Duration::hours(hours_old);
+            site
+        })
+        .collect();
+
+    // Both stores on sled, routed so the feature-selected default doesn't come into play
+    let temp = TempDir::new().expect("Failed to create temp dir");
+    let sled = Storage::new(&StorageConfig {
+        sites: StaticStorageConfig { path: temp.path().join("sites") },
+        db: vec![
+            StorageEntry { name: None, backend: "sled".to_string(), path: Some(temp.path().join("users-db")), role: Some(StorageRole::Users) },
+            StorageEntry { name: None, backend: "sled".to_string(), path: Some(temp.path().join("sites-db")), role: Some(StorageRole::Sites) },
+        ],
+        archive: ArchiveConfig::default(),
+        keep_temp_on_error: false,
+        max_concurrent_extractions: 4,
+        max_concurrent_uploads_per_user: 2,
+        audit_retention_days: 0,
+        debug_concurrent_writes: false,
+        db_retries: default_db_retries(),
+        db_retry_backoff_ms: default_db_retry_backoff_ms(),
+    }).await.expect("Failed to create storage");
+    let (memory, _memory_temp) = create_memory_storage().await;
+
+    apply_parity_script(&sled, &owner, &bystander, &sites).await;
+    apply_parity_script(&memory, &owner, &bystander, &sites).await;
+
+    let expected = parity_snapshot(&sled, owner.id).await;
+    let actual = parity_snapshot(&memory, owner.id).await;
+    for (sled_json, memory_json) in expected.iter().zip(&actual) {
+        assert_eq!(memory_json, sled_json);
+    }
+}
+
+#[cfg(feature = "orm")]
+#[tokio::test]
+async fn test_orm_retries_transient_errors_but_not_logical_ones() {
+    use obsidian_publisher_server::storage::{orm, retry::retry, RetryPolicy};
+    use sea_orm::{DbErr, RuntimeErr};
+    use std::sync::atomic::{AtomicU32, Ordering};
+    use std::time::Duration;
+
+    // A connection that drops twice, then answers
+    let policy = RetryPolicy { retries: 3, backoff: Duration::from_millis(1) };
+    let calls = &AtomicU32::new(0);
+    let flaky = retry(policy, orm::retry::is_transient, move || async move {
+        match calls.fetch_add(1, Ordering::SeqCst) {
+            0 | 1 => Err(DbErr::Conn(RuntimeErr::Internal("connection reset by peer".to_string()))),
+            _ => Ok("row"),
+        }
+    }).await;
+    assert_eq!(flaky.unwrap(), "row");
+    assert_eq!(calls.load(Ordering::SeqCst), 3);
+
+    // Still down after every retry: the last error comes back
+    calls.store(0, Ordering::SeqCst);
+    let down: Result<(), DbErr> = retry(policy, orm::retry::is_transient, move || async move {
+        calls.fetch_add(1, Ordering::SeqCst);
+        Err(DbErr::Conn(RuntimeErr::Internal("connection refused".to_string())))
+    }).await;
+    assert!(matches!(down, Err(DbErr::Conn(_))));
+    assert_eq!(calls.load(Ordering::SeqCst), 4);
+
+    calls.store(0, Ordering::SeqCst);
+    let missing: Result<(), DbErr> = retry(policy, orm::retry::is_transient, move || async move {
+        calls.fetch_add(1, Ordering::SeqCst);
+        Err(DbErr::RecordNotFound("sites".to_string()))
+    }).await;
+    assert!(matches!(missing, Err(DbErr::RecordNotFound(_))));
+    assert_eq!(calls.load(Ordering::SeqCst), 1);
+
+    // A real unique-constraint violation fails on the first attempt
+    let temp = TempDir::new().expect("Failed to create temp dir");
+    let url = obsidian_publisher_server::storage::get_database_url(&StorageEntry {
+        name: None, backend: "sqlite".to_string(), path: Some(temp.path().to_path_buf()), role: None,
+    });
+    let users = orm::UserStorage::new(&url).await.unwrap()
+        .with_retry(RetryPolicy { retries: 3, backoff: Duration::from_secs(1) });
+    let (logs, _guard) = capture_logs();
+    users.create(User::new("dup".to_string(), "pw".to_string())).await.unwrap();
+    let err = users.create(User::new("dup".to_string(), "pw".to_string())).await.unwrap_err();
+    assert!(matches!(err, AppError::UserAlreadyExists), "got {:?}", err);
+    assert!(!logs.contents().contains("transient database error"), "{}", logs.contents());
+}
+
+#[cfg(feature = "orm")]
+#[tokio::test]
+async fn test_retrying_connection_replays_reads_but_not_sent_writes() {
+    use obsidian_publisher_server::storage::{orm::retry::RetryingConnection, RetryPolicy};
+    use sea_orm::{ConnAcquireErr, ConnectionTrait, DbBackend, DbErr, MockDatabase, MockExecResult, RuntimeErr, Statement, Value};
+    use std::collections::BTreeMap;
+    use std::time::Duration;
+
+    let policy = RetryPolicy { retries: 3, backoff: Duration::from_millis(1) };
+    let dropped = || DbErr::Conn(RuntimeErr::Internal("connection reset by peer".to_string()));
+    let row = BTreeMap::from([("id", Value::from("a"))]);
+    let written = MockExecResult { last_insert_id: 0, rows_affected: 1 };
+
+    // A read whose connection drops twice is run again until it answers
+    let conn = RetryingConnection::new(
+        MockDatabase::new(DbBackend::Sqlite)
+            .append_query_errors([dropped(), dropped()])
+            .append_query_results([vec![row.clone()]])
+            .into_connection(),
+    ).with_policy(policy);
+    let select = Statement::from_string(DbBackend::Sqlite, "SELECT id FROM sites".to_string());
+    assert_eq!(conn.query_all(select).await.expect("read was not retried").len(), 1);
+
+    // A write that may have reached the database is not replayed...
+    let conn = RetryingConnection::new(
+        MockDatabase::new(DbBackend::Sqlite)
+            .append_exec_errors([dropped()])
+            .append_exec_results([written.clone()])
+            .into_connection(),
+    ).with_policy(policy);
+    let insert = Statement::from_string(DbBackend::Sqlite, "INSERT INTO sites (id) VALUES ('a')".to_string());
+    assert!(matches!(conn.execute(insert.clone()).await, Err(DbErr::Conn(_))));
+
+    // ...but one that never got a connection is
+    let conn = RetryingConnection::new(
+        MockDatabase::new(DbBackend::Sqlite)
+            .append_exec_errors([DbErr::ConnectionAcquire(ConnAcquireErr::Timeout)])
+            .append_exec_results([written])
+            .into_connection(),
+    ).with_policy(policy);
+    assert_eq!(conn.execute(insert).await.expect("unsent write was not retried").rows_affected(), 1);
+}
@@ -0,0 +1,172 @@
+/// Chunked upload handler tests
+///
+/// These tests drive the resumable upload handlers directly, without HTTP.
+
+mod utils;
+
+use axum::{
+    body::Body,
+    extract::{Path, Query, State},
+};
+use obsidian_publisher_server::{
+    auth::{AuthUser, AuthenticatedUser},
+    config::Config,
+    error::AppError,
+    handlers::json::JsonBody,
+    handlers::sites::UploadOrigin,
+    handlers::uploads::{complete_upload, start_upload, upload_chunk, upload_status},
+    models::{CompleteUploadRequest, StartUploadRequest},
+    storage::{Storage, CHUNKED_UPLOADS_DIR, CHUNKED_UPLOAD_TTL},
+};
+use std::collections::HashMap;
+use std::sync::Arc;
+use utils::storage::{create_test_archive_file, create_test_storage};
+use uuid::Uuid;
+
+type AppState = State<(Arc<Storage>, Arc<Config>)>;
+
+fn state(storage: &Arc<Storage>) -> AppState {
+    State((storage.clone(), Arc::new(Config::default())))
+}
+
+fn auth(user_id: Uuid) -> AuthenticatedUser {
+    AuthenticatedUser(AuthUser { id: user_id, username: "uploader".to_string(), exp: usize::MAX })
+}
+
+async fn start(storage: &Arc<Storage>, user_id: Uuid) -> Uuid {
+    let req = StartUploadRequest { filename: "site.tar.gz".to_string() };
+    start_upload(state(storage), auth(user_id), JsonBody(req)).await.expect("start_upload failed").0.upload_id
+}
+
+async fn put_chunk(storage: &Arc<Storage>, user_id: Uuid, id: Uuid, offset: u64, chunk: &[u8]) -> Result<u64, AppError> {
+    let mut params = HashMap::new();
+    params.insert("offset".to_string(), offset.to_string());
+    upload_chunk(state(storage), auth(user_id), Path(id), Query(params), Body::from(chunk.to_vec()))
+        .await
+        .map(|res| res.0.offset)
+}
+
+fn complete_request(site_id: Uuid) -> CompleteUploadRequest {
+    CompleteUploadRequest { uuid: site_id, site_name: "chunked-site".to_string(), mode: None, root_dir: None, spa_mode: None, fingerprint_assets: None, auto_sitemap: None }
+}
+
+#[tokio::test]
+async fn test_chunked_upload_two_chunks() {
+    let (storage, temp) = create_test_storage().await;
+    let storage = Arc::new(storage);
+    let user_id = Uuid::new_v4();
+    let site_id = Uuid::new_v4();
+    let archive = std::fs::read(create_test_archive_file(temp.path(), &site_id)).unwrap();
+    let (first, second) = archive.split_at(archive.len() / 2);
+
+    let id = start(&storage, user_id).await;
+    assert_eq!(put_chunk(&storage, user_id, id, 0, first).await.unwrap(), first.len() as u64);
+    assert_eq!(put_chunk(&storage, user_id, id, first.len() as u64, second).await.unwrap(), archive.len() as u64);
+
+    let status = upload_status(state(&storage), auth(user_id), Path(id)).await.unwrap().0;
+    assert_eq!(status.offset, archive.len() as u64);
+}
+
+#[tokio::test]
+async fn test_chunked_upload_resume_after_gap() {
+    let (storage, temp) = create_test_storage().await;
+    let storage = Arc::new(storage);
+    let user_id = Uuid::new_v4();
+    let site_id = Uuid::new_v4();
+    let archive = std::fs::read(create_test_archive_file(temp.path(), &site_id)).unwrap();
+    let (first, second) = archive.split_at(archive.len() / 3);
+
+    let id = start(&storage, user_id).await;
+    put_chunk(&storage, user_id, id, 0, first).await.unwrap();
+
+    // A chunk past the received bytes is refused and reports where to resume
+    let err = put_chunk(&storage, user_id, id, archive.len() as u64, second).await.unwrap_err();
+    assert!(matches!(err, AppError::UploadOffsetMismatch(n) if n == first.len() as u64), "got {:?}", err);
+
+    // The client asks for the offset and continues from there
+    let resume_at = upload_status(state(&storage), auth(user_id), Path(id)).await.unwrap().0.offset;
+    assert_eq!(resume_at, first.len() as u64);
+    assert_eq!(put_chunk(&storage, user_id, id, resume_at, second).await.unwrap(), archive.len() as u64);
+
+    // Other users can't see or extend the session
+    let stranger = Uuid::new_v4();
+    let err = upload_status(state(&storage), auth(stranger), Path(id)).await.unwrap_err();
+    assert!(matches!(err, AppError::AuthorizationFailed), "got {:?}", err);
+}
+
+#[tokio::test]
+async fn test_chunked_upload_complete_publishes_site() {
+    let (storage, temp) = create_test_storage().await;
+    let storage = Arc::new(storage);
+    let user_id = Uuid::new_v4();
+    let site_id = Uuid::new_v4();
+    let archive = std::fs::read(create_test_archive_file(temp.path(), &site_id)).unwrap();
+    let (first, second) = archive.split_at(archive.len() / 2);
+
+    let id = start(&storage, user_id).await;
+    put_chunk(&storage, user_id, id, 0, first).await.unwrap();
+    put_chunk(&storage, user_id, id, first.len() as u64, second).await.unwrap();
+
+    let site = complete_upload(state(&storage), auth(user_id), Path(id), UploadOrigin::default(), JsonBody(complete_request(site_id)))
+        .await
+        .expect("complete_upload failed")
+        .0;
+    assert_eq!(site.id, site_id);
+    assert_eq!(site.name, "chunked-site");
+
+    let record = storage.sites.get(site_id).await.unwrap().expect("site record missing");
+    assert_eq!(record.owner_id, user_id);
+    assert!(storage.sites.get_site_files_path_str(&site_id.to_string()).join("index.html").exists());
+    assert!(storage.sites.get_site_files_path_str("chunked-site").join("index.html").exists());
+
+    // The session is gone once published
+    let err = upload_status(state(&storage), auth(user_id), Path(id)).await.unwrap_err();
+    assert!(matches!(err, AppError::UploadNotFound), "got {:?}", err);
+}
+
+#[tokio::test]
+async fn test_chunked_upload_is_capped_at_the_archive_limit() {
+    let (storage, _temp) = create_test_storage().await;
+    let storage = Arc::new(storage);
+    let user_id = Uuid::new_v4();
+    let mut config = Config::default();
+    config.storage.archive.max_total_bytes = 10;
+    let config = Arc::new(config);
+    let put = |id: Uuid, offset: u64, chunk: &[u8]| {
+        let params: HashMap<String, String> = [("offset".to_string(), offset.to_string())].into_iter().collect();
+        upload_chunk(State((storage.clone(), config.clone())), auth(user_id), Path(id), Query(params), Body::from(chunk.to_vec()))
+    };
+
+    let id = start(&storage, user_id).await;
+    assert_eq!(put(id, 0, b"123456").await.unwrap().0.offset, 6);
+    // The whole chunk is dropped once the session would pass the limit
+    let err = put(id, 6, b"78901").await.unwrap_err();
+    assert!(matches!(err, AppError::PayloadTooLarge(10)), "got {:?}", err);
+    assert_eq!(upload_status(state(&storage), auth(user_id), Path(id)).await.unwrap().0.offset, 6);
+    assert_eq!(put(id, 6, b"7890").await.unwrap().0.offset, 10);
+}
+
+#[tokio::test]
+async fn test_cleanup_temp_expires_stale_chunked_uploads() {
+    let (storage, temp) = create_test_storage().await;
+    let storage = Arc::new(storage);
+    let user_id = Uuid::new_v4();
+
+    let stale = start(&storage, user_id).await;
+    put_chunk(&storage, user_id, stale, 0, b"partial").await.unwrap();
+    let fresh = start(&storage, user_id).await;
+
+    // Nothing was written to the stale session for longer than the TTL
+    let stale_dir = temp.path().join("sites").join(CHUNKED_UPLOADS_DIR).join(stale.to_string());
+    let long_ago = std::time::SystemTime::now() - CHUNKED_UPLOAD_TTL - std::time::Duration::from_secs(60);
+    for entry in std::fs::read_dir(&stale_dir).unwrap() {
+        std::fs::File::options().write(true).open(entry.unwrap().path()).unwrap().set_modified(long_ago).unwrap();
+    }
+    std::fs::File::open(&stale_dir).unwrap().set_modified(long_ago).unwrap();
+
+    let removed = storage.cleanup_temp().await.unwrap();
+    assert_eq!(removed, vec![format!("{}/{}", CHUNKED_UPLOADS_DIR, stale)]);
+    let err = upload_status(state(&storage), auth(user_id), Path(stale)).await.unwrap_err();
+    assert!(matches!(err, AppError::UploadNotFound), "got {:?}", err);
+    assert_eq!(upload_status(state(&storage), auth(user_id), Path(fresh)).await.unwrap().0.offset, 0);
+}
@@ -0,0 +1,61 @@
+use obsidian_publisher_server::{build_app, config::Config, storage::Storage};
+use std::net::SocketAddr;
+use std::sync::Arc;
+use tempfile::TempDir;
+
+use super::storage::create_test_storage;
+
+/// The fully assembled app (`build_app`, with every layer) served over real HTTP on an
+/// ephemeral local port. The server task lives as long as the test's runtime.
+pub struct TestApp {
+    pub addr: SocketAddr,
+    pub client: reqwest::Client,
+    pub storage: Arc<Storage>,
+    pub config: Arc<Config>,
+    _temp: TempDir,
+}
+
+impl TestApp {
+    pub async fn spawn() -> Self {
+        Self::spawn_with(|_| {}).await
+    }
+
+    /// Like `spawn`, with a chance to adjust the config first (`server.url` is preset
+    /// to the bound address)
+    pub async fn spawn_with(configure: impl FnOnce(&mut Config)) -> Self {
+        let (storage, temp) = create_test_storage().await;
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.expect("Failed to bind test listener");
+        let addr = listener.local_addr().expect("Failed to read bound address");
+
+        let mut config = Config::default();
+        config.server.url = format!("http://{}", addr);
+        configure(&mut config);
+
+        let storage = Arc::new(storage);
+        let config = Arc::new(config);
+        let app = build_app(storage.clone(), config.clone());
+        tokio::spawn(async move {
+            axum::serve(listener, app.into_make_service_with_connect_info::<SocketAddr>())
+                .await
+                .expect("test server failed");
+        });
+
+        Self { addr, client: reqwest::Client::new(), storage, config, _temp: temp }
+    }
+
+    pub fn url(&self, path: &str) -> String {
+        format!("http://{}{}", self.addr, path)
+    }
+
+    /// Register `username` and log in, returning the bearer token
+    pub async fn register_and_login(&self, username: &str, password: &str) -> String {
+        let credentials = serde_json::json!({ "username": username, "password": password });
+        let res = self.client.post(self.url("/auth/register")).json(&credentials).send().await.expect("register request failed");
+        assert_eq!(res.status(), reqwest::StatusCode::OK, "register failed: {}", res.text().await.unwrap_or_default());
+
+        let res = self.client.post(self.url("/auth/login")).json(&credentials).send().await.expect("login request failed");
+        assert_eq!(res.status(), reqwest::StatusCode::OK, "login failed: {}", res.text().await.unwrap_or_default());
+        let json: serde_json::Value = res.json().await.expect("login body should be JSON");
+        json["token"].as_str().expect("login should return a token").to_string()
+    }
+}
@@ -1,2 +1,5 @@
 #![cfg_attr(test, allow(unused))]
-pub mod storage;
\ No newline at end of file
+pub mod app;
+pub mod logs;
+pub mod multipart;
+pub mod storage;
@@ -0,0 +1,45 @@
+use axum::{
+    body::Body,
+    extract::{FromRequest, Multipart},
+    http::Request,
+};
+
+const BOUNDARY: &str = "obsidian-publisher-test-boundary";
+
+/// A single multipart field: (name, optional filename, content)
+pub type Part<'a> = (&'a str, Option<&'a str>, Vec<u8>);
+
+/// Encode parts as a multipart/form-data body; returns (content-type, body)
+pub fn multipart_body(parts: &[Part<'_>]) -> (String, Vec<u8>) {
+    let mut body: Vec<u8> = Vec::new();
+    for (name, filename, data) in parts {
+        body.extend_from_slice(format!("--{}\r\n", BOUNDARY).as_bytes());
+        match filename {
+            Some(f) => body.extend_from_slice(format!(
+                "Content-Disposition: form-data; name=\"{}\"; filename=\"{}\"\r\nContent-Type: application/octet-stream\r\n\r\n",
+                name, f
+            ).as_bytes()),
+            None => body.extend_from_slice(format!(
+                "Content-Disposition: form-data; name=\"{}\"\r\n\r\n",
+                name
+            ).as_bytes()),
+        }
+        body.extend_from_slice(data);
+        body.extend_from_slice(b"\r\n");
+    }
+    body.extend_from_slice(format!("--{}--\r\n", BOUNDARY).as_bytes());
+    (format!("multipart/form-data; boundary={}", BOUNDARY), body)
+}
+
+/// Build an axum `Multipart` extractor from raw parts, as if posted by the CLI
+pub async fn build_multipart(parts: &[Part<'_>]) -> Multipart {
+    let (content_type, body) = multipart_body(parts);
+    let req = Request::builder()
+        .method("POST")
+        .uri("/api/sites")
+        .header("content-type", content_type)
+        .body(Body::from(body))
+        .unwrap();
+
+    Multipart::from_request(req, &()).await.expect("Failed to build multipart")
+}
@@ -1,5 +1,5 @@
 use obsidian_publisher_server::{
-    config::{StorageConfig, StaticStorageConfig, StorageEntry},
+    config::{default_db_retries, default_db_retry_backoff_ms, ArchiveConfig, StorageConfig, StaticStorageConfig, StorageEntry},
     storage::Storage,
 };
 use tempfile::TempDir;
@@ -11,25 +11,47 @@ use flate2::Compression;
 
 /// Helper to create an isolated storage instance for testing
 pub async fn create_test_storage() -> (Storage, TempDir) {
+    create_test_storage_with(|_| {}).await
+}
+
+/// Like `create_test_storage`, with a chance to adjust the config first
+pub async fn create_test_storage_with(configure: impl FnOnce(&mut StorageConfig)) -> (Storage, TempDir) {
     let temp_dir = TempDir::new().expect("Failed to create temp dir");
     let sites_dir = temp_dir.path().join("sites");
     let db_sled_dir = temp_dir.path().join("sled");
     let db_sqlite_file = temp_dir.path().join("db.sqlite");
 
-    let config = StorageConfig {
+    let mut config = StorageConfig {
         sites: StaticStorageConfig {
             path: sites_dir
         },
         db: vec![
-            StorageEntry { name: Some("default".to_string()), backend: "sled".to_string(), path: Some(db_sled_dir) },
-            StorageEntry { name: Some("default".to_string()), backend: "sqlite".to_string(), path: Some(db_sqlite_file) },
+            StorageEntry { name: Some("default".to_string()), backend: "sled".to_string(), path: Some(db_sled_dir), role: None },
+            StorageEntry { name: Some("default".to_string()), backend: "sqlite".to_string(), path: Some(db_sqlite_file), role: None },
         ],
+        archive: ArchiveConfig::default(),
+        keep_temp_on_error: false,
+        max_concurrent_extractions: 4,
+        max_concurrent_uploads_per_user: 2,
+        audit_retention_days: 0,
+        debug_concurrent_writes: false,
+        db_retries: default_db_retries(),
+        db_retry_backoff_ms: default_db_retry_backoff_ms(),
     };
+    configure(&mut config);
     
     let storage = Storage::new(&config).await.expect("Failed to create storage");
     (storage, temp_dir)
 }
 
+/// Storage kept entirely in memory (`backend: "memory"`); only site files touch the temp dir
+pub async fn create_memory_storage() -> (Storage, TempDir) {
+    create_test_storage_with(|config| {
+        config.db = vec![StorageEntry { name: Some("memory".to_string()), backend: "memory".to_string(), path: None, role: None }];
+    })
+    .await
+}
+
 /// Create a simple tar.gz archive file for testing
 /// Returns the path to the created archive
 pub fn create_test_archive_file(dir: &std::path::Path, site_id: &Uuid) -> PathBuf {